@@ -0,0 +1,5 @@
+ALTER TABLE job_configs
+ADD COLUMN program_version int not null default 0;
+
+ALTER TABLE job_statuses
+ADD COLUMN program_version int not null default 0;
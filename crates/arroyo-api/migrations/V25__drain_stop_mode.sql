@@ -0,0 +1 @@
+ALTER TYPE stop_mode ADD VALUE 'drain';
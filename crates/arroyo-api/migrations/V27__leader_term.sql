@@ -0,0 +1,2 @@
+ALTER TABLE cluster_info
+ADD COLUMN leader_term BIGINT NOT NULL DEFAULT 0;
@@ -128,7 +128,7 @@ WHERE organization_id = :organization_id AND pub_id = :pub_id;
 
 ----------- pipelines -------------------
 
---: DbPipeline (state?, ttl_micros?)
+--: DbPipeline (state?, ttl_micros?, autoscaling?, restart_strategy?, checkpoint_config?)
 
 --! create_pipeline(udfs?, textual_repr?)
 INSERT INTO pipelines (pub_id, organization_id, created_by, name, type, textual_repr, udfs, program, proto_version)
@@ -136,7 +136,7 @@ VALUES (:pub_id, :organization_id, :created_by, :name, :type, :textual_repr, :ud
 RETURNING id;
 
 --! get_pipelines : DbPipeline
-SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros
+SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros, autoscaling, restart_strategy, checkpoint_config
 FROM pipelines
     INNER JOIN job_configs on pipelines.id = job_configs.pipeline_id
     LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
@@ -151,7 +151,7 @@ ORDER BY pipelines.created_at DESC
 LIMIT :limit::integer;
 
 --! get_pipeline: DbPipeline
-SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros
+SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros, autoscaling, restart_strategy, checkpoint_config
 FROM pipelines
     INNER JOIN job_configs on pipelines.id = job_configs.pipeline_id
     LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
@@ -161,6 +161,18 @@ WHERE pipelines.pub_id = :pub_id AND pipelines.organization_id = :organization_i
 INSERT INTO connection_table_pipelines(pub_id, pipeline_id, connection_table_id)
 VALUES (:pub_id, :pipeline_id, :connection_table_id);
 
+--! delete_pipeline_connection_tables
+DELETE FROM connection_table_pipelines
+WHERE pipeline_id = :pipeline_id;
+
+--! update_pipeline_program(udfs?)
+UPDATE pipelines
+SET
+   textual_repr = :textual_repr,
+   udfs = :udfs,
+   program = :program
+WHERE id = :pipeline_id AND organization_id = :organization_id;
+
 --! delete_pipeline
 DELETE FROM pipelines
 WHERE pub_id = :pub_id AND organization_id = :organization_id;
@@ -168,7 +180,7 @@ WHERE pub_id = :pub_id AND organization_id = :organization_id;
 
 ----------- jobs -----------------------
 
---! update_job(checkpoint_interval_micros?, stop?, parallelism_overrides?)
+--! update_job(checkpoint_interval_micros?, stop?, parallelism_overrides?, autoscaling?, restart_strategy?, checkpoint_config?)
 UPDATE job_configs
 SET
    updated_at = :updated_at,
@@ -176,7 +188,19 @@ SET
 
    stop = COALESCE(:stop, stop),
    checkpoint_interval_micros = COALESCE(:checkpoint_interval_micros, checkpoint_interval_micros),
-   parallelism_overrides = COALESCE(:parallelism_overrides, parallelism_overrides)
+   parallelism_overrides = COALESCE(:parallelism_overrides, parallelism_overrides),
+   autoscaling = COALESCE(:autoscaling, autoscaling),
+   restart_strategy = COALESCE(:restart_strategy, restart_strategy),
+   checkpoint_config = COALESCE(:checkpoint_config, checkpoint_config)
+WHERE id = :job_id AND organization_id = :organization_id;
+
+--! upgrade_job
+UPDATE job_configs
+SET
+   updated_at = :updated_at,
+   updated_by = :updated_by,
+   parallelism_overrides = :parallelism_overrides,
+   program_version = program_version + 1
 WHERE id = :job_id AND organization_id = :organization_id;
 
 --! restart_job(mode)
@@ -204,24 +228,24 @@ FROM job_configs
 WHERE job_configs.organization_id = :organization_id AND ttl_micros IS NULL
 ORDER BY COALESCE(job_configs.updated_at, job_configs.created_at) DESC;
 
---! get_pipeline_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?)
-SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at
+--! get_pipeline_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?, next_retry_time?)
+SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, next_retry_time, checkpoint_interval_micros, job_configs.created_at
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipelines.id = job_configs.pipeline_id
 WHERE job_configs.organization_id = :organization_id AND pipelines.pub_id = :pub_id
 ORDER BY job_configs.created_at DESC;
 
---! get_all_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?)
-SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at
+--! get_all_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?, next_retry_time?)
+SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, next_retry_time, checkpoint_interval_micros, job_configs.created_at
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipelines.id = job_configs.pipeline_id
 WHERE job_configs.organization_id = :organization_id AND ttl_micros IS NULL
 ORDER BY job_configs.created_at DESC;
 
---! get_pipeline_job : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?)
-SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at
+--! get_pipeline_job : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?, next_retry_time?)
+SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, next_retry_time, checkpoint_interval_micros, job_configs.created_at
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipelines.id = job_configs.pipeline_id
@@ -147,6 +147,12 @@ pub(crate) fn get_action(state: &str, running_desired: &bool) -> (String, Option
         ("CheckpointStopping", true) => ("Force Stop", Some(Immediate), InProgress),
         ("CheckpointStopping", false) => ("Force Stop", Some(Immediate), InProgress),
 
+        ("Draining", true) => ("Force Stop", Some(Immediate), InProgress),
+        ("Draining", false) => ("Force Stop", Some(Immediate), InProgress),
+
+        ("Reconnecting", true) => ("Stop", Some(Checkpoint), InProgress),
+        ("Reconnecting", false) => ("Stopping", Option::None, InProgress),
+
         ("Recovering", true) => ("Stop", Some(Checkpoint), InProgress),
         ("Recovering", false) => ("Stopping", Option::None, InProgress),
 
@@ -273,6 +273,14 @@ impl IntoResponse for HttpError {
         PipelineGraph,
         PipelineNode,
         PipelineEdge,
+        PipelineUpgrade,
+        OperatorUpgrade,
+        OperatorUpgradeState,
+        AutoscalingConfig,
+        RestartStrategy,
+        RestartBackoff,
+        FailureRate,
+        CheckpointConfig,
         Job,
         StopType,
         PipelineCollection,
@@ -299,6 +307,7 @@ impl IntoResponse for HttpError {
         ConnectionTableCollection,
         ConnectionSchema,
         ConnectionType,
+        MetadataField,
         SourceField,
         Format,
         SourceFieldType,
@@ -8,8 +8,9 @@ use cornucopia_async::{GenericClient, Params};
 use deadpool_postgres::{Object, Transaction};
 use http::StatusCode;
 
+use petgraph::graph::NodeIndex;
 use petgraph::{Direction, EdgeDirection};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 
 use petgraph::visit::NodeRef;
@@ -18,8 +19,9 @@ use std::time::Duration;
 use crate::{compiler_service, connection_profiles, jobs, pipelines, types};
 use arroyo_datastream::preview_sink;
 use arroyo_rpc::api_types::pipelines::{
-    Job, Pipeline, PipelinePatch, PipelinePost, PipelineRestart, QueryValidationResult, StopType,
-    ValidateQueryPost,
+    AutoscalingConfig, CheckpointConfig, Job, OperatorUpgrade, OperatorUpgradeState, Pipeline,
+    PipelinePatch, PipelinePost, PipelineRestart, PipelineUpgrade, QueryValidationResult,
+    RestartBackoff, RestartStrategy, StopType, ValidateQueryPost,
 };
 use arroyo_rpc::api_types::udfs::{GlobalUdf, Udf};
 use arroyo_rpc::api_types::{JobCollection, PaginationQueryParams, PipelineCollection};
@@ -29,7 +31,7 @@ use arroyo_rpc::grpc::api::{
 };
 
 use arroyo_connectors::kafka::{KafkaConfig, KafkaTable, SchemaRegistry};
-use arroyo_datastream::logical::{LogicalProgram, OperatorName};
+use arroyo_datastream::logical::{LogicalNode, LogicalProgram, OperatorName};
 use arroyo_df::{has_duplicate_udf_names, ArroyoSchemaProvider, CompiledSql, SqlConfig};
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_rpc::formats::Format;
@@ -59,6 +61,11 @@ use crate::{handle_db_error, AuthData};
 use create_pipeline_req::Config::Sql;
 
 const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_AUTOSCALING_COOLDOWN: Duration = Duration::from_secs(5 * 60);
+const MIN_AUTOSCALING_COOLDOWN: Duration = Duration::from_secs(60);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60 * 60);
+const MAX_CONCURRENT_CHECKPOINTS: u32 = 10;
+const MAX_RETAINED_CHECKPOINTS: u32 = 100;
 
 async fn compile_sql<'e, E>(
     query: String,
@@ -177,13 +184,162 @@ where
     })
 }
 
-fn set_parallelism(program: &mut LogicalProgram, parallelism: usize) {
-    for node in program.graph.node_weights_mut() {
-        node.parallelism = parallelism;
+/// Parallelism may be set for individual operators via hints in the query, so we need to check
+/// all of them against the plan's limit
+fn check_parallelism(program: &LogicalProgram, auth_data: &AuthData) -> Result<(), ErrorResp> {
+    if program
+        .graph
+        .node_weights()
+        .any(|n| n.parallelism > auth_data.org_metadata.max_parallelism as usize)
+    {
+        return Err(bad_request(format!(
+            "Your plan allows you to run pipelines up to parallelism {};
+            contact support@arroyo.systems for an increase",
+            auth_data.org_metadata.max_parallelism
+        )));
+    }
+
+    Ok(())
+}
+
+/// Validates an autoscaling configuration and fills in the defaults, so that the controller
+/// always sees complete bounds
+fn autoscaling_config(
+    config: &AutoscalingConfig,
+    auth_data: &AuthData,
+) -> Result<AutoscalingConfig, ErrorResp> {
+    let max_allowed = auth_data.org_metadata.max_parallelism as u64;
+    let min_parallelism = config.min_parallelism.unwrap_or(1);
+    let max_parallelism = config.max_parallelism.unwrap_or(max_allowed);
+
+    if min_parallelism == 0 {
+        return Err(bad_request(
+            "autoscaling minParallelism must be at least 1".to_string(),
+        ));
+    }
+
+    if min_parallelism > max_parallelism {
+        return Err(bad_request(
+            "autoscaling minParallelism must not be greater than maxParallelism".to_string(),
+        ));
+    }
+
+    if max_parallelism > max_allowed {
+        return Err(bad_request(format!(
+            "Your plan allows you to run pipelines up to parallelism {};
+            contact support@arroyo.systems for an increase",
+            auth_data.org_metadata.max_parallelism
+        )));
+    }
+
+    let cooldown = config
+        .cooldown_micros
+        .map(Duration::from_micros)
+        .unwrap_or(DEFAULT_AUTOSCALING_COOLDOWN);
+
+    if cooldown < MIN_AUTOSCALING_COOLDOWN {
+        return Err(bad_request(
+            "autoscaling cooldownMicros must be at least 1 minute".to_string(),
+        ));
+    }
+
+    Ok(AutoscalingConfig {
+        enabled: config.enabled,
+        min_parallelism: Some(min_parallelism),
+        max_parallelism: Some(max_parallelism),
+        cooldown_micros: Some(cooldown.as_micros() as u64),
+    })
+}
+
+fn validate_restart_strategy(strategy: &RestartStrategy) -> Result<(), ErrorResp> {
+    match strategy.backoff {
+        RestartBackoff::Fixed { delay_micros } => {
+            if Duration::from_micros(delay_micros) > MAX_RESTART_DELAY {
+                return Err(bad_request(
+                    "restart delayMicros must be at most 1 hour".to_string(),
+                ));
+            }
+        }
+        RestartBackoff::Exponential {
+            initial_delay_micros,
+            max_delay_micros,
+        } => {
+            if initial_delay_micros == 0 {
+                return Err(bad_request(
+                    "restart initialDelayMicros must be greater than 0".to_string(),
+                ));
+            }
+
+            if initial_delay_micros > max_delay_micros {
+                return Err(bad_request(
+                    "restart initialDelayMicros must not be greater than maxDelayMicros"
+                        .to_string(),
+                ));
+            }
+
+            if Duration::from_micros(max_delay_micros) > MAX_RESTART_DELAY {
+                return Err(bad_request(
+                    "restart maxDelayMicros must be at most 1 hour".to_string(),
+                ));
+            }
+        }
     }
+
+    if let Some(rate) = &strategy.failure_rate {
+        if rate.max_failures == 0 {
+            return Err(bad_request(
+                "restart failureRate.maxFailures must be at least 1".to_string(),
+            ));
+        }
+
+        if Duration::from_micros(rate.interval_micros) < Duration::from_secs(1) {
+            return Err(bad_request(
+                "restart failureRate.intervalMicros must be at least 1 second".to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_checkpoint_config(config: &CheckpointConfig) -> Result<(), ErrorResp> {
+    if let Some(timeout) = config.timeout_micros.map(Duration::from_micros) {
+        if timeout < Duration::from_secs(1) {
+            return Err(bad_request(
+                "checkpoint timeoutMicros must be at least 1 second".to_string(),
+            ));
+        }
+    }
+
+    if let Some(pause) = config.min_pause_micros.map(Duration::from_micros) {
+        if pause > Duration::from_secs(24 * 60 * 60) {
+            return Err(bad_request(
+                "checkpoint minPauseMicros must be at most 1 day".to_string(),
+            ));
+        }
+    }
+
+    if let Some(max_concurrent) = config.max_concurrent_checkpoints {
+        if max_concurrent == 0 || max_concurrent > MAX_CONCURRENT_CHECKPOINTS {
+            return Err(bad_request(format!(
+                "checkpoint maxConcurrentCheckpoints must be between 1 and {}",
+                MAX_CONCURRENT_CHECKPOINTS
+            )));
+        }
+    }
+
+    if let Some(retained) = config.retained_checkpoints {
+        if retained == 0 || retained > MAX_RETAINED_CHECKPOINTS {
+            return Err(bad_request(format!(
+                "checkpoint retainedCheckpoints must be between 1 and {}",
+                MAX_RETAINED_CHECKPOINTS
+            )));
+        }
+    }
+
+    Ok(())
 }
 
-#[allow(unused)]
 async fn try_register_confluent_schema(
     sink: &mut ConnectorOp,
     schema: &SchemaRef,
@@ -245,6 +401,17 @@ async fn try_register_confluent_schema(
     Ok(())
 }
 
+fn schema_registration_error(e: anyhow::Error) -> ErrorResp {
+    ErrorResp {
+        status_code: StatusCode::BAD_REQUEST,
+        message: format!(
+            "Failed to register schemas with the schema registry. Make sure \
+        that the schema_registry is configured correctly and running.\nDetails: {}",
+            error_chain(e)
+        ),
+    }
+}
+
 async fn register_schemas(compiled_sql: &mut CompiledSql) -> anyhow::Result<()> {
     // register schemas for sinks
     for idx in compiled_sql
@@ -348,6 +515,8 @@ pub(crate) async fn create_pipeline<'a>(
                 contact support@arroyo.systems for an increase", auth.org_metadata.max_operators)));
     }
 
+    check_parallelism(&compiled.program, &auth)?;
+
     // TODO: graph validation?
     // let errors = compiled.program.validate_graph();
     // if !errors.is_empty() {
@@ -359,8 +528,6 @@ pub(crate) async fn create_pipeline<'a>(
     //     )));
     // }
 
-    set_parallelism(&mut compiled.program, 1);
-
     if is_preview && !env::var("PREVIEW_SINKS").is_ok_and(|s| s == "true") {
         for node in compiled.program.graph.node_weights_mut() {
             // replace all sink connectors with websink for preview
@@ -372,14 +539,7 @@ pub(crate) async fn create_pipeline<'a>(
 
     register_schemas(&mut compiled)
         .await
-        .map_err(|e| ErrorResp {
-            status_code: StatusCode::BAD_REQUEST,
-            message: format!(
-                "Failed to register schemas with the schema registry. Make sure \
-            that the schema_registry is configured correctly and running.\nDetails: {}",
-                error_chain(e)
-            ),
-        })?;
+        .map_err(schema_registration_error)?;
 
     let proto_program: ArrowProgram = compiled.program.clone().into();
 
@@ -423,6 +583,259 @@ pub(crate) async fn create_pipeline<'a>(
     Ok((pipeline_id, compiled.program))
 }
 
+/// Connector operators are named after their tables, which are assigned by the user and so stay
+/// stable even as the operator's position in the graph changes
+fn connector_table(node: &LogicalNode) -> Option<&str> {
+    match node.operator_name {
+        OperatorName::ConnectorSource | OperatorName::ConnectorSink => {
+            node.operator_id.rsplit_once('_').map(|(table, _)| table)
+        }
+        _ => None,
+    }
+}
+
+/// Operators that have been reset by an upgrade get a new generation suffix (`join_3@1`), so that
+/// they don't pick up the state of the operator they replace
+fn base_operator_id(operator_id: &str) -> &str {
+    operator_id
+        .split_once('@')
+        .map(|(base, _)| base)
+        .unwrap_or(operator_id)
+}
+
+fn next_generation(operator_id: &str) -> String {
+    let generation = operator_id
+        .split_once('@')
+        .and_then(|(_, generation)| generation.parse::<u32>().ok())
+        .unwrap_or(0);
+
+    format!("{}@{}", base_operator_id(operator_id), generation + 1)
+}
+
+fn state_compatible(old: &LogicalNode, new: &LogicalNode) -> bool {
+    match new.operator_name {
+        // connector state (like source offsets) survives configuration changes, as long as it's
+        // still the same connector
+        OperatorName::ConnectorSource | OperatorName::ConnectorSink => {
+            match (
+                ConnectorOp::decode(&old.operator_config[..]),
+                ConnectorOp::decode(&new.operator_config[..]),
+            ) {
+                (Ok(old), Ok(new)) => old.connector == new.connector,
+                _ => false,
+            }
+        }
+        _ => old.operator_config == new.operator_config,
+    }
+}
+
+/// Maps the operators of a recompiled program onto those of the currently running program. New
+/// operators that match an existing operator take over its id (and thus its state in the final
+/// checkpoint) and parallelism; everything else starts without state, at the parallelism it was
+/// compiled with.
+fn map_operator_state(old: &LogicalProgram, new: &mut LogicalProgram) -> Vec<OperatorUpgrade> {
+    let mut unmatched: Vec<Option<&LogicalNode>> = old.graph.node_weights().map(Some).collect();
+    let new_indices: Vec<NodeIndex> = new.graph.node_indices().collect();
+    let mut matches: HashMap<NodeIndex, &LogicalNode> = HashMap::new();
+
+    // first match operators by id, then fall back to matching connectors by their table
+    for by_table in [false, true] {
+        for idx in &new_indices {
+            if matches.contains_key(idx) {
+                continue;
+            }
+
+            let node = &new.graph[*idx];
+            let found = unmatched.iter_mut().find(|old| {
+                old.is_some_and(|old| {
+                    old.operator_name == node.operator_name
+                        && if by_table {
+                            connector_table(old).is_some()
+                                && connector_table(old) == connector_table(node)
+                        } else {
+                            base_operator_id(&old.operator_id) == node.operator_id
+                        }
+                })
+            });
+
+            if let Some(old) = found.and_then(|old| old.take()) {
+                matches.insert(*idx, old);
+            }
+        }
+    }
+
+    let mut operators = vec![];
+    for idx in new_indices {
+        let old = matches.remove(&idx);
+        let node = new.graph.node_weight_mut(idx).unwrap();
+
+        let state = match old {
+            Some(old) => {
+                node.parallelism = old.parallelism;
+                if state_compatible(old, node) {
+                    node.operator_id = old.operator_id.clone();
+                    OperatorUpgradeState::Restored
+                } else {
+                    node.operator_id = next_generation(&old.operator_id);
+                    OperatorUpgradeState::Reset
+                }
+            }
+            None => OperatorUpgradeState::New,
+        };
+
+        operators.push(OperatorUpgrade {
+            node_id: node.operator_id.clone(),
+            previous_node_id: old.map(|old| old.operator_id.clone()),
+            description: node.description.clone(),
+            state,
+        });
+    }
+
+    operators.extend(unmatched.into_iter().flatten().map(|old| OperatorUpgrade {
+        node_id: old.operator_id.clone(),
+        previous_node_id: None,
+        description: old.description.clone(),
+        state: OperatorUpgradeState::Dropped,
+    }));
+
+    operators
+}
+
+/// Recompiles a pipeline with a new query and/or UDFs and maps the state of its current operators
+/// onto the new program. Unless this is a dry run, the new program is stored and the job's
+/// program version is bumped, which causes the controller to take a final checkpoint and restart
+/// the job on the new program from that checkpoint.
+async fn upgrade_pipeline<'a>(
+    patch: &PipelinePatch,
+    job_id: &str,
+    auth_data: &AuthData,
+    tx: &Transaction<'a>,
+) -> Result<PipelineUpgrade, ErrorResp> {
+    let dry_run = patch.dry_run.unwrap_or(false);
+
+    let details = api_queries::get_job_details()
+        .bind(tx, &auth_data.organization_id, &job_id)
+        .opt()
+        .await
+        .map_err(log_and_map)?
+        .ok_or_else(|| not_found("Job"))?;
+
+    let Some(current_query) = details.textual_repr else {
+        return Err(bad_request(
+            "Only SQL pipelines can be upgraded".to_string(),
+        ));
+    };
+
+    let query = patch.query.clone().unwrap_or(current_query);
+    let udfs = match &patch.udfs {
+        Some(udfs) => udfs.clone(),
+        None => serde_json::from_value::<Vec<api_proto::Udf>>(details.udfs)
+            .map_err(log_and_map)?
+            .into_iter()
+            .map(|u| u.into())
+            .collect(),
+    };
+
+    let mut current_program: LogicalProgram = ArrowProgram::decode(&details.program[..])
+        .map_err(log_and_map)?
+        .try_into()
+        .map_err(log_and_map)?;
+
+    current_program.update_parallelism(
+        &details
+            .parallelism_overrides
+            .as_object()
+            .unwrap()
+            .into_iter()
+            .map(|(k, v)| (k.clone(), v.as_u64().unwrap() as usize))
+            .collect(),
+    );
+
+    // operators without a parallelism hint are added at the pipeline's current parallelism
+    let parallelism = current_program
+        .graph
+        .node_weights()
+        .map(|n| n.parallelism)
+        .max()
+        .unwrap_or(1);
+
+    let mut compiled = compile_sql(query.clone(), &udfs, parallelism, auth_data, dry_run, tx)
+        .await
+        .map_err(|e| bad_request(e.to_string()))?;
+
+    if compiled.program.graph.node_count() > auth_data.org_metadata.max_operators as usize {
+        return Err(bad_request(
+            format!("This pipeline is too large to create under your plan, which only allows pipelines up to {} nodes;
+                contact support@arroyo.systems for an increase", auth_data.org_metadata.max_operators)));
+    }
+
+    check_parallelism(&compiled.program, auth_data)?;
+
+    let operators = map_operator_state(&current_program, &mut compiled.program);
+
+    if !dry_run {
+        register_schemas(&mut compiled)
+            .await
+            .map_err(schema_registration_error)?;
+
+        let program_bytes = ArrowProgram::from(compiled.program.clone()).encode_to_vec();
+
+        api_queries::update_pipeline_program()
+            .bind(
+                tx,
+                &query,
+                &Some(serde_json::to_value(&udfs).unwrap()),
+                &program_bytes,
+                &details.pipeline_id,
+                &auth_data.organization_id,
+            )
+            .await
+            .map_err(log_and_map)?;
+
+        api_queries::delete_pipeline_connection_tables()
+            .bind(tx, &details.pipeline_id)
+            .await
+            .map_err(log_and_map)?;
+
+        for connection in &compiled.connection_ids {
+            api_queries::add_pipeline_connection_table()
+                .bind(
+                    tx,
+                    &generate_id(IdTypes::ConnectionTablePipeline),
+                    &details.pipeline_id,
+                    connection,
+                )
+                .await
+                .map_err(log_and_map)?;
+        }
+
+        let parallelism_overrides: HashMap<String, u32> = compiled
+            .program
+            .graph
+            .node_weights()
+            .map(|node| (node.operator_id.clone(), node.parallelism as u32))
+            .collect();
+
+        api_queries::upgrade_job()
+            .bind(
+                tx,
+                &OffsetDateTime::now_utc(),
+                &auth_data.user_id,
+                &serde_json::to_value(parallelism_overrides).map_err(log_and_map)?,
+                &job_id,
+                &auth_data.organization_id,
+            )
+            .await
+            .map_err(log_and_map)?;
+    }
+
+    Ok(PipelineUpgrade {
+        dry_run,
+        graph: compiled.program.try_into().map_err(log_and_map)?,
+        operators,
+    })
+}
+
 impl TryInto<Pipeline> for DbPipeline {
     type Error = ErrorResp;
 
@@ -450,6 +863,7 @@ impl TryInto<Pipeline> for DbPipeline {
         let stop = match self.stop {
             StopMode::none => StopType::None,
             StopMode::checkpoint => StopType::Checkpoint,
+            StopMode::drain => StopType::Drain,
             StopMode::graceful => StopType::Graceful,
             StopMode::immediate => StopType::Immediate,
             StopMode::force => StopType::Force,
@@ -468,6 +882,22 @@ impl TryInto<Pipeline> for DbPipeline {
             action_text,
             action_in_progress,
             preview: self.ttl_micros.is_some(),
+            upgrade: None,
+            autoscaling: self
+                .autoscaling
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(log_and_map)?,
+            restart_strategy: self
+                .restart_strategy
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(log_and_map)?,
+            checkpoint_config: self
+                .checkpoint_config
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(log_and_map)?,
         })
     }
 }
@@ -483,6 +913,7 @@ impl From<DbPipelineJob> for Job {
             finish_time: val.finish_time.map(to_micros),
             tasks: val.tasks.map(|t| t as u64),
             failure_message: val.failure_message,
+            next_retry_time: val.next_retry_time.map(to_micros),
             created_at: to_micros(val.created_at),
         }
     }
@@ -626,6 +1057,17 @@ pub async fn post_pipeline(
 }
 
 /// Update a pipeline
+///
+/// Changing the query or UDFs upgrades the pipeline in place: the job takes a final checkpoint
+/// and is restarted on the new program, with the state of each operator carried over to its
+/// match in the new program. Set `dryRun` to see how state would be mapped without making any
+/// changes.
+///
+/// When `autoscaling` is enabled, the controller adjusts the parallelism of each operator
+/// between the configured bounds, recording each decision in the job's log messages.
+///
+/// `restartStrategy` controls how long the controller waits before restarting the job after a
+/// failure, and how many failures it tolerates before moving the job to Failed.
 #[utoipa::path(
     patch,
     path = "/v1/pipelines/{id}",
@@ -644,7 +1086,7 @@ pub async fn patch_pipeline(
     Path(pipeline_pub_id): Path<String>,
     WithRejection(Json(pipeline_patch), _): WithRejection<Json<PipelinePatch>, ApiError>,
 ) -> Result<Json<Pipeline>, ErrorResp> {
-    let client = client(&state.pool).await?;
+    let mut client = client(&state.pool).await?;
     let auth_data = authenticate(&state.pool, bearer_auth).await?;
 
     // this assumes there is just one job for the pipeline
@@ -655,6 +1097,15 @@ pub async fn patch_pipeline(
         .map_err(log_and_map)?
         .id;
 
+    let upgrading = pipeline_patch.query.is_some() || pipeline_patch.udfs.is_some();
+    let dry_run = pipeline_patch.dry_run.unwrap_or(false);
+
+    if dry_run && !upgrading {
+        return Err(bad_request(
+            "dryRun is only supported when changing the query or udfs".to_string(),
+        ));
+    }
+
     let interval = pipeline_patch
         .checkpoint_interval_micros
         .map(Duration::from_micros);
@@ -664,6 +1115,7 @@ pub async fn patch_pipeline(
         StopType::Graceful => types::public::StopMode::graceful,
         StopType::Immediate => types::public::StopMode::immediate,
         StopType::Checkpoint => types::public::StopMode::checkpoint,
+        StopType::Drain => types::public::StopMode::drain,
         StopType::Force => types::public::StopMode::force,
     });
 
@@ -675,34 +1127,161 @@ pub async fn patch_pipeline(
         }
     }
 
-    let parallelism_overrides = if let Some(parallelism) = pipeline_patch.parallelism {
-        let res = api_queries::get_job_details()
-            .bind(&client, &auth_data.organization_id, &job_id)
-            .opt()
-            .await
-            .map_err(log_and_map)?
-            .ok_or_else(|| not_found("Job"))?;
+    let autoscaling = pipeline_patch
+        .autoscaling
+        .as_ref()
+        .map(|config| autoscaling_config(config, &auth_data))
+        .transpose()?
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(log_and_map)?;
 
-        let program = ArrowProgram::decode(&res.program[..]).map_err(log_and_map)?;
-        let map: HashMap<String, u32> = program
-            .nodes
-            .into_iter()
-            .map(|node| (node.node_id, parallelism as u32))
-            .collect();
+    if let Some(strategy) = &pipeline_patch.restart_strategy {
+        validate_restart_strategy(strategy)?;
+    }
+
+    let restart_strategy = pipeline_patch
+        .restart_strategy
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(log_and_map)?;
+
+    if let Some(config) = &pipeline_patch.checkpoint_config {
+        validate_checkpoint_config(config)?;
+    }
+
+    let checkpoint_config = pipeline_patch
+        .checkpoint_config
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(log_and_map)?;
+
+    let transaction = client.transaction().await.map_err(log_and_map)?;
 
-        Some(serde_json::to_value(map).map_err(log_and_map)?)
+    let upgrade = if upgrading {
+        Some(upgrade_pipeline(&pipeline_patch, &job_id, &auth_data, &transaction).await?)
     } else {
         None
     };
 
+    if dry_run {
+        // nothing has been written, so we can just drop the transaction
+        drop(transaction);
+        let mut pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &client, &auth_data).await?;
+        pipeline.upgrade = upgrade;
+        return Ok(Json(pipeline));
+    }
+
+    // operator ids refer to the upgraded program, if the query is also being changed
+    let parallelism_overrides =
+        if pipeline_patch.parallelism.is_some() || pipeline_patch.operator_parallelism.is_some() {
+            let res = api_queries::get_job_details()
+                .bind(&transaction, &auth_data.organization_id, &job_id)
+                .opt()
+                .await
+                .map_err(log_and_map)?
+                .ok_or_else(|| not_found("Job"))?;
+
+            let program: LogicalProgram = ArrowProgram::decode(&res.program[..])
+                .map_err(log_and_map)?
+                .try_into()
+                .map_err(log_and_map)?;
+
+            let mut map: HashMap<String, u32> = res
+                .parallelism_overrides
+                .as_object()
+                .unwrap()
+                .into_iter()
+                .map(|(k, v)| (k.clone(), v.as_u64().unwrap() as u32))
+                .collect();
+
+            if let Some(parallelism) = pipeline_patch.parallelism {
+                for node in program.graph.node_weights() {
+                    map.insert(node.operator_id.clone(), parallelism as u32);
+                }
+            }
+
+            let operator_parallelism = pipeline_patch
+                .operator_parallelism
+                .clone()
+                .unwrap_or_default();
+            for node_id in operator_parallelism.keys() {
+                if !program
+                    .graph
+                    .node_weights()
+                    .any(|node| &node.operator_id == node_id)
+                {
+                    return Err(bad_request(format!(
+                        "Pipeline has no operator with id '{}'",
+                        node_id
+                    )));
+                }
+            }
+
+            // operators connected by forward edges must run with the same parallelism, so
+            // setting one of them sets the whole group
+            for group in program.forward_groups() {
+                let ids: Vec<_> = group
+                    .iter()
+                    .map(|idx| &program.graph[*idx].operator_id)
+                    .collect();
+
+                let requested: HashSet<u64> = ids
+                    .iter()
+                    .filter_map(|id| operator_parallelism.get(*id).copied())
+                    .collect();
+
+                if requested.len() > 1 {
+                    return Err(bad_request(format!(
+                        "Operators {} are connected by forward edges and must have the same \
+                    parallelism",
+                        ids.iter()
+                            .map(|id| format!("'{}'", id))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )));
+                }
+
+                if let Some(parallelism) = requested.into_iter().next() {
+                    for id in ids {
+                        map.insert(id.clone(), parallelism as u32);
+                    }
+                }
+            }
+
+            if let Some(parallelism) = map
+                .values()
+                .find(|p| **p == 0 || **p > auth_data.org_metadata.max_parallelism)
+            {
+                return Err(bad_request(if *parallelism == 0 {
+                    "Parallelism must be at least 1".to_string()
+                } else {
+                    format!(
+                        "Your plan allows you to run pipelines up to parallelism {};
+                    contact support@arroyo.systems for an increase",
+                        auth_data.org_metadata.max_parallelism
+                    )
+                }));
+            }
+
+            Some(serde_json::to_value(map).map_err(log_and_map)?)
+        } else {
+            None
+        };
+
     let res = api_queries::update_job()
         .bind(
-            &client,
+            &transaction,
             &OffsetDateTime::now_utc(),
             &auth_data.user_id,
             stop,
             &interval.map(|i| i.as_micros() as i64),
             &parallelism_overrides,
+            &autoscaling,
+            &restart_strategy,
+            &checkpoint_config,
             &job_id,
             &auth_data.organization_id,
         )
@@ -713,7 +1292,10 @@ pub async fn patch_pipeline(
         return Err(not_found("Job"));
     }
 
-    let pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &client, &auth_data).await?;
+    transaction.commit().await.map_err(log_and_map)?;
+
+    let mut pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &client, &auth_data).await?;
+    pipeline.upgrade = upgrade;
     Ok(Json(pipeline))
 }
 
@@ -969,3 +1551,192 @@ pub async fn query_job_by_pub_id(
 
     Ok(res.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(
+        operator_id: &str,
+        operator_name: OperatorName,
+        operator_config: Vec<u8>,
+        parallelism: usize,
+    ) -> LogicalNode {
+        LogicalNode {
+            operator_id: operator_id.to_string(),
+            description: operator_id.to_string(),
+            operator_name,
+            operator_config,
+            parallelism,
+        }
+    }
+
+    fn connector(connector: &str, config: &str) -> Vec<u8> {
+        ConnectorOp {
+            connector: connector.to_string(),
+            config: config.to_string(),
+            description: String::new(),
+        }
+        .encode_to_vec()
+    }
+
+    fn program(nodes: Vec<LogicalNode>) -> LogicalProgram {
+        let mut program = LogicalProgram::default();
+        for node in nodes {
+            program.graph.add_node(node);
+        }
+        program
+    }
+
+    fn upgrade<'a>(operators: &'a [OperatorUpgrade], node_id: &str) -> &'a OperatorUpgrade {
+        operators
+            .iter()
+            .find(|op| op.node_id == node_id)
+            .unwrap_or_else(|| panic!("no operator {} in {:?}", node_id, operators))
+    }
+
+    fn parallelism(program: &LogicalProgram, operator_id: &str) -> usize {
+        program
+            .graph
+            .node_weights()
+            .find(|node| node.operator_id == operator_id)
+            .unwrap()
+            .parallelism
+    }
+
+    #[test]
+    fn test_next_generation() {
+        assert_eq!(next_generation("join_3"), "join_3@1");
+        assert_eq!(next_generation("join_3@1"), "join_3@2");
+        assert_eq!(next_generation("join_3@x"), "join_3@1");
+        assert_eq!(base_operator_id("join_3@2"), "join_3");
+        assert_eq!(base_operator_id("join_3"), "join_3");
+    }
+
+    #[test]
+    fn test_state_compatible() {
+        let source = node(
+            "events_0",
+            OperatorName::ConnectorSource,
+            connector("kafka", "a"),
+            1,
+        );
+
+        // connectors keep their state across configuration changes
+        let reconfigured = node(
+            "events_0",
+            OperatorName::ConnectorSource,
+            connector("kafka", "b"),
+            1,
+        );
+        assert!(state_compatible(&source, &reconfigured));
+
+        let other_connector = node(
+            "events_0",
+            OperatorName::ConnectorSource,
+            connector("kinesis", "a"),
+            1,
+        );
+        assert!(!state_compatible(&source, &other_connector));
+
+        let invalid = node("events_0", OperatorName::ConnectorSource, vec![0xff], 1);
+        assert!(!state_compatible(&source, &invalid));
+
+        // other operators only keep their state if they're unchanged
+        let window = node(
+            "window_1",
+            OperatorName::TumblingWindowAggregate,
+            vec![1],
+            1,
+        );
+        assert!(state_compatible(&window, &window.clone()));
+        let changed = node(
+            "window_1",
+            OperatorName::TumblingWindowAggregate,
+            vec![2],
+            1,
+        );
+        assert!(!state_compatible(&window, &changed));
+    }
+
+    #[test]
+    fn test_map_operator_state() {
+        let old = program(vec![
+            node(
+                "events_0",
+                OperatorName::ConnectorSource,
+                connector("kafka", "a"),
+                1,
+            ),
+            node("value_1", OperatorName::ArrowValue, vec![1], 2),
+            node(
+                "window_2",
+                OperatorName::TumblingWindowAggregate,
+                vec![2],
+                4,
+            ),
+            node(
+                "out_3",
+                OperatorName::ConnectorSink,
+                connector("kafka", "a"),
+                1,
+            ),
+        ]);
+
+        let mut new = program(vec![
+            // the source moved in the graph, so it's matched by its table
+            node(
+                "events_5",
+                OperatorName::ConnectorSource,
+                connector("kafka", "b"),
+                1,
+            ),
+            node("value_1", OperatorName::ArrowValue, vec![1], 1),
+            node(
+                "window_2",
+                OperatorName::TumblingWindowAggregate,
+                vec![3],
+                1,
+            ),
+            node("join_4", OperatorName::Join, vec![4], 3),
+        ]);
+
+        let operators = map_operator_state(&old, &mut new);
+        assert_eq!(operators.len(), 5);
+
+        let source = upgrade(&operators, "events_0");
+        assert_eq!(source.state, OperatorUpgradeState::Restored);
+        assert_eq!(source.previous_node_id.as_deref(), Some("events_0"));
+        assert_eq!(parallelism(&new, "events_0"), 1);
+
+        let value = upgrade(&operators, "value_1");
+        assert_eq!(value.state, OperatorUpgradeState::Restored);
+        assert_eq!(parallelism(&new, "value_1"), 2);
+
+        // a changed operator gets a new id so that it doesn't pick up the old state
+        let window = upgrade(&operators, "window_2@1");
+        assert_eq!(window.state, OperatorUpgradeState::Reset);
+        assert_eq!(window.previous_node_id.as_deref(), Some("window_2"));
+        assert_eq!(parallelism(&new, "window_2@1"), 4);
+
+        let join = upgrade(&operators, "join_4");
+        assert_eq!(join.state, OperatorUpgradeState::New);
+        assert_eq!(join.previous_node_id, None);
+        assert_eq!(parallelism(&new, "join_4"), 3);
+
+        let sink = upgrade(&operators, "out_3");
+        assert_eq!(sink.state, OperatorUpgradeState::Dropped);
+
+        // a later upgrade that leaves the reset operator unchanged restores its new state
+        let mut newer = program(vec![node(
+            "window_2",
+            OperatorName::TumblingWindowAggregate,
+            vec![3],
+            1,
+        )]);
+        let operators = map_operator_state(&new, &mut newer);
+        let window = upgrade(&operators, "window_2@1");
+        assert_eq!(window.state, OperatorUpgradeState::Restored);
+        assert_eq!(parallelism(&newer, "window_2@1"), 4);
+    }
+}
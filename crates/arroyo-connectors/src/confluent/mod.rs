@@ -3,7 +3,7 @@ use crate::kafka::{
 };
 use crate::{kafka, pull_opt};
 use anyhow::anyhow;
-use arroyo_operator::connector::{Connection, Connector};
+use arroyo_operator::connector::{Connection, Connector, MetadataDef};
 use arroyo_operator::operator::OperatorNode;
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
@@ -113,6 +113,10 @@ impl Connector for ConfluentConnector {
         }
     }
 
+    fn metadata_defs(&self) -> &'static [MetadataDef] {
+        KafkaConnector {}.metadata_defs()
+    }
+
     fn config_description(&self, config: Self::ProfileT) -> String {
         (*config.bootstrap_servers).clone()
     }
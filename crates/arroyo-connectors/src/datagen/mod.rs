@@ -0,0 +1,277 @@
+mod operator;
+
+use anyhow::{anyhow, bail};
+use arrow::datatypes::{Field, Schema};
+use arroyo_operator::connector::{Connection, Connector};
+use arroyo_operator::operator::OperatorNode;
+use arroyo_rpc::api_types::connections::{
+    ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
+};
+use arroyo_rpc::OperatorConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use typify::import_types;
+
+use crate::datagen::operator::{ColumnGenerators, DatagenSourceFunc};
+use crate::{pull_opt, pull_option_to_i64, EmptyConfig};
+
+const TABLE_SCHEMA: &str = include_str!("./table.json");
+
+import_types!(schema = "src/datagen/table.json");
+const ICON: &str = include_str!("./datagen.svg");
+
+/// Parses per-field generator options of the form `fields.<name>.<property>`
+fn pull_field_generators(
+    options: &mut HashMap<String, String>,
+) -> anyhow::Result<Vec<FieldGenerator>> {
+    let keys: Vec<String> = options
+        .keys()
+        .filter(|k| k.starts_with("fields."))
+        .cloned()
+        .collect();
+
+    let mut generators: BTreeMap<String, FieldGenerator> = BTreeMap::new();
+
+    for key in keys {
+        let value = options.remove(&key).unwrap();
+        let Some((name, property)) = key["fields.".len()..].rsplit_once('.') else {
+            bail!(
+                "invalid datagen option '{}'; expected 'fields.<field>.<property>'",
+                key
+            );
+        };
+
+        let generator = generators
+            .entry(name.to_string())
+            .or_insert_with(|| FieldGenerator {
+                name: name.to_string(),
+                kind: None,
+                min: None,
+                max: None,
+                distribution: None,
+                values: vec![],
+                faker: None,
+                null_ratio: None,
+            });
+
+        let parse_f64 = |v: &str| {
+            f64::from_str(v).map_err(|_| anyhow!("invalid value for '{}'; expected a number", key))
+        };
+
+        match property {
+            "kind" => {
+                generator.kind = Some(GeneratorKind::try_from(value.clone()).map_err(|_| {
+                    anyhow!(
+                        "invalid value for '{}': '{}'; expected one of random, sequence, enum, faker",
+                        key,
+                        value
+                    )
+                })?);
+            }
+            "min" => generator.min = Some(parse_f64(&value)?),
+            "max" => generator.max = Some(parse_f64(&value)?),
+            "distribution" => {
+                generator.distribution =
+                    Some(Distribution::try_from(value.clone()).map_err(|_| {
+                        anyhow!(
+                            "invalid value for '{}': '{}'; expected one of uniform, normal, exponential",
+                            key,
+                            value
+                        )
+                    })?);
+            }
+            "values" => {
+                generator.values = value.split(',').map(|s| s.trim().to_string()).collect();
+            }
+            "faker" => {
+                generator.faker = Some(
+                    FakerKind::try_from(value.clone())
+                        .map_err(|_| anyhow!("invalid value for '{}': '{}'", key, value))?,
+                );
+            }
+            "null_ratio" => generator.null_ratio = Some(parse_f64(&value)?),
+            _ => bail!(
+                "unknown datagen option '{}'; supported properties are kind, min, max, \
+                distribution, values, faker and null_ratio",
+                key
+            ),
+        }
+    }
+
+    Ok(generators.into_values().collect())
+}
+
+pub struct DatagenConnector {}
+
+impl Connector for DatagenConnector {
+    type ProfileT = EmptyConfig;
+    type TableT = DatagenTable;
+
+    fn name(&self) -> &'static str {
+        "datagen"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "datagen".to_string(),
+            name: "Datagen".to_string(),
+            icon: ICON.to_string(),
+            description: "Generate synthetic data for any schema".to_string(),
+            enabled: true,
+            source: true,
+            sink: false,
+            testing: false,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Source
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: tokio::sync::mpsc::Sender<TestSourceMessage>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = TestSourceMessage {
+                error: false,
+                done: true,
+                message: "Successfully validated connection".to_string(),
+            };
+            tx.send(message).await.unwrap();
+        });
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        options: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+        _profile: Option<&ConnectionProfile>,
+    ) -> anyhow::Result<Connection> {
+        let event_rate = f64::from_str(&pull_opt("event_rate", options)?)
+            .map_err(|_| anyhow!("invalid value for event_rate; expected float"))?;
+
+        let message_count = pull_option_to_i64("message_count", options)?;
+        let out_of_order_millis = pull_option_to_i64("out_of_order_millis", options)?;
+        let seed = pull_option_to_i64("seed", options)?;
+        let fields = pull_field_generators(options)?;
+
+        self.from_config(
+            None,
+            name,
+            EmptyConfig {},
+            DatagenTable {
+                event_rate,
+                message_count,
+                out_of_order_millis,
+                seed,
+                fields,
+            },
+            schema,
+        )
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        if table.event_rate <= 0.0 {
+            bail!("event_rate must be positive");
+        }
+
+        if table.message_count.is_some_and(|c| c < 0) {
+            bail!("message_count must not be negative");
+        }
+
+        if table.out_of_order_millis.is_some_and(|t| t < 0) {
+            bail!("out_of_order_millis must not be negative");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for datagen source"))?;
+
+        if schema.fields.is_empty() {
+            bail!("datagen tables must declare the fields to generate");
+        }
+
+        if schema.format.is_some() {
+            bail!("datagen sources produce structured data and do not support 'format'");
+        }
+
+        let arrow_schema = Schema::new(
+            schema
+                .fields
+                .iter()
+                .map(|f| Field::from(f.clone()))
+                .collect::<Vec<_>>(),
+        );
+
+        for generator in &table.fields {
+            if arrow_schema.field_with_name(&generator.name).is_err() {
+                bail!(
+                    "datagen generator configured for unknown field '{}'",
+                    generator.name
+                );
+            }
+        }
+
+        // make sure we can generate every field in the schema
+        ColumnGenerators::new(&table.fields, &arrow_schema)?;
+
+        let description = format!(
+            "{}Datagen<{} eps>",
+            if table.message_count.is_some() {
+                "Bounded"
+            } else {
+                ""
+            },
+            table.event_rate,
+        );
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            format: None,
+            bad_data: None,
+            framing: None,
+            metadata_fields: vec![],
+        };
+
+        Ok(Connection {
+            id,
+            connector: self.name(),
+            name: name.to_string(),
+            connection_type: ConnectionType::Source,
+            schema,
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn make_operator(
+        &self,
+        _: Self::ProfileT,
+        table: Self::TableT,
+        _: OperatorConfig,
+    ) -> anyhow::Result<OperatorNode> {
+        Ok(OperatorNode::from_source(Box::new(DatagenSourceFunc::new(
+            table,
+        ))))
+    }
+}
@@ -0,0 +1,898 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail};
+use arrow::array::builder::TimestampNanosecondBuilder;
+use arrow::array::{ArrayRef, RecordBatch};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::SourceOperator;
+use arroyo_operator::SourceFinishType;
+use arroyo_rpc::grpc::{StopMode, TableConfig};
+use arroyo_rpc::{ControlMessage, TIMESTAMP_FIELD};
+use arroyo_types::{from_micros, to_micros, to_millis, to_nanos, TaskInfo};
+use async_trait::async_trait;
+use bincode::{Decode, Encode};
+use datafusion::common::ScalarValue;
+use rand::distributions::Alphanumeric;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tracing::{debug, info};
+
+use super::{DatagenTable, Distribution, FakerKind, FieldGenerator, GeneratorKind};
+
+const FIRST_NAMES: &[&str] = &[
+    "Alice", "Bob", "Carol", "Dave", "Erin", "Frank", "Grace", "Heidi", "Ivan", "Judy", "Mallory",
+    "Niaj", "Olivia", "Peggy", "Rupert", "Sybil", "Trent", "Victor", "Walter", "Yasmin",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Smith",
+    "Johnson",
+    "Williams",
+    "Brown",
+    "Jones",
+    "Garcia",
+    "Miller",
+    "Davis",
+    "Rodriguez",
+    "Martinez",
+    "Hernandez",
+    "Lopez",
+    "Wilson",
+    "Anderson",
+    "Thomas",
+    "Taylor",
+    "Moore",
+    "Lee",
+];
+
+const WORDS: &[&str] = &[
+    "stream", "window", "event", "river", "signal", "packet", "vector", "matrix", "cloud",
+    "harbor", "summit", "meadow", "canyon", "galaxy", "ember", "quartz", "willow", "falcon",
+    "lantern", "orbit",
+];
+
+const CITIES: &[&str] = &[
+    "Tokyo",
+    "Delhi",
+    "Shanghai",
+    "São Paulo",
+    "Mexico City",
+    "Cairo",
+    "Mumbai",
+    "Beijing",
+    "Osaka",
+    "New York",
+    "Karachi",
+    "Buenos Aires",
+    "Istanbul",
+    "Lagos",
+    "Paris",
+    "London",
+    "Berlin",
+    "Sydney",
+    "Toronto",
+    "Seoul",
+];
+
+const COUNTRIES: &[&str] = &[
+    "Argentina",
+    "Australia",
+    "Brazil",
+    "Canada",
+    "China",
+    "Egypt",
+    "France",
+    "Germany",
+    "India",
+    "Japan",
+    "Kenya",
+    "Mexico",
+    "Nigeria",
+    "Norway",
+    "Pakistan",
+    "South Korea",
+    "Spain",
+    "Turkey",
+    "United Kingdom",
+    "United States",
+];
+
+const DOMAINS: &[&str] = &["example.com", "example.org", "example.net", "test.dev"];
+
+fn choose<'a>(rng: &mut StdRng, values: &[&'a str]) -> &'a str {
+    values[rng.gen_range(0..values.len())]
+}
+
+/// Samples a value in `[min, max]` from the given distribution. Normal distributions are
+/// centered in the range with 99.7% of values falling inside it; exponential distributions
+/// are skewed towards `min`, with a mean a quarter of the way through the range. Values are
+/// clamped to the range.
+fn sample(rng: &mut StdRng, distribution: Distribution, min: f64, max: f64) -> f64 {
+    if min >= max {
+        return min;
+    }
+
+    let v = match distribution {
+        Distribution::Uniform => rng.gen_range(min..max),
+        Distribution::Normal => {
+            // Box-Muller transform
+            let u1: f64 = 1.0 - rng.gen::<f64>();
+            let u2: f64 = rng.gen();
+            let z = (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos();
+            (min + max) / 2.0 + z * (max - min) / 6.0
+        }
+        Distribution::Exponential => {
+            let u: f64 = 1.0 - rng.gen::<f64>();
+            min - u.ln() * (max - min) / 4.0
+        }
+    };
+
+    v.clamp(min, max)
+}
+
+fn fake(rng: &mut StdRng, kind: FakerKind) -> String {
+    match kind {
+        FakerKind::Name => format!("{} {}", choose(rng, FIRST_NAMES), choose(rng, LAST_NAMES)),
+        FakerKind::FirstName => choose(rng, FIRST_NAMES).to_string(),
+        FakerKind::LastName => choose(rng, LAST_NAMES).to_string(),
+        FakerKind::Email => format!(
+            "{}.{}{}@{}",
+            choose(rng, FIRST_NAMES).to_lowercase(),
+            choose(rng, LAST_NAMES).to_lowercase(),
+            rng.gen_range(1..100),
+            choose(rng, DOMAINS)
+        ),
+        FakerKind::Word => choose(rng, WORDS).to_string(),
+        FakerKind::Sentence => {
+            let len = rng.gen_range(4..10);
+            let mut sentence = (0..len)
+                .map(|_| choose(rng, WORDS))
+                .collect::<Vec<_>>()
+                .join(" ");
+            sentence[..1].make_ascii_uppercase();
+            sentence.push('.');
+            sentence
+        }
+        FakerKind::City => choose(rng, CITIES).to_string(),
+        FakerKind::Country => choose(rng, COUNTRIES).to_string(),
+        FakerKind::Ipv4 => format!(
+            "{}.{}.{}.{}",
+            rng.gen_range(1..=254),
+            rng.gen::<u8>(),
+            rng.gen::<u8>(),
+            rng.gen_range(1..=254)
+        ),
+        FakerKind::Url => format!(
+            "https://{}/{}/{}",
+            choose(rng, DOMAINS),
+            choose(rng, WORDS),
+            rng.gen_range(1..10000)
+        ),
+        FakerKind::Uuid => uuid::Builder::from_random_bytes(rng.gen())
+            .into_uuid()
+            .to_string(),
+    }
+}
+
+fn timestamp_scalar(unit: &TimeUnit, tz: &Option<Arc<str>>, millis: i64) -> ScalarValue {
+    match unit {
+        TimeUnit::Second => ScalarValue::TimestampSecond(Some(millis / 1000), tz.clone()),
+        TimeUnit::Millisecond => ScalarValue::TimestampMillisecond(Some(millis), tz.clone()),
+        TimeUnit::Microsecond => ScalarValue::TimestampMicrosecond(Some(millis * 1000), tz.clone()),
+        TimeUnit::Nanosecond => {
+            ScalarValue::TimestampNanosecond(Some(millis * 1_000_000), tz.clone())
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ValueGenerator {
+    Random {
+        min: f64,
+        max: f64,
+        distribution: Distribution,
+    },
+    Sequence {
+        start: i64,
+        end: Option<i64>,
+    },
+    Enum {
+        values: Vec<ScalarValue>,
+        distribution: Distribution,
+    },
+    Faker(FakerKind),
+}
+
+#[derive(Debug, Clone)]
+struct ColumnGenerator {
+    data_type: DataType,
+    null_ratio: f64,
+    // timestamp fields without an explicit range follow the event time
+    event_time: bool,
+    generator: ValueGenerator,
+}
+
+impl ColumnGenerator {
+    fn new(field: &Field, config: Option<&FieldGenerator>) -> anyhow::Result<Self> {
+        let name = field.name();
+        let data_type = field.data_type().clone();
+
+        let min = config.and_then(|c| c.min);
+        let max = config.and_then(|c| c.max);
+        let distribution = config.and_then(|c| c.distribution);
+        let values = config.map(|c| c.values.clone()).unwrap_or_default();
+        let faker = config.and_then(|c| c.faker);
+        let null_ratio = config.and_then(|c| c.null_ratio).unwrap_or(0.0);
+
+        let kind = config
+            .and_then(|c| c.kind)
+            .unwrap_or(match (faker, values.is_empty()) {
+                (Some(_), _) => GeneratorKind::Faker,
+                (None, false) => GeneratorKind::Enum,
+                (None, true) => GeneratorKind::Random,
+            });
+
+        if !(0.0..=1.0).contains(&null_ratio) {
+            bail!("null_ratio for field '{}' must be between 0 and 1", name);
+        }
+
+        if null_ratio > 0.0 && !field.is_nullable() {
+            bail!(
+                "field '{}' is NOT NULL, so it can't have a non-zero null_ratio",
+                name
+            );
+        }
+
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                bail!("min for field '{}' is greater than its max", name);
+            }
+        }
+
+        if distribution.is_some() && !matches!(kind, GeneratorKind::Random | GeneratorKind::Enum) {
+            bail!(
+                "distribution for field '{}' only applies to random and enum generators",
+                name
+            );
+        }
+
+        if !values.is_empty() && kind != GeneratorKind::Enum {
+            bail!("values for field '{}' only apply to enum generators", name);
+        }
+
+        if faker.is_some() && kind != GeneratorKind::Faker {
+            bail!(
+                "faker for field '{}' only applies to faker generators",
+                name
+            );
+        }
+
+        let is_integer = matches!(
+            data_type,
+            DataType::Int32 | DataType::Int64 | DataType::UInt32 | DataType::UInt64
+        );
+        let is_float = matches!(data_type, DataType::Float32 | DataType::Float64);
+
+        if matches!(data_type, DataType::UInt32 | DataType::UInt64) && min.is_some_and(|m| m < 0.0)
+        {
+            bail!("min for unsigned field '{}' must not be negative", name);
+        }
+
+        let mut event_time = false;
+
+        let generator = match kind {
+            GeneratorKind::Random => {
+                let (default_min, default_max) = match &data_type {
+                    _ if is_integer => (0.0, 1000.0),
+                    _ if is_float => (0.0, 1.0),
+                    DataType::Utf8 | DataType::Binary => {
+                        if min.is_some_and(|m| m < 0.0) {
+                            bail!("min length for field '{}' must not be negative", name);
+                        }
+                        (8.0, 16.0)
+                    }
+                    DataType::Boolean => (0.0, 1.0),
+                    DataType::Timestamp(_, _) => {
+                        if min.is_none() != max.is_none() {
+                            bail!(
+                                "random timestamp field '{}' needs both min and max \
+                                (in milliseconds since the epoch), or neither to use the event time",
+                                name
+                            );
+                        }
+                        event_time = min.is_none();
+                        (0.0, 0.0)
+                    }
+                    dt => bail!("datagen can't generate field '{}' of type {:?}", name, dt),
+                };
+
+                let min = min.unwrap_or_else(|| max.map_or(default_min, |m| default_min.min(m)));
+                ValueGenerator::Random {
+                    min,
+                    max: max.unwrap_or(default_max.max(min)),
+                    distribution: distribution.unwrap_or(Distribution::Uniform),
+                }
+            }
+            GeneratorKind::Sequence => {
+                if !(is_integer || is_float || data_type == DataType::Utf8) {
+                    bail!(
+                        "sequence generators can only be used for numeric and string fields, \
+                        but '{}' is {:?}",
+                        name,
+                        data_type
+                    );
+                }
+
+                let start = min.unwrap_or(0.0) as i64;
+                let end = max.map(|m| m as i64);
+                if end.is_some_and(|end| end < start) {
+                    bail!("max for sequence field '{}' is before its start", name);
+                }
+
+                ValueGenerator::Sequence { start, end }
+            }
+            GeneratorKind::Enum => {
+                if values.is_empty() {
+                    bail!("enum generator for field '{}' requires values", name);
+                }
+
+                let values = values
+                    .into_iter()
+                    .map(|v| {
+                        ScalarValue::try_from_string(v.clone(), &data_type).map_err(|e| {
+                            anyhow!(
+                                "value '{}' for field '{}' is not a valid {:?}: {}",
+                                v,
+                                name,
+                                data_type,
+                                e
+                            )
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+
+                ValueGenerator::Enum {
+                    values,
+                    distribution: distribution.unwrap_or(Distribution::Uniform),
+                }
+            }
+            GeneratorKind::Faker => {
+                if data_type != DataType::Utf8 {
+                    bail!(
+                        "faker generators can only be used for TEXT fields, but '{}' is {:?}",
+                        name,
+                        data_type
+                    );
+                }
+
+                ValueGenerator::Faker(faker.ok_or_else(|| {
+                    anyhow!("faker generator for field '{}' requires faker", name)
+                })?)
+            }
+        };
+
+        Ok(Self {
+            data_type,
+            null_ratio,
+            event_time,
+            generator,
+        })
+    }
+
+    fn int_scalar(&self, v: i64) -> ScalarValue {
+        match self.data_type {
+            DataType::Int32 => ScalarValue::Int32(Some(v as i32)),
+            DataType::Int64 => ScalarValue::Int64(Some(v)),
+            DataType::UInt32 => ScalarValue::UInt32(Some(v as u32)),
+            DataType::UInt64 => ScalarValue::UInt64(Some(v as u64)),
+            DataType::Float32 => ScalarValue::Float32(Some(v as f32)),
+            DataType::Float64 => ScalarValue::Float64(Some(v as f64)),
+            _ => ScalarValue::Utf8(Some(v.to_string())),
+        }
+    }
+
+    /// Generates the value for the message with the given (global) index
+    fn generate(&self, rng: &mut StdRng, index: u64, event_time: SystemTime) -> ScalarValue {
+        if self.null_ratio > 0.0 && rng.gen_bool(self.null_ratio) {
+            return ScalarValue::try_from(&self.data_type).unwrap();
+        }
+
+        match &self.generator {
+            ValueGenerator::Random {
+                min,
+                max,
+                distribution,
+            } => match &self.data_type {
+                DataType::Int32 | DataType::Int64 | DataType::UInt32 | DataType::UInt64 => {
+                    let v = sample(rng, *distribution, *min, *max + 1.0).floor();
+                    self.int_scalar(v.min(*max) as i64)
+                }
+                DataType::Float32 => {
+                    ScalarValue::Float32(Some(sample(rng, *distribution, *min, *max) as f32))
+                }
+                DataType::Float64 => {
+                    ScalarValue::Float64(Some(sample(rng, *distribution, *min, *max)))
+                }
+                DataType::Boolean => ScalarValue::Boolean(Some(rng.gen_bool(0.5))),
+                DataType::Utf8 => {
+                    let len = rng.gen_range(*min as usize..=*max as usize);
+                    ScalarValue::Utf8(Some(
+                        (0..len).map(|_| rng.sample(Alphanumeric) as char).collect(),
+                    ))
+                }
+                DataType::Binary => {
+                    let len = rng.gen_range(*min as usize..=*max as usize);
+                    ScalarValue::Binary(Some((0..len).map(|_| rng.gen()).collect()))
+                }
+                DataType::Timestamp(unit, tz) => {
+                    let millis = if self.event_time {
+                        to_millis(event_time) as i64
+                    } else {
+                        sample(rng, *distribution, *min, *max) as i64
+                    };
+                    timestamp_scalar(unit, tz, millis)
+                }
+                _ => unreachable!("unsupported types are rejected in new"),
+            },
+            ValueGenerator::Sequence { start, end } => {
+                let v = match end {
+                    Some(end) => start + (index % (end - start + 1) as u64) as i64,
+                    None => start + index as i64,
+                };
+                self.int_scalar(v)
+            }
+            ValueGenerator::Enum {
+                values,
+                distribution,
+            } => {
+                let i = sample(rng, *distribution, 0.0, values.len() as f64) as usize;
+                values[i.min(values.len() - 1)].clone()
+            }
+            ValueGenerator::Faker(kind) => ScalarValue::Utf8(Some(fake(rng, *kind))),
+        }
+    }
+}
+
+/// Generates the columns of a datagen table from its declared fields and the configured
+/// per-field generators
+#[derive(Debug, Clone)]
+pub struct ColumnGenerators {
+    schema: Arc<Schema>,
+    // the generator for each field of the schema, or None for the event time field
+    columns: Vec<Option<ColumnGenerator>>,
+}
+
+impl ColumnGenerators {
+    pub fn new(config: &[FieldGenerator], schema: &Schema) -> anyhow::Result<Self> {
+        let config: HashMap<&str, &FieldGenerator> =
+            config.iter().map(|c| (c.name.as_str(), c)).collect();
+
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|f| {
+                if f.name() == TIMESTAMP_FIELD {
+                    Ok(None)
+                } else {
+                    ColumnGenerator::new(f, config.get(f.name().as_str()).copied()).map(Some)
+                }
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            schema: Arc::new(schema.clone()),
+            columns,
+        })
+    }
+
+    /// Generates a batch with a row for each of the (global index, event time) pairs
+    pub fn generate(
+        &self,
+        rng: &mut StdRng,
+        rows: &[(u64, SystemTime)],
+    ) -> anyhow::Result<RecordBatch> {
+        let columns = self
+            .columns
+            .iter()
+            .map(|column| match column {
+                Some(column) => Ok(ScalarValue::iter_to_array(
+                    rows.iter()
+                        .map(|(index, time)| column.generate(rng, *index, *time)),
+                )?),
+                None => {
+                    let mut builder = TimestampNanosecondBuilder::with_capacity(rows.len());
+                    for (_, time) in rows {
+                        builder.append_value(to_nanos(*time) as i64);
+                    }
+                    Ok(Arc::new(builder.finish()) as ArrayRef)
+                }
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+#[derive(Encode, Decode, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DatagenSourceState {
+    pub counter: u64,
+    pub seed: u64,
+    /// when the source first started, in micros since the epoch; event times are derived from
+    /// it rather than the clock
+    pub start_time: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct DatagenSourceFunc {
+    table: DatagenTable,
+    state: DatagenSourceState,
+}
+
+impl DatagenSourceFunc {
+    pub fn new(table: DatagenTable) -> Self {
+        let seed = table.seed.map(|s| s as u64).unwrap_or_else(rand::random);
+        Self {
+            table,
+            state: DatagenSourceState {
+                counter: 0,
+                seed,
+                start_time: None,
+            },
+        }
+    }
+
+    /// The rng is reseeded from the counter at startup and after every checkpoint, and event
+    /// times are derived from the counter and the start time, so that the data replayed after a
+    /// restore is identical to what was originally generated
+    fn rng(&self, task_info: &TaskInfo) -> StdRng {
+        StdRng::seed_from_u64(
+            self.state
+                .seed
+                .wrapping_add((task_info.task_index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+                .wrapping_add(self.state.counter),
+        )
+    }
+
+    fn delay(&self, task_info: &TaskInfo) -> Duration {
+        Duration::from_secs_f64(task_info.parallelism as f64 / self.table.event_rate)
+    }
+
+    /// The (global index, event time) pairs of the next `count` messages, starting at the counter
+    fn rows(&self, task_info: &TaskInfo, rng: &mut StdRng, count: u64) -> Vec<(u64, SystemTime)> {
+        let delay = self.delay(task_info);
+        let start_time = from_micros(self.state.start_time.unwrap_or(0));
+        let parallelism = task_info.parallelism as u64;
+        let task_index = task_info.task_index as u64;
+        let out_of_order = self
+            .table
+            .out_of_order_millis
+            .map(|t| t as u64)
+            .unwrap_or(0);
+
+        (self.state.counter..self.state.counter + count)
+            .map(|counter| {
+                let time = start_time + delay.mul_f64(counter as f64);
+                let time = if out_of_order > 0 {
+                    time - Duration::from_millis(rng.gen_range(0..=out_of_order))
+                } else {
+                    time
+                };
+                (counter * parallelism + task_index, time)
+            })
+            .collect()
+    }
+
+    fn batch_size(&self, ctx: &ArrowContext) -> usize {
+        let duration_micros = self.delay(&ctx.task_info).as_micros();
+        if duration_micros == 0 {
+            return 8192;
+        }
+        let batch_size = Duration::from_millis(100).as_micros() / duration_micros;
+        batch_size.clamp(1, 8192) as usize
+    }
+
+    /// The number of messages this subtask emits; messages are assigned to subtasks
+    /// round-robin by their global index
+    fn limit(&self, ctx: &ArrowContext) -> u64 {
+        let Some(count) = self.table.message_count else {
+            return u64::MAX;
+        };
+
+        let count = count as u64;
+        let parallelism = ctx.task_info.parallelism as u64;
+        let task_index = ctx.task_info.task_index as u64;
+        if count > task_index {
+            (count - task_index).div_ceil(parallelism)
+        } else {
+            0
+        }
+    }
+
+    async fn run(&mut self, ctx: &mut ArrowContext) -> SourceFinishType {
+        let schema = ctx.out_schema.as_ref().unwrap().schema.clone();
+        let generators = match ColumnGenerators::new(&self.table.fields, &schema) {
+            Ok(generators) => generators,
+            Err(e) => {
+                ctx.report_error("Invalid datagen configuration", e.to_string())
+                    .await;
+                panic!("Invalid datagen configuration: {:?}", e);
+            }
+        };
+
+        let delay = self.delay(&ctx.task_info);
+        let batch_size = self.batch_size(ctx) as u64;
+        let limit = self.limit(ctx);
+
+        info!(
+            "Starting datagen source at {} with delay {:?} and limit {}",
+            self.state.counter, delay, limit
+        );
+
+        let start_time = SystemTime::now() - delay.mul_f64(self.state.counter as f64);
+        if self.state.start_time.is_none() {
+            self.state.start_time = Some(to_micros(start_time));
+        }
+        let mut rng = self.rng(&ctx.task_info);
+
+        while self.state.counter < limit {
+            let count = batch_size.min(limit - self.state.counter);
+            let rows = self.rows(&ctx.task_info, &mut rng, count);
+
+            match generators.generate(&mut rng, &rows) {
+                Ok(batch) => ctx.collect(batch).await,
+                Err(e) => {
+                    ctx.report_error("Failed to generate data", e.to_string())
+                        .await;
+                    panic!("Failed to generate data: {:?}", e);
+                }
+            }
+
+            self.state.counter += rows.len() as u64;
+
+            match ctx.control_rx.try_recv() {
+                Ok(ControlMessage::Checkpoint(c)) => {
+                    debug!("starting checkpointing {}", ctx.task_info.task_index);
+                    ctx.table_manager
+                        .get_global_keyed_state("d")
+                        .await
+                        .unwrap()
+                        .insert(ctx.task_info.task_index, self.state)
+                        .await;
+                    if self.start_checkpoint(c, ctx).await {
+                        return SourceFinishType::Immediate;
+                    }
+                    rng = self.rng(&ctx.task_info);
+                }
+                Ok(ControlMessage::Stop { mode }) => {
+                    info!("Stopping datagen source {:?}", mode);
+
+                    match mode {
+                        StopMode::Graceful => {
+                            return SourceFinishType::Graceful;
+                        }
+                        StopMode::Immediate => {
+                            return SourceFinishType::Immediate;
+                        }
+                    }
+                }
+                Ok(ControlMessage::Commit { .. }) => {
+                    unreachable!("sources shouldn't receive commit messages");
+                }
+                Ok(ControlMessage::LoadCompacted { compacted }) => {
+                    ctx.load_compacted(compacted).await;
+                }
+                Ok(ControlMessage::NoOp) => {}
+                Err(_) => {
+                    // no messages
+                }
+            }
+
+            let next_sleep = start_time + delay.mul_f64(self.state.counter as f64);
+            if let Ok(sleep_time) = next_sleep.duration_since(SystemTime::now()) {
+                tokio::time::sleep(sleep_time).await;
+            }
+        }
+
+        SourceFinishType::Final
+    }
+}
+
+#[async_trait]
+impl SourceOperator for DatagenSourceFunc {
+    fn name(&self) -> String {
+        "datagen-source".to_string()
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        arroyo_state::global_table_config("d", "datagen source state")
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        let s = ctx
+            .table_manager
+            .get_global_keyed_state("d")
+            .await
+            .expect("should have table d in datagen source");
+
+        if let Some(state) = s.get(&ctx.task_info.task_index) {
+            self.state = *state;
+        }
+    }
+
+    async fn run(&mut self, ctx: &mut ArrowContext) -> SourceFinishType {
+        self.run(ctx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, AsArray};
+    use arrow::datatypes::{Int64Type, UInt32Type};
+
+    fn generator(name: &str) -> FieldGenerator {
+        FieldGenerator {
+            name: name.to_string(),
+            kind: None,
+            min: None,
+            max: None,
+            distribution: None,
+            values: vec![],
+            faker: None,
+            null_ratio: None,
+        }
+    }
+
+    #[test]
+    fn test_generators() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("price", DataType::UInt32, true),
+            Field::new("status", DataType::Utf8, false),
+            Field::new("email", DataType::Utf8, false),
+            Field::new(
+                TIMESTAMP_FIELD,
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]);
+
+        let config = vec![
+            FieldGenerator {
+                kind: Some(GeneratorKind::Sequence),
+                min: Some(10.0),
+                ..generator("id")
+            },
+            FieldGenerator {
+                min: Some(5.0),
+                max: Some(20.0),
+                distribution: Some(Distribution::Normal),
+                null_ratio: Some(0.5),
+                ..generator("price")
+            },
+            FieldGenerator {
+                values: vec!["open".to_string(), "closed".to_string()],
+                ..generator("status")
+            },
+            FieldGenerator {
+                faker: Some(FakerKind::Email),
+                ..generator("email")
+            },
+        ];
+
+        let generators = ColumnGenerators::new(&config, &schema).unwrap();
+        let now = SystemTime::now();
+        let rows: Vec<_> = (0..1000).map(|i| (i, now)).collect();
+        let batch = generators
+            .generate(&mut StdRng::seed_from_u64(1), &rows)
+            .unwrap();
+
+        assert_eq!(batch.num_rows(), 1000);
+
+        let ids = batch.column(0).as_primitive::<Int64Type>();
+        assert!(ids
+            .iter()
+            .enumerate()
+            .all(|(i, v)| v == Some(i as i64 + 10)));
+
+        let prices = batch.column(1).as_primitive::<UInt32Type>();
+        assert!(prices.null_count() > 300 && prices.null_count() < 700);
+        assert!(prices.iter().flatten().all(|p| (5..=20).contains(&p)));
+
+        let statuses = batch.column(2).as_string::<i32>();
+        assert!(statuses
+            .iter()
+            .all(|s| s == Some("open") || s == Some("closed")));
+
+        let emails = batch.column(3).as_string::<i32>();
+        assert!(emails.iter().all(|e| e.unwrap().contains('@')));
+
+        // the same seed produces the same data
+        let again = generators
+            .generate(&mut StdRng::seed_from_u64(1), &rows)
+            .unwrap();
+        assert_eq!(batch, again);
+    }
+
+    #[test]
+    fn test_replay_after_restore() {
+        let table = || DatagenTable {
+            event_rate: 100.0,
+            message_count: None,
+            out_of_order_millis: Some(1000),
+            seed: Some(7),
+            fields: vec![],
+        };
+        let task_info = arroyo_types::get_test_task_info();
+
+        let mut source = DatagenSourceFunc::new(table());
+        source.state.start_time = Some(to_micros(SystemTime::now()));
+
+        let mut rng = source.rng(&task_info);
+        source.rows(&task_info, &mut rng, 5);
+        source.state.counter += 5;
+        let checkpoint = source.state;
+
+        let mut rng = source.rng(&task_info);
+        let original = source.rows(&task_info, &mut rng, 5);
+
+        // restoring later replays the same indices and event times
+        std::thread::sleep(Duration::from_millis(10));
+        let mut restored = DatagenSourceFunc::new(table());
+        restored.state = checkpoint;
+        let mut rng = restored.rng(&task_info);
+        assert_eq!(restored.rows(&task_info, &mut rng, 5), original);
+
+        let indices: Vec<_> = original.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_invalid_generators() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]);
+
+        // null ratio on a non-nullable field
+        let config = vec![FieldGenerator {
+            null_ratio: Some(0.1),
+            ..generator("id")
+        }];
+        assert!(ColumnGenerators::new(&config, &schema).is_err());
+
+        // faker on a non-string field
+        let config = vec![FieldGenerator {
+            faker: Some(FakerKind::Name),
+            ..generator("id")
+        }];
+        assert!(ColumnGenerators::new(&config, &schema).is_err());
+
+        // enum values that don't match the field type
+        let config = vec![FieldGenerator {
+            values: vec!["one".to_string()],
+            ..generator("id")
+        }];
+        assert!(ColumnGenerators::new(&config, &schema).is_err());
+
+        // min greater than max
+        let config = vec![FieldGenerator {
+            min: Some(10.0),
+            max: Some(1.0),
+            ..generator("id")
+        }];
+        assert!(ColumnGenerators::new(&config, &schema).is_err());
+
+        // nested types can't be generated
+        let schema = Schema::new(vec![Field::new(
+            "items",
+            DataType::List(Arc::new(Field::new("item", DataType::Int64, true))),
+            true,
+        )]);
+        assert!(ColumnGenerators::new(&[], &schema).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+mod operator;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_formats::ser::ArrowSerializer;
+use arroyo_operator::connector::{Connection, Connector};
+use arroyo_operator::operator::OperatorNode;
+use arroyo_rpc::api_types::connections::{
+    ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
+};
+use arroyo_rpc::formats::{Format, JsonFormat};
+use arroyo_rpc::var_str::VarStr;
+use arroyo_rpc::OperatorConfig;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use typify::import_types;
+
+use crate::elasticsearch::operator::{BulkClient, ElasticsearchSinkFunc, IndexTemplate};
+use crate::{pull_opt, pull_option_to_i64};
+
+const CONFIG_SCHEMA: &str = include_str!("./profile.json");
+const TABLE_SCHEMA: &str = include_str!("./table.json");
+const ICON: &str = include_str!("./elasticsearch.svg");
+
+import_types!(
+    schema = "src/elasticsearch/profile.json",
+    convert = {
+        {type = "string", format = "var-str"} = VarStr
+    }
+);
+import_types!(schema = "src/elasticsearch/table.json");
+
+const DEFAULT_MAX_BATCH_SIZE: usize = 1000;
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_RETRIES: usize = 10;
+
+impl ElasticsearchConfig {
+    fn endpoint(&self) -> anyhow::Result<String> {
+        let endpoint = self.endpoint.sub_env_vars()?;
+        reqwest::Url::parse(&endpoint)
+            .map_err(|e| anyhow!("invalid endpoint '{}': {}", endpoint, e))?;
+        Ok(endpoint.trim_end_matches('/').to_string())
+    }
+
+    fn client(&self) -> anyhow::Result<reqwest::Client> {
+        let mut headers = HeaderMap::new();
+
+        match (&self.username, &self.password, &self.api_key) {
+            (None, None, None) => {}
+            (Some(username), password, None) => {
+                let credentials = format!(
+                    "{}:{}",
+                    username.sub_env_vars()?,
+                    password
+                        .as_ref()
+                        .map(|p| p.sub_env_vars())
+                        .transpose()?
+                        .unwrap_or_default()
+                );
+                headers.insert(
+                    AUTHORIZATION,
+                    HeaderValue::try_from(format!("Basic {}", base64::encode(credentials)))?,
+                );
+            }
+            (None, None, Some(api_key)) => {
+                headers.insert(
+                    AUTHORIZATION,
+                    HeaderValue::try_from(format!("ApiKey {}", api_key.sub_env_vars()?))?,
+                );
+            }
+            (None, Some(_), _) => bail!("a password was provided without a username"),
+            (Some(_), _, Some(_)) => {
+                bail!("only one of username/password or api_key may be provided")
+            }
+        }
+
+        reqwest::ClientBuilder::new()
+            .default_headers(headers)
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| anyhow!("could not construct HTTP client: {:?}", e))
+    }
+}
+
+pub struct ElasticsearchConnector {}
+
+impl ElasticsearchConnector {
+    async fn test_int(config: &ElasticsearchConfig) -> anyhow::Result<String> {
+        let client = config.client()?;
+        let resp = client
+            .get(config.endpoint()?)
+            .send()
+            .await
+            .map_err(|e| anyhow!("failed to connect to cluster: {}", e))?;
+
+        if !resp.status().is_success() {
+            bail!(
+                "cluster responded with error code {}: {}",
+                resp.status(),
+                resp.text().await.unwrap_or_default()
+            );
+        }
+
+        let info: serde_json::Value = resp.json().await?;
+        Ok(format!(
+            "Successfully connected to cluster '{}' (version {})",
+            info.pointer("/cluster_name")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown"),
+            info.pointer("/version/number")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown"),
+        ))
+    }
+
+    fn connection_from_options(
+        options: &mut HashMap<String, String>,
+    ) -> anyhow::Result<ElasticsearchConfig> {
+        Ok(ElasticsearchConfig {
+            endpoint: VarStr::new(pull_opt("endpoint", options)?),
+            username: options.remove("username").map(VarStr::new),
+            password: options.remove("password").map(VarStr::new),
+            api_key: options.remove("api_key").map(VarStr::new),
+        })
+    }
+}
+
+impl Connector for ElasticsearchConnector {
+    type ProfileT = ElasticsearchConfig;
+    type TableT = ElasticsearchTable;
+
+    fn name(&self) -> &'static str {
+        "elasticsearch"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "elasticsearch".to_string(),
+            name: "Elasticsearch".to_string(),
+            icon: ICON.to_string(),
+            description: "Index documents into Elasticsearch or OpenSearch".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        config.endpoint.sub_env_vars().unwrap_or_default()
+    }
+
+    fn supports_updating_input(&self) -> bool {
+        true
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Sink
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<TestSourceMessage>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = match Self::test_int(&config).await {
+                Ok(message) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message,
+                },
+                Err(err) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: format!("{:?}", err),
+                },
+            };
+
+            tx.send(message).await.unwrap();
+        });
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        options: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+        profile: Option<&ConnectionProfile>,
+    ) -> anyhow::Result<Connection> {
+        let connection = profile
+            .map(|p| {
+                serde_json::from_value(p.config.clone()).map_err(|e| {
+                    anyhow!("invalid config for profile '{}' in database: {}", p.id, e)
+                })
+            })
+            .unwrap_or_else(|| Self::connection_from_options(options))?;
+
+        let table = ElasticsearchTable {
+            index: pull_opt("index", options)?,
+            document_id_field: options.remove("document_id_field"),
+            max_batch_size: pull_option_to_i64("max_batch_size", options)?,
+            flush_interval_millis: pull_option_to_i64("flush_interval_millis", options)?,
+            max_retries: pull_option_to_i64("max_retries", options)?,
+        };
+
+        self.from_config(None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        config.endpoint()?;
+        config.client()?;
+
+        if table.max_batch_size.is_some_and(|s| s <= 0) {
+            bail!("max_batch_size must be greater than 0");
+        }
+
+        if table.flush_interval_millis.is_some_and(|i| i <= 0) {
+            bail!("flush_interval_millis must be greater than 0");
+        }
+
+        if table.max_retries.is_some_and(|r| r < 0) {
+            bail!("max_retries must not be negative");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for Elasticsearch sink"))?;
+
+        // documents are always JSON; the only thing that may be configured is how it's encoded
+        let format = match &schema.format {
+            None => Format::Json(JsonFormat::default()),
+            Some(Format::Json(json)) if !json.debezium && !json.confluent_schema_registry => {
+                Format::Json(json.clone())
+            }
+            Some(_) => bail!("Elasticsearch sinks only support the 'json' format"),
+        };
+
+        let columns = IndexTemplate::columns(&table.index)?;
+        if !schema.fields.is_empty() {
+            for column in columns
+                .iter()
+                .chain(table.document_id_field.iter())
+                .filter(|c| *c != arroyo_rpc::TIMESTAMP_FIELD)
+            {
+                if !schema.fields.iter().any(|f| &f.field_name == column) {
+                    bail!(
+                        "column '{}' is referenced by the Elasticsearch sink, but is not in the schema",
+                        column
+                    );
+                }
+            }
+        }
+
+        let description = format!("ElasticsearchSink<{}>", table.index);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            format: Some(format),
+            bad_data: schema.bad_data.clone(),
+            framing: None,
+            metadata_fields: vec![],
+        };
+
+        Ok(Connection {
+            id,
+            connector: self.name(),
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn make_operator(
+        &self,
+        profile: Self::ProfileT,
+        table: Self::TableT,
+        config: OperatorConfig,
+    ) -> anyhow::Result<OperatorNode> {
+        Ok(OperatorNode::from_operator(Box::new(
+            ElasticsearchSinkFunc {
+                client: BulkClient {
+                    client: profile.client()?,
+                    url: format!("{}/_bulk", profile.endpoint()?),
+                    max_retries: table
+                        .max_retries
+                        .map(|r| r as usize)
+                        .unwrap_or(DEFAULT_MAX_RETRIES),
+                },
+                serializer: ArrowSerializer::new(
+                    config
+                        .format
+                        .expect("No format configured for Elasticsearch sink"),
+                ),
+                max_batch_size: table
+                    .max_batch_size
+                    .map(|s| s as usize)
+                    .unwrap_or(DEFAULT_MAX_BATCH_SIZE),
+                flush_interval: table
+                    .flush_interval_millis
+                    .map(|i| Duration::from_millis(i as u64))
+                    .unwrap_or(DEFAULT_FLUSH_INTERVAL),
+                table,
+                index: None,
+                id_index: None,
+                retract_index: None,
+                buffer: vec![],
+            },
+        )))
+    }
+}
@@ -0,0 +1,757 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arrow::array::{Array, AsArray, BooleanArray, RecordBatch};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Schema, TimeUnit, TimestampNanosecondType};
+use arroyo_formats::ser::ArrowSerializer;
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::grpc::TableConfig;
+use arroyo_rpc::IS_RETRACT_FIELD;
+use arroyo_types::{from_nanos, CheckpointBarrier, SignalMessage};
+use async_trait::async_trait;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Utc};
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::warn;
+
+use super::ElasticsearchTable;
+
+enum IndexPart {
+    Literal(String),
+    Column {
+        name: String,
+        format: Option<String>,
+    },
+}
+
+/// An index name that may reference columns of the row being indexed, like `events-{region}`,
+/// or format timestamp columns, like `logs-{_timestamp:%Y.%m.%d}`
+pub struct IndexTemplate {
+    parts: Vec<IndexPart>,
+    // indices of the referenced columns in the input schema, in the order of the parts
+    indices: Vec<usize>,
+}
+
+impl IndexTemplate {
+    fn parse(index: &str) -> anyhow::Result<Vec<IndexPart>> {
+        let mut parts = vec![];
+        let mut rest = index;
+
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                bail!("unclosed '{{' in index '{}'", index);
+            };
+
+            if start > 0 {
+                parts.push(IndexPart::Literal(rest[..start].to_string()));
+            }
+
+            let reference = &rest[start + 1..start + end];
+            let (name, format) = match reference.split_once(':') {
+                Some((name, format)) => {
+                    if StrftimeItems::new(format).any(|i| i == Item::Error) {
+                        bail!("invalid time format '{}' in index '{}'", format, index);
+                    }
+                    (name.trim(), Some(format.to_string()))
+                }
+                None => (reference.trim(), None),
+            };
+
+            if name.is_empty() {
+                bail!("empty column reference in index '{}'", index);
+            }
+
+            parts.push(IndexPart::Column {
+                name: name.to_string(),
+                format,
+            });
+
+            rest = &rest[start + end + 1..];
+        }
+
+        if rest.contains('}') {
+            bail!("unmatched '}}' in index '{}'", index);
+        }
+
+        if !rest.is_empty() {
+            parts.push(IndexPart::Literal(rest.to_string()));
+        }
+
+        Ok(parts)
+    }
+
+    /// Returns the columns referenced by the index
+    pub fn columns(index: &str) -> anyhow::Result<Vec<String>> {
+        Ok(Self::parse(index)?
+            .into_iter()
+            .filter_map(|part| match part {
+                IndexPart::Column { name, .. } => Some(name),
+                IndexPart::Literal(_) => None,
+            })
+            .collect())
+    }
+
+    pub fn new(index: &str, schema: &Schema) -> anyhow::Result<Self> {
+        let parts = Self::parse(index)?;
+
+        let indices = parts
+            .iter()
+            .filter_map(|part| match part {
+                IndexPart::Column { name, format } => Some((name, format)),
+                IndexPart::Literal(_) => None,
+            })
+            .map(|(name, format)| {
+                let i = schema
+                    .index_of(name)
+                    .map_err(|_| anyhow!("index references unknown column '{}'", name))?;
+
+                if format.is_some()
+                    && !matches!(schema.field(i).data_type(), DataType::Timestamp(_, _))
+                {
+                    bail!(
+                        "index formats column '{}' as a time, but it is not a timestamp",
+                        name
+                    );
+                }
+
+                Ok(i)
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { parts, indices })
+    }
+
+    /// Renders the index for each row of the batch; rows where a referenced column is null
+    /// have no index
+    fn render(&self, batch: &RecordBatch) -> anyhow::Result<Vec<Option<String>>> {
+        let columns = self
+            .parts
+            .iter()
+            .filter_map(|part| match part {
+                IndexPart::Column { format, .. } => Some(format.is_some()),
+                IndexPart::Literal(_) => None,
+            })
+            .zip(&self.indices)
+            .map(|(is_time, i)| {
+                cast(
+                    batch.column(*i),
+                    &if is_time {
+                        DataType::Timestamp(TimeUnit::Nanosecond, None)
+                    } else {
+                        DataType::Utf8
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((0..batch.num_rows())
+            .map(|row| {
+                let mut index = String::new();
+                let mut columns = columns.iter();
+                for part in &self.parts {
+                    match part {
+                        IndexPart::Literal(s) => index.push_str(s),
+                        IndexPart::Column { format, .. } => {
+                            let column = columns.next().unwrap();
+                            if column.is_null(row) {
+                                return None;
+                            }
+
+                            match format {
+                                Some(format) => {
+                                    let nanos =
+                                        column.as_primitive::<TimestampNanosecondType>().value(row);
+                                    let time: DateTime<Utc> = from_nanos(nanos as u128).into();
+                                    index.push_str(&time.format(format).to_string().to_lowercase());
+                                }
+                                None => {
+                                    index.push_str(
+                                        &column.as_string::<i32>().value(row).to_lowercase(),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+                Some(index)
+            })
+            .collect())
+    }
+}
+
+/// A single operation in a bulk request: the action line and, for index operations, the
+/// document
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkAction {
+    action: Vec<u8>,
+    document: Option<Vec<u8>>,
+}
+
+impl BulkAction {
+    fn index(index: &str, id: Option<&str>, document: Vec<u8>) -> Self {
+        let action = match id {
+            Some(id) => json!({"index": {"_index": index, "_id": id}}),
+            None => json!({"index": {"_index": index}}),
+        };
+
+        Self {
+            action: serde_json::to_vec(&action).unwrap(),
+            document: Some(document),
+        }
+    }
+
+    fn delete(index: &str, id: &str) -> Self {
+        Self {
+            action: serde_json::to_vec(&json!({"delete": {"_index": index, "_id": id}})).unwrap(),
+            document: None,
+        }
+    }
+
+    fn is_delete(&self) -> bool {
+        self.document.is_none()
+    }
+}
+
+#[derive(Deserialize)]
+struct BulkResponse {
+    errors: bool,
+    items: Vec<HashMap<String, BulkItem>>,
+}
+
+#[derive(Deserialize)]
+struct BulkItem {
+    status: u16,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+fn is_retryable(status: u16) -> bool {
+    status == 429 || status >= 500
+}
+
+pub struct BulkClient {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub max_retries: usize,
+}
+
+impl BulkClient {
+    /// Sends the actions as bulk requests, retrying requests that fail and documents that are
+    /// rejected with retryable errors (like 429s when the cluster is overloaded). Returns an
+    /// error message and details if the actions could not be applied.
+    async fn send(&self, mut actions: Vec<BulkAction>) -> Result<(), (String, String)> {
+        let mut retries = 0;
+
+        loop {
+            let mut body = vec![];
+            for action in &actions {
+                body.extend_from_slice(&action.action);
+                body.push(b'\n');
+                if let Some(document) = &action.document {
+                    body.extend_from_slice(document);
+                    body.push(b'\n');
+                }
+            }
+
+            let details = match self
+                .client
+                .post(&self.url)
+                .header(CONTENT_TYPE, "application/x-ndjson")
+                .body(body)
+                .send()
+                .await
+            {
+                Ok(resp) if resp.status().is_success() => {
+                    let resp: BulkResponse = resp.json().await.map_err(|e| {
+                        (
+                            "invalid bulk response from Elasticsearch".to_string(),
+                            e.to_string(),
+                        )
+                    })?;
+
+                    if !resp.errors {
+                        return Ok(());
+                    }
+
+                    let mut retry = vec![];
+                    let mut last_error = None;
+                    for (action, item) in actions.into_iter().zip(resp.items) {
+                        let Some(item) = item.into_values().next() else {
+                            continue;
+                        };
+
+                        // deleting a document that doesn't exist leaves us in the desired state
+                        if (200..300).contains(&item.status)
+                            || (action.is_delete() && item.status == 404)
+                        {
+                            continue;
+                        }
+
+                        let error = item
+                            .error
+                            .map(|e| e.to_string())
+                            .unwrap_or_else(|| format!("status {}", item.status));
+
+                        if !is_retryable(item.status) {
+                            return Err(("Elasticsearch rejected document".to_string(), error));
+                        }
+
+                        last_error = Some(error);
+                        retry.push(action);
+                    }
+
+                    if retry.is_empty() {
+                        return Ok(());
+                    }
+
+                    actions = retry;
+                    last_error.unwrap()
+                }
+                Ok(resp) => {
+                    let status = resp.status().as_u16();
+                    let details = format!(
+                        "Elasticsearch responded with error code {}: {}",
+                        status,
+                        resp.text().await.unwrap_or_default()
+                    );
+
+                    if !is_retryable(status) {
+                        return Err(("bulk request failed".to_string(), details));
+                    }
+
+                    details
+                }
+                Err(e) => e.to_string(),
+            };
+
+            if retries >= self.max_retries {
+                return Err((
+                    format!("bulk request failed after {} retries", retries),
+                    details,
+                ));
+            }
+
+            warn!("bulk request failed (retry {}): {}", retries, details);
+            retries += 1;
+            tokio::time::sleep(Duration::from_millis(
+                (50 * (1 << retries.min(10))).min(5_000),
+            ))
+            .await;
+        }
+    }
+}
+
+pub struct ElasticsearchSinkFunc {
+    pub table: ElasticsearchTable,
+    pub client: BulkClient,
+    pub serializer: ArrowSerializer,
+    pub max_batch_size: usize,
+    pub flush_interval: Duration,
+    pub index: Option<IndexTemplate>,
+    pub id_index: Option<usize>,
+    pub retract_index: Option<usize>,
+    pub buffer: Vec<BulkAction>,
+}
+
+impl ElasticsearchSinkFunc {
+    /// Converts the rows of the batch into bulk actions; rows that can't be written (because
+    /// their index or id is null) are returned as errors
+    fn actions(&mut self, batch: &RecordBatch) -> anyhow::Result<(Vec<BulkAction>, Vec<String>)> {
+        let indices = self
+            .index
+            .as_ref()
+            .expect("index should be initialized in on_start")
+            .render(batch)?;
+
+        let ids = self
+            .id_index
+            .map(|i| cast(batch.column(i), &DataType::Utf8))
+            .transpose()?;
+        let ids = ids.as_ref().map(|ids| ids.as_string::<i32>());
+
+        let retracts: Option<&BooleanArray> =
+            self.retract_index.map(|i| batch.column(i).as_boolean());
+
+        let mut actions = vec![];
+        let mut errors = vec![];
+
+        for (row, (document, index)) in self.serializer.serialize(batch).zip(indices).enumerate() {
+            let Some(index) = index else {
+                errors.push(format!(
+                    "a column referenced by index '{}' was null",
+                    self.table.index
+                ));
+                continue;
+            };
+
+            let id = match ids {
+                Some(ids) if ids.is_null(row) => {
+                    errors.push("document id was null".to_string());
+                    continue;
+                }
+                Some(ids) => Some(ids.value(row)),
+                None => None,
+            };
+
+            let is_retract = retracts
+                .map(|r| r.is_valid(row) && r.value(row))
+                .unwrap_or(false);
+
+            actions.push(match (is_retract, id) {
+                (true, Some(id)) => BulkAction::delete(&index, id),
+                (true, None) => unreachable!("updating input requires a document id"),
+                (false, id) => BulkAction::index(&index, id, document),
+            });
+        }
+
+        Ok((actions, errors))
+    }
+
+    async fn flush(&mut self, ctx: &mut ArrowContext) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let actions = std::mem::take(&mut self.buffer);
+        if let Err((message, details)) = self.client.send(actions).await {
+            ctx.report_error(&message, &details).await;
+            panic!("{}: {}", message, details);
+        }
+    }
+}
+
+#[async_trait]
+impl ArrowOperator for ElasticsearchSinkFunc {
+    fn name(&self) -> String {
+        "ElasticsearchSink".to_string()
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        HashMap::new()
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        Some(self.flush_interval)
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        let schema = ctx.in_schemas[0].schema.clone();
+
+        match IndexTemplate::new(&self.table.index, &schema) {
+            Ok(index) => {
+                self.index = Some(index);
+            }
+            Err(e) => {
+                ctx.report_error("Invalid Elasticsearch index", e.to_string())
+                    .await;
+                panic!("Invalid Elasticsearch index: {:?}", e);
+            }
+        }
+
+        if let Some(field) = &self.table.document_id_field {
+            match schema.index_of(field) {
+                Ok(i) => self.id_index = Some(i),
+                Err(_) => {
+                    let message = format!("document id field '{}' is not in the schema", field);
+                    ctx.report_error("Invalid Elasticsearch sink", &message)
+                        .await;
+                    panic!("{}", message);
+                }
+            }
+        }
+
+        // if our input is updating, retractions are applied by deleting the document
+        self.retract_index = schema.index_of(IS_RETRACT_FIELD).ok();
+
+        if self.retract_index.is_some() && self.id_index.is_none() {
+            let message = "document_id_field must be set to write updating data to Elasticsearch";
+            ctx.report_error("Invalid Elasticsearch sink", message)
+                .await;
+            panic!("{}", message);
+        }
+    }
+
+    async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
+        let (actions, errors) = match self.actions(&batch) {
+            Ok(actions) => actions,
+            Err(e) => {
+                ctx.report_error("Failed to build Elasticsearch documents", e.to_string())
+                    .await;
+                panic!("Failed to build Elasticsearch documents: {:?}", e);
+            }
+        };
+
+        if let Some(error) = errors.first() {
+            ctx.report_error(format!("Dropped {} documents", errors.len()), error.clone())
+                .await;
+        }
+
+        for action in actions {
+            self.buffer.push(action);
+            if self.buffer.len() >= self.max_batch_size {
+                self.flush(ctx).await;
+            }
+        }
+    }
+
+    async fn handle_tick(&mut self, _: u64, ctx: &mut ArrowContext) {
+        self.flush(ctx).await;
+    }
+
+    async fn handle_checkpoint(&mut self, _: CheckpointBarrier, ctx: &mut ArrowContext) {
+        // everything before the barrier must be indexed before the checkpoint can complete
+        self.flush(ctx).await;
+    }
+
+    async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        self.flush(ctx).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int64Array, StringArray, TimestampNanosecondArray};
+    use arrow::datatypes::Field;
+    use arroyo_rpc::formats::{Format, JsonFormat};
+    use arroyo_rpc::TIMESTAMP_FIELD;
+    use axum::extract::State;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use std::sync::{Arc, Mutex};
+
+    fn schema() -> Arc<Schema> {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("region", DataType::Utf8, true),
+            Field::new(
+                TIMESTAMP_FIELD,
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+            Field::new(IS_RETRACT_FIELD, DataType::Boolean, true),
+        ]))
+    }
+
+    fn batch() -> RecordBatch {
+        RecordBatch::try_new(
+            schema(),
+            vec![
+                Arc::new(Int64Array::from(vec![Some(1), Some(2), None])),
+                Arc::new(StringArray::from(vec![Some("US"), None, Some("eu")])),
+                // 2024-03-01T00:00:00Z
+                Arc::new(TimestampNanosecondArray::from(vec![
+                    1_709_251_200_000_000_000;
+                    3
+                ])),
+                Arc::new(BooleanArray::from(vec![Some(true), Some(false), None])),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn sink(index: &str, url: String) -> ElasticsearchSinkFunc {
+        let schema = schema();
+        ElasticsearchSinkFunc {
+            table: ElasticsearchTable {
+                index: index.to_string(),
+                document_id_field: Some("id".to_string()),
+                max_batch_size: None,
+                flush_interval_millis: None,
+                max_retries: None,
+            },
+            client: BulkClient {
+                client: reqwest::Client::new(),
+                url,
+                max_retries: 3,
+            },
+            serializer: ArrowSerializer::new(Format::Json(JsonFormat::default())),
+            max_batch_size: 1000,
+            flush_interval: Duration::from_secs(1),
+            index: Some(IndexTemplate::new(index, &schema).unwrap()),
+            id_index: Some(0),
+            retract_index: Some(3),
+            buffer: vec![],
+        }
+    }
+
+    #[test]
+    fn test_index_template() {
+        let schema = schema();
+        let template =
+            IndexTemplate::new("events-{region}-{ _timestamp:%Y.%m.%d}", &schema).unwrap();
+        assert_eq!(
+            template.render(&batch()).unwrap(),
+            vec![
+                Some("events-us-2024.03.01".to_string()),
+                None,
+                Some("events-eu-2024.03.01".to_string()),
+            ]
+        );
+
+        assert!(IndexTemplate::new("events-{missing}", &schema).is_err());
+        assert!(IndexTemplate::new("events-{region:%Y}", &schema).is_err());
+        assert!(IndexTemplate::columns("events-{region").is_err());
+        assert!(IndexTemplate::columns("events-{_timestamp:%Q}").is_err());
+    }
+
+    fn parse_lines(body: &[u8]) -> Vec<serde_json::Value> {
+        std::str::from_utf8(body)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_actions() {
+        let mut sink = sink("events-{_timestamp:%Y}", String::new());
+        let (actions, errors) = sink.actions(&batch()).unwrap();
+
+        // the first row is a retraction, the second an upsert and the third has no id
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0], BulkAction::delete("events-2024", "1"));
+        assert_eq!(
+            parse_lines(&actions[1].action),
+            vec![json!({"index": {"_index": "events-2024", "_id": "2"}})]
+        );
+        assert_eq!(
+            parse_lines(actions[1].document.as_ref().unwrap())[0]["id"],
+            json!(2)
+        );
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[derive(Default)]
+    struct MockState {
+        requests: Vec<String>,
+    }
+
+    async fn bulk(
+        State(state): State<Arc<Mutex<MockState>>>,
+        body: String,
+    ) -> (StatusCode, Json<serde_json::Value>) {
+        let mut state = state.lock().unwrap();
+        state.requests.push(body.clone());
+
+        let items: Vec<_> = body
+            .lines()
+            .filter_map(|line| {
+                let line: serde_json::Value = serde_json::from_str(line).unwrap();
+                if line.get("delete").is_some() {
+                    Some(json!({"delete": {"status": 404}}))
+                } else if line.get("index").is_some() {
+                    // the first attempt of a document is rejected as the cluster is overloaded
+                    let status = if state.requests.len() == 1 { 429 } else { 201 };
+                    Some(json!({"index": {"status": status}}))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let errors = items
+            .iter()
+            .any(|i| i.as_object().unwrap().values().any(|v| v["status"] != 201));
+
+        (
+            StatusCode::OK,
+            Json(json!({"took": 1, "errors": errors, "items": items})),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_bulk_retries() {
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let app = Router::new()
+            .route("/_bulk", post(bulk))
+            .with_state(state.clone());
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        let client = BulkClient {
+            client: reqwest::Client::new(),
+            url: format!("http://{}/_bulk", addr),
+            max_retries: 3,
+        };
+
+        client
+            .send(vec![
+                BulkAction::delete("events", "1"),
+                BulkAction::index("events", Some("2"), b"{\"id\":2}".to_vec()),
+            ])
+            .await
+            .unwrap();
+
+        let requests = state.lock().unwrap().requests.clone();
+        assert_eq!(requests.len(), 2);
+
+        // the delete of a missing document succeeds, so only the rejected document is retried
+        assert_eq!(
+            parse_lines(requests[0].as_bytes()),
+            vec![
+                json!({"delete": {"_index": "events", "_id": "1"}}),
+                json!({"index": {"_index": "events", "_id": "2"}}),
+                json!({"id": 2}),
+            ]
+        );
+        assert_eq!(
+            parse_lines(requests[1].as_bytes()),
+            vec![
+                json!({"index": {"_index": "events", "_id": "2"}}),
+                json!({"id": 2}),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bulk_rejection() {
+        async fn reject(body: String) -> Json<serde_json::Value> {
+            let items: Vec<_> = body
+                .lines()
+                .step_by(2)
+                .map(|_| {
+                    json!({
+                        "index": {"status": 400, "error": {"type": "mapper_parsing_exception"}}
+                    })
+                })
+                .collect();
+            Json(json!({"took": 1, "errors": true, "items": items}))
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener).unwrap().serve(
+                Router::new()
+                    .route("/_bulk", post(reject))
+                    .into_make_service(),
+            ),
+        );
+
+        let client = BulkClient {
+            client: reqwest::Client::new(),
+            url: format!("http://{}/_bulk", addr),
+            max_retries: 3,
+        };
+
+        let (message, details) = client
+            .send(vec![BulkAction::index("events", None, b"{}".to_vec())])
+            .await
+            .unwrap_err();
+        assert_eq!(message, "Elasticsearch rejected document");
+        assert!(details.contains("mapper_parsing_exception"));
+    }
+}
@@ -0,0 +1,188 @@
+mod operator;
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::formats::Format;
+use arroyo_rpc::{var_str::VarStr, OperatorConfig};
+use tokio::sync::mpsc::Sender;
+use typify::import_types;
+
+use arroyo_operator::connector::Connection;
+use arroyo_operator::operator::OperatorNode;
+use arroyo_rpc::api_types::connections::{
+    ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::http_push::operator::HttpPushSourceFunc;
+use crate::{pull_option_to_i64, EmptyConfig};
+
+use arroyo_operator::connector::Connector;
+
+const TABLE_SCHEMA: &str = include_str!("./table.json");
+
+import_types!(
+    schema = "src/http_push/table.json",
+    convert = { {type = "string", format = "var-str"} = VarStr }
+);
+const ICON: &str = include_str!("./http.svg");
+
+pub struct HttpPushConnector {}
+
+impl Connector for HttpPushConnector {
+    type ProfileT = EmptyConfig;
+
+    type TableT = HttpPushTable;
+
+    fn name(&self) -> &'static str {
+        "http_push"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "http_push".to_string(),
+            name: "HTTP Push".to_string(),
+            icon: ICON.to_string(),
+            description: "Receive events sent to an HTTP endpoint".to_string(),
+            enabled: true,
+            source: true,
+            sink: false,
+            testing: false,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_owned(),
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<TestSourceMessage>,
+    ) {
+        tokio::task::spawn(async move {
+            tx.send(TestSourceMessage::done(
+                "HTTP push sources do not require testing",
+            ))
+            .await
+            .unwrap();
+        });
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Source
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let description = format!("HttpPushSource<{}>", table.path);
+
+        if table.path.is_empty()
+            || !table
+                .path
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!(
+                "invalid path '{}'; must be non-empty and contain only letters, numbers, '-', and '_'",
+                table.path
+            );
+        }
+
+        if let Some(port) = table.port {
+            if !(1..=u16::MAX as i64).contains(&port) {
+                bail!("invalid port {}", port);
+            }
+        }
+
+        if let Some(token) = &table.auth_token {
+            token.sub_env_vars()?;
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for HTTP push connection"))?;
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for HTTP push connection"))?;
+
+        if !matches!(format, Format::Json(_)) {
+            bail!("HTTP push sources only support JSON formats");
+        }
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            format: Some(format),
+            bad_data: schema.bad_data.clone(),
+            framing: schema.framing.clone(),
+            metadata_fields: vec![],
+        };
+
+        Ok(Connection {
+            id,
+            connector: self.name(),
+            name: name.to_string(),
+            connection_type: ConnectionType::Source,
+            schema,
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        options: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+        _profile: Option<&ConnectionProfile>,
+    ) -> anyhow::Result<Connection> {
+        let path = options.remove("path").unwrap_or_else(|| name.to_string());
+        let port = pull_option_to_i64("port", options)?;
+        let auth_token = options.remove("auth_token").map(VarStr::new);
+
+        self.from_config(
+            None,
+            name,
+            EmptyConfig {},
+            HttpPushTable {
+                path,
+                port,
+                auth_token,
+            },
+            schema,
+        )
+    }
+
+    fn make_operator(
+        &self,
+        _: Self::ProfileT,
+        table: Self::TableT,
+        config: OperatorConfig,
+    ) -> anyhow::Result<OperatorNode> {
+        Ok(OperatorNode::from_source(Box::new(HttpPushSourceFunc {
+            path: table.path,
+            port: table.port.map(|p| p as u16),
+            auth_token: table
+                .auth_token
+                .map(|t| t.sub_env_vars().expect("Failed to substitute env vars")),
+            format: config.format.expect("HTTP push source must have a format"),
+            framing: config.framing,
+            bad_data: config.bad_data,
+        })))
+    }
+}
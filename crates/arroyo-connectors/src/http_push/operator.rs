@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::SourceOperator;
+use arroyo_operator::SourceFinishType;
+use arroyo_rpc::formats::{BadData, Format, Framing};
+use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
+use arroyo_rpc::grpc::{RegisterIngestEndpointReq, StopMode, TableConfig};
+use arroyo_rpc::ControlMessage;
+use arroyo_types::{default_controller_addr, UserError, CONTROLLER_ADDR_ENV};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use local_ip_address::local_ip;
+use tokio::select;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+// the number of requests that may be waiting to be processed before new requests are held
+const REQUEST_QUEUE_SIZE: usize = 16;
+
+pub struct HttpPushSourceFunc {
+    pub path: String,
+    pub port: Option<u16>,
+    pub auth_token: Option<String>,
+    pub format: Format,
+    pub framing: Option<Framing>,
+    pub bad_data: Option<BadData>,
+}
+
+struct IngestRequest {
+    records: Vec<Vec<u8>>,
+    ack: oneshot::Sender<Result<(), String>>,
+}
+
+#[derive(Clone)]
+struct ServerState {
+    tx: mpsc::Sender<IngestRequest>,
+    auth_token: Option<Arc<String>>,
+}
+
+/// Splits a request body into records: NDJSON bodies contain one record per line, while JSON
+/// bodies contain either a single record or an array of records
+fn split_records(content_type: Option<&str>, body: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let is_ndjson = content_type.is_some_and(|t| {
+        t.contains("application/x-ndjson")
+            || t.contains("application/jsonl")
+            || t.contains("application/jsonlines")
+    });
+
+    if is_ndjson {
+        return Ok(body
+            .split(|b| *b == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+            .map(|line| line.to_vec())
+            .collect());
+    }
+
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Array(records)) => Ok(records
+            .iter()
+            .map(|r| serde_json::to_vec(r).unwrap())
+            .collect()),
+        Ok(_) => Ok(vec![body.to_vec()]),
+        Err(e) => Err(format!("request body is not valid JSON: {}", e)),
+    }
+}
+
+async fn handle_request(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Some(token) = &state.auth_token {
+        let authorized = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .is_some_and(|t| t == token.as_str());
+
+        if !authorized {
+            return (StatusCode::UNAUTHORIZED, "missing or invalid auth token").into_response();
+        }
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+
+    let records = match split_records(content_type, &body) {
+        Ok(records) => records,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, e).into_response();
+        }
+    };
+
+    let (ack, rx) = oneshot::channel();
+
+    // if the source is backpressured this will wait until it has room for our request
+    if state.tx.send(IngestRequest { records, ack }).await.is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "source is shutting down").into_response();
+    }
+
+    match rx.await {
+        Ok(Ok(())) => StatusCode::OK.into_response(),
+        Ok(Err(e)) => (StatusCode::BAD_REQUEST, e).into_response(),
+        Err(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "source stopped before the request was processed",
+        )
+            .into_response(),
+    }
+}
+
+#[async_trait]
+impl SourceOperator for HttpPushSourceFunc {
+    fn name(&self) -> String {
+        format!("HttpPushSource<{}>", self.path)
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        HashMap::new()
+    }
+
+    async fn run(&mut self, ctx: &mut ArrowContext) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+}
+
+impl HttpPushSourceFunc {
+    /// Registers this subtask's server with the controller, which routes ingest requests to it
+    async fn register(&self, ctx: &ArrowContext, port: u16) {
+        let controller_addr =
+            std::env::var(CONTROLLER_ADDR_ENV).unwrap_or_else(|_| default_controller_addr());
+
+        let ip = local_ip()
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|_| "localhost".to_string());
+
+        let result = match ControllerGrpcClient::connect(controller_addr).await {
+            Ok(mut client) => client
+                .register_ingest_endpoint(RegisterIngestEndpointReq {
+                    job_id: ctx.task_info.job_id.clone(),
+                    path: self.path.clone(),
+                    subtask_index: ctx.task_info.task_index as u32,
+                    address: format!("http://{}:{}", ip, port),
+                })
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        if let Err(e) = result {
+            warn!(
+                "Failed to register ingest endpoint with controller; requests must be sent \
+                directly to the subtasks: {}",
+                e
+            );
+        }
+    }
+
+    async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
+        ctx.initialize_deserializer(
+            self.format.clone(),
+            self.framing.clone(),
+            self.bad_data.clone(),
+            &[],
+        );
+
+        let (tx, rx) = mpsc::channel(REQUEST_QUEUE_SIZE);
+
+        let app = Router::new()
+            .route(&format!("/{}", self.path), post(handle_request))
+            .with_state(ServerState {
+                tx,
+                auth_token: self.auth_token.clone().map(Arc::new),
+            });
+
+        // each subtask listens on its own port, offset from the configured one by its index
+        let port = match self.port {
+            Some(port) => u16::try_from(ctx.task_info.task_index)
+                .ok()
+                .and_then(|i| port.checked_add(i))
+                .ok_or_else(|| {
+                    UserError::new(
+                        "Invalid port configuration",
+                        format!(
+                            "port {} is too high for subtask {}; subtasks listen on consecutive \
+                            ports starting from the configured port, which must not exceed 65535",
+                            port, ctx.task_info.task_index
+                        ),
+                    )
+                })?,
+            None => 0,
+        };
+        let addr = SocketAddr::from(([0, 0, 0, 0], port));
+
+        let server = axum::Server::try_bind(&addr)
+            .map_err(|e| {
+                UserError::new(
+                    "Failed to start HTTP server",
+                    format!("could not bind to {}: {}", addr, e),
+                )
+            })?
+            .serve(app.into_make_service());
+
+        let local_addr = server.local_addr();
+        info!("HTTP push source listening on {}", local_addr);
+
+        let server = tokio::spawn(async move {
+            if let Err(e) = server.await {
+                warn!("HTTP push source server failed: {:?}", e);
+            }
+        });
+
+        self.register(ctx, local_addr.port()).await;
+
+        let result = self.process(ctx, rx).await;
+        server.abort();
+        Ok(result)
+    }
+
+    async fn process(
+        &mut self,
+        ctx: &mut ArrowContext,
+        mut rx: mpsc::Receiver<IngestRequest>,
+    ) -> SourceFinishType {
+        loop {
+            select! {
+                Some(request) = rx.recv() => {
+                    let mut result = Ok(());
+                    for record in &request.records {
+                        result = ctx.deserialize_slice(record, SystemTime::now(), None).await;
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+
+                    // only acknowledge the request once its data has been sent downstream
+                    if result.is_ok() {
+                        result = ctx.flush_buffer().await;
+                    }
+
+                    match result {
+                        Ok(()) => {
+                            let _ = request.ack.send(Ok(()));
+                        }
+                        Err(e) => {
+                            // invalid data that isn't dropped by the bad_data policy rejects the
+                            // whole request, but one bad client shouldn't fail the pipeline
+                            warn!("rejecting HTTP push request: {}: {}", e.name, e.details);
+                            ctx.discard_buffer();
+                            let _ = request.ack.send(Err(format!("{}: {}", e.name, e.details)));
+                        }
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    match control_message {
+                        Some(ControlMessage::Checkpoint(c)) => {
+                            debug!("starting checkpointing {}", ctx.task_info.task_index);
+                            if self.start_checkpoint(c, ctx).await {
+                                return SourceFinishType::Immediate;
+                            }
+                        }
+                        Some(ControlMessage::Stop { mode }) => {
+                            info!("Stopping HTTP push source: {:?}", mode);
+
+                            match mode {
+                                StopMode::Graceful => {
+                                    return SourceFinishType::Graceful;
+                                }
+                                StopMode::Immediate => {
+                                    return SourceFinishType::Immediate;
+                                }
+                            }
+                        }
+                        Some(ControlMessage::Commit { .. }) => {
+                            unreachable!("sources shouldn't receive commit messages");
+                        }
+                        Some(ControlMessage::LoadCompacted { compacted }) => {
+                            ctx.load_compacted(compacted).await;
+                        }
+                        Some(ControlMessage::NoOp) | None => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use arroyo_operator::context::{batch_bounded, BatchReceiver};
+    use arroyo_rpc::df::ArroyoSchema;
+    use arroyo_rpc::formats::JsonFormat;
+    use arroyo_types::ArrowMessage;
+    use std::time::Duration;
+    use tokio::task::JoinHandle;
+
+    async fn post(url: &str, body: &'static str) -> StatusCode {
+        reqwest::Client::new()
+            .post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    struct RunningSource {
+        url: String,
+        control_tx: mpsc::Sender<ControlMessage>,
+        data_rx: BatchReceiver,
+        task: JoinHandle<SourceFinishType>,
+    }
+
+    impl RunningSource {
+        async fn start(bad_data: Option<BadData>, queue_size: u32) -> Self {
+            // find a free port for the source to listen on
+            let port = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+
+            let mut source = HttpPushSourceFunc {
+                path: "events".to_string(),
+                port: Some(port),
+                auth_token: None,
+                format: Format::Json(JsonFormat::default()),
+                framing: None,
+                bad_data,
+            };
+
+            let (control_tx, control_rx) = mpsc::channel(16);
+            let (command_tx, _command_rx) = mpsc::channel(128);
+            let (data_tx, data_rx) = batch_bounded(queue_size);
+
+            let mut ctx = ArrowContext::new(
+                arroyo_types::get_test_task_info(),
+                None,
+                control_rx,
+                command_tx,
+                vec![1],
+                vec![],
+                Some(ArroyoSchema::new_unkeyed(
+                    Arc::new(Schema::new(vec![
+                        Field::new(
+                            "_timestamp",
+                            DataType::Timestamp(TimeUnit::Nanosecond, None),
+                            false,
+                        ),
+                        Field::new("value", DataType::Utf8, false),
+                    ])),
+                    0,
+                )),
+                None,
+                vec![vec![data_tx]],
+                source.tables(),
+            )
+            .await;
+
+            let task = tokio::spawn(async move { source.run(&mut ctx).await });
+
+            let running = Self {
+                url: format!("http://127.0.0.1:{}/events", port),
+                control_tx,
+                data_rx,
+                task,
+            };
+
+            // wait for the server to come up
+            for _ in 0..50 {
+                if tokio::net::TcpStream::connect(("127.0.0.1", port))
+                    .await
+                    .is_ok()
+                {
+                    return running;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            panic!("HTTP push source did not start listening");
+        }
+
+        async fn post(&self, body: &'static str) -> StatusCode {
+            post(&self.url, body).await
+        }
+
+        async fn next_values(&mut self) -> Vec<String> {
+            loop {
+                match self.data_rx.recv().await {
+                    Some(ArrowMessage::Data(batch)) => {
+                        let values = batch
+                            .column(1)
+                            .as_any()
+                            .downcast_ref::<StringArray>()
+                            .unwrap();
+                        return values.iter().map(|v| v.unwrap().to_string()).collect();
+                    }
+                    Some(ArrowMessage::Signal(_)) => {}
+                    None => panic!("source stopped"),
+                }
+            }
+        }
+
+        async fn stop(self) {
+            self.control_tx
+                .send(ControlMessage::Stop {
+                    mode: StopMode::Immediate,
+                })
+                .await
+                .unwrap();
+            assert!(matches!(
+                self.task.await.unwrap(),
+                SourceFinishType::Immediate
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_accept() {
+        let mut source = RunningSource::start(None, 128).await;
+
+        let status = source.post(r#"[{"value": "a"}, {"value": "b"}]"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(source.next_values().await, vec!["a", "b"]);
+
+        source.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_reject() {
+        let mut source = RunningSource::start(Some(BadData::Fail {}), 128).await;
+
+        // bodies that aren't JSON are rejected before they reach the source
+        let status = source.post("{not json").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // as are requests with records that don't match the schema, without failing the source
+        let status = source
+            .post(r#"[{"value": "a"}, {"value": {"a": 1}}]"#)
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(!source.task.is_finished());
+
+        // and none of the rejected request's records are emitted
+        let status = source.post(r#"{"value": "c"}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(source.next_values().await, vec!["c"]);
+
+        source.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_drop_bad_data() {
+        let mut source = RunningSource::start(Some(BadData::Drop {}), 128).await;
+
+        let status = source
+            .post(r#"[{"value": "a"}, {"value": {"a": 1}}]"#)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(source.next_values().await, vec!["a"]);
+
+        source.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        // the downstream queue only has room for one record
+        let mut source = RunningSource::start(None, 1).await;
+
+        let status = source.post(r#"{"value": "a"}"#).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // the next request isn't acknowledged until its record has been sent downstream, which
+        // can't happen until there's room in the queue
+        let url = source.url.clone();
+        let pending = tokio::spawn(async move { post(&url, r#"{"value": "b"}"#).await });
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(!pending.is_finished());
+
+        assert_eq!(source.next_values().await, vec!["a"]);
+        assert_eq!(pending.await.unwrap(), StatusCode::OK);
+        assert_eq!(source.next_values().await, vec!["b"]);
+
+        source.stop().await;
+    }
+
+    #[test]
+    fn test_split_records() {
+        assert_eq!(
+            split_records(Some("application/json"), br#"[{"a": 1}, {"a": 2}]"#).unwrap(),
+            vec![br#"{"a":1}"#.to_vec(), br#"{"a":2}"#.to_vec()]
+        );
+
+        assert_eq!(
+            split_records(None, br#"{"a": 1}"#).unwrap(),
+            vec![br#"{"a": 1}"#.to_vec()]
+        );
+
+        assert_eq!(
+            split_records(
+                Some("application/x-ndjson"),
+                b"{\"a\": 1}\n\n{\"a\": 2}\r\n"
+            )
+            .unwrap(),
+            vec![br#"{"a": 1}"#.to_vec(), br#"{"a": 2}"#.to_vec()]
+        );
+
+        assert!(split_records(Some("application/json"), b"{not json").is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::{anyhow, bail};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
 use arroyo_formats::de::ArrowDeserializer;
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::connector::Connection;
@@ -15,6 +16,7 @@ use rdkafka::{
     consumer::{BaseConsumer, Consumer},
     ClientConfig, Message, Offset, TopicPartitionList,
 };
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::borrow::Cow;
@@ -29,11 +31,11 @@ use tonic::Status;
 use tracing::{error, info, warn};
 use typify::import_types;
 
-use crate::{pull_opt, send, ConnectionType};
+use crate::{pull_opt, pull_option_to_i64, send, ConnectionType};
 
-use crate::kafka::sink::KafkaSinkFunc;
-use crate::kafka::source::KafkaSourceFunc;
-use arroyo_operator::connector::Connector;
+use crate::kafka::sink::{FieldIndices, KafkaSinkFunc};
+use crate::kafka::source::{KafkaSourceFunc, StopAt};
+use arroyo_operator::connector::{Connector, MetadataDef};
 use arroyo_operator::operator::OperatorNode;
 
 mod sink;
@@ -43,6 +45,33 @@ const CONFIG_SCHEMA: &str = include_str!("./profile.json");
 const TABLE_SCHEMA: &str = include_str!("./table.json");
 const ICON: &str = include_str!("./kafka.svg");
 
+const METADATA_DEFS: &[MetadataDef] = &[
+    MetadataDef {
+        name: "key",
+        data_type: DataType::Binary,
+    },
+    MetadataDef {
+        name: "headers",
+        data_type: DataType::Utf8,
+    },
+    MetadataDef {
+        name: "topic",
+        data_type: DataType::Utf8,
+    },
+    MetadataDef {
+        name: "partition",
+        data_type: DataType::Int32,
+    },
+    MetadataDef {
+        name: "offset",
+        data_type: DataType::Int64,
+    },
+    MetadataDef {
+        name: "timestamp",
+        data_type: DataType::Timestamp(TimeUnit::Nanosecond, None),
+    },
+];
+
 import_types!(
     schema = "src/kafka/profile.json",
     convert = {
@@ -51,6 +80,30 @@ import_types!(
 );
 import_types!(schema = "src/kafka/table.json");
 
+/// The set of topics a Kafka source reads from
+#[derive(Debug, Clone)]
+pub enum TopicSubscription {
+    Topics(Vec<String>),
+    Pattern(Regex),
+}
+
+impl TopicSubscription {
+    pub fn matches(&self, topic: &str) -> bool {
+        match self {
+            TopicSubscription::Topics(topics) => topics.iter().any(|t| t == topic),
+            TopicSubscription::Pattern(pattern) => pattern.is_match(topic),
+        }
+    }
+
+    /// Returns the name of the topic if this subscribes to exactly one topic
+    pub fn single_topic(&self) -> Option<&str> {
+        match self {
+            TopicSubscription::Topics(topics) if topics.len() == 1 => Some(&topics[0]),
+            _ => None,
+        }
+    }
+}
+
 impl KafkaTable {
     pub fn subject(&self) -> Cow<str> {
         match &self.value_subject {
@@ -58,6 +111,97 @@ impl KafkaTable {
             Some(s) => Cow::Borrowed(s),
         }
     }
+
+    /// Returns the topics this table reads from; sources may specify a comma-separated list of
+    /// topics or (if `topic_pattern` is set) a regular expression matched against topic names
+    pub fn subscription(&self) -> anyhow::Result<TopicSubscription> {
+        match &self.type_ {
+            TableType::Source {
+                topic_pattern: Some(true),
+                ..
+            } => Ok(TopicSubscription::Pattern(
+                Regex::new(&format!("^(?:{})$", self.topic))
+                    .map_err(|e| anyhow!("invalid topic pattern '{}': {}", self.topic, e))?,
+            )),
+            TableType::Source { .. } => {
+                let topics: Vec<_> = self
+                    .topic
+                    .split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect();
+
+                if topics.is_empty() {
+                    bail!("at least one topic must be specified");
+                }
+
+                Ok(TopicSubscription::Topics(topics))
+            }
+            TableType::Sink { .. } => Ok(TopicSubscription::Topics(vec![self.topic.clone()])),
+        }
+    }
+
+    /// Parses the `start_offsets` of a source, which are keyed by (topic, partition)
+    pub fn start_offsets(&self) -> anyhow::Result<HashMap<(String, i32), i64>> {
+        let TableType::Source {
+            start_offsets: Some(start_offsets),
+            ..
+        } = &self.type_
+        else {
+            return Ok(HashMap::new());
+        };
+
+        let subscription = self.subscription()?;
+
+        start_offsets
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                let parts: Vec<_> = s.split(':').collect();
+                let (topic, partition, offset) = match parts.as_slice() {
+                    [partition, offset] => {
+                        let Some(topic) = subscription.single_topic() else {
+                            bail!("start offset '{}' must include the topic (as `topic:partition:offset`) when reading from multiple topics", s);
+                        };
+                        (topic, *partition, *offset)
+                    }
+                    [topic, partition, offset] => (*topic, *partition, *offset),
+                    _ => bail!(
+                        "invalid start offset '{}'; expected `partition:offset` or `topic:partition:offset`",
+                        s
+                    ),
+                };
+
+                let partition = partition
+                    .parse()
+                    .map_err(|_| anyhow!("invalid partition in start offset '{}'", s))?;
+                let offset = offset
+                    .parse()
+                    .map_err(|_| anyhow!("invalid offset in start offset '{}'", s))?;
+
+                Ok(((topic.to_string(), partition), offset))
+            })
+            .collect()
+    }
+
+    /// Returns when a bounded source should stop reading, or None if it is unbounded
+    pub fn stop_at(&self) -> anyhow::Result<Option<StopAt>> {
+        match &self.type_ {
+            TableType::Source {
+                bounded_mode: Some(BoundedMode::Latest),
+                ..
+            } => Ok(Some(StopAt::LatestOffsets)),
+            TableType::Source {
+                bounded_mode: Some(BoundedMode::Timestamp),
+                stop_timestamp,
+                ..
+            } => Ok(Some(StopAt::Timestamp(stop_timestamp.ok_or_else(
+                || anyhow!("stop_timestamp must be set when bounded_mode is 'timestamp'"),
+            )?))),
+            _ => Ok(None),
+        }
+    }
 }
 
 pub struct KafkaConnector {}
@@ -105,6 +249,8 @@ impl KafkaConnector {
                     offset: match offset.as_deref() {
                         Some("earliest") => SourceOffset::Earliest,
                         Some("group") => SourceOffset::Group,
+                        Some("timestamp") => SourceOffset::Timestamp,
+                        Some("specific") => SourceOffset::Specific,
                         None | Some("latest") => SourceOffset::Latest,
                         Some(other) => bail!("invalid value for source.offset '{}'", other),
                     },
@@ -114,6 +260,27 @@ impl KafkaConnector {
                         Some(other) => bail!("invalid value for source.read_mode '{}'", other),
                     },
                     group_id: options.remove("source.group_id"),
+                    start_timestamp: pull_option_to_i64("source.start_timestamp", options)?,
+                    start_offsets: options.remove("source.start_offsets"),
+                    bounded_mode: match options.remove("source.bounded_mode").as_deref() {
+                        Some("unbounded") | None => None,
+                        Some("latest") => Some(BoundedMode::Latest),
+                        Some("timestamp") => Some(BoundedMode::Timestamp),
+                        Some(other) => bail!("invalid value for source.bounded_mode '{}'", other),
+                    },
+                    stop_timestamp: pull_option_to_i64("source.stop_timestamp", options)?,
+                    topic_pattern: options
+                        .remove("source.topic_pattern")
+                        .map(|s| {
+                            s.parse::<bool>().map_err(|_| {
+                                anyhow!("'source.topic_pattern' must be either 'true' or 'false'")
+                            })
+                        })
+                        .transpose()?,
+                    topic_discovery_interval_secs: pull_option_to_i64(
+                        "source.topic_discovery_interval_secs",
+                        options,
+                    )?,
                 }
             }
             "sink" => {
@@ -124,6 +291,19 @@ impl KafkaConnector {
                         Some("exactly_once") => SinkCommitMode::ExactlyOnce,
                         Some(other) => bail!("invalid value for commit_mode '{}'", other),
                     },
+                    key_field: options.remove("sink.key_field"),
+                    key_format: match options.remove("sink.key_format").as_deref() {
+                        Some("raw") => Some(KeyFormat::Raw),
+                        Some("json") => Some(KeyFormat::Json),
+                        None => None,
+                        Some(other) => bail!("invalid value for sink.key_format '{}'", other),
+                    },
+                    header_fields: options
+                        .remove("sink.header_fields")
+                        .map(|fields| fields.split(',').map(|f| f.trim().to_string()).collect())
+                        .unwrap_or_default(),
+                    topic_field: options.remove("sink.topic_field"),
+                    partition_field: options.remove("sink.partition_field"),
                 }
             }
             _ => {
@@ -173,6 +353,10 @@ impl Connector for KafkaConnector {
         }
     }
 
+    fn metadata_defs(&self) -> &'static [MetadataDef] {
+        METADATA_DEFS
+    }
+
     fn config_description(&self, config: Self::ProfileT) -> String {
         (*config.bootstrap_servers).clone()
     }
@@ -203,6 +387,63 @@ impl Connector for KafkaConnector {
             .map(|t| t.to_owned())
             .ok_or_else(|| anyhow!("'format' must be set for Kafka connection"))?;
 
+        if let TableType::Source {
+            offset,
+            start_timestamp,
+            topic_discovery_interval_secs,
+            ..
+        } = &table.type_
+        {
+            if *offset == SourceOffset::Timestamp && start_timestamp.is_none() {
+                bail!("start_timestamp must be set when offset is 'timestamp'");
+            }
+
+            if *offset == SourceOffset::Specific && table.start_offsets()?.is_empty() {
+                bail!("start_offsets must be set when offset is 'specific'");
+            }
+
+            table.stop_at()?;
+
+            if topic_discovery_interval_secs.is_some_and(|i| i <= 0) {
+                bail!("topic_discovery_interval_secs must be greater than 0");
+            }
+
+            let uses_schema_registry = match &format {
+                Format::Avro(avro) => avro.confluent_schema_registry,
+                Format::Json(json) => json.confluent_schema_registry,
+                _ => false,
+            };
+
+            if uses_schema_registry
+                && table.value_subject.is_none()
+                && table.subscription()?.single_topic().is_none()
+            {
+                bail!("value.subject must be set when reading from multiple topics with a schema registry");
+            }
+        }
+
+        if let TableType::Sink {
+            key_field,
+            header_fields,
+            topic_field,
+            partition_field,
+            ..
+        } = &table.type_
+        {
+            // if the table has no columns its schema will be inferred from the query, in which
+            // case the fields are checked when the sink starts
+            if !schema.fields.is_empty() {
+                let fields: Vec<Field> = schema.fields.iter().map(|f| f.clone().into()).collect();
+                FieldIndices::resolve(
+                    &Schema::new(fields),
+                    key_field.as_deref(),
+                    header_fields,
+                    topic_field.as_deref(),
+                    partition_field.as_deref(),
+                )?;
+            }
+        }
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
@@ -210,6 +451,7 @@ impl Connector for KafkaConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            metadata_fields: schema.metadata_fields.clone(),
         };
 
         Ok(Connection {
@@ -333,6 +575,9 @@ impl Connector for KafkaConnector {
                 group_id,
                 offset,
                 read_mode,
+                topic_discovery_interval_secs,
+                start_timestamp,
+                ..
             } => {
                 let mut client_configs = client_configs(&profile, &table);
                 if let Some(ReadMode::ReadCommitted) = read_mode {
@@ -361,10 +606,16 @@ impl Connector for KafkaConnector {
                     };
 
                 Ok(OperatorNode::from_source(Box::new(KafkaSourceFunc {
-                    topic: table.topic,
+                    topics: table.subscription()?,
+                    topic_discovery_interval: Duration::from_secs(
+                        topic_discovery_interval_secs.unwrap_or(60) as u64,
+                    ),
                     bootstrap_servers: profile.bootstrap_servers.to_string(),
                     group_id: group_id.clone(),
                     offset_mode: *offset,
+                    start_timestamp: *start_timestamp,
+                    start_offsets: table.start_offsets()?,
+                    stop_at: table.stop_at()?,
                     format: config.format.expect("Format must be set for Kafka source"),
                     framing: config.framing,
                     schema_resolver,
@@ -377,21 +628,38 @@ impl Connector for KafkaConnector {
                             .unwrap_or(u32::MAX),
                     )
                     .unwrap(),
+                    metadata_fields: config.metadata_fields,
                 })))
             }
-            TableType::Sink { commit_mode } => {
-                Ok(OperatorNode::from_operator(Box::new(KafkaSinkFunc {
-                    bootstrap_servers: profile.bootstrap_servers.to_string(),
-                    producer: None,
-                    consistency_mode: (*commit_mode).into(),
-                    write_futures: vec![],
-                    client_config: client_configs(&profile, &table),
-                    topic: table.topic,
-                    serializer: ArrowSerializer::new(
-                        config.format.expect("Format must be defined for KafkaSink"),
-                    ),
-                })))
-            }
+            TableType::Sink {
+                commit_mode,
+                key_field,
+                key_format,
+                header_fields,
+                topic_field,
+                partition_field,
+            } => Ok(OperatorNode::from_operator(Box::new(KafkaSinkFunc {
+                bootstrap_servers: profile.bootstrap_servers.to_string(),
+                producer: None,
+                consistency_mode: (*commit_mode).into(),
+                write_futures: vec![],
+                client_config: client_configs(&profile, &table),
+                key_field: key_field.clone(),
+                key_serializer: match key_format {
+                    Some(KeyFormat::Json) => {
+                        Some(ArrowSerializer::new(Format::Json(JsonFormat::default())))
+                    }
+                    Some(KeyFormat::Raw) | None => None,
+                },
+                header_fields: header_fields.clone(),
+                topic_field: topic_field.clone(),
+                partition_field: partition_field.clone(),
+                field_indices: FieldIndices::default(),
+                topic: table.topic,
+                serializer: ArrowSerializer::new(
+                    config.format.expect("Format must be defined for KafkaSink"),
+                ),
+            }))),
         }
     }
 }
@@ -589,13 +857,14 @@ impl KafkaTester {
                         format.clone(),
                         None,
                         aschema.clone(),
+                        &schema.metadata_fields,
                         BadData::Fail {},
                         Arc::new(schema_resolver),
                     );
                     let mut builders = aschema.builders();
 
                     let mut error = deserializer
-                        .deserialize_slice(&mut builders, &msg, SystemTime::now())
+                        .deserialize_slice(&mut builders, &msg, SystemTime::now(), None)
                         .await
                         .into_iter()
                         .next();
@@ -611,13 +880,14 @@ impl KafkaTester {
                     let mut deserializer = ArrowDeserializer::new(
                         format.clone(),
                         aschema.clone(),
+                        &schema.metadata_fields,
                         None,
                         BadData::Fail {},
                     );
                     let mut builders = aschema.builders();
 
                     let mut error = deserializer
-                        .deserialize_slice(&mut builders, &msg, SystemTime::now())
+                        .deserialize_slice(&mut builders, &msg, SystemTime::now(), None)
                         .await
                         .into_iter()
                         .next();
@@ -664,7 +934,22 @@ impl KafkaTester {
 
         self.info(&mut tx, "Connected to Kafka").await;
 
-        let topic = table.topic.clone();
+        let topic = match table.subscription()? {
+            TopicSubscription::Topics(topics) => topics[0].clone(),
+            TopicSubscription::Pattern(pattern) => {
+                let metadata = client
+                    .fetch_metadata(None, Duration::from_secs(10))
+                    .map_err(|e| anyhow!("Failed to fetch metadata: {:?}", e))?;
+
+                metadata
+                    .topics()
+                    .iter()
+                    .map(|t| t.name())
+                    .find(|t| pattern.is_match(t))
+                    .ok_or_else(|| anyhow!("No topics match the pattern '{}'", table.topic))?
+                    .to_string()
+            }
+        };
 
         let metadata = client
             .fetch_metadata(Some(&topic), Duration::from_secs(10))
@@ -818,11 +1103,14 @@ impl KafkaTester {
 }
 
 impl SourceOffset {
-    fn get_offset(&self) -> Offset {
+    /// Returns the starting offset for modes that apply to every partition; the `timestamp`
+    /// and `specific` modes are resolved per-partition by the source
+    fn get_offset(&self) -> Option<Offset> {
         match self {
-            SourceOffset::Earliest => Offset::Beginning,
-            SourceOffset::Latest => Offset::End,
-            SourceOffset::Group => Offset::Stored,
+            SourceOffset::Earliest => Some(Offset::Beginning),
+            SourceOffset::Latest => Some(Offset::End),
+            SourceOffset::Group => Some(Offset::Stored),
+            SourceOffset::Timestamp | SourceOffset::Specific => None,
         }
     }
 }
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::{CheckpointEvent, ControlMessage, ControlResp};
@@ -7,12 +7,16 @@ use std::collections::HashMap;
 
 use tracing::{error, warn};
 
+use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord, Producer};
 use rdkafka::util::Timeout;
 
 use rdkafka::ClientConfig;
 
-use arrow::array::RecordBatch;
+use arrow::array::{Array, ArrayRef, AsArray, Int32Array, RecordBatch};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Int32Type, Schema};
+use arrow::util::display::array_value_to_string;
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::ArrowOperator;
@@ -33,9 +37,89 @@ pub struct KafkaSinkFunc {
     pub producer: Option<FutureProducer>,
     pub write_futures: Vec<DeliveryFuture>,
     pub client_config: HashMap<String, String>,
+    pub key_field: Option<String>,
+    pub key_serializer: Option<ArrowSerializer>,
+    pub header_fields: Vec<String>,
+    pub topic_field: Option<String>,
+    pub partition_field: Option<String>,
+    pub field_indices: FieldIndices,
     pub serializer: ArrowSerializer,
 }
 
+/// Column indices for the optional key, header, topic and partition fields, resolved against the
+/// input schema in `on_start`
+#[derive(Default)]
+pub struct FieldIndices {
+    key: Option<usize>,
+    headers: Vec<(String, usize)>,
+    topic: Option<usize>,
+    partition: Option<usize>,
+}
+
+impl FieldIndices {
+    /// Resolves the key, header, topic and partition fields against a schema, failing if any of
+    /// them don't exist or the topic and partition fields don't have usable types
+    pub fn resolve(
+        schema: &Schema,
+        key_field: Option<&str>,
+        header_fields: &[String],
+        topic_field: Option<&str>,
+        partition_field: Option<&str>,
+    ) -> Result<Self> {
+        let index_of = |field: &str, option: &str| {
+            schema.index_of(field).map_err(|_| {
+                anyhow!(
+                    "{} ({}) does not exist in the schema for kafka sink",
+                    option,
+                    field
+                )
+            })
+        };
+
+        let key = key_field.map(|f| index_of(f, "key_field")).transpose()?;
+
+        let headers = header_fields
+            .iter()
+            .map(|f| Ok((f.clone(), index_of(f, "header_fields")?)))
+            .collect::<Result<_>>()?;
+
+        let topic = topic_field
+            .map(|f| {
+                let idx = index_of(f, "topic_field")?;
+                if !matches!(schema.field(idx).data_type(), DataType::Utf8) {
+                    bail!("topic_field ({}) must be a TEXT column", f);
+                }
+                Ok(idx)
+            })
+            .transpose()?;
+
+        let partition = partition_field
+            .map(|f| {
+                let idx = index_of(f, "partition_field")?;
+                if !schema.field(idx).data_type().is_integer() {
+                    bail!("partition_field ({}) must be an integer column", f);
+                }
+                Ok(idx)
+            })
+            .transpose()?;
+
+        Ok(Self {
+            key,
+            headers,
+            topic,
+            partition,
+        })
+    }
+}
+
+/// Per-record metadata extracted from the non-value columns of a batch
+struct RecordMetadata<'a> {
+    key: Option<Vec<u8>>,
+    headers: Option<OwnedHeaders>,
+    topic: Option<&'a str>,
+    partition: Option<i32>,
+}
+
 pub enum ConsistencyMode {
     AtLeastOnce,
     ExactlyOnce {
@@ -56,6 +140,24 @@ impl From<SinkCommitMode> for ConsistencyMode {
     }
 }
 
+/// Returns the bytes for a single value of a column; strings and binary values are written as-is,
+/// while other types use their display representation
+fn raw_bytes(array: &ArrayRef, idx: usize) -> Option<Vec<u8>> {
+    if array.is_null(idx) {
+        return None;
+    }
+
+    Some(match array.data_type() {
+        DataType::Utf8 => array.as_string::<i32>().value(idx).as_bytes().to_vec(),
+        DataType::LargeUtf8 => array.as_string::<i64>().value(idx).as_bytes().to_vec(),
+        DataType::Binary => array.as_binary::<i32>().value(idx).to_vec(),
+        DataType::LargeBinary => array.as_binary::<i64>().value(idx).to_vec(),
+        _ => array_value_to_string(array, idx)
+            .expect("failed to format value")
+            .into_bytes(),
+    })
+}
+
 impl KafkaSinkFunc {
     fn is_committing(&self) -> bool {
         matches!(self.consistency_mode, ConsistencyMode::ExactlyOnce { .. })
@@ -116,14 +218,56 @@ impl KafkaSinkFunc {
         }
     }
 
-    async fn publish(&mut self, k: Option<Vec<u8>>, v: Vec<u8>, ctx: &mut ArrowContext) {
-        let mut rec = {
-            if let Some(k) = k.as_ref() {
-                FutureRecord::to(&self.topic).key(k).payload(&v)
-            } else {
-                FutureRecord::to(&self.topic).payload(&v)
+    fn resolve_fields(&mut self, ctx: &ArrowContext) {
+        let schema = &ctx
+            .in_schemas
+            .first()
+            .expect("no in-schema for kafka sink!")
+            .schema;
+
+        // tables with declared columns are validated when they're created, but the schema of
+        // tables that are inferred from the query is only known here
+        self.field_indices = FieldIndices::resolve(
+            schema,
+            self.key_field.as_deref(),
+            &self.header_fields,
+            self.topic_field.as_deref(),
+            self.partition_field.as_deref(),
+        )
+        .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    fn serialize_keys(&mut self, batch: &RecordBatch) -> Option<Vec<Option<Vec<u8>>>> {
+        let idx = self.field_indices.key?;
+        let column = batch.column(idx);
+
+        Some(match &mut self.key_serializer {
+            Some(serializer) => {
+                let keys = batch.project(&[idx]).expect("key column out of bounds");
+                serializer
+                    .serialize(&keys)
+                    .enumerate()
+                    .map(|(i, k)| (!column.is_null(i)).then_some(k))
+                    .collect()
             }
-        };
+            None => (0..batch.num_rows())
+                .map(|i| raw_bytes(column, i))
+                .collect(),
+        })
+    }
+
+    async fn publish(&mut self, metadata: RecordMetadata<'_>, v: Vec<u8>, ctx: &mut ArrowContext) {
+        let topic = metadata.topic.unwrap_or(&self.topic);
+        let mut rec: FutureRecord<Vec<u8>, Vec<u8>> = FutureRecord::to(topic).payload(&v);
+        if let Some(k) = metadata.key.as_ref() {
+            rec = rec.key(k);
+        }
+        if let Some(headers) = metadata.headers {
+            rec = rec.headers(headers);
+        }
+        if let Some(partition) = metadata.partition {
+            rec = rec.partition(partition);
+        }
 
         loop {
             match self.producer.as_mut().unwrap().send_result(rec) {
@@ -164,15 +308,57 @@ impl ArrowOperator for KafkaSinkFunc {
     }
 
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        self.resolve_fields(ctx);
         self.init_producer(&ctx.task_info)
             .expect("Producer creation failed");
     }
 
     async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
         let values = self.serializer.serialize(&batch);
+        let mut keys = self.serialize_keys(&batch);
+
+        let topics = self
+            .field_indices
+            .topic
+            .map(|i| batch.column(i).as_string::<i32>().clone());
+
+        let partitions: Option<Int32Array> = self.field_indices.partition.map(|i| {
+            cast(batch.column(i), &DataType::Int32)
+                .expect("partition column cannot be converted to a 32-bit integer")
+                .as_primitive::<Int32Type>()
+                .clone()
+        });
+
+        let headers: Vec<_> = self
+            .field_indices
+            .headers
+            .iter()
+            .map(|(name, i)| (name.clone(), batch.column(*i).clone()))
+            .collect();
+
+        for (i, v) in values.enumerate() {
+            let metadata = RecordMetadata {
+                key: keys.as_mut().and_then(|k| k[i].take()),
+                headers: (!headers.is_empty()).then(|| {
+                    headers.iter().fold(
+                        OwnedHeaders::new_with_capacity(headers.len()),
+                        |h, (name, column)| {
+                            h.insert(Header {
+                                key: name,
+                                value: raw_bytes(column, i).as_ref(),
+                            })
+                        },
+                    )
+                }),
+                topic: topics
+                    .as_ref()
+                    .and_then(|t| t.is_valid(i).then(|| t.value(i))),
+                partition: partitions
+                    .as_ref()
+                    .and_then(|p| p.is_valid(i).then(|| p.value(i))),
+            };
 
-        for v in values {
-            self.publish(None, v, ctx).await;
+            self.publish(metadata, v, ctx).await;
         }
     }
 
@@ -8,8 +8,10 @@ use arrow::array::{RecordBatch, UInt32Array};
 use arrow::datatypes::Field;
 use arrow::datatypes::{DataType, Schema, SchemaRef};
 use arroyo_formats::ser::ArrowSerializer;
+use arroyo_operator::connector::Connector;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::api_types::connections::ConnectionSchema;
 use arroyo_rpc::df::ArroyoSchema;
 use arroyo_rpc::formats::{Format, JsonFormat};
 use arroyo_types::CheckpointBarrier;
@@ -17,12 +19,14 @@ use arroyo_types::*;
 use itertools::Itertools;
 use rdkafka::admin::{AdminClient, AdminOptions, NewTopic};
 use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::Headers;
 use rdkafka::producer::Producer;
 use rdkafka::{ClientConfig, Message};
 use serde::Deserialize;
 use tokio::sync::mpsc::channel;
 
-use super::{ConsistencyMode, KafkaSinkFunc};
+use super::{ConsistencyMode, FieldIndices, KafkaSinkFunc};
+use crate::kafka::KafkaConnector;
 
 pub struct KafkaTopicTester {
     topic: String,
@@ -70,6 +74,14 @@ impl KafkaTopicTester {
     }
 
     async fn get_sink_with_writes(&self) -> KafkaSinkWithWrites {
+        self.get_sink_with_fields(None, vec![]).await
+    }
+
+    async fn get_sink_with_fields(
+        &self,
+        key_field: Option<&str>,
+        header_fields: Vec<String>,
+    ) -> KafkaSinkWithWrites {
         let mut kafka = KafkaSinkFunc {
             topic: self.topic.to_string(),
             bootstrap_servers: self.server.to_string(),
@@ -77,6 +89,12 @@ impl KafkaTopicTester {
             consistency_mode: ConsistencyMode::AtLeastOnce,
             write_futures: vec![],
             client_config: HashMap::new(),
+            key_field: key_field.map(|f| f.to_string()),
+            key_serializer: None,
+            header_fields,
+            topic_field: None,
+            partition_field: None,
+            field_indices: FieldIndices::default(),
             serializer: ArrowSerializer::new(Format::Json(JsonFormat::default())),
         };
 
@@ -90,7 +108,7 @@ impl KafkaTopicTester {
             None,
             control_rx,
             command_tx,
-            1,
+            vec![1],
             vec![ArroyoSchema::new_unkeyed(schema(), 0)],
             None,
             None,
@@ -160,6 +178,8 @@ async fn test_kafka_checkpoint_flushes() {
         min_epoch: 0,
         timestamp: SystemTime::now(),
         then_stop: false,
+        drain: false,
+        unaligned: false,
     };
     sink_with_writes
         .sink
@@ -204,3 +224,141 @@ async fn test_kafka() {
         assert_eq!(message, result.value);
     }
 }
+
+#[tokio::test]
+async fn test_kafka_keys_and_headers() {
+    let mut kafka_topic_tester = KafkaTopicTester {
+        topic: "arroyo-sink-keyed".to_string(),
+        server: "0.0.0.0:9092".to_string(),
+    };
+
+    kafka_topic_tester.create_topic("keyed", 1).await;
+    let mut sink_with_writes = kafka_topic_tester
+        .get_sink_with_fields(Some("value"), vec!["value".to_string()])
+        .await;
+    let mut consumer = kafka_topic_tester.get_consumer("2");
+
+    let data = UInt32Array::from_iter_values(1u32..10);
+    let batch = RecordBatch::try_new(schema(), vec![Arc::new(data)]).unwrap();
+
+    sink_with_writes
+        .sink
+        .process_batch(batch, &mut sink_with_writes.ctx)
+        .await;
+    sink_with_writes
+        .sink
+        .producer
+        .as_ref()
+        .unwrap()
+        .flush(Duration::from_secs(3))
+        .unwrap();
+
+    for message in 1u32..10 {
+        let record = consumer
+            .recv()
+            .await
+            .expect("shouldn't have errored")
+            .detach();
+
+        assert_eq!(record.key().unwrap(), message.to_string().as_bytes());
+
+        let header = record.headers().unwrap().get(0);
+        assert_eq!(header.key, "value");
+        assert_eq!(header.value.unwrap(), message.to_string().as_bytes());
+    }
+}
+
+fn routing_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("region", DataType::Utf8, false),
+        Field::new("shard", DataType::Int32, true),
+        Field::new("amount", DataType::Float64, false),
+    ])
+}
+
+#[test]
+fn test_resolve_field_indices() {
+    let schema = routing_schema();
+
+    let indices = FieldIndices::resolve(
+        &schema,
+        Some("id"),
+        &["region".to_string(), "amount".to_string()],
+        Some("region"),
+        Some("shard"),
+    )
+    .unwrap();
+    assert_eq!(indices.key, Some(0));
+    assert_eq!(
+        indices.headers,
+        vec![("region".to_string(), 1), ("amount".to_string(), 3)]
+    );
+    assert_eq!(indices.topic, Some(1));
+    assert_eq!(indices.partition, Some(2));
+
+    let error = |key: Option<&str>, headers: &[&str], topic: Option<&str>, partition| {
+        let headers: Vec<_> = headers.iter().map(|h| h.to_string()).collect();
+        FieldIndices::resolve(&schema, key, &headers, topic, partition)
+            .unwrap_err()
+            .to_string()
+    };
+
+    assert!(error(Some("missing"), &[], None, None).contains("key_field (missing)"));
+    assert!(error(None, &["missing"], None, None).contains("header_fields (missing)"));
+    assert!(error(None, &[], Some("id"), None).contains("must be a TEXT column"));
+    assert!(error(None, &[], None, Some("amount")).contains("must be an integer column"));
+}
+
+#[test]
+fn test_sink_fields_validated_at_creation() {
+    let connection_schema = |schema: Schema| {
+        ConnectionSchema::try_new(
+            Some(Format::Json(JsonFormat::default())),
+            None,
+            None,
+            None,
+            schema
+                .fields()
+                .iter()
+                .map(|f| (**f).clone().try_into().unwrap())
+                .collect(),
+            None,
+            None,
+            vec![],
+        )
+        .unwrap()
+    };
+
+    let create = |schema: &ConnectionSchema, field_options: &[(&str, &str)]| {
+        let mut options: HashMap<String, String> = [
+            ("bootstrap_servers", "localhost:9092"),
+            ("topic", "orders"),
+            ("type", "sink"),
+        ]
+        .iter()
+        .chain(field_options)
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        KafkaConnector {}.from_options("orders", &mut options, Some(schema), None)
+    };
+
+    let schema = connection_schema(routing_schema());
+    assert!(create(
+        &schema,
+        &[
+            ("sink.key_field", "id"),
+            ("sink.topic_field", "region"),
+            ("sink.partition_field", "shard")
+        ]
+    )
+    .is_ok());
+    assert!(create(&schema, &[("sink.key_field", "missing")]).is_err());
+    assert!(create(&schema, &[("sink.topic_field", "amount")]).is_err());
+    assert!(create(&schema, &[("sink.partition_field", "region")]).is_err());
+
+    // without columns the schema is inferred from the query, so the fields can't be checked yet
+    let inferred = connection_schema(Schema::empty());
+    assert!(create(&inferred, &[("sink.key_field", "missing")]).is_ok());
+}
@@ -1,3 +1,6 @@
+use anyhow::anyhow;
+use arroyo_formats::de::FieldValueType;
+use arroyo_rpc::api_types::connections::MetadataField;
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::schema_resolver::SchemaResolver;
@@ -7,12 +10,15 @@ use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::SourceOperator;
 use arroyo_operator::SourceFinishType;
 use arroyo_types::*;
+
+use crate::kafka::TopicSubscription;
 use async_trait::async_trait;
 use bincode::{Decode, Encode};
 use governor::{Quota, RateLimiter as GovernorRateLimiter};
 use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::Headers;
 use rdkafka::{ClientConfig, Message as KMessage, Offset, TopicPartitionList};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::num::NonZeroU32;
 use std::sync::Arc;
 use std::time::Duration;
@@ -24,26 +30,246 @@ use tracing::{debug, error, info, warn};
 mod test;
 
 pub struct KafkaSourceFunc {
-    pub topic: String,
+    pub topics: TopicSubscription,
+    pub topic_discovery_interval: Duration,
     pub bootstrap_servers: String,
     pub group_id: Option<String>,
     pub offset_mode: super::SourceOffset,
+    pub start_timestamp: Option<i64>,
+    pub start_offsets: HashMap<(String, i32), i64>,
+    pub stop_at: Option<StopAt>,
     pub format: Format,
     pub framing: Option<Framing>,
     pub bad_data: Option<BadData>,
     pub schema_resolver: Arc<dyn SchemaResolver + Sync>,
     pub client_configs: HashMap<String, String>,
     pub messages_per_second: NonZeroU32,
+    pub metadata_fields: Vec<MetadataField>,
 }
 
-#[derive(Copy, Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
+// Offsets are stored by (topic, partition). Before sources could read multiple topics they were
+// stored by partition in the legacy table, which is still read when restoring older checkpoints.
+const OFFSETS_TABLE: &str = "o";
+const LEGACY_OFFSETS_TABLE: &str = "k";
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
 pub struct KafkaState {
+    topic: String,
+    partition: i32,
+    offset: i64,
+    stop_offset: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
+struct LegacyKafkaState {
     partition: i32,
     offset: i64,
 }
 
+/// Where a bounded source stops reading
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StopAt {
+    /// the latest offsets of each partition when the source starts
+    LatestOffsets,
+    /// the first record at or after this time, in milliseconds since the epoch; partitions that
+    /// don't have such a record stop at their latest offsets when the source starts
+    Timestamp(i64),
+}
+
+/// Tracks the progress of a bounded source towards the stop offset of each of its partitions
+struct Bounds {
+    stop_at: StopAt,
+    stop_offsets: HashMap<(String, i32), i64>,
+    finished: HashSet<(String, i32)>,
+}
+
+impl Bounds {
+    /// Determines whether a record should be emitted, marking its partition as finished if the
+    /// record is at or past the end
+    fn check(
+        &mut self,
+        consumer: &StreamConsumer,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+        timestamp: i64,
+    ) -> bool {
+        let key = (topic.to_string(), partition);
+        if self.finished.contains(&key) {
+            return false;
+        }
+
+        let stop_offset = self.stop_offsets.get(&key).copied();
+
+        let past_end = stop_offset.is_some_and(|stop| offset >= stop)
+            || matches!(self.stop_at, StopAt::Timestamp(stop) if timestamp >= stop);
+
+        if past_end {
+            self.finish(consumer, key);
+            return false;
+        }
+
+        if stop_offset.is_some_and(|stop| offset + 1 >= stop) {
+            self.finish(consumer, key);
+        }
+
+        true
+    }
+
+    /// Checks the consumer's positions, which may have moved past the stop offsets without
+    /// returning a record (for example, due to transaction markers)
+    fn update_positions(&mut self, consumer: &StreamConsumer) {
+        let Ok(positions) = consumer.position() else {
+            return;
+        };
+
+        for e in positions.elements() {
+            let key = (e.topic().to_string(), e.partition());
+            if let (Offset::Offset(position), Some(stop)) =
+                (e.offset(), self.stop_offsets.get(&key))
+            {
+                if position >= *stop && !self.finished.contains(&key) {
+                    self.finish(consumer, key);
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, consumer: &StreamConsumer, key: (String, i32)) {
+        info!("Finished reading Kafka partition {}-{}", key.0, key.1);
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition(&key.0, key.1);
+        if let Err(e) = consumer.pause(&tpl) {
+            warn!("Failed to pause finished Kafka partition: {:?}", e);
+        }
+        self.finished.insert(key);
+    }
+
+    fn is_done(&self) -> bool {
+        self.stop_offsets
+            .keys()
+            .all(|key| self.finished.contains(key))
+    }
+}
+
+/// Deterministically assigns each (topic, partition) to a subtask. Partitions of a topic are
+/// spread round-robin starting from a per-topic offset, so assignments are balanced within each
+/// topic and stay stable as new topics are discovered.
+fn is_assigned(topic: &str, partition: i32, ctx: &ArrowContext) -> bool {
+    let parallelism = ctx.task_info.parallelism;
+    let start = (topic_hash(topic) % parallelism as u64) as usize;
+    (start + partition as usize) % parallelism == ctx.task_info.task_index
+}
+
+/// FNV-1a, which unlike the std hasher is stable across releases and platforms, so that every
+/// worker (and every version of Arroyo) agrees on the assignments
+fn topic_hash(topic: &str) -> u64 {
+    topic.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Encodes the headers of a message as a JSON object, with (lossily-decoded) UTF-8 values
+fn headers_to_json(msg: &impl KMessage) -> String {
+    let headers: serde_json::Map<String, serde_json::Value> = msg
+        .headers()
+        .map(|headers| {
+            headers
+                .iter()
+                .map(|h| {
+                    (
+                        h.key.to_string(),
+                        h.value
+                            .map(|v| String::from_utf8_lossy(v).into_owned().into())
+                            .unwrap_or(serde_json::Value::Null),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    serde_json::Value::Object(headers).to_string()
+}
+
+fn metadata_values<'a>(
+    msg: &'a impl KMessage,
+    timestamp: i64,
+    headers: Option<&'a str>,
+) -> HashMap<&'static str, FieldValueType<'a>> {
+    let mut values = HashMap::new();
+    if let Some(key) = msg.key() {
+        values.insert("key", FieldValueType::Bytes(key));
+    }
+    if let Some(headers) = headers {
+        values.insert("headers", FieldValueType::String(headers));
+    }
+    values.insert("topic", FieldValueType::String(msg.topic()));
+    values.insert("partition", FieldValueType::Int32(msg.partition()));
+    values.insert("offset", FieldValueType::Int64(msg.offset()));
+    values.insert(
+        "timestamp",
+        FieldValueType::Timestamp(from_millis(timestamp as u64)),
+    );
+    values
+}
+
+/// The offset at which a bounded source stops reading a partition (exclusive). Stop offsets are
+/// stored in state so that they don't move if the source restarts. When stopping at a timestamp,
+/// partitions with no records at or after it stop at the high watermark, i.e., after the records
+/// that had been written when the source started.
+fn stop_offset(
+    stop_at: StopAt,
+    restored: Option<i64>,
+    timestamp_offset: Option<Offset>,
+    high: i64,
+) -> i64 {
+    match (restored, stop_at, timestamp_offset) {
+        (Some(stop), _, _) => stop,
+        (None, StopAt::Timestamp(_), Some(Offset::Offset(o))) => o,
+        (None, _, _) => high,
+    }
+}
+
+/// The first offset a partition will be read from, if known
+fn start_offset(start: Offset, low: i64, high: i64) -> Option<i64> {
+    match start {
+        Offset::Offset(o) => Some(o),
+        Offset::Beginning => Some(low),
+        Offset::End => Some(high),
+        _ => None,
+    }
+}
+
+/// Finds the first offset in each partition with a timestamp at or after `timestamp` (in millis);
+/// partitions with no such records resolve to [`Offset::End`]
+fn offsets_for_timestamp(
+    consumer: &StreamConsumer,
+    partitions: &[(String, i32)],
+    timestamp: i64,
+) -> anyhow::Result<HashMap<(String, i32), Offset>> {
+    let mut tpl = TopicPartitionList::new();
+    for (topic, partition) in partitions {
+        tpl.add_partition_offset(topic, *partition, Offset::Offset(timestamp))?;
+    }
+
+    let offsets = consumer.offsets_for_times(tpl, Duration::from_secs(30))?;
+
+    Ok(offsets
+        .elements()
+        .iter()
+        .map(|e| ((e.topic().to_string(), e.partition()), e.offset()))
+        .collect())
+}
+
 impl KafkaSourceFunc {
-    async fn get_consumer(&mut self, ctx: &mut ArrowContext) -> anyhow::Result<StreamConsumer> {
+    async fn get_consumer(
+        &mut self,
+        ctx: &mut ArrowContext,
+    ) -> anyhow::Result<(
+        StreamConsumer,
+        HashMap<(String, i32), Offset>,
+        Option<Bounds>,
+    )> {
         info!("Creating kafka consumer for {}", self.bootstrap_servers);
         let mut client_config = ClientConfig::new();
 
@@ -65,67 +291,209 @@ impl KafkaSourceFunc {
             )
             .create()?;
 
-        let state: Vec<_> = ctx
+        let mut state: HashMap<(String, i32), KafkaState> = ctx
             .table_manager
-            .get_global_keyed_state::<i32, KafkaState>("k")
+            .get_global_keyed_state::<(String, i32), KafkaState>(OFFSETS_TABLE)
             .await?
             .get_all()
             .values()
+            .map(|s| ((s.topic.clone(), s.partition), s.clone()))
             .collect();
 
-        // did we restore any partitions?
-        let has_state = !state.is_empty();
+        if state.is_empty() {
+            state = self.legacy_state(ctx).await?;
+        }
 
-        let state: HashMap<i32, KafkaState> = state.iter().map(|s| (s.partition, **s)).collect();
-        let metadata = consumer.fetch_metadata(Some(&self.topic), Duration::from_secs(30))?;
+        state.retain(|(topic, _), _| self.topics.matches(topic));
 
-        info!("Fetched metadata for topic {}", self.topic);
+        // did we restore any partitions?
+        let has_state = !state.is_empty();
 
-        let our_partitions: HashMap<_, _> = {
-            let partitions = metadata.topics()[0].partitions();
-            partitions
-                .iter()
-                .enumerate()
-                .filter(|(i, _)| i % ctx.task_info.parallelism == ctx.task_info.task_index)
-                .map(|(_, p)| {
-                    let offset = state
-                        .get(&p.id())
-                        .map(|s| Offset::Offset(s.offset))
-                        .unwrap_or_else(|| {
-                            if has_state {
-                                // if we've restored partitions and we don't know about this one, that means it's
-                                // new, and we want to start from the beginning so we don't drop data
-                                Offset::Beginning
-                            } else {
-                                self.offset_mode.get_offset()
-                            }
-                        });
+        let partitions = self.fetch_partitions(&consumer, ctx)?;
+
+        let mut our_partitions = HashMap::new();
+        let mut from_timestamp = vec![];
+        for (topic, partition) in &partitions {
+            let key = (topic.clone(), *partition);
+            let offset = if let Some(s) = state.get(&key) {
+                Offset::Offset(s.offset)
+            } else if has_state {
+                // if we've restored partitions and we don't know about this one, that means it's
+                // new, and we want to start from the beginning so we don't drop data
+                Offset::Beginning
+            } else if let Some(offset) = self.offset_mode.get_offset() {
+                offset
+            } else if self.offset_mode == super::SourceOffset::Specific {
+                self.start_offsets
+                    .get(&key)
+                    .map(|o| Offset::Offset(*o))
+                    .unwrap_or(Offset::Beginning)
+            } else {
+                from_timestamp.push(key);
+                continue;
+            };
+
+            our_partitions.insert(key, offset);
+        }
 
-                    ((self.topic.clone(), p.id()), offset)
-                })
-                .collect()
-        };
+        if !from_timestamp.is_empty() {
+            let timestamp = self
+                .start_timestamp
+                .ok_or_else(|| anyhow!("start_timestamp must be set when offset is 'timestamp'"))?;
+            our_partitions.extend(offsets_for_timestamp(
+                &consumer,
+                &from_timestamp,
+                timestamp,
+            )?);
+        }
 
         info!(
             "partition map for {}-{}: {:?}",
-            self.topic, ctx.task_info.task_index, our_partitions
+            self.name(),
+            ctx.task_info.task_index,
+            our_partitions
         );
 
         let topic_partitions = TopicPartitionList::from_topic_map(&our_partitions)?;
 
         consumer.assign(&topic_partitions)?;
 
-        Ok(consumer)
+        let bounds = self
+            .stop_at
+            .map(|stop_at| self.compute_bounds(&consumer, stop_at, &our_partitions, &state))
+            .transpose()?;
+
+        Ok((consumer, our_partitions, bounds))
+    }
+
+    /// Reads offsets from checkpoints taken before sources could read multiple topics, which only
+    /// recorded the partition; they belong to the single topic the source was configured with
+    async fn legacy_state(
+        &self,
+        ctx: &mut ArrowContext,
+    ) -> anyhow::Result<HashMap<(String, i32), KafkaState>> {
+        let legacy: Vec<LegacyKafkaState> = ctx
+            .table_manager
+            .get_global_keyed_state::<i32, LegacyKafkaState>(LEGACY_OFFSETS_TABLE)
+            .await?
+            .get_all()
+            .values()
+            .copied()
+            .collect();
+
+        if legacy.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let topic = self.topics.single_topic().ok_or_else(|| {
+            anyhow!(
+                "cannot restore Kafka offsets from a checkpoint taken by an older version of \
+                Arroyo, as it doesn't record which topic they belong to and the source now reads \
+                from multiple topics"
+            )
+        })?;
+
+        Ok(legacy
+            .into_iter()
+            .map(|s| {
+                (
+                    (topic.to_string(), s.partition),
+                    KafkaState {
+                        topic: topic.to_string(),
+                        partition: s.partition,
+                        offset: s.offset,
+                        stop_offset: None,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    fn compute_bounds(
+        &self,
+        consumer: &StreamConsumer,
+        stop_at: StopAt,
+        partitions: &HashMap<(String, i32), Offset>,
+        state: &HashMap<(String, i32), KafkaState>,
+    ) -> anyhow::Result<Bounds> {
+        let mut stop_offsets = HashMap::new();
+
+        let timestamp_offsets = match stop_at {
+            StopAt::Timestamp(timestamp) => {
+                let keys: Vec<_> = partitions.keys().cloned().collect();
+                offsets_for_timestamp(consumer, &keys, timestamp)?
+            }
+            StopAt::LatestOffsets => HashMap::new(),
+        };
+
+        let mut finished = HashSet::new();
+
+        for (key, start) in partitions {
+            let (low, high) = consumer.fetch_watermarks(&key.0, key.1, Duration::from_secs(30))?;
+
+            let stop = stop_offset(
+                stop_at,
+                state.get(key).and_then(|s| s.stop_offset),
+                timestamp_offsets.get(key).copied(),
+                high,
+            );
+
+            if start_offset(*start, low, high).is_some_and(|start| start >= stop) {
+                finished.insert(key.clone());
+            }
+
+            stop_offsets.insert(key.clone(), stop);
+        }
+
+        info!("Stop offsets for bounded Kafka source: {:?}", stop_offsets);
+
+        Ok(Bounds {
+            stop_at,
+            stop_offsets,
+            finished,
+        })
+    }
+
+    /// Returns the (topic, partition) pairs that are assigned to this subtask
+    fn fetch_partitions(
+        &self,
+        consumer: &StreamConsumer,
+        ctx: &ArrowContext,
+    ) -> anyhow::Result<Vec<(String, i32)>> {
+        let metadata = match &self.topics {
+            TopicSubscription::Topics(topics) if topics.len() == 1 => {
+                consumer.fetch_metadata(Some(&topics[0]), Duration::from_secs(30))?
+            }
+            _ => consumer.fetch_metadata(None, Duration::from_secs(30))?,
+        };
+
+        let mut partitions: Vec<_> = metadata
+            .topics()
+            .iter()
+            .filter(|t| self.topics.matches(t.name()) && t.error().is_none())
+            .flat_map(|t| {
+                t.partitions()
+                    .iter()
+                    .map(|p| (t.name().to_string(), p.id()))
+            })
+            .filter(|(topic, partition)| is_assigned(topic, *partition, ctx))
+            .collect();
+
+        partitions.sort();
+
+        debug!("Fetched metadata for topics {:?}", self.topics);
+
+        Ok(partitions)
     }
 
     async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
-        let consumer = self
+        let (consumer, mut assigned, mut bounds) = self
             .get_consumer(ctx)
             .await
             .map_err(|e| UserError::new("Could not create Kafka consumer", format!("{:?}", e)))?;
 
         let rate_limiter = GovernorRateLimiter::direct(Quota::per_second(self.messages_per_second));
-        let mut offsets = HashMap::new();
+        let mut offsets: HashMap<(String, i32), i64> = HashMap::new();
 
         if consumer.assignment().unwrap().count() == 0 {
             warn!("Kafka Consumer {}-{} is subscribed to no partitions, as there are more subtasks than partitions... setting idle",
@@ -140,12 +508,33 @@ impl KafkaSourceFunc {
             self.format.clone(),
             self.framing.clone(),
             self.bad_data.clone(),
+            &self.metadata_fields,
             self.schema_resolver.clone(),
         );
 
+        let include_headers = self.metadata_fields.iter().any(|f| f.key == "headers");
+
         let mut flush_ticker = tokio::time::interval(Duration::from_millis(50));
         flush_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
+        let mut discovery_ticker = tokio::time::interval(self.topic_discovery_interval);
+        discovery_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // the first tick completes immediately, and we've just fetched the partitions
+        discovery_ticker.tick().await;
+
+        // bounded sources read a fixed set of partitions, so don't need discovery
+        let discover_topics = bounds.is_none();
+
+        if let Some(bounds) = &bounds {
+            for key in &bounds.finished {
+                let mut tpl = TopicPartitionList::new();
+                tpl.add_partition(&key.0, key.1);
+                if let Err(e) = consumer.pause(&tpl) {
+                    warn!("Failed to pause finished Kafka partition: {:?}", e);
+                }
+            }
+        }
+
         loop {
             select! {
                 message = consumer.recv() => {
@@ -156,13 +545,23 @@ impl KafkaSourceFunc {
                                     .ok_or_else(|| UserError::new("Failed to read timestamp from Kafka record",
                                         "The message read from Kafka did not contain a message timestamp"))?;
 
-                                ctx.deserialize_slice(v, from_millis(timestamp as u64)).await?;
+                                if let Some(bounds) = &mut bounds {
+                                    if !bounds.check(&consumer, msg.topic(), msg.partition(), msg.offset(), timestamp) {
+                                        continue;
+                                    }
+                                }
+
+                                let headers = include_headers.then(|| headers_to_json(&msg));
+                                let additional_fields = (!self.metadata_fields.is_empty())
+                                    .then(|| metadata_values(&msg, timestamp, headers.as_deref()));
+
+                                ctx.deserialize_slice(v, from_millis(timestamp as u64), additional_fields.as_ref()).await?;
 
                                 if ctx.should_flush() {
                                     ctx.flush_buffer().await?;
                                 }
 
-                                offsets.insert(msg.partition(), msg.offset());
+                                offsets.insert((msg.topic().to_string(), msg.partition()), msg.offset());
                                 rate_limiter.until_ready().await;
                             }
                         },
@@ -175,21 +574,75 @@ impl KafkaSourceFunc {
                     if ctx.should_flush() {
                         ctx.flush_buffer().await?;
                     }
+
+                    if let Some(bounds) = &mut bounds {
+                        bounds.update_positions(&consumer);
+                        if bounds.is_done() {
+                            ctx.flush_buffer().await?;
+                            info!("Kafka source {}-{} has read all partitions to their stop offsets",
+                                ctx.task_info.operator_id, ctx.task_info.task_index);
+                            return Ok(SourceFinishType::Final);
+                        }
+                    }
+                }
+                _ = discovery_ticker.tick(), if discover_topics => {
+                    let partitions = match self.fetch_partitions(&consumer, ctx) {
+                        Ok(partitions) => partitions,
+                        Err(e) => {
+                            warn!("Failed to fetch Kafka metadata for topic discovery: {:?}", e);
+                            continue;
+                        }
+                    };
+
+                    let new_partitions: Vec<_> = partitions
+                        .into_iter()
+                        .filter(|p| !assigned.contains_key(p))
+                        .collect();
+
+                    if !new_partitions.is_empty() {
+                        info!("Discovered new Kafka partitions {:?}", new_partitions);
+
+                        // newly-created topics and partitions are read from the beginning so we
+                        // don't drop any data written before we discovered them
+                        assigned.extend(new_partitions.into_iter().map(|p| (p, Offset::Beginning)));
+
+                        // re-assigning resets the consumer's positions, so resume from where we
+                        // left off for partitions we've already read
+                        let positions: HashMap<_, _> = assigned
+                            .iter()
+                            .map(|(p, offset)| {
+                                let position = offsets
+                                    .get(p)
+                                    .map(|o| Offset::Offset(*o + 1))
+                                    .unwrap_or(*offset);
+                                (p.clone(), position)
+                            })
+                            .collect();
+
+                        let topic_partitions = TopicPartitionList::from_topic_map(&positions)
+                            .map_err(|e| UserError::new("Failed to assign Kafka partitions", e.to_string()))?;
+                        consumer.assign(&topic_partitions)
+                            .map_err(|e| UserError::new("Failed to assign Kafka partitions", e.to_string()))?;
+                    }
                 }
                 control_message = ctx.control_rx.recv() => {
                     match control_message {
                         Some(ControlMessage::Checkpoint(c)) => {
                             debug!("starting checkpointing {}", ctx.task_info.task_index);
                             let mut topic_partitions = TopicPartitionList::new();
-                            let s = ctx.table_manager.get_global_keyed_state("k").await
+                            let s = ctx.table_manager.get_global_keyed_state(OFFSETS_TABLE).await
                                 .map_err(|err| UserError::new("failed to get global key value", err.to_string()))?;
-                            for (partition, offset) in &offsets {
-                                s.insert(*partition, KafkaState {
+                            for ((topic, partition), offset) in &offsets {
+                                let stop_offset = bounds.as_ref()
+                                    .and_then(|b| b.stop_offsets.get(&(topic.clone(), *partition)).copied());
+                                s.insert((topic.clone(), *partition), KafkaState {
+                                    topic: topic.clone(),
                                     partition: *partition,
                                     offset: *offset + 1,
+                                    stop_offset,
                                 }).await;
                                 topic_partitions.add_partition_offset(
-                                    &self.topic, *partition, Offset::Offset(*offset)).unwrap();
+                                    topic, *partition, Offset::Offset(*offset)).unwrap();
                             }
 
                             if let Err(e) = consumer.commit(&topic_partitions, CommitMode::Async) {
@@ -252,10 +705,18 @@ impl SourceOperator for KafkaSourceFunc {
     }
 
     fn name(&self) -> String {
-        format!("kafka-{}", self.topic)
+        match &self.topics {
+            TopicSubscription::Topics(topics) => format!("kafka-{}", topics.join(",")),
+            TopicSubscription::Pattern(pattern) => format!("kafka-{}", pattern),
+        }
     }
 
     fn tables(&self) -> HashMap<String, TableConfig> {
-        arroyo_state::global_table_config("k", "kafka offsets")
+        let mut tables = arroyo_state::global_table_config(OFFSETS_TABLE, "kafka offsets");
+        tables.extend(arroyo_state::global_table_config(
+            LEGACY_OFFSETS_TABLE,
+            "kafka offsets by partition, from older checkpoints",
+        ));
+        tables
     }
 }
@@ -12,24 +12,28 @@ use std::num::NonZeroU32;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
-use crate::kafka::SourceOffset;
+use crate::kafka::{KafkaTable, SourceOffset, TopicSubscription};
 use arroyo_operator::context::{batch_bounded, ArrowContext, BatchReceiver};
 use arroyo_operator::operator::SourceOperator;
+use arroyo_operator::SourceFinishType;
 use arroyo_rpc::df::ArroyoSchema;
 use arroyo_rpc::formats::{Format, RawStringFormat};
 use arroyo_rpc::grpc::{CheckpointMetadata, OperatorCheckpointMetadata, OperatorMetadata};
 use arroyo_rpc::schema_resolver::FailingSchemaResolver;
 use arroyo_rpc::{CheckpointCompleted, ControlMessage, ControlResp};
 use arroyo_types::{
-    single_item_hash_map, to_micros, ArrowMessage, CheckpointBarrier, SignalMessage, TaskInfo,
+    single_item_hash_map, to_micros, to_millis, ArrowMessage, CheckpointBarrier, SignalMessage,
+    TaskInfo,
 };
 use rdkafka::admin::{AdminClient, AdminOptions, NewTopic};
-use rdkafka::producer::{BaseProducer, BaseRecord};
-use rdkafka::ClientConfig;
+use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
+use rdkafka::{ClientConfig, Offset};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::JoinHandle;
 
-use super::KafkaSourceFunc;
+use super::{start_offset, stop_offset, topic_hash, KafkaSourceFunc, StopAt, OFFSETS_TABLE};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct TestData {
@@ -43,6 +47,10 @@ pub struct KafkaTopicTester {
 }
 
 impl KafkaTopicTester {
+    fn subscription(&self) -> TopicSubscription {
+        TopicSubscription::Topics(vec![self.topic.clone()])
+    }
+
     async fn create_topic(&self) {
         let admin_client: AdminClient<_> = ClientConfig::new()
             .set("bootstrap.servers", self.server.to_string())
@@ -75,18 +83,34 @@ impl KafkaTopicTester {
         &self,
         task_info: TaskInfo,
         restore_from: Option<u32>,
+    ) -> KafkaSourceWithReads {
+        self.get_source_with_subscription(self.subscription(), task_info, restore_from, None)
+            .await
+    }
+
+    async fn get_source_with_subscription(
+        &self,
+        topics: TopicSubscription,
+        task_info: TaskInfo,
+        restore_from: Option<u32>,
+        stop_at: Option<StopAt>,
     ) -> KafkaSourceWithReads {
         let mut kafka = Box::new(KafkaSourceFunc {
             bootstrap_servers: self.server.clone(),
-            topic: self.topic.clone(),
+            topics,
+            topic_discovery_interval: Duration::from_secs(1),
             group_id: self.group_id.clone(),
             offset_mode: SourceOffset::Earliest,
+            start_timestamp: None,
+            start_offsets: HashMap::new(),
+            stop_at,
             format: Format::RawString(RawStringFormat {}),
             framing: None,
             bad_data: None,
             schema_resolver: Arc::new(FailingSchemaResolver::new()),
             client_configs: HashMap::new(),
             messages_per_second: NonZeroU32::new(100).unwrap(),
+            metadata_fields: vec![],
         });
 
         let (to_control_tx, control_rx) = channel(128);
@@ -107,7 +131,7 @@ impl KafkaTopicTester {
             checkpoint_metadata,
             control_rx,
             command_tx,
-            1,
+            vec![1],
             vec![],
             Some(ArroyoSchema::new_unkeyed(
                 Arc::new(Schema::new(vec![
@@ -126,13 +150,12 @@ impl KafkaTopicTester {
         )
         .await;
 
-        tokio::spawn(async move {
-            kafka.run(&mut ctx).await;
-        });
+        let task = tokio::spawn(async move { kafka.run(&mut ctx).await });
         KafkaSourceWithReads {
             to_control_tx,
             from_control_rx,
             data_recv: recv,
+            task,
         }
     }
 
@@ -164,12 +187,17 @@ impl KafkaTopicProducer {
             .send(BaseRecord::<(), String>::to(&self.topic).payload(&json))
             .expect("could not send message")
     }
+
+    fn flush(&self) {
+        self.base_producer.flush(Duration::from_secs(3)).unwrap();
+    }
 }
 
 struct KafkaSourceWithReads {
     to_control_tx: Sender<ControlMessage>,
     from_control_rx: Receiver<ControlResp>,
     data_recv: BatchReceiver,
+    task: JoinHandle<SourceFinishType>,
 }
 
 impl KafkaSourceWithReads {
@@ -201,6 +229,26 @@ impl KafkaSourceWithReads {
             }
         }
     }
+    async fn read_values(&mut self, count: usize) -> Vec<String> {
+        let mut values = vec![];
+        while values.len() < count {
+            match self.data_recv.recv().await {
+                Some(ArrowMessage::Data(record)) => {
+                    let a = record.columns()[1]
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .unwrap();
+                    values.extend(a.iter().map(|v| v.unwrap().to_string()));
+                }
+                Some(ArrowMessage::Signal(_)) => {}
+                None => {
+                    unreachable!("option shouldn't be missing")
+                }
+            }
+        }
+        values
+    }
+
     async fn assert_next_message_checkpoint(&mut self, expected_epoch: u32) {
         match self.data_recv.recv().await {
             Some(item) => {
@@ -265,6 +313,8 @@ async fn test_kafka() {
         min_epoch: 0,
         timestamp: (SystemTime::now()),
         then_stop: false,
+        drain: false,
+        unaligned: false,
     });
     reader.to_control_tx.send(barrier).await.unwrap();
     let checkpoint_completed = reader.assert_control_checkpoint(1).await;
@@ -273,10 +323,18 @@ async fn test_kafka() {
     reader.assert_next_message_checkpoint(1).await;
     let subtask_metadata = checkpoint_completed.subtask_metadata;
     let table_metadata = GlobalKeyedTable::merge_checkpoint_metadata(
-        subtask_metadata.table_configs.get("k").unwrap().clone(),
+        subtask_metadata
+            .table_configs
+            .get(OFFSETS_TABLE)
+            .unwrap()
+            .clone(),
         single_item_hash_map(
             0u32,
-            subtask_metadata.table_metadata.get("k").unwrap().clone(),
+            subtask_metadata
+                .table_metadata
+                .get(OFFSETS_TABLE)
+                .unwrap()
+                .clone(),
         ),
     )
     .unwrap()
@@ -285,7 +343,7 @@ async fn test_kafka() {
     StateBackend::write_operator_checkpoint_metadata(OperatorCheckpointMetadata {
         start_time: 0,
         finish_time: 0,
-        table_checkpoint_metadata: single_item_hash_map("k", table_metadata),
+        table_checkpoint_metadata: single_item_hash_map(OFFSETS_TABLE, table_metadata),
         table_configs: subtask_metadata.table_configs,
         operator_metadata: Some(OperatorMetadata {
             job_id: task_info.job_id.clone(),
@@ -342,3 +400,217 @@ async fn test_kafka() {
         )
         .await;
 }
+
+#[tokio::test]
+async fn test_kafka_topic_pattern() {
+    let mut first = KafkaTopicTester {
+        topic: "__arroyo-source-pattern-test-1".to_string(),
+        server: "0.0.0.0:9092".to_string(),
+        group_id: Some("test-consumer-group".to_string()),
+    };
+    let mut second = KafkaTopicTester {
+        topic: "__arroyo-source-pattern-test-2".to_string(),
+        server: "0.0.0.0:9092".to_string(),
+        group_id: Some("test-consumer-group".to_string()),
+    };
+
+    let mut task_info = arroyo_types::get_test_task_info();
+    task_info.job_id = format!("kafka-job-{}", random::<u64>());
+
+    first.create_topic().await;
+
+    let mut reader = first
+        .get_source_with_subscription(
+            TopicSubscription::Pattern(
+                Regex::new("^(?:__arroyo-source-pattern-test-.*)$").unwrap(),
+            ),
+            task_info,
+            None,
+            None,
+        )
+        .await;
+
+    let mut expected = vec![];
+    let mut producer = first.get_producer();
+    for i in 0u64..10 {
+        let data = TestData { i };
+        expected.push(serde_json::to_string(&data).unwrap());
+        producer.send_data(data);
+    }
+
+    // the second topic is created after the source has started, and should be picked up by
+    // topic discovery
+    second.create_topic().await;
+    let mut producer = second.get_producer();
+    for i in 10u64..20 {
+        let data = TestData { i };
+        expected.push(serde_json::to_string(&data).unwrap());
+        producer.send_data(data);
+    }
+
+    let mut values = reader.read_values(expected.len()).await;
+    values.sort_by_key(|v| serde_json::from_str::<TestData>(v).unwrap().i);
+
+    assert_eq!(expected, values);
+}
+
+#[test]
+fn test_topic_hash_is_stable() {
+    // partition assignments must agree across workers and versions, so the hash is pinned to the
+    // published FNV-1a test vectors
+    assert_eq!(topic_hash(""), 0xcbf29ce484222325);
+    assert_eq!(topic_hash("a"), 0xaf63dc4c8601ec8c);
+    assert_eq!(topic_hash("foobar"), 0x85944171f73967e8);
+}
+
+async fn test_bounded(topic: &str, stop_at: StopAt) {
+    let mut tester = KafkaTopicTester {
+        topic: topic.to_string(),
+        server: "0.0.0.0:9092".to_string(),
+        group_id: Some("test-consumer-group".to_string()),
+    };
+
+    let mut task_info = arroyo_types::get_test_task_info();
+    task_info.job_id = format!("kafka-job-{}", random::<u64>());
+
+    tester.create_topic().await;
+
+    let mut producer = tester.get_producer();
+    let mut expected = vec![];
+    for i in 0u64..10 {
+        let data = TestData { i };
+        expected.push(serde_json::to_string(&data).unwrap());
+        producer.send_data(data);
+    }
+    producer.flush();
+
+    let mut reader = tester
+        .get_source_with_subscription(tester.subscription(), task_info, None, Some(stop_at))
+        .await;
+
+    assert_eq!(reader.read_values(expected.len()).await, expected);
+
+    let finish = tokio::time::timeout(Duration::from_secs(10), reader.task)
+        .await
+        .expect("bounded source didn't finish")
+        .unwrap();
+    assert!(matches!(finish, SourceFinishType::Final));
+}
+
+#[tokio::test]
+async fn test_kafka_bounded_latest() {
+    test_bounded("__arroyo-source-bounded-latest-test", StopAt::LatestOffsets).await;
+}
+
+#[tokio::test]
+async fn test_kafka_bounded_timestamp_without_later_records() {
+    // no records have been written at or after the stop timestamp, so the source stops at the
+    // offsets that were current when it started
+    let stop = to_millis(SystemTime::now() + Duration::from_secs(3600)) as i64;
+    test_bounded(
+        "__arroyo-source-bounded-timestamp-test",
+        StopAt::Timestamp(stop),
+    )
+    .await;
+}
+
+#[test]
+fn test_stop_offset() {
+    // restored stop offsets always win, so that the end doesn't move on restart
+    assert_eq!(stop_offset(StopAt::LatestOffsets, Some(5), None, 10), 5);
+    assert_eq!(
+        stop_offset(StopAt::Timestamp(0), Some(5), Some(Offset::Offset(7)), 10),
+        5
+    );
+
+    assert_eq!(stop_offset(StopAt::LatestOffsets, None, None, 10), 10);
+    assert_eq!(
+        stop_offset(StopAt::Timestamp(0), None, Some(Offset::Offset(7)), 10),
+        7
+    );
+
+    // no record at or after the timestamp
+    assert_eq!(
+        stop_offset(StopAt::Timestamp(0), None, Some(Offset::End), 10),
+        10
+    );
+    assert_eq!(stop_offset(StopAt::Timestamp(0), None, None, 10), 10);
+}
+
+#[test]
+fn test_start_offset() {
+    assert_eq!(start_offset(Offset::Offset(3), 1, 10), Some(3));
+    assert_eq!(start_offset(Offset::Beginning, 1, 10), Some(1));
+    assert_eq!(start_offset(Offset::End, 1, 10), Some(10));
+    assert_eq!(start_offset(Offset::Stored, 1, 10), None);
+}
+
+fn source_table(topic: &str, source: serde_json::Value) -> KafkaTable {
+    serde_json::from_value(serde_json::json!({
+        "topic": topic,
+        "type": source,
+    }))
+    .unwrap()
+}
+
+fn specific_offsets(topic: &str, start_offsets: &str) -> KafkaTable {
+    source_table(
+        topic,
+        serde_json::json!({
+            "offset": "specific",
+            "start_offsets": start_offsets,
+        }),
+    )
+}
+
+#[test]
+fn test_start_offsets() {
+    let offsets = |pairs: &[(&str, i32, i64)]| -> HashMap<(String, i32), i64> {
+        pairs
+            .iter()
+            .map(|(topic, partition, offset)| ((topic.to_string(), *partition), *offset))
+            .collect()
+    };
+
+    // the topic may be omitted when reading from a single topic
+    assert_eq!(
+        specific_offsets("events", "0:10, 1:20,")
+            .start_offsets()
+            .unwrap(),
+        offsets(&[("events", 0, 10), ("events", 1, 20)])
+    );
+    assert_eq!(
+        specific_offsets("events", "events:2:5")
+            .start_offsets()
+            .unwrap(),
+        offsets(&[("events", 2, 5)])
+    );
+    assert_eq!(
+        specific_offsets("a, b", "a:0:5,b:3:7")
+            .start_offsets()
+            .unwrap(),
+        offsets(&[("a", 0, 5), ("b", 3, 7)])
+    );
+
+    assert!(specific_offsets("a,b", "0:5").start_offsets().is_err());
+    let pattern = source_table(
+        "events-.*",
+        serde_json::json!({
+            "offset": "specific",
+            "start_offsets": "0:5",
+            "topic_pattern": true,
+        }),
+    );
+    assert!(pattern.start_offsets().is_err());
+
+    for invalid in ["5", "x:5", "0:y", "a:0:5:1"] {
+        assert!(
+            specific_offsets("events", invalid).start_offsets().is_err(),
+            "{} should be invalid",
+            invalid
+        );
+    }
+
+    let earliest = source_table("events", serde_json::json!({ "offset": "earliest" }));
+    assert!(earliest.start_offsets().unwrap().is_empty());
+}
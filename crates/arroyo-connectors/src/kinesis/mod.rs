@@ -1,5 +1,6 @@
 use anyhow::{anyhow, bail, Result};
-use std::collections::HashMap;
+use arrow::datatypes::{DataType, TimeUnit};
+use std::collections::{HashMap, HashSet};
 use typify::import_types;
 
 use arroyo_formats::ser::ArrowSerializer;
@@ -12,12 +13,35 @@ use crate::{pull_opt, pull_option_to_i64, ConnectionSchema, ConnectionType, Empt
 
 use crate::kinesis::sink::{FlushConfig, KinesisSinkFunc};
 use crate::kinesis::source::KinesisSourceFunc;
-use arroyo_operator::connector::Connector;
+use arroyo_operator::connector::{Connector, MetadataDef};
 use arroyo_operator::operator::OperatorNode;
 
 const TABLE_SCHEMA: &str = include_str!("./table.json");
 const ICON: &str = include_str!("./kinesis.svg");
 
+const METADATA_DEFS: &[MetadataDef] = &[
+    MetadataDef {
+        name: "partition_key",
+        data_type: DataType::Utf8,
+    },
+    MetadataDef {
+        name: "sequence_number",
+        data_type: DataType::Utf8,
+    },
+    MetadataDef {
+        name: "shard_id",
+        data_type: DataType::Utf8,
+    },
+    MetadataDef {
+        name: "stream",
+        data_type: DataType::Utf8,
+    },
+    MetadataDef {
+        name: "timestamp",
+        data_type: DataType::Timestamp(TimeUnit::Nanosecond, None),
+    },
+];
+
 import_types!(schema = "src/kinesis/table.json");
 
 mod sink;
@@ -34,6 +58,10 @@ impl Connector for KinesisConnector {
         "kinesis"
     }
 
+    fn metadata_defs(&self) -> &'static [MetadataDef] {
+        METADATA_DEFS
+    }
+
     fn metadata(&self) -> api_types::connections::Connector {
         api_types::connections::Connector {
             id: "kinesis".to_string(),
@@ -84,6 +112,24 @@ impl Connector for KinesisConnector {
         table: Self::TableT,
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<arroyo_operator::connector::Connection> {
+        if let TableType::Source {
+            consumer_name: Some(consumer_name),
+            ..
+        } = &table.type_
+        {
+            if consumer_name.is_empty()
+                || consumer_name.len() > 128
+                || !consumer_name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+            {
+                bail!(
+                    "invalid consumer name '{}'; must be 1-128 characters of letters, numbers, '_', '.', and '-'",
+                    consumer_name
+                );
+            }
+        }
+
         let (connection_type, description) = match table.type_ {
             TableType::Source { .. } => (
                 ConnectionType::Source,
@@ -111,6 +157,7 @@ impl Connector for KinesisConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            metadata_fields: schema.metadata_fields.clone(),
         };
 
         Ok(Connection {
@@ -141,6 +188,7 @@ impl Connector for KinesisConnector {
                         None | Some("latest") => SourceOffset::Latest,
                         Some(other) => bail!("invalid value for source.offset '{}'", other),
                     },
+                    consumer_name: options.remove("source.consumer_name"),
                 }
             }
             "sink" => {
@@ -176,20 +224,27 @@ impl Connector for KinesisConnector {
         config: OperatorConfig,
     ) -> Result<OperatorNode> {
         match table.type_ {
-            TableType::Source { offset } => {
-                Ok(OperatorNode::from_source(Box::new(KinesisSourceFunc {
-                    stream_name: table.stream_name,
-                    kinesis_client: None,
-                    aws_region: table.aws_region,
-                    offset,
-                    shards: HashMap::new(),
-                    format: config
-                        .format
-                        .ok_or_else(|| anyhow!("format required for kinesis source"))?,
-                    framing: config.framing,
-                    bad_data: config.bad_data,
-                })))
-            }
+            TableType::Source {
+                offset,
+                consumer_name,
+            } => Ok(OperatorNode::from_source(Box::new(KinesisSourceFunc {
+                stream_name: table.stream_name,
+                kinesis_client: None,
+                aws_region: table.aws_region,
+                offset,
+                consumer_name,
+                consumer_arn: None,
+                shards: HashMap::new(),
+                lineage_roots: HashMap::new(),
+                closed_shards: HashSet::new(),
+                subscription_failures: HashMap::new(),
+                format: config
+                    .format
+                    .ok_or_else(|| anyhow!("format required for kinesis source"))?,
+                framing: config.framing,
+                bad_data: config.bad_data,
+                metadata_fields: config.metadata_fields,
+            }))),
             TableType::Sink {
                 batch_flush_interval_millis,
                 batch_max_buffer_size,
@@ -1,5 +1,5 @@
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     fmt::Debug,
     hash::{Hash, Hasher},
     pin::Pin,
@@ -7,9 +7,11 @@ use std::{
 };
 
 use anyhow::{anyhow, bail, Context as AnyhowContext, Result};
+use arroyo_formats::de::FieldValueType;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::SourceOperator;
 use arroyo_operator::SourceFinishType;
+use arroyo_rpc::api_types::connections::MetadataField;
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::{grpc::StopMode, ControlMessage};
@@ -20,8 +22,11 @@ use async_trait::async_trait;
 use aws_config::from_env;
 use aws_sdk_kinesis::{
     client::fluent_builders::GetShardIterator,
-    model::{Shard, ShardIteratorType},
-    output::GetRecordsOutput,
+    model::{
+        ConsumerStatus, Record, Shard, ShardIteratorType, StartingPosition, SubscribeToShardEvent,
+        SubscribeToShardEventStream,
+    },
+    output::{GetRecordsOutput, SubscribeToShardOutput},
     types::SdkError,
     Client as KinesisClient, Region,
 };
@@ -41,8 +46,19 @@ pub enum KinesisOffset {
     Latest,
     SequenceNumber(String),
     Timestamp(SystemTime),
+    // resume after the given sequence number, which may be a continuation sequence number
+    // from an enhanced fan-out subscription rather than that of a record
+    AfterSequenceNumber(String),
 }
 
+// the number of consecutive times we'll fail to subscribe to a shard before failing the source
+const MAX_SUBSCRIPTION_FAILURES: u32 = 10;
+
+// The state of every shard, keyed by shard id. Before sources tracked the lineage of shards it was
+// stored without it in the legacy table, which is still read when restoring older checkpoints.
+const SHARDS_TABLE: &str = "s";
+const LEGACY_SHARDS_TABLE: &str = "k";
+
 pub struct KinesisSourceFunc {
     pub stream_name: String,
     pub format: Format,
@@ -52,6 +68,14 @@ pub struct KinesisSourceFunc {
     pub aws_region: Option<String>,
     pub shards: HashMap<String, ShardState>,
     pub offset: SourceOffset,
+    pub consumer_name: Option<String>,
+    pub consumer_arn: Option<String>,
+    // the root of the lineage of every shard we've seen, including those read by other subtasks
+    pub lineage_roots: HashMap<String, String>,
+    // shards read by other subtasks that are known to have been read to the end
+    pub closed_shards: HashSet<String>,
+    pub subscription_failures: HashMap<String, u32>,
+    pub metadata_fields: Vec<MetadataField>,
 }
 
 #[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
@@ -60,43 +84,153 @@ pub struct ShardState {
     shard_id: String,
     offset: KinesisOffset,
     closed: bool,
+    // the shards this shard was split or merged from, which must be read to the end before this one
+    parent_shard_ids: Vec<String>,
+    // the shard at the root of this shard's lineage, which determines which subtask reads it; this
+    // keeps split shards on the same subtask as their parents
+    lineage_root: String,
 }
 
-impl TryFrom<(String, Shard, SourceOffset)> for ShardState {
-    type Error = anyhow::Error;
-    fn try_from(
-        (stream_name, shard, source_offset): (String, Shard, SourceOffset),
-    ) -> Result<Self> {
-        let shard_id = shard
-            .shard_id()
-            .map(|s| s.to_string())
-            .ok_or_else(|| anyhow!("missing_shard_id"))?;
-        let offset = match source_offset {
-            SourceOffset::Earliest => KinesisOffset::Earliest,
-            SourceOffset::Latest => KinesisOffset::Latest,
-        };
-        Ok(Self {
-            stream_name,
-            shard_id,
-            offset,
-            closed: false,
-        })
+#[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
+struct LegacyShardState {
+    stream_name: String,
+    shard_id: String,
+    offset: KinesisOffset,
+    closed: bool,
+}
+
+impl From<LegacyShardState> for ShardState {
+    // shards were assigned to subtasks by their own id, so each is the root of its own lineage;
+    // their parents have already been read
+    fn from(legacy: LegacyShardState) -> Self {
+        Self {
+            stream_name: legacy.stream_name,
+            lineage_root: legacy.shard_id.clone(),
+            shard_id: legacy.shard_id,
+            offset: legacy.offset,
+            closed: legacy.closed,
+            parent_shard_ids: vec![],
+        }
     }
 }
+
 type BoxedFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
 
+fn shard_parents(shard: &Shard) -> Vec<String> {
+    shard
+        .parent_shard_id()
+        .into_iter()
+        .chain(shard.adjacent_parent_shard_id())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Determines the lineage root of each listed shard that doesn't already have one, by following
+/// its parents back until reaching a shard with no known parent. Only the first parent is
+/// followed, so the two halves of a merge may be rooted at different shards.
+///
+/// Roots are never recomputed, as parents eventually expire from the stream and we don't want
+/// shards to move between subtasks once that happens.
+fn update_lineage_roots(
+    roots: &mut HashMap<String, String>,
+    listed: &HashMap<String, Option<String>>,
+) {
+    for shard_id in listed.keys() {
+        let mut current = shard_id;
+        let root = loop {
+            if let Some(root) = roots.get(current) {
+                break root.clone();
+            }
+
+            match listed.get(current).and_then(|p| p.as_ref()) {
+                Some(parent) if listed.contains_key(parent) || roots.contains_key(parent) => {
+                    current = parent;
+                }
+                _ => break current.clone(),
+            }
+        };
+
+        roots.insert(shard_id.clone(), root);
+    }
+}
+
+/// Whether a parent shard has been read to the end, so that its children can be read. Parents read
+/// by other subtasks (which can happen for the adjacent parent of a merge) hold up their children
+/// until they're known to be closed; parents we've never seen expired before we started reading.
+fn parent_finished(
+    parent: &str,
+    shards: &HashMap<String, ShardState>,
+    closed_shards: &HashSet<String>,
+    lineage_roots: &HashMap<String, String>,
+) -> bool {
+    match shards.get(parent) {
+        Some(state) => state.closed,
+        None => closed_shards.contains(parent) || !lineage_roots.contains_key(parent),
+    }
+}
+
+fn owns_lineage(lineage_root: &str, ctx: &ArrowContext) -> bool {
+    let mut hasher = DefaultHasher::new();
+    lineage_root.hash(&mut hasher);
+    let shard_hash = hasher.finish() as usize;
+    shard_hash % ctx.task_info.parallelism == ctx.task_info.task_index
+}
+
 impl ShardState {
-    fn new(stream_name: String, shard: Shard, source_offset: SourceOffset) -> Self {
+    fn new(stream_name: String, shard: Shard, offset: KinesisOffset, lineage_root: String) -> Self {
         Self {
             stream_name,
             shard_id: shard.shard_id().unwrap().to_string(),
-            offset: match source_offset {
-                SourceOffset::Earliest => KinesisOffset::Earliest,
-                SourceOffset::Latest => KinesisOffset::Latest,
-            },
+            offset,
             closed: false,
+            parent_shard_ids: shard_parents(&shard),
+            lineage_root,
         }
     }
+
+    fn starting_position(&self) -> StartingPosition {
+        let position = StartingPosition::builder();
+        match &self.offset {
+            KinesisOffset::Earliest => position.r#type(ShardIteratorType::TrimHorizon),
+            KinesisOffset::Latest => position.r#type(ShardIteratorType::Latest),
+            KinesisOffset::SequenceNumber(sequence_number) => position
+                .r#type(ShardIteratorType::AtSequenceNumber)
+                .sequence_number(sequence_number),
+            KinesisOffset::AfterSequenceNumber(sequence_number) => position
+                .r#type(ShardIteratorType::AfterSequenceNumber)
+                .sequence_number(sequence_number),
+            KinesisOffset::Timestamp(timestamp) => position
+                .r#type(ShardIteratorType::AtTimestamp)
+                .timestamp((*timestamp).into()),
+        }
+        .build()
+    }
+
+    fn get_subscribe_future(
+        &self,
+        kinesis_client: &KinesisClient,
+        consumer_arn: &str,
+        delay: Duration,
+    ) -> BoxedFuture<AsyncNamedResult<AsyncResult>> {
+        let subscribe_call = kinesis_client
+            .subscribe_to_shard()
+            .consumer_arn(consumer_arn)
+            .shard_id(&self.shard_id)
+            .starting_position(self.starting_position());
+
+        Box::pin(AsyncNamedResult::wrap_future(
+            self.shard_id.clone(),
+            async move {
+                // subscriptions are limited to one per second per shard
+                tokio::time::sleep(delay).await;
+                match subscribe_call.send().await {
+                    Ok(output) => Ok(next_subscription_event(ShardSubscription(output)).await),
+                    Err(e) => Ok(AsyncResult::SubscriptionFailed(e.to_string())),
+                }
+            },
+        ))
+    }
+
     fn get_update_shard_iterator_future(
         &self,
         kinesis_client: &KinesisClient,
@@ -115,6 +249,9 @@ impl ShardState {
             KinesisOffset::SequenceNumber(sequence_number) => shard_iterator_call
                 .shard_iterator_type(ShardIteratorType::AtSequenceNumber)
                 .starting_sequence_number(sequence_number.clone()),
+            KinesisOffset::AfterSequenceNumber(sequence_number) => shard_iterator_call
+                .shard_iterator_type(ShardIteratorType::AfterSequenceNumber)
+                .starting_sequence_number(sequence_number.clone()),
             KinesisOffset::Timestamp(timestamp) => shard_iterator_call
                 .shard_iterator_type(ShardIteratorType::AtTimestamp)
                 .timestamp((*timestamp).into()),
@@ -150,12 +287,43 @@ impl<T: Debug> AsyncNamedResult<T> {
     }
 }
 
+struct ShardSubscription(SubscribeToShardOutput);
+
+impl Debug for ShardSubscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ShardSubscription")
+    }
+}
+
+/// Waits for the next event on an enhanced fan-out subscription; `None` means that the
+/// subscription has expired (which happens every five minutes) and must be renewed
+async fn next_subscription_event(mut subscription: ShardSubscription) -> AsyncResult {
+    loop {
+        match subscription.0.event_stream.recv().await {
+            Ok(Some(SubscribeToShardEventStream::SubscribeToShardEvent(event))) => {
+                return AsyncResult::SubscriptionEvent(subscription, Some(event));
+            }
+            Ok(Some(_)) => {
+                // ignore event types we don't know about
+            }
+            Ok(None) => {
+                return AsyncResult::SubscriptionEvent(subscription, None);
+            }
+            Err(e) => {
+                return AsyncResult::SubscriptionFailed(e.to_string());
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 enum AsyncResult {
     // returns the new shard iterator id. Should always initialize a read after receiving this, if it is not None.
     ShardIteratorIdUpdate(Option<String>),
     GetRecords(GetRecordsOutput),
     NeedNewIterator,
+    SubscriptionEvent(ShardSubscription, Option<SubscribeToShardEvent>),
+    SubscriptionFailed(String),
 }
 
 #[async_trait]
@@ -165,7 +333,12 @@ impl SourceOperator for KinesisSourceFunc {
     }
 
     fn tables(&self) -> HashMap<String, TableConfig> {
-        global_table_config("k", "kinesis source state")
+        let mut tables = global_table_config(SHARDS_TABLE, "kinesis source state");
+        tables.extend(global_table_config(
+            LEGACY_SHARDS_TABLE,
+            "kinesis source state, from older checkpoints",
+        ));
+        tables
     }
 
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
@@ -173,6 +346,7 @@ impl SourceOperator for KinesisSourceFunc {
             self.format.clone(),
             self.framing.clone(),
             self.bad_data.clone(),
+            &self.metadata_fields,
         );
     }
 
@@ -187,48 +361,165 @@ impl SourceOperator for KinesisSourceFunc {
     }
 }
 
+type ShardFutures = Vec<BoxedFuture<AsyncNamedResult<AsyncResult>>>;
+
 impl KinesisSourceFunc {
     /// Initializes the shards for the operator. First shards are read out of state,
     /// then `sync_shards()` is called to find any new shards.
-    /// It returns a future for each shard to fetch the next shard iterator id.
-    async fn init_shards(
-        &mut self,
-        ctx: &mut ArrowContext,
-    ) -> anyhow::Result<Vec<BoxedFuture<AsyncNamedResult<AsyncResult>>>> {
-        let mut futures = Vec::new();
+    /// It returns a future for each shard that is ready to be read.
+    async fn init_shards(&mut self, ctx: &mut ArrowContext) -> anyhow::Result<ShardFutures> {
         let s: &mut GlobalKeyedView<String, ShardState> = ctx
             .table_manager
-            .get_global_keyed_state("k")
+            .get_global_keyed_state(SHARDS_TABLE)
             .await
             .expect("failed to get state for kinesis source");
-        for (shard_id, shard_state) in s
-            .get_all()
-            .values()
-            .map(|shard_state| (shard_state.shard_id.clone(), shard_state.clone()))
-            .filter(|(shard_id, _shard_state)| {
-                let mut hasher = DefaultHasher::new();
-                shard_id.hash(&mut hasher);
-                let shard_hash = hasher.finish() as usize;
-                shard_hash % ctx.task_info.parallelism == ctx.task_info.task_index
-            })
-        {
-            futures.push(
-                shard_state.get_update_shard_iterator_future(self.kinesis_client.as_ref().unwrap()),
+
+        let mut restored: Vec<ShardState> = s.get_all().values().cloned().collect();
+
+        if restored.is_empty() {
+            let legacy: &mut GlobalKeyedView<String, LegacyShardState> = ctx
+                .table_manager
+                .get_global_keyed_state(LEGACY_SHARDS_TABLE)
+                .await
+                .expect("failed to get legacy state for kinesis source");
+            restored = legacy.get_all().values().cloned().map(Into::into).collect();
+        }
+
+        for shard_state in restored {
+            // we record the lineage and progress of every shard so that new children are assigned
+            // consistently and read in order, but only read those that belong to this subtask
+            self.lineage_roots.insert(
+                shard_state.shard_id.clone(),
+                shard_state.lineage_root.clone(),
             );
-            self.shards.insert(shard_id, shard_state);
+
+            if shard_state.closed {
+                self.closed_shards.insert(shard_state.shard_id.clone());
+            }
+
+            if owns_lineage(&shard_state.lineage_root, ctx) {
+                self.shards
+                    .insert(shard_state.shard_id.clone(), shard_state);
+            }
         }
-        let new_futures = self.sync_shards(ctx).await?;
-        futures.extend(new_futures.into_iter());
+
+        let ready: Vec<_> = self
+            .shards
+            .values()
+            .filter(|shard| !shard.closed && self.is_ready(shard))
+            .map(|shard| shard.shard_id.clone())
+            .collect();
+
+        let mut futures: ShardFutures = ready
+            .iter()
+            .map(|shard_id| self.start_shard(shard_id, Duration::ZERO))
+            .collect();
+
+        futures.extend(self.sync_shards(ctx).await?);
 
         Ok(futures)
     }
 
+    /// A shard is ready to be read once all of its parents have been read to the end
+    fn is_ready(&self, shard: &ShardState) -> bool {
+        shard.parent_shard_ids.iter().all(|parent| {
+            parent_finished(
+                parent,
+                &self.shards,
+                &self.closed_shards,
+                &self.lineage_roots,
+            )
+        })
+    }
+
+    /// Shards that are waiting for parents read by other subtasks learn that those have been read
+    /// to the end from the previous checkpoint, which has the state of every subtask. Returns
+    /// futures for any that can now start.
+    async fn refresh_closed_shards(
+        &mut self,
+        ctx: &mut ArrowContext,
+        epoch: u32,
+    ) -> Result<ShardFutures> {
+        let waiting: Vec<_> = self
+            .shards
+            .values()
+            .filter(|shard| !shard.closed && !self.is_ready(shard))
+            .map(|shard| shard.shard_id.clone())
+            .collect();
+
+        if waiting.is_empty() || epoch <= 1 {
+            return Ok(vec![]);
+        }
+
+        let Some(state) = ctx
+            .table_manager
+            .read_global_keyed_state_at::<String, ShardState>(SHARDS_TABLE, epoch - 1)
+            .await?
+        else {
+            return Ok(vec![]);
+        };
+
+        self.closed_shards.extend(
+            state
+                .into_values()
+                .filter(|shard| shard.closed)
+                .map(|shard| shard.shard_id),
+        );
+
+        Ok(waiting
+            .iter()
+            .filter(|shard_id| self.is_ready(self.shards.get(*shard_id).unwrap()))
+            .map(|shard_id| {
+                debug!("starting shard {} after its parents were read", shard_id);
+                self.start_shard(shard_id, Duration::ZERO)
+            })
+            .collect())
+    }
+
+    fn start_shard(
+        &self,
+        shard_id: &str,
+        delay: Duration,
+    ) -> BoxedFuture<AsyncNamedResult<AsyncResult>> {
+        let shard_state = self.shards.get(shard_id).unwrap();
+        let client = self.kinesis_client.as_ref().unwrap();
+        match &self.consumer_arn {
+            Some(consumer_arn) => shard_state.get_subscribe_future(client, consumer_arn, delay),
+            None => shard_state.get_update_shard_iterator_future(client),
+        }
+    }
+
+    /// Marks a shard as fully read, returning futures for any of its children that can now start
+    fn finish_shard(&mut self, shard_id: &str) -> ShardFutures {
+        info!("finished reading shard {}", shard_id);
+        self.shards.get_mut(shard_id).unwrap().closed = true;
+
+        let ready: Vec<_> = self
+            .shards
+            .values()
+            .filter(|shard| {
+                !shard.closed
+                    && shard.parent_shard_ids.iter().any(|p| p == shard_id)
+                    && self.is_ready(shard)
+            })
+            .map(|shard| shard.shard_id.clone())
+            .collect();
+
+        ready
+            .iter()
+            .map(|child| {
+                debug!("starting child shard {} of {}", child, shard_id);
+                self.start_shard(child, Duration::ZERO)
+            })
+            .collect()
+    }
+
     async fn handle_async_result_split(
         &mut self,
         shard_id: String,
         async_result: AsyncResult,
         ctx: &mut ArrowContext,
-    ) -> Result<Option<BoxedFuture<AsyncNamedResult<AsyncResult>>>, UserError> {
+    ) -> Result<ShardFutures, UserError> {
         match async_result {
             AsyncResult::ShardIteratorIdUpdate(new_shard_iterator) => {
                 self.handle_shard_iterator_id_update(shard_id, new_shard_iterator)
@@ -238,6 +529,13 @@ impl KinesisSourceFunc {
                 self.handle_get_records(shard_id, get_records, ctx).await
             }
             AsyncResult::NeedNewIterator => self.handle_need_new_iterator(shard_id).await,
+            AsyncResult::SubscriptionEvent(subscription, event) => {
+                self.handle_subscription_event(shard_id, subscription, event, ctx)
+                    .await
+            }
+            AsyncResult::SubscriptionFailed(error) => {
+                self.handle_subscription_failed(shard_id, error)
+            }
         }
     }
 
@@ -245,14 +543,10 @@ impl KinesisSourceFunc {
         &mut self,
         shard_id: String,
         shard_iterator_id: Option<String>,
-    ) -> Result<Option<BoxedFuture<AsyncNamedResult<AsyncResult>>>, UserError> {
-        let shard_state = self.shards.get_mut(&shard_id).unwrap();
+    ) -> Result<ShardFutures, UserError> {
         match shard_iterator_id {
-            Some(shard_iterator) => Ok(Some(self.next_read_future(shard_id, shard_iterator))),
-            None => {
-                shard_state.closed = true;
-                Ok(None)
-            }
+            Some(shard_iterator) => Ok(vec![self.next_read_future(shard_id, shard_iterator)]),
+            None => Ok(self.finish_shard(&shard_id)),
         }
     }
 
@@ -309,36 +603,166 @@ impl KinesisSourceFunc {
         shard_id: String,
         get_records: GetRecordsOutput,
         ctx: &mut ArrowContext,
-    ) -> Result<Option<BoxedFuture<AsyncNamedResult<AsyncResult>>>, UserError> {
-        let last_sequence_number = get_records.records().and_then(|records| {
-            records
-                .last()
-                .map(|record| record.sequence_number().unwrap().to_owned())
-        });
-
-        let next_shard_iterator = self.process_records(get_records, ctx).await?;
+    ) -> Result<ShardFutures, UserError> {
+        let records = get_records.records.unwrap_or_default();
+        let last_sequence_number = records
+            .last()
+            .map(|record| record.sequence_number().unwrap().to_owned());
+
+        self.process_records(&shard_id, records, ctx).await?;
         let shard_state = self.shards.get_mut(&shard_id).unwrap();
 
         if let Some(last_sequence_number) = last_sequence_number {
-            shard_state.offset = KinesisOffset::SequenceNumber(last_sequence_number.to_string());
+            shard_state.offset = KinesisOffset::SequenceNumber(last_sequence_number);
         }
 
-        match next_shard_iterator {
-            Some(shard_iterator_id) => Ok(Some(self.next_read_future(shard_id, shard_iterator_id))),
-            None => {
-                shard_state.closed = true;
-                Ok(None)
-            }
+        match get_records.next_shard_iterator {
+            Some(shard_iterator_id) => Ok(vec![self.next_read_future(shard_id, shard_iterator_id)]),
+            None => Ok(self.finish_shard(&shard_id)),
         }
     }
+
     async fn handle_need_new_iterator(
         &mut self,
         shard_id: String,
-    ) -> Result<Option<BoxedFuture<AsyncNamedResult<AsyncResult>>>, UserError> {
+    ) -> Result<ShardFutures, UserError> {
         let shard_state = self.shards.get_mut(&shard_id).unwrap();
-        Ok(Some(shard_state.get_update_shard_iterator_future(
+        Ok(vec![shard_state.get_update_shard_iterator_future(
             self.kinesis_client.as_ref().unwrap(),
-        )))
+        )])
+    }
+
+    async fn handle_subscription_event(
+        &mut self,
+        shard_id: String,
+        subscription: ShardSubscription,
+        event: Option<SubscribeToShardEvent>,
+        ctx: &mut ArrowContext,
+    ) -> Result<ShardFutures, UserError> {
+        let Some(event) = event else {
+            // the subscription has expired; renew it from where we left off
+            return Ok(vec![self.start_shard(&shard_id, Duration::ZERO)]);
+        };
+
+        self.subscription_failures.remove(&shard_id);
+
+        self.process_records(&shard_id, event.records.unwrap_or_default(), ctx)
+            .await?;
+
+        match event.continuation_sequence_number {
+            Some(sequence_number) => {
+                self.shards.get_mut(&shard_id).unwrap().offset =
+                    KinesisOffset::AfterSequenceNumber(sequence_number);
+
+                Ok(vec![Box::pin(AsyncNamedResult::wrap_future(
+                    shard_id,
+                    async move { Ok(next_subscription_event(subscription).await) },
+                ))])
+            }
+            // the shard has been closed by a split or merge, and we've read all of its records
+            None => Ok(self.finish_shard(&shard_id)),
+        }
+    }
+
+    fn handle_subscription_failed(
+        &mut self,
+        shard_id: String,
+        error: String,
+    ) -> Result<ShardFutures, UserError> {
+        let failures = self
+            .subscription_failures
+            .entry(shard_id.clone())
+            .or_default();
+        *failures += 1;
+
+        if *failures > MAX_SUBSCRIPTION_FAILURES {
+            return Err(UserError::new(
+                "Failed to subscribe to Kinesis shard",
+                format!(
+                    "failed to subscribe to shard {} after {} attempts: {}",
+                    shard_id, MAX_SUBSCRIPTION_FAILURES, error
+                ),
+            ));
+        }
+
+        let delay = Duration::from_secs(1 << (*failures).min(5));
+        warn!(
+            "subscription to shard {} failed, resubscribing in {:?}: {}",
+            shard_id, delay, error
+        );
+
+        Ok(vec![self.start_shard(&shard_id, delay)])
+    }
+
+    /// Finds the ARN of the configured enhanced fan-out consumer, registering it if it does
+    /// not exist, and waits for it to become active
+    async fn init_consumer(&mut self, consumer_name: &str) -> Result<()> {
+        let client = self.kinesis_client.as_ref().unwrap();
+
+        let stream_arn = client
+            .describe_stream_summary()
+            .stream_name(&self.stream_name)
+            .send()
+            .await
+            .context("failed to describe stream")?
+            .stream_description_summary()
+            .and_then(|s| s.stream_arn())
+            .ok_or_else(|| anyhow!("no ARN for stream {}", self.stream_name))?
+            .to_string();
+
+        match client
+            .register_stream_consumer()
+            .stream_arn(&stream_arn)
+            .consumer_name(consumer_name)
+            .send()
+            .await
+        {
+            Ok(_) => {
+                info!("registered enhanced fan-out consumer {}", consumer_name);
+            }
+            Err(SdkError::ServiceError { err, .. }) if err.is_resource_in_use_exception() => {
+                // the consumer already exists, possibly registered by another subtask
+            }
+            Err(e) => {
+                return Err(anyhow!(e).context("failed to register stream consumer"));
+            }
+        }
+
+        loop {
+            let description = client
+                .describe_stream_consumer()
+                .stream_arn(&stream_arn)
+                .consumer_name(consumer_name)
+                .send()
+                .await
+                .context("failed to describe stream consumer")?;
+
+            let consumer = description
+                .consumer_description()
+                .ok_or_else(|| anyhow!("missing description for consumer {}", consumer_name))?;
+
+            match consumer.consumer_status() {
+                Some(ConsumerStatus::Active) => {
+                    self.consumer_arn = Some(
+                        consumer
+                            .consumer_arn()
+                            .ok_or_else(|| anyhow!("missing ARN for consumer {}", consumer_name))?
+                            .to_string(),
+                    );
+                    return Ok(());
+                }
+                Some(ConsumerStatus::Deleting) => {
+                    bail!("consumer {} is being deleted", consumer_name);
+                }
+                status => {
+                    debug!(
+                        "waiting for consumer {} to become active ({:?})",
+                        consumer_name, status
+                    );
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
     }
 
     async fn init_client(&mut self) {
@@ -358,6 +782,16 @@ impl KinesisSourceFunc {
     /// * Polling off of the control queue, to perform checkpointing and stop the operator.
     async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
         self.init_client().await;
+
+        if let Some(consumer_name) = self.consumer_name.clone() {
+            self.init_consumer(&consumer_name).await.map_err(|e| {
+                UserError::new(
+                    "failed to initialize enhanced fan-out consumer",
+                    format!("{:?}", e),
+                )
+            })?;
+        }
+
         let starting_futures = self
             .init_shards(ctx)
             .await
@@ -372,10 +806,8 @@ impl KinesisSourceFunc {
             select! {
                 result = futures.select_next_some() => {
                     let shard_id = result.name;
-                    if let Some(future) = self.handle_async_result_split(shard_id,
-                        result.result.map_err(|e| UserError::new("Fatal Kinesis error", e.to_string()))?, ctx).await? {
-                            futures.push(future);
-                        }
+                    futures.extend(self.handle_async_result_split(shard_id,
+                        result.result.map_err(|e| UserError::new("Fatal Kinesis error", e.to_string()))?, ctx).await?);
                 },
                 _ = shard_poll_interval.tick() => {
                     if ctx.should_flush() {
@@ -395,13 +827,17 @@ impl KinesisSourceFunc {
                     match control_message {
                         Some(ControlMessage::Checkpoint(c)) => {
                             debug!("starting checkpointing {}", ctx.task_info.task_index);
-                            let s = ctx.table_manager.get_global_keyed_state("k").await.unwrap();
+                            let s = ctx.table_manager.get_global_keyed_state(SHARDS_TABLE).await.unwrap();
                             for (shard_id, shard_state) in &self.shards {
                                 s.insert(shard_id.clone(), shard_state.clone()).await;
                             }
                             if self.start_checkpoint(c, ctx).await {
                                 return Ok(SourceFinishType::Immediate);
                             }
+                            match self.refresh_closed_shards(ctx, c.epoch).await {
+                                Ok(new_futures) => futures.extend(new_futures),
+                                Err(err) => warn!("failed to read the state of other subtasks' shards: {:?}", err),
+                            }
                         },
                         Some(ControlMessage::Stop { mode }) => {
                             info!("Stopping kinesis source: {:?}", mode);
@@ -432,49 +868,90 @@ impl KinesisSourceFunc {
 
     async fn process_records(
         &mut self,
-        get_records_output: GetRecordsOutput,
+        shard_id: &str,
+        records: Vec<Record>,
         ctx: &mut ArrowContext,
-    ) -> Result<Option<String>, UserError> {
-        let records = get_records_output.records.unwrap_or_default();
+    ) -> Result<(), UserError> {
         for record in records {
-            let data = record.data.unwrap().into_inner();
-            let timestamp = record.approximate_arrival_timestamp.unwrap();
+            let data = record.data().unwrap().as_ref();
+            let timestamp =
+                from_nanos(record.approximate_arrival_timestamp().unwrap().as_nanos() as u128);
+
+            let additional_fields = (!self.metadata_fields.is_empty()).then(|| {
+                let mut fields = HashMap::new();
+                if let Some(partition_key) = record.partition_key() {
+                    fields.insert("partition_key", FieldValueType::String(partition_key));
+                }
+                if let Some(sequence_number) = record.sequence_number() {
+                    fields.insert("sequence_number", FieldValueType::String(sequence_number));
+                }
+                fields.insert("shard_id", FieldValueType::String(shard_id));
+                fields.insert("stream", FieldValueType::String(&self.stream_name));
+                fields.insert("timestamp", FieldValueType::Timestamp(timestamp));
+                fields
+            });
 
-            ctx.deserialize_slice(&data, from_nanos(timestamp.as_nanos() as u128))
+            ctx.deserialize_slice(data, timestamp, additional_fields.as_ref())
                 .await?;
 
             if ctx.should_flush() {
                 ctx.flush_buffer().await?
             }
         }
-        Ok(get_records_output.next_shard_iterator)
+        Ok(())
     }
 
-    async fn sync_shards(
-        &mut self,
-        ctx: &mut ArrowContext,
-    ) -> Result<Vec<BoxedFuture<AsyncNamedResult<AsyncResult>>>> {
-        let mut futures = Vec::new();
-        for shard in self.get_splits().await? {
-            // check hash
+    /// Lists the shards in the stream, and starts reading any new ones that belong to this
+    /// subtask. Shards created by resharding are held until their parents have been read.
+    async fn sync_shards(&mut self, ctx: &mut ArrowContext) -> Result<ShardFutures> {
+        let listed = self.get_splits().await?;
+
+        // on the first sync of a fresh source all shards start at the configured offset; any
+        // that appear after that (including while we were stopped, if we've been restored from
+        // a checkpoint) are the result of resharding, and are read from the beginning so that
+        // we don't lose data
+        let initial_sync = self.lineage_roots.is_empty();
+
+        update_lineage_roots(
+            &mut self.lineage_roots,
+            &listed
+                .iter()
+                .map(|shard| {
+                    (
+                        shard.shard_id().unwrap().to_string(),
+                        shard.parent_shard_id().map(|s| s.to_string()),
+                    )
+                })
+                .collect(),
+        );
+
+        let mut new_shards = vec![];
+        for shard in listed {
             let shard_id = shard.shard_id().unwrap().to_string();
-            let mut hasher = DefaultHasher::new();
-            shard_id.hash(&mut hasher);
-            let shard_hash = hasher.finish() as usize;
+            let lineage_root = self.lineage_roots.get(&shard_id).unwrap().clone();
 
-            if self.shards.contains_key(&shard_id)
-                || shard_hash % ctx.task_info.parallelism != ctx.task_info.task_index
-            {
+            if self.shards.contains_key(&shard_id) || !owns_lineage(&lineage_root, ctx) {
                 continue;
             }
-            let shard_state = ShardState::new(self.stream_name.clone(), shard, self.offset);
 
-            futures.push(
-                shard_state.get_update_shard_iterator_future(self.kinesis_client.as_ref().unwrap()),
-            );
-            self.shards.insert(shard_id, shard_state);
+            let offset = match self.offset {
+                SourceOffset::Latest if initial_sync => KinesisOffset::Latest,
+                SourceOffset::Earliest | SourceOffset::Latest => KinesisOffset::Earliest,
+            };
+
+            let shard_state =
+                ShardState::new(self.stream_name.clone(), shard, offset, lineage_root);
+            self.shards.insert(shard_id.clone(), shard_state);
+            new_shards.push(shard_id);
         }
-        Ok(futures)
+
+        // shards are only started once all of them have been added, as a child may be listed
+        // before its parent
+        Ok(new_shards
+            .iter()
+            .filter(|shard_id| self.is_ready(self.shards.get(*shard_id).unwrap()))
+            .map(|shard_id| self.start_shard(shard_id, Duration::ZERO))
+            .collect())
     }
 
     async fn get_splits(&mut self) -> Result<Vec<Shard>> {
@@ -520,3 +997,208 @@ impl KinesisSourceFunc {
         Ok(shard_collect)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parent_finished, update_lineage_roots, KinesisOffset, LegacyShardState, ShardState,
+    };
+    use std::collections::{HashMap, HashSet};
+
+    fn listing(shards: &[(&str, Option<&str>)]) -> HashMap<String, Option<String>> {
+        shards
+            .iter()
+            .map(|(id, parent)| (id.to_string(), parent.map(|p| p.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn test_lineage_roots() {
+        let mut roots = HashMap::new();
+
+        // shard 0 has been split into 2 and 3, and 3 has been merged with 1 into 4
+        update_lineage_roots(
+            &mut roots,
+            &listing(&[
+                ("shard-4", Some("shard-3")),
+                ("shard-0", None),
+                ("shard-1", None),
+                ("shard-2", Some("shard-0")),
+                ("shard-3", Some("shard-0")),
+            ]),
+        );
+
+        assert_eq!(roots["shard-0"], "shard-0");
+        assert_eq!(roots["shard-1"], "shard-1");
+        assert_eq!(roots["shard-2"], "shard-0");
+        assert_eq!(roots["shard-3"], "shard-0");
+        assert_eq!(roots["shard-4"], "shard-0");
+
+        // once the parents have expired, new children still end up with the same root
+        update_lineage_roots(
+            &mut roots,
+            &listing(&[("shard-4", Some("shard-3")), ("shard-5", Some("shard-4"))]),
+        );
+
+        assert_eq!(roots["shard-4"], "shard-0");
+        assert_eq!(roots["shard-5"], "shard-0");
+
+        // shards whose parents we've never seen are their own roots
+        update_lineage_roots(&mut roots, &listing(&[("shard-7", Some("shard-6"))]));
+        assert_eq!(roots["shard-7"], "shard-7");
+    }
+
+    fn shard(id: &str, parents: &[&str], closed: bool) -> ShardState {
+        ShardState {
+            stream_name: "stream".to_string(),
+            shard_id: id.to_string(),
+            offset: KinesisOffset::Earliest,
+            closed,
+            parent_shard_ids: parents.iter().map(|p| p.to_string()).collect(),
+            lineage_root: id.to_string(),
+        }
+    }
+
+    fn ready(
+        child: &ShardState,
+        shards: &HashMap<String, ShardState>,
+        closed_shards: &HashSet<String>,
+        roots: &HashMap<String, String>,
+    ) -> bool {
+        child
+            .parent_shard_ids
+            .iter()
+            .all(|p| parent_finished(p, shards, closed_shards, roots))
+    }
+
+    #[test]
+    fn test_split_children_wait_for_parent() {
+        // shard 0 has been split into 1 and 2, all read by this subtask
+        let roots = listing(&[
+            ("shard-0", None),
+            ("shard-1", Some("shard-0")),
+            ("shard-2", Some("shard-0")),
+        ]);
+        let mut lineage_roots = HashMap::new();
+        update_lineage_roots(&mut lineage_roots, &roots);
+
+        let mut shards: HashMap<_, _> = [
+            shard("shard-0", &[], false),
+            shard("shard-1", &["shard-0"], false),
+            shard("shard-2", &["shard-0"], false),
+        ]
+        .into_iter()
+        .map(|s| (s.shard_id.clone(), s))
+        .collect();
+        let closed_shards = HashSet::new();
+
+        assert!(!ready(
+            &shards["shard-1"],
+            &shards,
+            &closed_shards,
+            &lineage_roots
+        ));
+        assert!(!ready(
+            &shards["shard-2"],
+            &shards,
+            &closed_shards,
+            &lineage_roots
+        ));
+
+        shards.get_mut("shard-0").unwrap().closed = true;
+
+        assert!(ready(
+            &shards["shard-1"],
+            &shards,
+            &closed_shards,
+            &lineage_roots
+        ));
+        assert!(ready(
+            &shards["shard-2"],
+            &shards,
+            &closed_shards,
+            &lineage_roots
+        ));
+    }
+
+    #[test]
+    fn test_merge_child_waits_for_parent_on_other_subtask() {
+        // shards 0 and 1 have been merged into 2; this subtask reads 0 and 2, while another reads 1
+        let mut lineage_roots = HashMap::new();
+        update_lineage_roots(
+            &mut lineage_roots,
+            &listing(&[
+                ("shard-0", None),
+                ("shard-1", None),
+                ("shard-2", Some("shard-0")),
+            ]),
+        );
+
+        let shards: HashMap<_, _> = [
+            shard("shard-0", &[], true),
+            shard("shard-2", &["shard-0", "shard-1"], false),
+        ]
+        .into_iter()
+        .map(|s| (s.shard_id.clone(), s))
+        .collect();
+        let mut closed_shards = HashSet::new();
+
+        // our parent is done, but the other subtask may still be reading shard 1
+        assert!(!ready(
+            &shards["shard-2"],
+            &shards,
+            &closed_shards,
+            &lineage_roots
+        ));
+
+        // until its state records that it has been read to the end
+        closed_shards.insert("shard-1".to_string());
+        assert!(ready(
+            &shards["shard-2"],
+            &shards,
+            &closed_shards,
+            &lineage_roots
+        ));
+    }
+
+    #[test]
+    fn test_expired_parents_do_not_block() {
+        let mut lineage_roots = HashMap::new();
+        update_lineage_roots(
+            &mut lineage_roots,
+            &listing(&[("shard-3", Some("shard-2"))]),
+        );
+
+        let shards: HashMap<_, _> = [shard("shard-3", &["shard-2", "shard-1"], false)]
+            .into_iter()
+            .map(|s| (s.shard_id.clone(), s))
+            .collect();
+
+        assert!(ready(
+            &shards["shard-3"],
+            &shards,
+            &HashSet::new(),
+            &lineage_roots
+        ));
+    }
+
+    #[test]
+    fn test_legacy_shard_state() {
+        let legacy = LegacyShardState {
+            stream_name: "stream".to_string(),
+            shard_id: "shard-1".to_string(),
+            offset: KinesisOffset::SequenceNumber("123".to_string()),
+            closed: false,
+        };
+
+        let state: ShardState = legacy.into();
+        assert_eq!(state.shard_id, "shard-1");
+        assert_eq!(state.lineage_root, "shard-1");
+        assert_eq!(
+            state.offset,
+            KinesisOffset::SequenceNumber("123".to_string())
+        );
+        assert!(state.parent_shard_ids.is_empty());
+        assert!(!state.closed);
+    }
+}
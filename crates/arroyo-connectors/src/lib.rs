@@ -1,6 +1,9 @@
 use crate::confluent::ConfluentConnector;
+use crate::datagen::DatagenConnector;
+use crate::elasticsearch::ElasticsearchConnector;
 use crate::filesystem::delta::DeltaLakeConnector;
 use crate::filesystem::FileSystemConnector;
+use crate::http_push::HttpPushConnector;
 use crate::kinesis::KinesisConnector;
 use crate::mqtt::MqttConnector;
 use crate::polling_http::PollingHTTPConnector;
@@ -35,8 +38,11 @@ use self::kafka::KafkaConnector;
 
 pub mod blackhole;
 pub mod confluent;
+pub mod datagen;
+pub mod elasticsearch;
 pub mod filesystem;
 pub mod fluvio;
+pub mod http_push;
 pub mod impulse;
 pub mod kafka;
 pub mod kinesis;
@@ -55,9 +61,12 @@ pub fn connectors() -> HashMap<&'static str, Box<dyn ErasedConnector>> {
     let connectors: Vec<Box<dyn ErasedConnector>> = vec![
         Box::new(BlackholeConnector {}),
         Box::new(ConfluentConnector {}),
+        Box::new(DatagenConnector {}),
         Box::new(DeltaLakeConnector {}),
+        Box::new(ElasticsearchConnector {}),
         Box::new(FileSystemConnector {}),
         Box::new(FluvioConnector {}),
+        Box::new(HttpPushConnector {}),
         Box::new(ImpulseConnector {}),
         Box::new(KafkaConnector {}),
         Box::new(KinesisConnector {}),
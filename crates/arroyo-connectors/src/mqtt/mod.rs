@@ -6,10 +6,11 @@ use std::time::Duration;
 
 use crate::mqtt::sink::MqttSinkFunc;
 use crate::mqtt::source::MqttSourceFunc;
-use crate::pull_opt;
+use crate::{pull_opt, pull_option_to_i64};
 use anyhow::{anyhow, bail};
+use arrow::datatypes::DataType;
 use arroyo_formats::ser::ArrowSerializer;
-use arroyo_operator::connector::{Connection, Connector};
+use arroyo_operator::connector::{Connection, Connector, MetadataDef};
 use arroyo_operator::operator::OperatorNode;
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
@@ -29,6 +30,14 @@ const CONFIG_SCHEMA: &str = include_str!("./profile.json");
 const TABLE_SCHEMA: &str = include_str!("./table.json");
 const ICON: &str = include_str!("./mqtt.svg");
 
+const METADATA_DEFS: &[MetadataDef] = &[MetadataDef {
+    name: "topic",
+    data_type: DataType::Utf8,
+}];
+
+// how long the broker keeps persistent sessions after we disconnect, if not configured
+const DEFAULT_SESSION_EXPIRY: Duration = Duration::from_secs(60 * 60);
+
 pub mod sink;
 pub mod source;
 
@@ -95,7 +104,18 @@ impl MqttConnector {
             .transpose()?;
 
         let table_type = match typ.as_str() {
-            "source" => TableType::Source {},
+            "source" => TableType::Source {
+                shared_subscription_group: options.remove("source.shared_subscription_group"),
+                persistent_session: options
+                    .remove("source.persistent_session")
+                    .map(|s| {
+                        s.parse::<bool>().map_err(|_| {
+                            anyhow!("'source.persistent_session' must be either 'true' or 'false'")
+                        })
+                    })
+                    .transpose()?,
+                session_expiry_secs: pull_option_to_i64("source.session_expiry_secs", options)?,
+            },
             "sink" => TableType::Sink {
                 retain: options
                     .remove("sink.retain")
@@ -127,6 +147,10 @@ impl Connector for MqttConnector {
         "mqtt"
     }
 
+    fn metadata_defs(&self) -> &'static [MetadataDef] {
+        METADATA_DEFS
+    }
+
     fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
         arroyo_rpc::api_types::connections::Connector {
             id: "mqtt".to_string(),
@@ -156,6 +180,26 @@ impl Connector for MqttConnector {
         table: MqttTable,
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<Connection> {
+        if let TableType::Source {
+            shared_subscription_group,
+            session_expiry_secs,
+            ..
+        } = &table.type_
+        {
+            if let Some(group) = shared_subscription_group {
+                if group.is_empty() || group.contains(|c| matches!(c, '/' | '+' | '#')) {
+                    bail!(
+                        "invalid shared subscription group '{}'; must be non-empty and cannot contain '/', '+', or '#'",
+                        group
+                    );
+                }
+            }
+
+            if session_expiry_secs.is_some_and(|s| s <= 0 || s > u32::MAX as i64) {
+                bail!("session_expiry_secs must be between 1 and {}", u32::MAX);
+            }
+        }
+
         let (typ, desc) = match table.type_ {
             TableType::Source { .. } => (
                 ConnectionType::Source,
@@ -181,6 +225,7 @@ impl Connector for MqttConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            metadata_fields: schema.metadata_fields.clone(),
         };
 
         Ok(Connection {
@@ -263,10 +308,21 @@ impl Connector for MqttConnector {
     ) -> anyhow::Result<OperatorNode> {
         let qos = table.qos();
         Ok(match table.type_ {
-            TableType::Source {} => OperatorNode::from_source(Box::new(MqttSourceFunc {
+            TableType::Source {
+                shared_subscription_group,
+                persistent_session,
+                session_expiry_secs,
+            } => OperatorNode::from_source(Box::new(MqttSourceFunc {
                 config: profile,
                 topic: table.topic,
                 qos,
+                shared_subscription_group,
+                session_expiry: persistent_session.unwrap_or(false).then(|| {
+                    session_expiry_secs
+                        .map(|s| Duration::from_secs(s as u64))
+                        .unwrap_or(DEFAULT_SESSION_EXPIRY)
+                }),
+                metadata_fields: config.metadata_fields,
                 format: config
                     .format
                     .ok_or_else(|| anyhow!("format is required for mqtt source"))?,
@@ -388,6 +444,7 @@ fn load_private_key(certificate: &str) -> anyhow::Result<PrivateKey> {
     Ok(PrivateKey(cert))
 }
 
+/// Creates a connection with a clean session and a unique client id
 pub(crate) fn create_connection(
     c: &MqttConfig,
     task_id: usize,
@@ -406,9 +463,28 @@ pub(crate) fn create_connection(
             % 100000,
     );
 
+    Ok(AsyncClient::new(mqtt_options(c, &client_id)?, 100))
+}
+
+/// Creates a connection that resumes the broker's session for `client_id` if one exists, and
+/// asks the broker to keep the session for `session_expiry` after we disconnect, so that messages
+/// for our subscriptions are queued while we're restarting
+pub(crate) fn create_persistent_connection(
+    c: &MqttConfig,
+    client_id: &str,
+    session_expiry: Duration,
+) -> anyhow::Result<(AsyncClient, EventLoop)> {
+    let mut options = mqtt_options(c, client_id)?;
+    options.set_clean_start(false);
+    options.set_session_expiry_interval(Some(session_expiry.as_secs() as u32));
+
+    Ok(AsyncClient::new(options, 100))
+}
+
+fn mqtt_options(c: &MqttConfig, client_id: &str) -> anyhow::Result<MqttOptions> {
     let mut url = url::Url::parse(&c.url)?;
     let ssl = matches!(url.scheme(), "mqtts" | "ssl");
-    url.query_pairs_mut().append_pair("client_id", &client_id);
+    url.query_pairs_mut().append_pair("client_id", client_id);
 
     let mut options = MqttOptions::try_from(url)?;
 
@@ -465,5 +541,5 @@ pub(crate) fn create_connection(
         );
     }
 
-    Ok(AsyncClient::new(options, 100))
+    Ok(options)
 }
@@ -85,7 +85,7 @@ impl MqttTopicTester {
             None,
             control_rx,
             command_tx,
-            1,
+            vec![1],
             vec![ArroyoSchema::new_unkeyed(schema(), 0)],
             None,
             None,
@@ -5,6 +5,8 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
+use arroyo_formats::de::FieldValueType;
+use arroyo_rpc::api_types::connections::MetadataField;
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::{grpc::StopMode, ControlMessage, ControlResp};
 use arroyo_types::{ArrowMessage, SignalMessage, UserError, Watermark};
@@ -13,7 +15,7 @@ use rumqttc::v5::mqttbytes::QoS;
 use rumqttc::v5::{ConnectionError, Event as MqttEvent, Incoming};
 use rumqttc::Outgoing;
 
-use crate::mqtt::{create_connection, MqttConfig};
+use crate::mqtt::{create_connection, create_persistent_connection, MqttConfig};
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::SourceOperator;
 use arroyo_operator::SourceFinishType;
@@ -28,6 +30,10 @@ pub struct MqttSourceFunc {
     pub config: MqttConfig,
     pub topic: String,
     pub qos: QoS,
+    pub shared_subscription_group: Option<String>,
+    // if set, we use a persistent session that the broker keeps for this long after we disconnect
+    pub session_expiry: Option<Duration>,
+    pub metadata_fields: Vec<MetadataField>,
     pub format: Format,
     pub framing: Option<Framing>,
     pub bad_data: Option<BadData>,
@@ -79,6 +85,9 @@ impl MqttSourceFunc {
             config,
             topic,
             qos,
+            shared_subscription_group: None,
+            session_expiry: None,
+            metadata_fields: vec![],
             format,
             framing,
             bad_data,
@@ -91,16 +100,63 @@ impl MqttSourceFunc {
         self.subscribed.clone()
     }
 
+    /// The topic filter to subscribe to; with a shared subscription the broker distributes
+    /// messages across all of the subtasks in the group
+    fn subscription_topic(&self) -> String {
+        match &self.shared_subscription_group {
+            Some(group) => format!("$share/{}/{}", group, self.topic),
+            None => self.topic.clone(),
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut ArrowContext,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                tracing::debug!("starting checkpointing {}", ctx.task_info.task_index);
+                if self.start_checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                tracing::info!("Stopping Mqtt source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+
     async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
         ctx.initialize_deserializer(
             self.format.clone(),
             self.framing.clone(),
             self.bad_data.clone(),
+            &self.metadata_fields,
         );
 
-        if ctx.task_info.task_index > 0 {
+        // without a shared subscription every subscriber receives every message, so only
+        // the first subtask can read
+        if ctx.task_info.task_index > 0 && self.shared_subscription_group.is_none() {
             tracing::warn!(
-                "Mqtt Consumer {}-{} can only be executed on a single worker... setting idle",
+                "Mqtt Consumer {}-{} can only be executed on a single worker without a shared subscription... setting idle",
                 ctx.task_info.operator_id,
                 ctx.task_info.task_index
             );
@@ -108,20 +164,47 @@ impl MqttSourceFunc {
                 Watermark::Idle,
             )))
             .await;
-        }
 
-        let (client, mut eventloop) =
-            match create_connection(&self.config, ctx.task_info.task_index) {
-                Ok(c) => c,
-                Err(e) => {
-                    return Err(UserError {
-                        name: "MqttSourceError".to_string(),
-                        details: format!("Failed to create connection: {}", e),
-                    });
+            loop {
+                let msg = ctx.control_rx.recv().await;
+                if let Some(r) = self.handle_control_message(ctx, msg).await {
+                    return Ok(r);
                 }
-            };
+            }
+        }
 
-        match client.subscribe(self.topic.clone(), self.qos).await {
+        let connection = match self.session_expiry {
+            Some(session_expiry) => {
+                // the client id must be stable across restarts for the broker to resume our session
+                let client_id = format!(
+                    "{}_{}_{}_{}",
+                    self.config
+                        .client_prefix
+                        .as_deref()
+                        .unwrap_or("arroyo-mqtt"),
+                    ctx.task_info.job_id,
+                    ctx.task_info.operator_id,
+                    ctx.task_info.task_index
+                );
+                create_persistent_connection(&self.config, &client_id, session_expiry)
+            }
+            None => create_connection(&self.config, ctx.task_info.task_index),
+        };
+
+        let (client, mut eventloop) = match connection {
+            Ok(c) => c,
+            Err(e) => {
+                return Err(UserError {
+                    name: "MqttSourceError".to_string(),
+                    details: format!("Failed to create connection: {}", e),
+                });
+            }
+        };
+
+        let topic = self.subscription_topic();
+        let qos = self.qos;
+
+        match client.subscribe(topic.clone(), qos).await {
             Ok(_) => (),
             Err(e) => {
                 return Err(UserError {
@@ -133,8 +216,6 @@ impl MqttSourceFunc {
 
         let rate_limiter = GovernorRateLimiter::direct(Quota::per_second(self.messages_per_second));
 
-        let topic = self.topic.clone();
-        let qos = self.qos;
         let mut flush_ticker = tokio::time::interval(Duration::from_millis(50));
         flush_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
@@ -143,7 +224,16 @@ impl MqttSourceFunc {
                 event = eventloop.poll() => {
                     match event {
                         Ok(MqttEvent::Incoming(Incoming::Publish(p))) => {
-                            ctx.deserialize_slice(&p.payload, SystemTime::now()).await?;
+                            let additional_fields = (!self.metadata_fields.is_empty()).then(|| {
+                                let mut fields = HashMap::new();
+                                // for wildcard subscriptions, this is the concrete topic the message was published to
+                                if let Ok(topic) = std::str::from_utf8(&p.topic) {
+                                    fields.insert("topic", FieldValueType::String(topic));
+                                }
+                                fields
+                            });
+
+                            ctx.deserialize_slice(&p.payload, SystemTime::now(), additional_fields.as_ref()).await?;
                             rate_limiter.until_ready().await;
                         }
                         Ok(MqttEvent::Outgoing(Outgoing::Subscribe(_))) => {
@@ -175,35 +265,8 @@ impl MqttSourceFunc {
                     }
                 }
                 control_message = ctx.control_rx.recv() => {
-                    match control_message {
-                        Some(ControlMessage::Checkpoint(c)) => {
-                            tracing::debug!("starting checkpointing {}", ctx.task_info.task_index);
-                            if self.start_checkpoint(c, ctx).await {
-                                return Ok(SourceFinishType::Immediate);
-                            }
-                        },
-                        Some(ControlMessage::Stop { mode }) => {
-                            tracing::info!("Stopping Mqtt source: {:?}", mode);
-
-                            match mode {
-                                StopMode::Graceful => {
-                                    return Ok(SourceFinishType::Graceful);
-                                }
-                                StopMode::Immediate => {
-                                    return Ok(SourceFinishType::Immediate);
-                                }
-                            }
-                        }
-                        Some(ControlMessage::Commit { .. }) => {
-                            unreachable!("sources shouldn't receive commit messages");
-                        }
-                        Some(ControlMessage::LoadCompacted {compacted}) => {
-                            ctx.load_compacted(compacted).await;
-                        }
-                        Some(ControlMessage::NoOp) => {}
-                        None => {
-
-                        }
+                    if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                        return Ok(r);
                     }
                 }
             }
@@ -43,6 +43,21 @@ impl MqttSourceWithReads {
         }
     }
 
+    async fn read_values(&mut self, values: &mut Vec<u64>) {
+        while let Ok(Some(item)) =
+            tokio::time::timeout(std::time::Duration::from_millis(500), self.data_recv.recv()).await
+        {
+            if let ArrowMessage::Data(record) = item {
+                let a = record.columns()[1]
+                    .as_any()
+                    .downcast_ref::<UInt64Array>()
+                    .unwrap();
+
+                values.extend(a.iter().map(|v| v.unwrap()));
+            }
+        }
+    }
+
     async fn assert_next_message_record_value(&mut self, mut expected_values: VecDeque<u64>) {
         match self.data_recv.recv().await {
             Some(item) => {
@@ -114,7 +129,11 @@ impl MqttTopicTester {
         client
     }
 
-    async fn get_source_with_reader(&self, task_info: TaskInfo) -> MqttSourceWithReads {
+    async fn get_source_with_reader(
+        &self,
+        task_info: TaskInfo,
+        shared_subscription_group: Option<String>,
+    ) -> MqttSourceWithReads {
         let config = self.get_config();
 
         let mut mqtt = MqttSourceFunc::new(
@@ -126,6 +145,7 @@ impl MqttTopicTester {
             None,
             10,
         );
+        mqtt.shared_subscription_group = shared_subscription_group;
 
         let (to_control_tx, control_rx) = channel(128);
         let (command_tx, from_control_rx) = channel(128);
@@ -136,7 +156,7 @@ impl MqttTopicTester {
             None,
             control_rx,
             command_tx,
-            1,
+            vec![1],
             vec![],
             Some(ArroyoSchema::new_unkeyed(
                 Arc::new(Schema::new(vec![
@@ -185,7 +205,9 @@ async fn test_mqtt() {
     let mut task_info = arroyo_types::get_test_task_info();
     task_info.job_id = format!("mqtt-job-{}", random::<u64>());
 
-    let mut reader = mqtt_tester.get_source_with_reader(task_info.clone()).await;
+    let mut reader = mqtt_tester
+        .get_source_with_reader(task_info.clone(), None)
+        .await;
 
     reader
         .wait_for_subscription(std::time::Duration::from_secs(5))
@@ -220,3 +242,66 @@ async fn test_mqtt() {
         .await
         .unwrap();
 }
+
+#[tokio::test]
+async fn test_mqtt_shared_subscription() {
+    let mqtt_tester = MqttTopicTester {
+        topic: "mqtt-arroyo-shared-test".to_string(),
+        port: 1883,
+        ca: None,
+        cert: None,
+        key: None,
+        username: None,
+        password: None,
+    };
+
+    let mut task_info = arroyo_types::get_test_task_info();
+    task_info.job_id = format!("mqtt-job-{}", random::<u64>());
+    task_info.parallelism = 2;
+
+    let group = format!("arroyo-{}", random::<u32>());
+    let mut readers = vec![];
+    for task_index in 0..2 {
+        let mut task_info = task_info.clone();
+        task_info.task_index = task_index;
+        let reader = mqtt_tester
+            .get_source_with_reader(task_info, Some(group.clone()))
+            .await;
+        reader
+            .wait_for_subscription(std::time::Duration::from_secs(5))
+            .await;
+        readers.push(reader);
+    }
+
+    let client = mqtt_tester.get_client().await;
+
+    for message in 1u64..20 {
+        client
+            .publish(
+                &mqtt_tester.topic,
+                QoS::AtLeastOnce,
+                false,
+                serde_json::to_vec(&TestData { value: message }).unwrap(),
+            )
+            .await
+            .expect("Failed to publish message");
+    }
+
+    // each message should be delivered to exactly one member of the group
+    let mut values = vec![];
+    for reader in &mut readers {
+        reader.read_values(&mut values).await;
+    }
+    values.sort();
+    assert_eq!(values, (1u64..20).collect::<Vec<_>>());
+
+    for reader in readers {
+        reader
+            .to_control_tx
+            .send(ControlMessage::Stop {
+                mode: arroyo_rpc::grpc::StopMode::Graceful,
+            })
+            .await
+            .unwrap();
+    }
+}
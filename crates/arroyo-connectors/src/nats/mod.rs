@@ -1,4 +1,4 @@
-use crate::nats::sink::NatsSinkFunc;
+use crate::nats::sink::{NatsSinkFunc, SubjectTemplate};
 use crate::nats::source::NatsSourceFunc;
 use crate::pull_opt;
 use anyhow::anyhow;
@@ -152,7 +152,17 @@ impl NatsConnector {
                 ConnectorType::Source { source_type }
             }
             "sink" => {
+                let jetstream = options
+                    .remove("jetstream")
+                    .map(|s| {
+                        s.parse::<bool>()
+                            .map_err(|_| anyhow!("'jetstream' must be either 'true' or 'false'"))
+                    })
+                    .transpose()?
+                    .unwrap_or(false);
+
                 let sink_type = match pull_opt("subject", options).ok() {
+                    Some(subject) if jetstream => Some(SinkType::JetstreamSubject(subject)),
                     Some(subject) => Some(SinkType::Subject(subject)),
                     None => bail!("`subject` must be set for sink"),
                 };
@@ -262,7 +272,7 @@ impl Connector for NatsConnector {
                     .as_ref()
                     .ok_or_else(|| anyhow!("sinkType is required"))?
                 {
-                    SinkType::Subject(s) => s,
+                    SinkType::Subject(s) | SinkType::JetstreamSubject(s) => s,
                 }
             }
         };
@@ -288,6 +298,21 @@ impl Connector for NatsConnector {
             .map(|t| t.to_owned())
             .ok_or_else(|| anyhow!("'format' must be set for NATS connection"))?;
 
+        if let ConnectorType::Sink { .. } = &table.connector_type {
+            let columns = SubjectTemplate::columns(stream_or_subject)?;
+            if !schema.fields.is_empty() {
+                for column in columns {
+                    if !schema.fields.iter().any(|f| f.field_name == column) {
+                        bail!(
+                            "subject '{}' references column '{}', which is not in the schema",
+                            stream_or_subject,
+                            column
+                        );
+                    }
+                }
+            }
+        }
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
@@ -295,6 +320,7 @@ impl Connector for NatsConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            metadata_fields: vec![],
         };
 
         Ok(Connection {
@@ -372,6 +398,11 @@ impl Connector for NatsConnector {
                     connection: profile.clone(),
                     table: table.clone(),
                     publisher: None,
+                    jetstream: None,
+                    subject_template: None,
+                    epoch: 1,
+                    sequence: 0,
+                    pending_acks: vec![],
                     serializer: ArrowSerializer::new(
                         config.format.expect("Format must be set for NATS source"),
                     ),
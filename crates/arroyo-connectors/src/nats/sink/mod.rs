@@ -1,7 +1,10 @@
 use super::NatsConfig;
 use super::NatsTable;
 use super::{get_nats_client, SinkType};
-use arrow::array::RecordBatch;
+use anyhow::{anyhow, bail};
+use arrow::array::{AsArray, RecordBatch};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Schema};
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::ArrowOperator;
@@ -9,48 +12,225 @@ use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::ControlMessage;
 use arroyo_rpc::ControlResp;
 use arroyo_types::*;
+use async_nats::jetstream::context::PublishAckFuture;
 use async_trait::async_trait;
 use std::collections::HashMap;
 use tracing::warn;
 
+// the number of JetStream publishes that may be awaiting acks before we wait for them
+const MAX_PENDING_ACKS: usize = 10_000;
+
+enum SubjectPart {
+    Literal(String),
+    Column(String),
+}
+
+/// A subject that may reference columns of the row being published, like `orders.{region}`
+pub struct SubjectTemplate {
+    parts: Vec<SubjectPart>,
+    // indices of the referenced columns in the input schema, in the order of the parts
+    indices: Vec<usize>,
+}
+
+impl SubjectTemplate {
+    fn parse(subject: &str) -> anyhow::Result<Vec<SubjectPart>> {
+        let mut parts = vec![];
+        let mut rest = subject;
+
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                bail!("unclosed '{{' in subject '{}'", subject);
+            };
+
+            if start > 0 {
+                parts.push(SubjectPart::Literal(rest[..start].to_string()));
+            }
+
+            let column = rest[start + 1..start + end].trim();
+            if column.is_empty() {
+                bail!("empty column reference in subject '{}'", subject);
+            }
+            parts.push(SubjectPart::Column(column.to_string()));
+
+            rest = &rest[start + end + 1..];
+        }
+
+        if rest.contains('}') {
+            bail!("unmatched '}}' in subject '{}'", subject);
+        }
+
+        if !rest.is_empty() {
+            parts.push(SubjectPart::Literal(rest.to_string()));
+        }
+
+        Ok(parts)
+    }
+
+    /// Returns the columns referenced by the subject
+    pub fn columns(subject: &str) -> anyhow::Result<Vec<String>> {
+        Ok(Self::parse(subject)?
+            .into_iter()
+            .filter_map(|part| match part {
+                SubjectPart::Column(c) => Some(c),
+                SubjectPart::Literal(_) => None,
+            })
+            .collect())
+    }
+
+    pub fn new(subject: &str, schema: &Schema) -> anyhow::Result<Self> {
+        let parts = Self::parse(subject)?;
+
+        let indices = parts
+            .iter()
+            .filter_map(|part| match part {
+                SubjectPart::Column(c) => Some(
+                    schema
+                        .index_of(c)
+                        .map_err(|_| anyhow!("subject references unknown column '{}'", c)),
+                ),
+                SubjectPart::Literal(_) => None,
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { parts, indices })
+    }
+
+    fn is_static(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Renders the subject for each row of the batch; rows where a referenced column is null
+    /// have no subject
+    fn render(&self, batch: &RecordBatch) -> anyhow::Result<Vec<Option<String>>> {
+        let columns = self
+            .indices
+            .iter()
+            .map(|i| cast(batch.column(*i), &DataType::Utf8))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((0..batch.num_rows())
+            .map(|row| {
+                let mut subject = String::new();
+                let mut columns = columns.iter();
+                for part in &self.parts {
+                    match part {
+                        SubjectPart::Literal(s) => subject.push_str(s),
+                        SubjectPart::Column(_) => {
+                            let column = columns.next().unwrap().as_string::<i32>();
+                            if column.is_null(row) {
+                                return None;
+                            }
+                            subject.push_str(column.value(row));
+                        }
+                    }
+                }
+                Some(subject)
+            })
+            .collect())
+    }
+}
+
 pub struct NatsSinkFunc {
     pub sink_type: SinkType,
     pub servers: String,
     pub connection: NatsConfig,
     pub table: NatsTable,
     pub publisher: Option<async_nats::Client>,
+    pub jetstream: Option<async_nats::jetstream::Context>,
+    pub subject_template: Option<SubjectTemplate>,
     pub serializer: ArrowSerializer,
+    // the epoch and sequence number within it of the next JetStream message, used to build
+    // deterministic message ids so that messages replayed after a restore are deduplicated
+    pub epoch: u32,
+    pub sequence: u64,
+    pub pending_acks: Vec<PublishAckFuture>,
+}
+
+impl NatsSinkFunc {
+    fn subject(&self) -> &str {
+        match &self.sink_type {
+            SinkType::Subject(s) | SinkType::JetstreamSubject(s) => s,
+        }
+    }
+
+    async fn report_error(ctx: &mut ArrowContext, message: &str) {
+        ctx.control_tx
+            .send(ControlResp::Error {
+                operator_id: ctx.task_info.operator_id.clone(),
+                task_index: ctx.task_info.task_index,
+                message: message.to_string(),
+                details: message.to_string(),
+            })
+            .await
+            .expect("Something went wrong, data will never be received.");
+    }
+
+    /// Waits for all outstanding JetStream publishes to be acknowledged
+    async fn await_acks(&mut self, ctx: &mut ArrowContext) {
+        for ack in self.pending_acks.drain(..) {
+            if let Err(e) = ack.await {
+                let message = format!("Failed to receive JetStream publish ack: {}", e);
+                Self::report_error(ctx, &message).await;
+                panic!("{}", message);
+            }
+        }
+    }
+
+    fn message_id(&mut self, ctx: &ArrowContext) -> String {
+        let id = format!(
+            "{}-{}-{}-{}-{}",
+            ctx.task_info.job_id,
+            ctx.task_info.operator_id,
+            ctx.task_info.task_index,
+            self.epoch,
+            self.sequence
+        );
+        self.sequence += 1;
+        id
+    }
 }
 
 #[async_trait]
 impl ArrowOperator for NatsSinkFunc {
     fn name(&self) -> String {
-        format!(
-            "nats-publisher-{}",
-            match &self.sink_type {
-                SinkType::Subject(s) => {
-                    s
-                }
-            }
-        )
+        format!("nats-publisher-{}", self.subject())
     }
 
     fn tables(&self) -> HashMap<String, TableConfig> {
         HashMap::new()
     }
 
-    async fn on_start(&mut self, _ctx: &mut ArrowContext) {
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
         match get_nats_client(&self.connection).await {
             Ok(client) => {
+                if let SinkType::JetstreamSubject(_) = &self.sink_type {
+                    self.jetstream = Some(async_nats::jetstream::new(client.clone()));
+                }
                 self.publisher = Some(client);
             }
             Err(e) => {
                 panic!("Failed to construct NATS publisher: {:?}", e);
             }
         }
+
+        match SubjectTemplate::new(self.subject(), &ctx.in_schemas[0].schema) {
+            Ok(subject) => {
+                self.subject_template = Some(subject);
+            }
+            Err(e) => {
+                ctx.report_error("Invalid NATS subject", e.to_string())
+                    .await;
+                panic!("Invalid NATS subject: {:?}", e);
+            }
+        }
+
+        self.epoch = ctx.table_manager.epoch();
+        self.sequence = 0;
     }
 
     async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        self.await_acks(ctx).await;
+
         if let Some(ControlMessage::Commit { epoch, commit_data }) = ctx.control_rx.recv().await {
             self.handle_commit(epoch, &commit_data, ctx).await;
         } else {
@@ -58,7 +238,7 @@ impl ArrowOperator for NatsSinkFunc {
         }
     }
 
-    async fn handle_checkpoint(&mut self, _: CheckpointBarrier, _ctx: &mut ArrowContext) {
+    async fn handle_checkpoint(&mut self, barrier: CheckpointBarrier, ctx: &mut ArrowContext) {
         // TODO: Implement checkpointing of in-progress data to avoid depending on
         // the downstream NATS availability to flush and checkpoint.
         let publisher = self
@@ -72,34 +252,129 @@ impl ArrowOperator for NatsSinkFunc {
                 panic!("Failed to flush NATS publisher: {:?}", e);
             }
         }
+
+        // the checkpoint can't complete until JetStream has persisted everything before it
+        self.await_acks(ctx).await;
+
+        self.epoch = barrier.epoch + 1;
+        self.sequence = 0;
     }
 
     async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
-        let s = match &self.sink_type {
-            SinkType::Subject(s) => s,
-        };
-        let nats_subject = async_nats::Subject::from(s.clone());
-        for msg in self.serializer.serialize(&batch) {
-            let publisher = self
-                .publisher
-                .as_mut()
-                .expect("Something went wrong while instantiating the publisher.");
-
-            match publisher.publish(nats_subject.clone(), msg.into()).await {
-                Ok(_) => {}
+        let template = self
+            .subject_template
+            .as_ref()
+            .expect("subject should be initialized in on_start");
+
+        let subjects = if template.is_static() {
+            vec![Some(self.subject().to_string()); batch.num_rows()]
+        } else {
+            match template.render(&batch) {
+                Ok(subjects) => subjects,
                 Err(e) => {
-                    ctx.control_tx
-                        .send(ControlResp::Error {
-                            operator_id: ctx.task_info.operator_id.clone(),
-                            task_index: ctx.task_info.task_index,
-                            message: e.to_string(),
-                            details: e.to_string(),
-                        })
-                        .await
-                        .expect("Something went wrong, data will never be received.");
-                    panic!("Panicked while processing element: {}", e.to_string());
+                    let message = format!("Failed to render NATS subject: {}", e);
+                    Self::report_error(ctx, &message).await;
+                    panic!("{}", message);
+                }
+            }
+        };
+
+        for (msg, subject) in self.serializer.serialize(&batch).zip(subjects) {
+            let Some(subject) = subject else {
+                ctx.report_error(
+                    "Dropped message with null subject",
+                    format!(
+                        "a column referenced by subject '{}' was null",
+                        self.subject()
+                    ),
+                )
+                .await;
+                continue;
+            };
+            let nats_subject = async_nats::Subject::from(subject);
+
+            let result = if self.jetstream.is_some() {
+                let mut headers = async_nats::HeaderMap::new();
+                let id = self.message_id(ctx);
+                headers.insert(async_nats::header::NATS_MESSAGE_ID, id.as_str());
+
+                match self
+                    .jetstream
+                    .as_ref()
+                    .unwrap()
+                    .publish_with_headers(nats_subject, headers, msg.into())
+                    .await
+                {
+                    Ok(ack) => {
+                        self.pending_acks.push(ack);
+                        Ok(())
+                    }
+                    Err(e) => Err(e.to_string()),
                 }
+            } else {
+                let publisher = self
+                    .publisher
+                    .as_mut()
+                    .expect("Something went wrong while instantiating the publisher.");
+
+                publisher
+                    .publish(nats_subject, msg.into())
+                    .await
+                    .map_err(|e| e.to_string())
+            };
+
+            if let Err(e) = result {
+                Self::report_error(ctx, &e).await;
+                panic!("Panicked while processing element: {}", e);
             }
         }
+
+        if self.pending_acks.len() >= MAX_PENDING_ACKS {
+            self.await_acks(ctx).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubjectTemplate;
+    use arrow::array::{Int64Array, RecordBatch, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_subject_template() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("region", DataType::Utf8, true),
+            Field::new("id", DataType::Int64, false),
+        ]));
+
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![Some("us"), None, Some("eu")])),
+                Arc::new(Int64Array::from(vec![1, 2, 3])),
+            ],
+        )
+        .unwrap();
+
+        let template = SubjectTemplate::new("orders.{region}.{ id }", &schema).unwrap();
+        assert_eq!(
+            template.render(&batch).unwrap(),
+            vec![
+                Some("orders.us.1".to_string()),
+                None,
+                Some("orders.eu.3".to_string())
+            ]
+        );
+
+        assert!(SubjectTemplate::new("orders", &schema).unwrap().is_static());
+        assert!(SubjectTemplate::new("orders.{missing}", &schema).is_err());
+        assert!(SubjectTemplate::columns("orders.{region").is_err());
+        assert!(SubjectTemplate::columns("orders.region}").is_err());
+        assert_eq!(
+            SubjectTemplate::columns("{a}.{b}").unwrap(),
+            vec!["a".to_string(), "b".to_string()]
+        );
     }
 }
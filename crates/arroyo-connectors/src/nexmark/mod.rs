@@ -91,6 +91,7 @@ pub fn nexmark_schema() -> ConnectionSchema {
             .collect(),
         definition: None,
         inferred: None,
+        metadata_fields: vec![],
     }
 }
 
@@ -211,6 +212,7 @@ impl Connector for NexmarkConnector {
             format: None,
             bad_data: None,
             framing: None,
+            metadata_fields: vec![],
         };
 
         Ok(Connection {
@@ -4,7 +4,8 @@ use std::collections::HashMap;
 use std::str::FromStr;
 use std::time::Duration;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
+use arroyo_rpc::formats::Format;
 use arroyo_rpc::{var_str::VarStr, OperatorConfig};
 use arroyo_types::string_to_map;
 use reqwest::{Client, Request};
@@ -16,15 +17,20 @@ use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
 };
 use serde::{Deserialize, Serialize};
+use serde_json_path::JsonPath;
 
 use crate::{construct_http_client, pull_opt, pull_option_to_i64, EmptyConfig};
 
-use crate::polling_http::operator::{PollingHttpSourceFunc, PollingHttpSourceState};
+use crate::polling_http::operator::{
+    Incremental, OAuth2ClientCredentials, PaginationStrategy, PollingHttpSourceFunc,
+    PollingHttpSourceState,
+};
 use arroyo_operator::connector::Connector;
 use arroyo_operator::operator::OperatorNode;
 
 const TABLE_SCHEMA: &str = include_str!("./table.json");
 const DEFAULT_POLLING_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_PAGES: usize = 100;
 
 import_types!(
     schema = "src/polling_http/table.json",
@@ -34,6 +40,10 @@ const ICON: &str = include_str!("./http.svg");
 
 pub struct PollingHTTPConnector {}
 
+fn parse_json_path(name: &str, path: &str) -> anyhow::Result<JsonPath> {
+    JsonPath::parse(path).map_err(|e| anyhow!("invalid JSONPath for '{}': {}", name, e))
+}
+
 impl PollingHTTPConnector {
     fn construct_test_request(
         client: &Client,
@@ -71,7 +81,35 @@ impl PollingHTTPConnector {
             .transpose()?;
 
         let client = construct_http_client(&config.endpoint, headers)?;
-        let req = Self::construct_test_request(&client, config)?;
+        let mut req = Self::construct_test_request(&client, config)?;
+
+        if let (Some(token_url), Some(client_id), Some(client_secret)) = (
+            &config.oauth2_token_url,
+            &config.oauth2_client_id,
+            &config.oauth2_client_secret,
+        ) {
+            tx.send(TestSourceMessage::info("Fetching OAuth2 access token"))
+                .await
+                .unwrap();
+
+            let token = OAuth2ClientCredentials::new(
+                url::Url::from_str(token_url)
+                    .map_err(|e| anyhow!("invalid 'oauth2_token_url': {}", e))?,
+                client_id.clone(),
+                client_secret.sub_env_vars()?,
+                config.oauth2_scopes.clone(),
+            )
+            .access_token(&client)
+            .await
+            .map_err(|e| anyhow!("{}: {}", e.name, e.details))?;
+
+            req.headers_mut().insert(
+                reqwest::header::AUTHORIZATION,
+                format!("Bearer {}", token)
+                    .try_into()
+                    .map_err(|_| anyhow!("invalid OAuth2 access token"))?,
+            );
+        }
 
         tx.send(TestSourceMessage {
             error: false,
@@ -170,6 +208,12 @@ impl Connector for PollingHTTPConnector {
             .transpose()
             .map_err(|_| anyhow!("invalid value for 'emit_behavior'"))?;
 
+        let pagination: Option<Pagination> = options
+            .remove("pagination")
+            .map(|s| s.try_into())
+            .transpose()
+            .map_err(|_| anyhow!("invalid value for 'pagination'"))?;
+
         self.from_config(
             None,
             name,
@@ -181,6 +225,18 @@ impl Connector for PollingHTTPConnector {
                 body,
                 poll_interval_ms: interval,
                 emit_behavior,
+                records_path: options.remove("records_path"),
+                pagination,
+                cursor_path: options.remove("cursor_path"),
+                cursor_param: options.remove("cursor_param"),
+                page_param: options.remove("page_param"),
+                max_pages: pull_option_to_i64("max_pages", options)?,
+                incremental_field: options.remove("incremental_field"),
+                incremental_param: options.remove("incremental_param"),
+                oauth2_token_url: options.remove("oauth2_token_url"),
+                oauth2_client_id: options.remove("oauth2_client_id"),
+                oauth2_client_secret: options.remove("oauth2_client_secret").map(VarStr::new),
+                oauth2_scopes: options.remove("oauth2_scopes"),
             },
             schema,
         )
@@ -215,6 +271,66 @@ impl Connector for PollingHTTPConnector {
             .map(|t| t.to_owned())
             .ok_or_else(|| anyhow!("'format' must be set for polling HTTP connection"))?;
 
+        if let Some(path) = &table.records_path {
+            parse_json_path("records_path", path)?;
+            if !matches!(format, Format::Json(_)) {
+                bail!("'records_path' can only be used with a JSON format");
+            }
+        }
+
+        match table.pagination {
+            Some(Pagination::Cursor) => {
+                let Some(path) = &table.cursor_path else {
+                    bail!("'cursor_path' must be set for cursor pagination");
+                };
+                parse_json_path("cursor_path", path)?;
+
+                if table.cursor_param.is_none() {
+                    bail!("'cursor_param' must be set for cursor pagination");
+                }
+            }
+            Some(Pagination::PageNumber) if table.records_path.is_none() => {
+                bail!("'records_path' must be set for page number pagination, so that the last page can be detected");
+            }
+            _ => {}
+        }
+
+        if table.max_pages.is_some_and(|p| p <= 0) {
+            bail!("'max_pages' must be greater than 0");
+        }
+
+        match (&table.incremental_field, &table.incremental_param) {
+            (Some(field), Some(_)) => {
+                parse_json_path("incremental_field", field)?;
+            }
+            (None, None) => {}
+            _ => {
+                bail!("'incremental_field' and 'incremental_param' must be set together");
+            }
+        }
+
+        match (
+            &table.oauth2_token_url,
+            &table.oauth2_client_id,
+            &table.oauth2_client_secret,
+        ) {
+            (Some(token_url), Some(_), Some(secret)) => {
+                url::Url::from_str(token_url)
+                    .map_err(|e| anyhow!("invalid 'oauth2_token_url': {}", e))?;
+                secret.sub_env_vars()?;
+            }
+            (None, None, None) => {
+                if table.oauth2_scopes.is_some() {
+                    bail!(
+                        "'oauth2_scopes' requires the OAuth2 client credentials to be configured"
+                    );
+                }
+            }
+            _ => {
+                bail!("'oauth2_token_url', 'oauth2_client_id', and 'oauth2_client_secret' must be set together");
+            }
+        }
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
@@ -222,6 +338,7 @@ impl Connector for PollingHTTPConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            metadata_fields: vec![],
         };
 
         Ok(Connection {
@@ -281,6 +398,48 @@ impl Connector for PollingHTTPConnector {
                 .map(|d| Duration::from_millis(d as u64))
                 .unwrap_or(DEFAULT_POLLING_INTERVAL),
             emit_behavior: table.emit_behavior.unwrap_or(EmitBehavior::All),
+            records_path: table
+                .records_path
+                .map(|p| JsonPath::parse(&p).expect("invalid records_path")),
+            pagination: match table.pagination {
+                None | Some(Pagination::None) => PaginationStrategy::None,
+                Some(Pagination::Cursor) => PaginationStrategy::Cursor {
+                    path: JsonPath::parse(&table.cursor_path.expect("cursor_path must be set"))
+                        .expect("invalid cursor_path"),
+                    param: table.cursor_param.expect("cursor_param must be set"),
+                },
+                Some(Pagination::LinkHeader) => PaginationStrategy::LinkHeader,
+                Some(Pagination::PageNumber) => PaginationStrategy::PageNumber {
+                    param: table.page_param.unwrap_or_else(|| "page".to_string()),
+                },
+            },
+            max_pages: table
+                .max_pages
+                .map(|p| p as usize)
+                .unwrap_or(DEFAULT_MAX_PAGES),
+            incremental: table.incremental_field.zip(table.incremental_param).map(
+                |(field, param)| Incremental {
+                    field: JsonPath::parse(&field).expect("invalid incremental_field"),
+                    param,
+                },
+            ),
+            oauth2: match (
+                table.oauth2_token_url,
+                table.oauth2_client_id,
+                table.oauth2_client_secret,
+            ) {
+                (Some(token_url), Some(client_id), Some(client_secret)) => {
+                    Some(OAuth2ClientCredentials::new(
+                        url::Url::from_str(&token_url).expect("invalid oauth2_token_url"),
+                        client_id,
+                        client_secret
+                            .sub_env_vars()
+                            .expect("Failed to substitute env vars"),
+                        table.oauth2_scopes,
+                    ))
+                }
+                _ => None,
+            },
             format: config
                 .format
                 .expect("PollingHTTP source must have a format"),
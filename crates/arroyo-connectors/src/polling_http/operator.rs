@@ -2,10 +2,16 @@ use async_trait::async_trait;
 use bincode::{Decode, Encode};
 use bytes::Bytes;
 use futures::StreamExt;
+use reqwest::header::{HeaderMap, LINK};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+use serde_json_path::JsonPath;
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::time::Duration;
 use std::time::SystemTime;
+use std::time::{Duration, Instant};
+use url::Url;
 
 use arroyo_rpc::ControlMessage;
 use arroyo_types::{ArrowMessage, SignalMessage, UserError, Watermark};
@@ -24,22 +30,233 @@ use tracing::{debug, info, warn};
 
 const MAX_BODY_SIZE: usize = 5 * 1024 * 1024; // 5M ought to be enough for anybody
 
+// tokens are refreshed this long before they expire, to avoid racing the expiration
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(3600);
+
 pub struct PollingHttpSourceFunc {
     pub state: PollingHttpSourceState,
     pub client: reqwest::Client,
-    pub endpoint: url::Url,
+    pub endpoint: Url,
     pub method: reqwest::Method,
     pub body: Option<Bytes>,
     pub polling_interval: Duration,
     pub emit_behavior: EmitBehavior,
+    pub records_path: Option<JsonPath>,
+    pub pagination: PaginationStrategy,
+    pub max_pages: usize,
+    pub incremental: Option<Incremental>,
+    pub oauth2: Option<OAuth2ClientCredentials>,
     pub format: Format,
     pub framing: Option<Framing>,
     pub bad_data: Option<BadData>,
 }
 
+pub enum PaginationStrategy {
+    None,
+    Cursor { path: JsonPath, param: String },
+    LinkHeader,
+    PageNumber { param: String },
+}
+
+/// Tracks the largest value of `field` across all records, which is sent on subsequent polls
+/// as the query parameter `param`
+pub struct Incremental {
+    pub field: JsonPath,
+    pub param: String,
+}
+
+pub struct OAuth2ClientCredentials {
+    token_url: Url,
+    client_id: String,
+    client_secret: String,
+    scopes: Option<String>,
+    token: Option<(String, Instant)>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+impl OAuth2ClientCredentials {
+    pub fn new(
+        token_url: Url,
+        client_id: String,
+        client_secret: String,
+        scopes: Option<String>,
+    ) -> Self {
+        Self {
+            token_url,
+            client_id,
+            client_secret,
+            scopes,
+            token: None,
+        }
+    }
+
+    pub async fn access_token(&mut self, client: &reqwest::Client) -> Result<String, UserError> {
+        if let Some((token, expires_at)) = &self.token {
+            if Instant::now() < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+        ];
+
+        if let Some(scopes) = &self.scopes {
+            form.push(("scope", scopes.as_str()));
+        }
+
+        let resp = client
+            .post(self.token_url.clone())
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| {
+                UserError::new(
+                    "failed to fetch OAuth2 token",
+                    format!("request to {} failed: {}", self.token_url, e),
+                )
+            })?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(UserError::new(
+                "failed to fetch OAuth2 token",
+                format!(
+                    "token endpoint {} responded with {}",
+                    self.token_url,
+                    status.as_u16()
+                ),
+            ));
+        }
+
+        let body = resp.bytes().await.map_err(|e| {
+            UserError::new(
+                "failed to fetch OAuth2 token",
+                format!("failed while reading token response: {}", e),
+            )
+        })?;
+
+        let token: TokenResponse = serde_json::from_slice(&body).map_err(|e| {
+            UserError::new(
+                "failed to fetch OAuth2 token",
+                format!("invalid token response: {}", e),
+            )
+        })?;
+
+        let lifetime = token
+            .expires_in
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+
+        self.token = Some((
+            token.access_token.clone(),
+            Instant::now() + lifetime.saturating_sub(TOKEN_EXPIRY_MARGIN),
+        ));
+
+        Ok(token.access_token)
+    }
+
+    fn invalidate(&mut self) {
+        self.token = None;
+    }
+}
+
+// Before sources could poll incrementally their state only held the last message, and was stored
+// in the legacy table, which is still read when restoring older checkpoints
+const STATE_TABLE: &str = "p";
+const LEGACY_STATE_TABLE: &str = "s";
+
 #[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd, Default)]
 pub struct PollingHttpSourceState {
     last_message: Option<Vec<u8>>,
+    last_value: Option<String>,
+}
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
+struct LegacyPollingHttpSourceState {
+    last_message: Option<Vec<u8>>,
+}
+
+impl From<LegacyPollingHttpSourceState> for PollingHttpSourceState {
+    fn from(legacy: LegacyPollingHttpSourceState) -> Self {
+        Self {
+            last_message: legacy.last_message,
+            last_value: None,
+        }
+    }
+}
+
+impl PollingHttpSourceState {
+    fn observe(&mut self, field: &JsonPath, record: &Value) {
+        let Some(value) = field.query(record).first().and_then(|v| match v {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        }) else {
+            return;
+        };
+
+        // numeric values are compared as numbers, everything else (like ISO-8601 timestamps)
+        // lexicographically
+        let newer = match &self.last_value {
+            None => true,
+            Some(last) => match (value.parse::<f64>(), last.parse::<f64>()) {
+                (Ok(v), Ok(l)) => v > l,
+                _ => value.as_str() > last.as_str(),
+            },
+        };
+
+        if newer {
+            self.last_value = Some(value);
+        }
+    }
+}
+
+fn set_query_param(url: &mut Url, name: &str, value: &str) {
+    let params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(k, _)| k != name)
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(params)
+        .append_pair(name, value);
+}
+
+/// Finds the `rel="next"` target in a Link header (RFC 8288)
+fn next_link(headers: &HeaderMap, base: &Url) -> Option<Url> {
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split('<').skip(1))
+        .find_map(|link| {
+            let (target, params) = link.split_once('>')?;
+            let is_next = params
+                .split(';')
+                .filter_map(|p| p.trim().trim_end_matches(',').trim().strip_prefix("rel="))
+                .any(|rel| {
+                    rel.trim_matches('"')
+                        .split_whitespace()
+                        .any(|r| r == "next")
+                });
+
+            if is_next {
+                base.join(target.trim()).ok()
+            } else {
+                None
+            }
+        })
 }
 
 #[async_trait]
@@ -49,18 +266,35 @@ impl SourceOperator for PollingHttpSourceFunc {
     }
 
     fn tables(&self) -> HashMap<String, TableConfig> {
-        arroyo_state::global_table_config("s", "polling http source state")
+        let mut tables =
+            arroyo_state::global_table_config(STATE_TABLE, "polling http source state");
+        tables.extend(arroyo_state::global_table_config(
+            LEGACY_STATE_TABLE,
+            "polling http source state, from older checkpoints",
+        ));
+        tables
     }
 
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
         let s: &mut GlobalKeyedView<(), PollingHttpSourceState> = ctx
             .table_manager
-            .get_global_keyed_state("s")
+            .get_global_keyed_state(STATE_TABLE)
             .await
             .expect("should be able to read http state");
 
         if let Some(state) = s.get(&()) {
             self.state = state.clone();
+            return;
+        }
+
+        let legacy: &mut GlobalKeyedView<(), LegacyPollingHttpSourceState> = ctx
+            .table_manager
+            .get_global_keyed_state(LEGACY_STATE_TABLE)
+            .await
+            .expect("should be able to read legacy http state");
+
+        if let Some(state) = legacy.get(&()) {
+            self.state = state.clone().into();
         }
     }
 
@@ -88,7 +322,7 @@ impl PollingHttpSourceFunc {
                 let state = self.state.clone();
                 let s = ctx
                     .table_manager
-                    .get_global_keyed_state("s")
+                    .get_global_keyed_state(STATE_TABLE)
                     .await
                     .expect("should be able to get http state");
                 s.insert((), state).await;
@@ -120,27 +354,45 @@ impl PollingHttpSourceFunc {
         None
     }
 
-    async fn request(&mut self) -> Result<Vec<u8>, UserError> {
-        let mut request = self
-            .client
-            .request(self.method.clone(), self.endpoint.clone());
+    async fn request(&mut self, url: Url) -> Result<(HeaderMap, Vec<u8>), UserError> {
+        let mut refreshed_token = false;
 
-        if let Some(body) = self.body.clone() {
-            request = request.body(body);
-        }
+        let resp = loop {
+            let mut request = self.client.request(self.method.clone(), url.clone());
 
-        let resp = self
-            .client
-            .execute(request.build().expect("building request failed"))
-            .await
-            .map_err(|e| {
-                UserError::new(
-                    "request failed",
-                    format!("failed to execute HTTP request: {}", e),
-                )
-            })?;
+            if let Some(body) = self.body.clone() {
+                request = request.body(body);
+            }
+
+            if let Some(oauth2) = &mut self.oauth2 {
+                request = request.bearer_auth(oauth2.access_token(&self.client).await?);
+            }
+
+            let resp = self
+                .client
+                .execute(request.build().expect("building request failed"))
+                .await
+                .map_err(|e| {
+                    UserError::new(
+                        "request failed",
+                        format!("failed to execute HTTP request: {}", e),
+                    )
+                })?;
+
+            // our token may have been revoked before its expiration, so fetch a new one and retry
+            if resp.status() == StatusCode::UNAUTHORIZED && !refreshed_token {
+                if let Some(oauth2) = &mut self.oauth2 {
+                    oauth2.invalidate();
+                    refreshed_token = true;
+                    continue;
+                }
+            }
+
+            break resp;
+        };
 
         if resp.status().is_success() {
+            let headers = resp.headers().clone();
             let content_len = resp.content_length().unwrap_or(0);
             if content_len > MAX_BODY_SIZE as u64 {
                 return Err(UserError::new(
@@ -171,7 +423,7 @@ impl PollingHttpSourceFunc {
                 }
             }
 
-            Ok(buf)
+            Ok((headers, buf))
         } else {
             let status = resp.status();
             let bytes = resp.bytes().await;
@@ -182,7 +434,7 @@ impl PollingHttpSourceFunc {
 
             warn!(
                 "HTTP request to {} failed with {}: {}",
-                self.endpoint,
+                url,
                 status.as_u16(),
                 error_body
             );
@@ -194,11 +446,143 @@ impl PollingHttpSourceFunc {
         }
     }
 
+    fn needs_json(&self) -> bool {
+        self.records_path.is_some()
+            || self.incremental.is_some()
+            || matches!(self.pagination, PaginationStrategy::Cursor { .. })
+    }
+
+    /// Emits the records in a response, returning the number of records found
+    async fn emit(
+        &mut self,
+        ctx: &mut ArrowContext,
+        buf: &[u8],
+        json: Option<&Value>,
+    ) -> Result<usize, UserError> {
+        let count = match (&self.records_path, json) {
+            (Some(path), Some(json)) => {
+                let records = path.query(json).all();
+                for record in &records {
+                    ctx.deserialize_slice(
+                        &serde_json::to_vec(record).unwrap(),
+                        SystemTime::now(),
+                        None,
+                    )
+                    .await?;
+
+                    if let Some(incremental) = &self.incremental {
+                        self.state.observe(&incremental.field, record);
+                    }
+                }
+                records.len()
+            }
+            _ => {
+                ctx.deserialize_slice(buf, SystemTime::now(), None).await?;
+
+                if let (Some(incremental), Some(json)) = (&self.incremental, json) {
+                    self.state.observe(&incremental.field, json);
+                }
+                1
+            }
+        };
+
+        if ctx.should_flush() {
+            ctx.flush_buffer().await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Fetches and emits all pages for a single poll. Errors returned from this method are fatal;
+    /// request failures are reported and end the current poll.
+    async fn poll(&mut self, ctx: &mut ArrowContext) -> Result<(), UserError> {
+        let mut url = self.endpoint.clone();
+        if let (Some(incremental), Some(last_value)) = (&self.incremental, &self.state.last_value) {
+            set_query_param(&mut url, &incremental.param, last_value);
+        }
+
+        let mut page = 1;
+        for i in 0..self.max_pages {
+            let (headers, buf) = match self.request(url.clone()).await {
+                Ok(r) => r,
+                Err(e) => {
+                    ctx.report_user_error(e).await;
+                    return Ok(());
+                }
+            };
+
+            if i == 0 {
+                if self.emit_behavior == EmitBehavior::Changed
+                    && Some(&buf) == self.state.last_message.as_ref()
+                {
+                    return Ok(());
+                }
+                self.state.last_message = Some(buf.clone());
+            }
+
+            let json: Option<Value> = if self.needs_json() {
+                match serde_json::from_slice(&buf) {
+                    Ok(json) => Some(json),
+                    Err(e) => {
+                        ctx.report_error(
+                            "invalid response",
+                            format!("response from {} is not valid JSON: {}", url, e),
+                        )
+                        .await;
+                        return Ok(());
+                    }
+                }
+            } else {
+                None
+            };
+
+            let count = self.emit(ctx, &buf, json.as_ref()).await?;
+
+            let next = match &self.pagination {
+                PaginationStrategy::None => None,
+                PaginationStrategy::Cursor { path, param } => json
+                    .as_ref()
+                    .and_then(|json| {
+                        path.query(json).first().and_then(|cursor| match cursor {
+                            Value::String(s) if !s.is_empty() => Some(s.clone()),
+                            Value::Number(n) => Some(n.to_string()),
+                            _ => None,
+                        })
+                    })
+                    .map(|cursor| {
+                        let mut next = url.clone();
+                        set_query_param(&mut next, param, &cursor);
+                        next
+                    }),
+                PaginationStrategy::LinkHeader => next_link(&headers, &url),
+                PaginationStrategy::PageNumber { param } => (count > 0).then(|| {
+                    page += 1;
+                    let mut next = url.clone();
+                    set_query_param(&mut next, param, &page.to_string());
+                    next
+                }),
+            };
+
+            match next {
+                Some(next) => url = next,
+                None => return Ok(()),
+            }
+        }
+
+        debug!(
+            "stopping polling http pagination after {} pages",
+            self.max_pages
+        );
+
+        Ok(())
+    }
+
     async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
         ctx.initialize_deserializer(
             self.format.clone(),
             self.framing.clone(),
             self.bad_data.clone(),
+            &[],
         );
 
         // since there's no way to partition across an http source, only read on the first task
@@ -209,24 +593,7 @@ impl PollingHttpSourceFunc {
             loop {
                 select! {
                     _ = timer.tick()  => {
-                        match self.request().await {
-                            Ok(buf) => {
-                                if self.emit_behavior == EmitBehavior::Changed && Some(&buf) == self.state.last_message.as_ref() {
-                                    continue;
-                                }
-
-                                ctx.deserialize_slice(&buf, SystemTime::now()).await?;
-
-                                if ctx.should_flush() {
-                                    ctx.flush_buffer().await?;
-                                }
-
-                                self.state.last_message = Some(buf);
-                            }
-                            Err(e) => {
-                                ctx.report_user_error(e).await;
-                            }
-                        }
+                        self.poll(ctx).await?;
                     }
                     control_message = ctx.control_rx.recv() => {
                         if let Some(r) = self.our_handle_control_message(ctx, control_message).await {
@@ -250,3 +617,71 @@ impl PollingHttpSourceFunc {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_next_link() {
+        let base = Url::parse("https://api.example.com/items?page=1").unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            LINK,
+            HeaderValue::from_static(
+                "<https://api.example.com/items?page=1>; rel=\"prev\", </items?page=3>; rel=\"next\"",
+            ),
+        );
+        assert_eq!(
+            next_link(&headers, &base).unwrap().as_str(),
+            "https://api.example.com/items?page=3"
+        );
+
+        headers.insert(
+            LINK,
+            HeaderValue::from_static("<https://api.example.com/items?page=1>; rel=\"first\""),
+        );
+        assert_eq!(next_link(&headers, &base), None);
+    }
+
+    #[test]
+    fn test_incremental_state() {
+        let path = JsonPath::parse("$.updated").unwrap();
+        let mut state = PollingHttpSourceState::default();
+
+        state.observe(&path, &serde_json::json!({"updated": 9}));
+        state.observe(&path, &serde_json::json!({"updated": 10}));
+        state.observe(&path, &serde_json::json!({"updated": 2}));
+        assert_eq!(state.last_value.as_deref(), Some("10"));
+
+        let path = JsonPath::parse("$.ts").unwrap();
+        let mut state = PollingHttpSourceState::default();
+        state.observe(&path, &serde_json::json!({"ts": "2024-03-01T00:00:00Z"}));
+        state.observe(&path, &serde_json::json!({"ts": "2024-02-01T00:00:00Z"}));
+        state.observe(
+            &path,
+            &serde_json::json!({"missing": "2025-02-01T00:00:00Z"}),
+        );
+        assert_eq!(state.last_value.as_deref(), Some("2024-03-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn test_legacy_state() {
+        let legacy = LegacyPollingHttpSourceState {
+            last_message: Some(b"hello".to_vec()),
+        };
+
+        let state: PollingHttpSourceState = legacy.into();
+        assert_eq!(state.last_message.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(state.last_value, None);
+    }
+
+    #[test]
+    fn test_set_query_param() {
+        let mut url = Url::parse("https://example.com/items?limit=10&cursor=a").unwrap();
+        set_query_param(&mut url, "cursor", "b");
+        assert_eq!(url.as_str(), "https://example.com/items?limit=10&cursor=b");
+    }
+}
@@ -21,6 +21,7 @@ use arroyo_rpc::api_types::connections::{
 use arroyo_rpc::OperatorConfig;
 
 use crate::redis::operator::sink::{GeneralConnection, RedisSinkFunc};
+use crate::redis::operator::source::RedisStreamSourceFunc;
 use crate::{pull_opt, pull_option_to_u64};
 
 pub struct RedisConnector {}
@@ -161,9 +162,9 @@ impl Connector for RedisConnector {
             id: "redis".to_string(),
             name: "Redis".to_string(),
             icon: ICON.to_string(),
-            description: "Write results to Redis".to_string(),
+            description: "Read from Redis Streams or write results to Redis".to_string(),
             enabled: true,
-            source: false,
+            source: true,
             sink: true,
             testing: false,
             hidden: false,
@@ -173,8 +174,15 @@ impl Connector for RedisConnector {
         }
     }
 
-    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
-        ConnectionType::Source
+    fn supports_updating_input(&self) -> bool {
+        true
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.connector_type {
+            TableType::Target(_) => ConnectionType::Sink,
+            TableType::Streams(_) => ConnectionType::Source,
+        }
     }
 
     fn get_schema(
@@ -287,7 +295,52 @@ impl Connector for RedisConnector {
             Ok(column)
         }
 
-        let sink = match typ.as_str() {
+        fn validate_score_column(
+            schema: &ConnectionSchema,
+            column: String,
+        ) -> anyhow::Result<String> {
+            if !schema.fields.iter().any(|f| {
+                f.field_name == column
+                    && matches!(
+                        f.field_type.r#type,
+                        FieldType::Primitive(
+                            PrimitiveType::Int32
+                                | PrimitiveType::Int64
+                                | PrimitiveType::UInt32
+                                | PrimitiveType::UInt64
+                                | PrimitiveType::F32
+                                | PrimitiveType::F64
+                        )
+                    )
+                    && !f.nullable
+            }) {
+                bail!("invalid value '{}' for target.score_column, must be the name of a non-nullable numeric column on the table", column);
+            };
+
+            Ok(column)
+        }
+
+        let connector_type = match typ.as_str() {
+            "source" => TableType::Streams(StreamSource {
+                stream_keys: pull_opt("source.streams", options)?
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+                group_name: pull_opt("source.group", options)?,
+                start_position: match options.remove("source.start").as_deref() {
+                    Some("latest") | None => Some(StartPosition::Latest),
+                    Some("earliest") => Some(StartPosition::Earliest),
+                    Some(s) => {
+                        bail!("'{}' is not a valid value for source.start; must be one of 'latest' or 'earliest'", s);
+                    }
+                },
+                value_field: options.remove("source.value_field"),
+                batch_size: pull_option_to_u64("source.batch_size", options)?
+                    .map(|t| t.try_into())
+                    .transpose()
+                    .map_err(|_| anyhow!("source.batch_size must be greater than 0"))?,
+            }),
             "sink" => TableType::Target(match pull_opt("target", options)?.as_str() {
                 "string" => Target::StringTable {
                     key_prefix: pull_opt("target.key_prefix", options)?,
@@ -330,12 +383,27 @@ impl Connector for RedisConnector {
                         .transpose()?,
                     hash_key_prefix: pull_opt("target.key_prefix", options)?,
                 },
+                "sorted_set" => Target::SortedSetTable {
+                    sorted_set_key_prefix: pull_opt("target.key_prefix", options)?,
+                    sorted_set_key_column: options
+                        .remove("target.key_column")
+                        .map(|name| validate_column(schema, name, "target.key_column"))
+                        .transpose()?,
+                    score_column: validate_score_column(
+                        schema,
+                        pull_opt("target.score_column", options)?,
+                    )?,
+                    member_column: options
+                        .remove("target.member_column")
+                        .map(|name| validate_column(schema, name, "target.member_column"))
+                        .transpose()?,
+                },
                 s => {
                     bail!("'{}' is not a valid redis target", s);
                 }
             }),
             s => {
-                bail!("'{}' is not a valid type; must be `sink`", s);
+                bail!("'{}' is not a valid type; must be `source` or `sink`", s);
             }
         };
 
@@ -343,9 +411,7 @@ impl Connector for RedisConnector {
             None,
             name,
             connection_config,
-            RedisTable {
-                connector_type: sink,
-            },
+            RedisTable { connector_type },
             s,
         )
     }
@@ -370,6 +436,19 @@ impl Connector for RedisConnector {
 
         let _ = RedisClient::new(&config)?;
 
+        let (connection_type, description) = match &table.connector_type {
+            TableType::Target(_) => (ConnectionType::Sink, "RedisSink".to_string()),
+            TableType::Streams(streams) => {
+                if streams.stream_keys.is_empty() {
+                    bail!("at least one stream key must be provided for a Redis source");
+                }
+                (
+                    ConnectionType::Source,
+                    format!("RedisStreamSource<{}>", streams.stream_keys.join(",")),
+                )
+            }
+        };
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
@@ -377,16 +456,17 @@ impl Connector for RedisConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            metadata_fields: vec![],
         };
 
         Ok(Connection {
             id,
             connector: self.name(),
             name: name.to_string(),
-            connection_type: ConnectionType::Sink,
+            connection_type,
             schema,
             config: serde_json::to_string(&config).unwrap(),
-            description: "RedisSink".to_string(),
+            description,
         })
     }
 
@@ -398,6 +478,20 @@ impl Connector for RedisConnector {
     ) -> anyhow::Result<OperatorNode> {
         let client = RedisClient::new(&profile)?;
 
+        if let TableType::Streams(streams) = table.connector_type {
+            return Ok(OperatorNode::from_source(Box::new(RedisStreamSourceFunc {
+                client,
+                stream_keys: streams.stream_keys,
+                group_name: streams.group_name,
+                start_position: streams.start_position.unwrap_or(StartPosition::Latest),
+                value_field: streams.value_field.unwrap_or_else(|| "value".to_string()),
+                batch_size: streams.batch_size.map(|s| s.get() as usize).unwrap_or(100),
+                format: config.format.expect("redis table must have a format"),
+                framing: config.framing,
+                bad_data: config.bad_data,
+            })));
+        }
+
         let (tx, cmd_rx) = tokio::sync::mpsc::channel(128);
         let (cmd_tx, rx) = tokio::sync::mpsc::channel(128);
 
@@ -412,6 +506,9 @@ impl Connector for RedisConnector {
             rx,
             key_index: None,
             hash_index: None,
+            score_index: None,
+            member_index: None,
+            retract_index: None,
         })))
     }
 }
@@ -1,8 +1,10 @@
 use crate::redis::{ListOperation, RedisClient, RedisTable, TableType, Target};
-use arrow::array::{AsArray, RecordBatch};
+use arrow::array::{AsArray, BooleanArray, RecordBatch};
+use arrow::datatypes::{DataType, Float64Type};
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::context::{ArrowContext, ErrorReporter};
 use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::IS_RETRACT_FIELD;
 use arroyo_types::CheckpointBarrier;
 use async_trait::async_trait;
 use redis::aio::{ConnectionLike, ConnectionManager};
@@ -28,6 +30,9 @@ pub struct RedisSinkFunc {
 
     pub key_index: Option<usize>,
     pub hash_index: Option<usize>,
+    pub score_index: Option<usize>,
+    pub member_index: Option<usize>,
+    pub retract_index: Option<usize>,
 }
 
 impl RedisSinkFunc {
@@ -40,6 +45,104 @@ impl RedisSinkFunc {
 
         key
     }
+
+    fn column_index(ctx: &ArrowContext, column: &str, description: &str) -> usize {
+        ctx.in_schemas
+            .first()
+            .expect("no in-schema for redis sink!")
+            .schema
+            .index_of(column)
+            .unwrap_or_else(|_| {
+                panic!("{description} ({column}) does not exist in input schema for redis sink")
+            })
+    }
+
+    fn commands(&mut self, batch: &RecordBatch) -> Vec<RedisCmd> {
+        let retracts: Option<&BooleanArray> =
+            self.retract_index.map(|i| batch.column(i).as_boolean());
+
+        let scores = self.score_index.map(|i| {
+            arrow::compute::cast(batch.column(i), &DataType::Float64)
+                .expect("score column must be numeric")
+        });
+
+        let mut cmds = vec![];
+        for (i, value) in self.serializer.serialize(batch).enumerate() {
+            let is_retract = retracts
+                .map(|r| r.is_valid(i) && r.value(i))
+                .unwrap_or(false);
+
+            let cmd = match &self.table.connector_type {
+                TableType::Target(target) => match &target {
+                    Target::StringTable { key_prefix, .. } => {
+                        let key = self.make_key(key_prefix, batch, i);
+                        if is_retract {
+                            RedisCmd::Del { key, value }
+                        } else {
+                            RedisCmd::Data { key, value }
+                        }
+                    }
+                    Target::ListTable { list_prefix, .. } => {
+                        let key = self.make_key(list_prefix, batch, i);
+                        if is_retract {
+                            RedisCmd::Del { key, value }
+                        } else {
+                            RedisCmd::Data { key, value }
+                        }
+                    }
+                    Target::HashTable {
+                        hash_key_prefix, ..
+                    } => {
+                        let key = self.make_key(hash_key_prefix, batch, i);
+                        let field = batch
+                            .column(self.hash_index.expect("no hash index"))
+                            .as_string::<i32>()
+                            .value(i)
+                            .to_string();
+
+                        if is_retract {
+                            RedisCmd::HDel { key, field }
+                        } else {
+                            RedisCmd::HData { key, field, value }
+                        }
+                    }
+                    Target::SortedSetTable {
+                        sorted_set_key_prefix,
+                        ..
+                    } => {
+                        let key = self.make_key(sorted_set_key_prefix, batch, i);
+                        let member = match self.member_index {
+                            Some(idx) => batch
+                                .column(idx)
+                                .as_string::<i32>()
+                                .value(i)
+                                .as_bytes()
+                                .to_vec(),
+                            None => value,
+                        };
+
+                        if is_retract {
+                            RedisCmd::Del { key, value: member }
+                        } else {
+                            let score = scores
+                                .as_ref()
+                                .expect("no score column")
+                                .as_primitive::<Float64Type>()
+                                .value(i);
+                            RedisCmd::ZData { key, member, score }
+                        }
+                    }
+                },
+                TableType::Streams(_) => {
+                    unreachable!("redis sink configured with a stream source table")
+                }
+            };
+
+            cmds.push(cmd);
+        }
+
+        cmds
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -47,6 +150,7 @@ enum RedisBehavior {
     Set { ttl: Option<usize> },
     Push { append: bool, max: Option<usize> },
     Hash,
+    SortedSet,
 }
 
 pub enum RedisCmd {
@@ -61,6 +165,23 @@ pub enum RedisCmd {
         value: Vec<u8>,
     },
 
+    ZData {
+        key: String,
+        member: Vec<u8>,
+        score: f64,
+    },
+
+    /// Removes a value that was previously written, in response to a retraction
+    Del {
+        key: String,
+        value: Vec<u8>,
+    },
+
+    HDel {
+        key: String,
+        field: String,
+    },
+
     Flush(u32),
 }
 
@@ -97,14 +218,123 @@ impl ConnectionLike for GeneralConnection {
     }
 }
 
+/// Accumulates commands into a pipeline, which is written to Redis when the writer flushes
+struct CommandPipeline {
+    behavior: RedisBehavior,
+    pipeline: Pipeline,
+    max_push_keys: HashSet<String>,
+    size_estimate: usize,
+}
+
+impl CommandPipeline {
+    fn new(behavior: RedisBehavior) -> Self {
+        Self {
+            behavior,
+            pipeline: redis::pipe(),
+            max_push_keys: HashSet::new(),
+            size_estimate: 0,
+        }
+    }
+
+    fn add(&mut self, cmd: RedisCmd) {
+        match cmd {
+            RedisCmd::Data { key, value } => {
+                self.size_estimate += key.len() + value.len();
+
+                match self.behavior {
+                    RedisBehavior::Set { ttl } => {
+                        // TODO: resolve duplicates before sending
+                        if let Some(ttl) = ttl {
+                            self.pipeline.set_ex(key, value, ttl as u64);
+                        } else {
+                            self.pipeline.set(key, value);
+                        }
+                    }
+                    RedisBehavior::Push { append, max } => {
+                        if max.is_some() && !self.max_push_keys.contains(&key) {
+                            self.max_push_keys.insert(key.clone());
+                        }
+
+                        if append {
+                            self.pipeline.rpush(key, value);
+                        } else {
+                            self.pipeline.lpush(key, value);
+                        }
+                    }
+                    RedisBehavior::Hash | RedisBehavior::SortedSet => {
+                        unreachable!();
+                    }
+                }
+            }
+            RedisCmd::HData { key, field, value } => {
+                self.size_estimate += key.len() + field.len() + value.len();
+
+                self.pipeline.hset(key, field, value);
+            }
+            RedisCmd::ZData { key, member, score } => {
+                self.size_estimate += key.len() + member.len() + 8;
+
+                self.pipeline.zadd(key, member, score);
+            }
+            RedisCmd::Del { key, value } => {
+                self.size_estimate += key.len() + value.len();
+
+                match self.behavior {
+                    RedisBehavior::Set { .. } => {
+                        self.pipeline.del(key);
+                    }
+                    RedisBehavior::Push { append, .. } => {
+                        // remove the most recently pushed copy of the value
+                        let count = if append { -1 } else { 1 };
+                        self.pipeline.lrem(key, count, value);
+                    }
+                    RedisBehavior::SortedSet => {
+                        self.pipeline.zrem(key, value);
+                    }
+                    RedisBehavior::Hash => {
+                        unreachable!();
+                    }
+                }
+            }
+            RedisCmd::HDel { key, field } => {
+                self.size_estimate += key.len() + field.len();
+
+                self.pipeline.hdel(key, field);
+            }
+            RedisCmd::Flush(_) => {
+                unreachable!("flushes are handled by the writer");
+            }
+        }
+    }
+
+    // trims the lists that have been pushed to since the last flush to their max length
+    fn trim_lists(&mut self) {
+        if let RedisBehavior::Push {
+            max: Some(max),
+            append,
+        } = self.behavior
+        {
+            for k in self.max_push_keys.drain() {
+                if append {
+                    self.pipeline.ltrim(k, -(max as isize), -1);
+                } else {
+                    self.pipeline.ltrim(k, 0, max as isize - 1);
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pipeline.clear();
+        self.size_estimate = 0;
+    }
+}
+
 struct RedisWriter {
     rx: Receiver<RedisCmd>,
     tx: Sender<u32>,
-    max_push_keys: HashSet<String>,
-    behavior: RedisBehavior,
     connection: GeneralConnection,
-    pipeline: Pipeline,
-    size_estimate: usize,
+    commands: CommandPipeline,
     last_flushed: Instant,
     error_reporter: ErrorReporter,
 }
@@ -115,7 +345,7 @@ impl RedisWriter {
             loop {
                 let flush_duration = FLUSH_TIMEOUT.checked_sub(self.last_flushed.elapsed());
 
-                if self.size_estimate > FLUSH_BYTES || flush_duration.is_none() {
+                if self.commands.size_estimate > FLUSH_BYTES || flush_duration.is_none() {
                     self.flush().await;
                     continue;
                 }
@@ -129,39 +359,6 @@ impl RedisWriter {
                                 info!("closing Redis writer");
                                 return;
                             }
-                            Some(RedisCmd::Data { key, value }) => {
-                                self.size_estimate += key.len() + value.len();
-
-                                match self.behavior {
-                                    RedisBehavior::Set {ttl } => {
-                                        // TODO: resolve duplicates before sending
-                                        if let Some(ttl) = ttl {
-                                            self.pipeline.set_ex(key, value, ttl as u64);
-                                        } else {
-                                            self.pipeline.set(key, value);
-                                        }
-                                    }
-                                    RedisBehavior::Push { append, max } => {
-                                        if max.is_some() && !self.max_push_keys.contains(&key) {
-                                            self.max_push_keys.insert(key.clone());
-                                        }
-
-                                        if append {
-                                            self.pipeline.rpush(key, value);
-                                        } else {
-                                            self.pipeline.lpush(key, value);
-                                        }
-                                    }
-                                    RedisBehavior::Hash => {
-                                        unreachable!();
-                                    }
-                                }
-                            }
-                            Some(RedisCmd::HData { key, field, value }) => {
-                                self.size_estimate += key.len() + field.len() + value.len();
-
-                                self.pipeline.hset(key, field, value);
-                            }
                             Some(RedisCmd::Flush(i)) => {
                                 self.flush().await;
                                 if self.tx.send(i).await.is_err() {
@@ -169,6 +366,9 @@ impl RedisWriter {
                                     return;
                                 }
                             }
+                            Some(cmd) => {
+                                self.commands.add(cmd);
+                            }
                         }
                     }
                     _ = flush_timeout => {
@@ -182,29 +382,17 @@ impl RedisWriter {
     async fn flush(&mut self) {
         let mut attempts = 0;
 
-        if let RedisBehavior::Push {
-            max: Some(max),
-            append,
-        } = self.behavior
-        {
-            for k in self.max_push_keys.drain() {
-                if append {
-                    self.pipeline.ltrim(k, -(max as isize), -1);
-                } else {
-                    self.pipeline.ltrim(k, 0, max as isize - 1);
-                }
-            }
-        }
+        self.commands.trim_lists();
 
         while attempts < 20 {
             match self
+                .commands
                 .pipeline
                 .query_async::<_, ()>(&mut self.connection)
                 .await
             {
                 Ok(_) => {
-                    self.pipeline.clear();
-                    self.size_estimate = 0;
+                    self.commands.clear();
                     self.last_flushed = Instant::now();
                     return;
                 }
@@ -245,31 +433,46 @@ impl ArrowOperator for RedisSinkFunc {
             | TableType::Target(Target::HashTable {
                 hash_key_column: Some(key),
                 ..
+            })
+            | TableType::Target(Target::SortedSetTable {
+                sorted_set_key_column: Some(key),
+                ..
             }) => {
-                self.key_index = Some(
-                    ctx.in_schemas
-                        .first()
-                        .expect("no in-schema for redis sink!")
-                        .schema
-                        .index_of(key)
-                        .unwrap_or_else(|_| {
-                            panic!(
-                                "key column ({key}) does not exist in input schema for redis sink"
-                            )
-                        }),
-                );
+                self.key_index = Some(Self::column_index(ctx, key, "key column"));
             }
             _ => {}
         }
 
+        if let TableType::Target(Target::SortedSetTable {
+            score_column,
+            member_column,
+            ..
+        }) = &self.table.connector_type
+        {
+            self.score_index = Some(Self::column_index(ctx, score_column, "score column"));
+            self.member_index = member_column
+                .as_ref()
+                .map(|c| Self::column_index(ctx, c, "member column"));
+        }
+
+        // if our input is updating, retractions are applied by deleting the previously-written value
+        self.retract_index = ctx
+            .in_schemas
+            .first()
+            .expect("no in-schema for redis sink!")
+            .schema
+            .index_of(IS_RETRACT_FIELD)
+            .ok();
+
         if let TableType::Target(Target::HashTable {
             hash_field_column, ..
         }) = &self.table.connector_type
         {
-            self.hash_index = Some(ctx.in_schemas.first().expect("no in-schema for redis sink!")
-                .schema
-                .index_of(hash_field_column)
-                .unwrap_or_else(|_| panic!("hash field column ({hash_field_column}) does not exist in input schema for redis sink")));
+            self.hash_index = Some(Self::column_index(
+                ctx,
+                hash_field_column,
+                "hash field column",
+            ));
         }
 
         let mut attempts = 0;
@@ -282,11 +485,8 @@ impl ArrowOperator for RedisSinkFunc {
                         error_reporter: ctx.error_reporter.clone(),
                         tx,
                         rx,
-                        pipeline: redis::pipe(),
-                        size_estimate: 0,
                         last_flushed: Instant::now(),
-                        max_push_keys: HashSet::new(),
-                        behavior: match self.table.connector_type {
+                        commands: CommandPipeline::new(match self.table.connector_type {
                             TableType::Target(Target::StringTable { ttl_secs, .. }) => {
                                 RedisBehavior::Set {
                                     ttl: ttl_secs.map(|t| t.get() as usize),
@@ -308,7 +508,13 @@ impl ArrowOperator for RedisSinkFunc {
                                 }
                             }
                             TableType::Target(Target::HashTable { .. }) => RedisBehavior::Hash,
-                        },
+                            TableType::Target(Target::SortedSetTable { .. }) => {
+                                RedisBehavior::SortedSet
+                            }
+                            TableType::Streams(_) => {
+                                unreachable!("redis sink configured with a stream source table")
+                            }
+                        }),
                     }
                     .start();
                     return;
@@ -326,41 +532,8 @@ impl ArrowOperator for RedisSinkFunc {
     }
 
     async fn process_batch(&mut self, batch: RecordBatch, _: &mut ArrowContext) {
-        for (i, value) in self.serializer.serialize(&batch).enumerate() {
-            match &self.table.connector_type {
-                TableType::Target(target) => match &target {
-                    Target::StringTable { key_prefix, .. } => {
-                        let key = self.make_key(key_prefix, &batch, i);
-                        self.tx
-                            .send(RedisCmd::Data { key, value })
-                            .await
-                            .expect("Redis writer panicked");
-                    }
-                    Target::ListTable { list_prefix, .. } => {
-                        let key = self.make_key(list_prefix, &batch, i);
-
-                        self.tx
-                            .send(RedisCmd::Data { key, value })
-                            .await
-                            .expect("Redis writer panicked");
-                    }
-                    Target::HashTable {
-                        hash_key_prefix, ..
-                    } => {
-                        let key = self.make_key(hash_key_prefix, &batch, i);
-                        let field = batch
-                            .column(self.hash_index.expect("no hash index"))
-                            .as_string::<i32>()
-                            .value(i)
-                            .to_string();
-
-                        self.tx
-                            .send(RedisCmd::HData { key, field, value })
-                            .await
-                            .expect("Redis writer panicked");
-                    }
-                },
-            };
+        for cmd in self.commands(&batch) {
+            self.tx.send(cmd).await.expect("Redis writer panicked");
         }
     }
 
@@ -387,3 +560,179 @@ impl ArrowOperator for RedisSinkFunc {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::RedisClient;
+    use arrow::array::{Int64Array, StringArray};
+    use arrow::datatypes::{Field, Schema};
+    use arroyo_rpc::formats::{Format, JsonFormat};
+    use serde_json::json;
+    use std::sync::Arc;
+    use tokio::sync::mpsc::channel;
+
+    // the second row is a retraction of a previously-written row
+    fn batch() -> RecordBatch {
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("key", DataType::Utf8, false),
+                Field::new("field", DataType::Utf8, false),
+                Field::new("score", DataType::Int64, false),
+                Field::new(IS_RETRACT_FIELD, DataType::Boolean, true),
+            ])),
+            vec![
+                Arc::new(StringArray::from(vec!["a", "b", "c"])),
+                Arc::new(StringArray::from(vec!["f1", "f2", "f3"])),
+                Arc::new(Int64Array::from(vec![10, 20, 30])),
+                Arc::new(BooleanArray::from(vec![Some(false), Some(true), None])),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn row(i: usize) -> Vec<u8> {
+        let (key, field, score) = [("a", "f1", 10), ("b", "f2", 20), ("c", "f3", 30)][i];
+        format!(r#"{{"key":"{key}","field":"{field}","score":{score}}}"#).into_bytes()
+    }
+
+    fn sink(target: serde_json::Value) -> RedisSinkFunc {
+        let (tx, _) = channel(1);
+        let (_, rx) = channel(1);
+        RedisSinkFunc {
+            serializer: ArrowSerializer::new(Format::Json(JsonFormat::default())),
+            table: serde_json::from_value(json!({ "connectorType": { "target": target } }))
+                .unwrap(),
+            client: RedisClient::Standard(redis::Client::open("redis://localhost").unwrap()),
+            cmd_q: None,
+            rx,
+            tx,
+            key_index: Some(0),
+            hash_index: None,
+            score_index: None,
+            member_index: None,
+            retract_index: Some(3),
+        }
+    }
+
+    fn packed(sink: &mut RedisSinkFunc, behavior: RedisBehavior) -> Vec<u8> {
+        let mut commands = CommandPipeline::new(behavior);
+        for cmd in sink.commands(&batch()) {
+            commands.add(cmd);
+        }
+        commands.trim_lists();
+        commands.pipeline.get_packed_pipeline()
+    }
+
+    #[test]
+    fn test_string_retractions() {
+        let mut sink = sink(json!({"keyPrefix": "k:", "keyColumn": "key"}));
+
+        assert_eq!(
+            packed(&mut sink, RedisBehavior::Set { ttl: None }),
+            redis::pipe()
+                .set("k:a", row(0))
+                .del("k:b")
+                .set("k:c", row(2))
+                .get_packed_pipeline()
+        );
+    }
+
+    #[test]
+    fn test_list_retractions() {
+        let mut sink = sink(json!({
+            "listPrefix": "l:",
+            "listKeyColumn": "key",
+            "operation": "Append"
+        }));
+
+        // the most recently appended copy of the value is removed
+        assert_eq!(
+            packed(
+                &mut sink,
+                RedisBehavior::Push {
+                    append: true,
+                    max: None
+                }
+            ),
+            redis::pipe()
+                .rpush("l:a", row(0))
+                .lrem("l:b", -1, row(1))
+                .rpush("l:c", row(2))
+                .get_packed_pipeline()
+        );
+
+        // or the most recently prepended one
+        assert_eq!(
+            packed(
+                &mut sink,
+                RedisBehavior::Push {
+                    append: false,
+                    max: None
+                }
+            ),
+            redis::pipe()
+                .lpush("l:a", row(0))
+                .lrem("l:b", 1, row(1))
+                .lpush("l:c", row(2))
+                .get_packed_pipeline()
+        );
+    }
+
+    #[test]
+    fn test_hash_retractions() {
+        let mut sink = sink(json!({
+            "hashKeyPrefix": "h:",
+            "hashKeyColumn": "key",
+            "hashFieldColumn": "field"
+        }));
+        sink.hash_index = Some(1);
+
+        assert_eq!(
+            packed(&mut sink, RedisBehavior::Hash),
+            redis::pipe()
+                .hset("h:a", "f1", row(0))
+                .hdel("h:b", "f2")
+                .hset("h:c", "f3", row(2))
+                .get_packed_pipeline()
+        );
+    }
+
+    #[test]
+    fn test_sorted_set() {
+        let mut sink = sink(json!({
+            "sortedSetKeyPrefix": "z:",
+            "sortedSetKeyColumn": "key",
+            "scoreColumn": "score",
+            "memberColumn": "field"
+        }));
+        sink.score_index = Some(2);
+        sink.member_index = Some(1);
+
+        assert_eq!(
+            packed(&mut sink, RedisBehavior::SortedSet),
+            redis::pipe()
+                .zadd("z:a", b"f1".to_vec(), 10.0)
+                .zrem("z:b", b"f2".to_vec())
+                .zadd("z:c", b"f3".to_vec(), 30.0)
+                .get_packed_pipeline()
+        );
+
+        // without a member column, the serialized row is the member
+        let mut sink = self::sink(json!({
+            "sortedSetKeyPrefix": "z:",
+            "sortedSetKeyColumn": "key",
+            "scoreColumn": "score"
+        }));
+        sink.score_index = Some(2);
+
+        assert_eq!(
+            packed(&mut sink, RedisBehavior::SortedSet),
+            redis::pipe()
+                .zadd("z:a", row(0), 10.0)
+                .zrem("z:b", row(1))
+                .zadd("z:c", row(2), 30.0)
+                .get_packed_pipeline()
+        );
+    }
+}
@@ -0,0 +1,822 @@
+use crate::redis::{RedisClient, StartPosition};
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::SourceOperator;
+use arroyo_operator::SourceFinishType;
+use arroyo_rpc::formats::{BadData, Format, Framing};
+use arroyo_rpc::grpc::{GlobalKeyedTableConfig, TableConfig, TableEnum};
+use arroyo_rpc::{grpc::StopMode, ControlMessage, ControlResp};
+use arroyo_types::{from_millis, UserError};
+use async_trait::async_trait;
+use bincode::config;
+use prost::Message;
+use redis::streams::{
+    StreamClaimReply, StreamId, StreamInfoConsumersReply, StreamPendingCountReply,
+    StreamReadOptions, StreamReadReply,
+};
+use redis::AsyncCommands;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::select;
+use tracing::{debug, info, warn};
+
+use super::sink::GeneralConnection;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const RECOVERY_SUFFIX: &str = "-recovery";
+
+pub struct RedisStreamSourceFunc {
+    pub client: RedisClient,
+    pub stream_keys: Vec<String>,
+    pub group_name: String,
+    pub start_position: StartPosition,
+    pub value_field: String,
+    pub batch_size: usize,
+    pub format: Format,
+    pub framing: Option<Framing>,
+    pub bad_data: Option<BadData>,
+}
+
+/// Compares Redis stream IDs, which have the form `<millis>-<sequence>`
+fn parse_id(id: &str) -> Option<(u64, u64)> {
+    let (millis, seq) = id.split_once('-')?;
+    Some((millis.parse().ok()?, seq.parse().ok()?))
+}
+
+fn consumer_prefix(job_id: &str, operator_id: &str) -> String {
+    format!("arroyo-{}-{}-", job_id, operator_id)
+}
+
+/// Returns whether the subtask should take over the pending entries of another consumer in the
+/// group, which is the case for consumers (and their recovery consumers) of subtasks that no
+/// longer exist after the pipeline was rescaled
+fn should_take_over(consumer: &str, prefix: &str, task_index: usize, parallelism: usize) -> bool {
+    let Some(rest) = consumer.strip_prefix(prefix) else {
+        return false;
+    };
+    let index = rest.strip_suffix(RECOVERY_SUFFIX).unwrap_or(rest);
+    match index.parse::<usize>() {
+        Ok(index) => index >= parallelism && index % parallelism == task_index,
+        Err(_) => false,
+    }
+}
+
+/// What the restored checkpoint says about the entries a consumer had emitted
+#[derive(Debug, PartialEq)]
+enum Emitted {
+    /// Regular consumers emit entries in order, so every entry up to this id has been emitted
+    UpTo(String),
+    /// Entries claimed from other consumers are emitted out of order, so their ids are stored
+    Ids(HashSet<String>),
+    Nothing,
+}
+
+impl Emitted {
+    fn contains(&self, id: &str) -> bool {
+        match self {
+            Emitted::UpTo(last_id) => parse_id(id) <= parse_id(last_id),
+            Emitted::Ids(ids) => ids.contains(id),
+            Emitted::Nothing => false,
+        }
+    }
+}
+
+struct RestoredState {
+    // (stream, consumer) -> last id emitted by a regular consumer
+    last_ids: HashMap<(String, String), String>,
+    // (stream, recovery consumer) -> emitted ids that were still unacked
+    claimed: HashMap<(String, String), Vec<String>>,
+}
+
+impl RestoredState {
+    fn emitted(&self, key: &str, consumer: &str) -> Emitted {
+        let state_key = (key.to_string(), consumer.to_string());
+        if consumer.ends_with(RECOVERY_SUFFIX) {
+            match self.claimed.get(&state_key) {
+                Some(ids) => Emitted::Ids(ids.iter().cloned().collect()),
+                None => Emitted::Nothing,
+            }
+        } else {
+            match self.last_ids.get(&state_key) {
+                Some(id) => Emitted::UpTo(id.clone()),
+                None => Emitted::Nothing,
+            }
+        }
+    }
+
+    fn last_ids(&self, consumer: &str) -> HashMap<String, String> {
+        self.last_ids
+            .iter()
+            .filter(|((_, c), _)| c == consumer)
+            .map(|((key, _), id)| (key.clone(), id.clone()))
+            .collect()
+    }
+}
+
+struct StreamReader {
+    connection: GeneralConnection,
+    consumer_name: String,
+    // entries taken over from other consumers are claimed by this consumer, so that the ids it
+    // has emitted can be tracked separately from the in-order ids of the main consumer
+    recovery_consumer: String,
+    // the ID of the last entry we've emitted for each stream
+    last_ids: HashMap<String, String>,
+    // the IDs of the entries the recovery consumer has emitted but not yet acked, by stream
+    claimed: HashMap<String, HashSet<String>>,
+    // IDs that have been emitted in the current epoch, which will be acked once the epoch's
+    // checkpoint has been committed
+    unacked: HashMap<String, Vec<String>>,
+}
+
+impl RedisStreamSourceFunc {
+    async fn create_groups(&self, connection: &mut GeneralConnection) -> Result<(), UserError> {
+        let start_id = match self.start_position {
+            StartPosition::Latest => "$",
+            StartPosition::Earliest => "0",
+        };
+
+        for key in &self.stream_keys {
+            match connection
+                .xgroup_create_mkstream::<_, _, _, ()>(key, &self.group_name, start_id)
+                .await
+            {
+                Ok(_) => {
+                    info!(
+                        "Created consumer group {} for stream {}",
+                        self.group_name, key
+                    );
+                }
+                // the group already exists
+                Err(e) if e.code() == Some("BUSYGROUP") => {}
+                Err(e) => {
+                    return Err(UserError::new(
+                        "Failed to create Redis consumer group",
+                        format!(
+                            "could not create consumer group {} for stream {}: {:?}",
+                            self.group_name, key, e
+                        ),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the next batch of entries from each stream, returning the number of entries read.
+    /// Reading from `0` returns entries that were previously delivered to this consumer but not
+    /// acked, while `>` returns new entries.
+    async fn read_streams(
+        &self,
+        reader: &mut StreamReader,
+        ctx: &mut ArrowContext,
+        pending: bool,
+    ) -> Result<usize, UserError> {
+        let mut count = 0;
+        for key in &self.stream_keys {
+            let start = if pending {
+                reader
+                    .last_ids
+                    .get(key)
+                    .cloned()
+                    .unwrap_or_else(|| "0".to_string())
+            } else {
+                ">".to_string()
+            };
+
+            let opts = StreamReadOptions::default()
+                .group(&self.group_name, &reader.consumer_name)
+                .count(self.batch_size);
+
+            // reads from different streams are issued separately, as the keys may live in
+            // different slots of a cluster
+            let reply: Option<StreamReadReply> = reader
+                .connection
+                .xread_options(&[key], &[&start], &opts)
+                .await
+                .map_err(|e| {
+                    UserError::new(
+                        "Failed to read from Redis stream",
+                        format!("XREADGROUP on {} failed: {:?}", key, e),
+                    )
+                })?;
+
+            for stream in reply.into_iter().flat_map(|r| r.keys) {
+                for entry in stream.ids {
+                    self.process_entry(reader, &stream.key, entry, false, ctx)
+                        .await?;
+                    count += 1;
+                }
+            }
+        }
+
+        Ok(count)
+    }
+
+    async fn process_entry(
+        &self,
+        reader: &mut StreamReader,
+        key: &str,
+        entry: StreamId,
+        claimed: bool,
+        ctx: &mut ArrowContext,
+    ) -> Result<(), UserError> {
+        let Some((millis, _)) = parse_id(&entry.id) else {
+            warn!("Skipping Redis stream entry with invalid id {}", entry.id);
+            return Ok(());
+        };
+
+        if let Some(value) = entry.get::<Vec<u8>>(&self.value_field) {
+            ctx.deserialize_slice(&value, from_millis(millis), None)
+                .await?;
+        } else {
+            ctx.report_error(
+                "Missing value field",
+                format!(
+                    "entry {} in stream {} does not have a '{}' field",
+                    entry.id, key, self.value_field
+                ),
+            )
+            .await;
+        }
+
+        if ctx.should_flush() {
+            ctx.flush_buffer().await?;
+        }
+
+        reader
+            .unacked
+            .entry(key.to_string())
+            .or_default()
+            .push(entry.id.clone());
+        if claimed {
+            reader
+                .claimed
+                .entry(key.to_string())
+                .or_default()
+                .insert(entry.id);
+        } else {
+            reader.last_ids.insert(key.to_string(), entry.id);
+        }
+
+        Ok(())
+    }
+
+    async fn ack_ids(
+        &self,
+        reader: &mut StreamReader,
+        key: &str,
+        ids: &[String],
+    ) -> Result<(), UserError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        reader
+            .connection
+            .xack::<_, _, _, i64>(key, &self.group_name, ids)
+            .await
+            .map_err(|e| {
+                UserError::new(
+                    "Failed to ack Redis stream entries",
+                    format!("XACK on {} failed: {:?}", key, e),
+                )
+            })?;
+        Ok(())
+    }
+
+    /// Replays the entries pending for our recovery consumer that the restored checkpoint
+    /// doesn't cover, and acks the ones it does
+    async fn recover_claimed(
+        &self,
+        reader: &mut StreamReader,
+        restored: &RestoredState,
+        ctx: &mut ArrowContext,
+    ) -> Result<(), UserError> {
+        for key in &self.stream_keys {
+            let emitted = restored.emitted(key, &reader.recovery_consumer);
+            let opts = StreamReadOptions::default()
+                .group(&self.group_name, &reader.recovery_consumer)
+                .count(self.batch_size);
+
+            let mut start = "0".to_string();
+            loop {
+                let reply: Option<StreamReadReply> = reader
+                    .connection
+                    .xread_options(&[key], &[&start], &opts)
+                    .await
+                    .map_err(|e| {
+                        UserError::new(
+                            "Failed to read from Redis stream",
+                            format!("XREADGROUP on {} failed: {:?}", key, e),
+                        )
+                    })?;
+
+                let entries: Vec<_> = reply
+                    .into_iter()
+                    .flat_map(|r| r.keys)
+                    .flat_map(|k| k.ids)
+                    .collect();
+                let Some(last) = entries.last() else {
+                    break;
+                };
+                start = last.id.clone();
+
+                let mut to_ack = vec![];
+                for entry in entries {
+                    if emitted.contains(&entry.id) {
+                        to_ack.push(entry.id);
+                    } else {
+                        self.process_entry(reader, key, entry, true, ctx).await?;
+                    }
+                }
+                self.ack_ids(reader, key, &to_ack).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes over the entries pending for the consumers of subtasks that no longer exist after a
+    /// rescale: those covered by the restored checkpoint are acked, while the rest are claimed by
+    /// our recovery consumer and replayed
+    async fn take_over_consumers(
+        &self,
+        reader: &mut StreamReader,
+        restored: &RestoredState,
+        ctx: &mut ArrowContext,
+    ) -> Result<(), UserError> {
+        let prefix = consumer_prefix(&ctx.task_info.job_id, &ctx.task_info.operator_id);
+        let task_index = ctx.task_info.task_index;
+        let parallelism = ctx.task_info.parallelism;
+
+        for key in &self.stream_keys {
+            let consumers: StreamInfoConsumersReply = reader
+                .connection
+                .xinfo_consumers(key, &self.group_name)
+                .await
+                .map_err(|e| {
+                    UserError::new(
+                        "Failed to list Redis stream consumers",
+                        format!("XINFO CONSUMERS on {} failed: {:?}", key, e),
+                    )
+                })?;
+
+            for consumer in consumers.consumers {
+                if !should_take_over(&consumer.name, &prefix, task_index, parallelism) {
+                    continue;
+                }
+
+                info!(
+                    "Taking over {} pending entries of consumer {} for stream {}",
+                    consumer.pending, consumer.name, key
+                );
+                let emitted = restored.emitted(key, &consumer.name);
+                loop {
+                    // entries leave the consumer's pending list as they're acked or claimed
+                    let pending: StreamPendingCountReply = reader
+                        .connection
+                        .xpending_consumer_count(
+                            key,
+                            &self.group_name,
+                            "-",
+                            "+",
+                            self.batch_size,
+                            &consumer.name,
+                        )
+                        .await
+                        .map_err(|e| {
+                            UserError::new(
+                                "Failed to read pending Redis stream entries",
+                                format!("XPENDING on {} failed: {:?}", key, e),
+                            )
+                        })?;
+                    if pending.ids.is_empty() {
+                        break;
+                    }
+
+                    let (mut to_ack, to_claim): (Vec<_>, Vec<_>) = pending
+                        .ids
+                        .into_iter()
+                        .map(|pending| pending.id)
+                        .partition(|id| emitted.contains(id));
+
+                    if !to_claim.is_empty() {
+                        let claimed: StreamClaimReply = reader
+                            .connection
+                            .xclaim(
+                                key,
+                                &self.group_name,
+                                &reader.recovery_consumer,
+                                0,
+                                &to_claim,
+                            )
+                            .await
+                            .map_err(|e| {
+                                UserError::new(
+                                    "Failed to claim Redis stream entries",
+                                    format!("XCLAIM on {} failed: {:?}", key, e),
+                                )
+                            })?;
+
+                        // entries that have been deleted from the stream can't be claimed, so
+                        // ack them to remove them from the pending list
+                        let claimed_ids: HashSet<_> =
+                            claimed.ids.iter().map(|entry| entry.id.clone()).collect();
+                        to_ack.extend(to_claim.into_iter().filter(|id| !claimed_ids.contains(id)));
+
+                        for entry in claimed.ids {
+                            self.process_entry(reader, key, entry, true, ctx).await?;
+                        }
+                    }
+                    self.ack_ids(reader, key, &to_ack).await?;
+                }
+
+                if let Err(e) = reader
+                    .connection
+                    .xgroup_delconsumer::<_, _, _, i64>(key, &self.group_name, &consumer.name)
+                    .await
+                {
+                    warn!(
+                        "Failed to delete consumer {} of stream {}: {:?}",
+                        consumer.name, key, e
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// On restore, entries that were delivered to us but not acked fall into two groups: those
+    /// covered by the restored checkpoint (which only need to be acked) and those that were read
+    /// after it (which need to be replayed)
+    async fn recover_pending(
+        &self,
+        reader: &mut StreamReader,
+        ctx: &mut ArrowContext,
+    ) -> Result<(), UserError> {
+        for key in &self.stream_keys {
+            let Some(last_id) = reader.last_ids.get(key).cloned() else {
+                continue;
+            };
+
+            let opts = StreamReadOptions::default()
+                .group(&self.group_name, &reader.consumer_name)
+                .count(self.batch_size);
+
+            let mut start = "0".to_string();
+            let mut to_ack = vec![];
+            loop {
+                let reply: Option<StreamReadReply> = reader
+                    .connection
+                    .xread_options(&[key], &[&start], &opts)
+                    .await
+                    .map_err(|e| {
+                        UserError::new(
+                            "Failed to read from Redis stream",
+                            format!("XREADGROUP on {} failed: {:?}", key, e),
+                        )
+                    })?;
+
+                let entries: Vec<_> = reply
+                    .into_iter()
+                    .flat_map(|r| r.keys)
+                    .flat_map(|k| k.ids)
+                    .collect();
+
+                let Some(last) = entries.last() else {
+                    break;
+                };
+                start = last.id.clone();
+
+                let mut done = false;
+                for entry in entries {
+                    if parse_id(&entry.id) <= parse_id(&last_id) {
+                        to_ack.push(entry.id);
+                    } else {
+                        done = true;
+                        break;
+                    }
+                }
+
+                if done {
+                    break;
+                }
+            }
+
+            if !to_ack.is_empty() {
+                debug!(
+                    "Acking {} entries from {} covered by the restored checkpoint",
+                    to_ack.len(),
+                    key
+                );
+            }
+            self.ack_ids(reader, key, &to_ack).await?;
+        }
+
+        // anything left in the pending list was read after the checkpoint, so replay it
+        while self.read_streams(reader, ctx, true).await? > 0 {}
+
+        Ok(())
+    }
+
+    async fn ack(
+        &self,
+        reader: &mut StreamReader,
+        commit_data: &HashMap<String, HashMap<u32, Vec<u8>>>,
+        task_index: usize,
+    ) {
+        let Some(data) = commit_data
+            .get("a")
+            .and_then(|data| data.get(&(task_index as u32)))
+        else {
+            return;
+        };
+
+        let ids: HashMap<String, Vec<String>> =
+            match bincode::decode_from_slice(data, config::standard()) {
+                Ok((ids, _)) => ids,
+                Err(e) => {
+                    warn!("Failed to decode Redis stream commit data: {:?}", e);
+                    return;
+                }
+            };
+
+        for (key, ids) in ids {
+            if ids.is_empty() {
+                continue;
+            }
+
+            // if this fails the entries will stay in our pending list, and will be acked after
+            // the next restore
+            if let Err(e) = reader
+                .connection
+                .xack::<_, _, _, i64>(&key, &self.group_name, &ids)
+                .await
+            {
+                warn!("Failed to ack Redis stream entries for {}: {:?}", key, e);
+                continue;
+            }
+
+            if let Some(claimed) = reader.claimed.get_mut(&key) {
+                for id in &ids {
+                    claimed.remove(id);
+                }
+            }
+        }
+    }
+
+    async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
+        let mut connection = self
+            .client
+            .get_connection()
+            .await
+            .map_err(|e| UserError::new("Failed to connect to Redis", format!("{:?}", e)))?;
+
+        self.create_groups(&mut connection).await?;
+
+        let consumer_name = format!(
+            "{}{}",
+            consumer_prefix(&ctx.task_info.job_id, &ctx.task_info.operator_id),
+            ctx.task_info.task_index
+        );
+        let recovery_consumer = format!("{}{}", consumer_name, RECOVERY_SUFFIX);
+
+        let restored = RestoredState {
+            last_ids: ctx
+                .table_manager
+                .get_global_keyed_state::<(String, String), String>("s")
+                .await
+                .map_err(|e| UserError::new("failed to get global key value", e.to_string()))?
+                .get_all()
+                .clone(),
+            claimed: ctx
+                .table_manager
+                .get_global_keyed_state::<(String, String), Vec<String>>("c")
+                .await
+                .map_err(|e| UserError::new("failed to get global key value", e.to_string()))?
+                .get_all()
+                .clone(),
+        };
+
+        let mut reader = StreamReader {
+            connection,
+            last_ids: restored.last_ids(&consumer_name),
+            consumer_name,
+            recovery_consumer,
+            claimed: HashMap::new(),
+            unacked: HashMap::new(),
+        };
+
+        ctx.initialize_deserializer(
+            self.format.clone(),
+            self.framing.clone(),
+            self.bad_data.clone(),
+            &[],
+        );
+
+        self.recover_pending(&mut reader, ctx).await?;
+        self.recover_claimed(&mut reader, &restored, ctx).await?;
+        self.take_over_consumers(&mut reader, &restored, ctx)
+            .await?;
+
+        let mut read_delay = Duration::ZERO;
+
+        loop {
+            select! {
+                _ = tokio::time::sleep(read_delay) => {
+                    let count = self.read_streams(&mut reader, ctx, false).await?;
+                    read_delay = if count == 0 {
+                        POLL_INTERVAL
+                    } else {
+                        Duration::ZERO
+                    };
+
+                    if ctx.should_flush() {
+                        ctx.flush_buffer().await?;
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    match control_message {
+                        Some(ControlMessage::Checkpoint(c)) => {
+                            debug!("starting checkpointing {}", ctx.task_info.task_index);
+                            let s = ctx.table_manager.get_global_keyed_state("s").await
+                                .map_err(|err| UserError::new("failed to get global key value", err.to_string()))?;
+                            for (key, id) in &reader.last_ids {
+                                s.insert((key.clone(), reader.consumer_name.clone()), id.clone()).await;
+                            }
+                            let claimed = ctx.table_manager.get_global_keyed_state("c").await
+                                .map_err(|err| UserError::new("failed to get global key value", err.to_string()))?;
+                            for (key, ids) in &reader.claimed {
+                                if !ids.is_empty() {
+                                    claimed.insert((key.clone(), reader.recovery_consumer.clone()), ids.iter().cloned().collect::<Vec<_>>()).await;
+                                }
+                            }
+
+                            let unacked = std::mem::take(&mut reader.unacked);
+                            ctx.table_manager
+                                .insert_committing_data("a", bincode::encode_to_vec(&unacked, config::standard()).unwrap())
+                                .await
+                                .map_err(|err| UserError::new("failed to write committing data", err.to_string()))?;
+
+                            if self.start_checkpoint(c, ctx).await {
+                                return Ok(SourceFinishType::Immediate);
+                            }
+                        },
+                        Some(ControlMessage::Commit { epoch, commit_data }) => {
+                            debug!("acking entries for epoch {}", epoch);
+                            self.ack(&mut reader, &commit_data, ctx.task_info.task_index).await;
+                        }
+                        Some(ControlMessage::Stop { mode }) => {
+                            info!("Stopping Redis stream source: {:?}", mode);
+
+                            match mode {
+                                StopMode::Graceful => {
+                                    return Ok(SourceFinishType::Graceful);
+                                }
+                                StopMode::Immediate => {
+                                    return Ok(SourceFinishType::Immediate);
+                                }
+                            }
+                        }
+                        Some(ControlMessage::LoadCompacted { compacted }) => {
+                            ctx.load_compacted(compacted).await;
+                        }
+                        Some(ControlMessage::NoOp) | None => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SourceOperator for RedisStreamSourceFunc {
+    fn name(&self) -> String {
+        format!("redis-streams-{}", self.stream_keys.join(","))
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        let mut tables = arroyo_state::global_table_config("s", "redis stream ids");
+        tables.extend(arroyo_state::global_table_config(
+            "c",
+            "redis stream entries claimed from other consumers",
+        ));
+        tables.insert(
+            "a".into(),
+            TableConfig {
+                table_type: TableEnum::GlobalKeyValue.into(),
+                config: GlobalKeyedTableConfig {
+                    table_name: "a".into(),
+                    description: "stream entries to ack".into(),
+                    uses_two_phase_commit: true,
+                }
+                .encode_to_vec(),
+            },
+        );
+        tables
+    }
+
+    async fn run(&mut self, ctx: &mut ArrowContext) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.control_tx
+                    .send(ControlResp::Error {
+                        operator_id: ctx.task_info.operator_id.clone(),
+                        task_index: ctx.task_info.task_index,
+                        message: e.name.clone(),
+                        details: e.details.clone(),
+                    })
+                    .await
+                    .unwrap();
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(parse_id("1526919030474-55"), Some((1526919030474, 55)));
+        assert_eq!(parse_id("1526919030474"), None);
+        assert_eq!(parse_id("a-1"), None);
+        // ids compare numerically rather than lexicographically
+        assert!(parse_id("10-0") > parse_id("9-1"));
+    }
+
+    #[test]
+    fn test_should_take_over() {
+        let prefix = consumer_prefix("job", "op");
+        let take_over = |consumer: &str, task_index| {
+            should_take_over(&format!("{}{}", prefix, consumer), &prefix, task_index, 2)
+        };
+
+        // consumers of subtasks that still exist are left alone
+        assert!(!take_over("0", 0));
+        assert!(!take_over("1", 0));
+        assert!(!take_over("0-recovery", 0));
+        assert!(!take_over("1-recovery", 1));
+
+        // the consumers of removed subtasks are spread over the remaining ones
+        assert!(take_over("2", 0));
+        assert!(!take_over("2", 1));
+        assert!(take_over("3", 1));
+        assert!(take_over("4", 0));
+        assert!(take_over("3-recovery", 1));
+        assert!(!take_over("3-recovery", 0));
+
+        assert!(!should_take_over("arroyo-job-other-2", &prefix, 0, 2));
+        assert!(!should_take_over("other-consumer", &prefix, 0, 2));
+        assert!(!take_over("2-other", 0));
+    }
+
+    #[test]
+    fn test_restored_state() {
+        let stream = "events".to_string();
+        let restored = RestoredState {
+            last_ids: [
+                ((stream.clone(), "c0".to_string()), "5-0".to_string()),
+                ((stream.clone(), "c1".to_string()), "9-0".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            claimed: [(
+                (stream.clone(), format!("c0{}", RECOVERY_SUFFIX)),
+                vec!["3-0".to_string(), "7-1".to_string()],
+            )]
+            .into_iter()
+            .collect(),
+        };
+
+        assert_eq!(
+            restored.last_ids("c0"),
+            [(stream.clone(), "5-0".to_string())].into_iter().collect()
+        );
+        assert!(restored.last_ids("c2").is_empty());
+
+        let emitted = restored.emitted(&stream, "c0");
+        assert_eq!(emitted, Emitted::UpTo("5-0".to_string()));
+        assert!(emitted.contains("4-9"));
+        assert!(emitted.contains("5-0"));
+        assert!(!emitted.contains("5-1"));
+        assert!(!emitted.contains("10-0"));
+
+        // claimed entries are emitted out of order, so only the stored ids are covered
+        let emitted = restored.emitted(&stream, &format!("c0{}", RECOVERY_SUFFIX));
+        assert!(emitted.contains("3-0"));
+        assert!(emitted.contains("7-1"));
+        assert!(!emitted.contains("5-0"));
+        assert!(!emitted.contains("1-0"));
+
+        assert_eq!(restored.emitted(&stream, "c2"), Emitted::Nothing);
+        assert_eq!(
+            restored.emitted(&stream, &format!("c1{}", RECOVERY_SUFFIX)),
+            Emitted::Nothing
+        );
+        assert_eq!(restored.emitted("other", "c0"), Emitted::Nothing);
+        assert!(!Emitted::Nothing.contains("0-0"));
+    }
+}
@@ -34,6 +34,7 @@ impl SingleFileSourceFunc {
             self.format.clone(),
             self.framing.clone(),
             self.bad_data.clone(),
+            &[],
         );
 
         let state: &mut arroyo_state::tables::global_keyed_map::GlobalKeyedView<String, usize> =
@@ -52,7 +53,7 @@ impl SingleFileSourceFunc {
                 continue;
             }
 
-            ctx.deserialize_slice(s.as_bytes(), SystemTime::now())
+            ctx.deserialize_slice(s.as_bytes(), SystemTime::now(), None)
                 .await
                 .unwrap();
             if ctx.should_flush() {
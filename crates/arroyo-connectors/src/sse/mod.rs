@@ -19,7 +19,7 @@ use arroyo_rpc::api_types::connections::{
 use serde::{Deserialize, Serialize};
 
 use crate::sse::operator::SSESourceFunc;
-use crate::{pull_opt, EmptyConfig};
+use crate::{pull_opt, pull_option_to_i64, EmptyConfig};
 
 use arroyo_operator::connector::Connector;
 
@@ -90,6 +90,14 @@ impl Connector for SSEConnector {
             })?;
         }
 
+        if table.max_reconnect_attempts.is_some_and(|a| a < 0) {
+            bail!("max_reconnect_attempts must not be negative");
+        }
+
+        if table.heartbeat_timeout_secs.is_some_and(|t| t <= 0) {
+            bail!("heartbeat_timeout_secs must be positive");
+        }
+
         let schema = schema
             .map(|s| s.to_owned())
             .ok_or_else(|| anyhow!("no schema defined for SSE connection"))?;
@@ -107,6 +115,7 @@ impl Connector for SSEConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            metadata_fields: vec![],
         };
 
         Ok(Connection {
@@ -130,6 +139,8 @@ impl Connector for SSEConnector {
         let endpoint = pull_opt("endpoint", options)?;
         let headers = options.remove("headers");
         let events = options.remove("events");
+        let max_reconnect_attempts = pull_option_to_i64("max_reconnect_attempts", options)?;
+        let heartbeat_timeout_secs = pull_option_to_i64("heartbeat_timeout_secs", options)?;
 
         self.from_config(
             None,
@@ -139,6 +150,8 @@ impl Connector for SSEConnector {
                 endpoint,
                 events,
                 headers: headers.map(VarStr::new),
+                max_reconnect_attempts,
+                heartbeat_timeout_secs,
             },
             schema,
         )
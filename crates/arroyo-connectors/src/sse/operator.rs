@@ -4,18 +4,33 @@ use arroyo_operator::operator::{OperatorNode, SourceOperator};
 use arroyo_operator::SourceFinishType;
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::{StopMode, TableConfig};
-use arroyo_rpc::{ControlMessage, ControlResp, OperatorConfig};
+use arroyo_rpc::{ControlMessage, OperatorConfig};
 use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
 use arroyo_types::{string_to_map, ArrowMessage, SignalMessage, UserError, Watermark};
 use async_trait::async_trait;
 use bincode::{Decode, Encode};
-use eventsource_client::{Client, SSE};
+use eventsource_client::{Client, ReconnectOptions, SSE};
+use futures::stream::BoxStream;
 use futures::StreamExt;
 use std::collections::{HashMap, HashSet};
 use std::time::{Duration, SystemTime};
 use tokio::select;
-use tokio::time::MissedTickBehavior;
-use tracing::{debug, info};
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+const INITIAL_RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+fn reconnect_backoff(attempt: u32) -> Duration {
+    INITIAL_RECONNECT_BACKOFF
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_RECONNECT_BACKOFF)
+}
+
+enum ReadResult {
+    Finished(SourceFinishType),
+    Disconnected(String),
+}
 
 #[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd, Default)]
 pub struct SSESourceState {
@@ -29,6 +44,8 @@ pub struct SSESourceFunc {
     format: Format,
     framing: Option<Framing>,
     bad_data: Option<BadData>,
+    max_reconnect_attempts: Option<u32>,
+    heartbeat_timeout: Option<Duration>,
     state: SSESourceState,
 }
 
@@ -52,6 +69,10 @@ impl SSESourceFunc {
             format: config.format.expect("SSE requires a format"),
             framing: config.framing,
             bad_data: config.bad_data,
+            max_reconnect_attempts: table.max_reconnect_attempts.map(|a| a as u32),
+            heartbeat_timeout: table
+                .heartbeat_timeout_secs
+                .map(|t| Duration::from_secs(t as u64)),
             state: SSESourceState::default(),
         })))
     }
@@ -132,85 +153,128 @@ impl SSESourceFunc {
         None
     }
 
-    async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
-        ctx.initialize_deserializer(
-            self.format.clone(),
-            self.framing.clone(),
-            self.bad_data.clone(),
-        );
-
-        let mut client = eventsource_client::ClientBuilder::for_url(&self.url).unwrap();
+    fn connect(&self) -> Result<BoxStream<'static, eventsource_client::Result<SSE>>, UserError> {
+        let mut client = eventsource_client::ClientBuilder::for_url(&self.url)
+            .map_err(|e| UserError::new("Invalid SSE endpoint", format!("{:?}", e)))?;
 
+        // resume from the last event we've emitted
         if let Some(id) = &self.state.last_id {
             client = client.last_event_id(id.clone());
         }
 
         for (k, v) in &self.headers {
-            client = client.header(k, v).unwrap();
+            client = client
+                .header(k, v)
+                .map_err(|e| UserError::new("Invalid header", format!("{}: {:?}", k, e)))?;
         }
 
-        let mut stream = client.build().stream();
-        let events: HashSet<_> = self.events.iter().cloned().collect();
+        // reconnects are handled by the source, so that they pick up our last event id and
+        // can be interleaved with control messages
+        Ok(client
+            .reconnect(ReconnectOptions::reconnect(false).build())
+            .build()
+            .stream())
+    }
 
+    /// Reads from a connection until it fails or the source is stopped
+    async fn read(
+        &mut self,
+        ctx: &mut ArrowContext,
+        stream: &mut BoxStream<'static, eventsource_client::Result<SSE>>,
+        events: &HashSet<String>,
+        attempts: &mut u32,
+    ) -> Result<ReadResult, UserError> {
         let mut flush_ticker = tokio::time::interval(Duration::from_millis(50));
         flush_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-        // since there's no way to partition across an event source, only read on the first task
-        if ctx.task_info.task_index == 0 {
-            loop {
-                select! {
-                    message = stream.next()  => {
-                        match message {
-                            Some(Ok(msg)) => {
-                                match msg {
-                                    SSE::Event(event) => {
-                                        if let Some(id) = event.id {
-                                            self.state.last_id = Some(id);
-                                        }
+        let heartbeat_timeout = self.heartbeat_timeout.unwrap_or(Duration::from_secs(3600));
+        let heartbeat = tokio::time::sleep(heartbeat_timeout);
+        tokio::pin!(heartbeat);
 
-                                        if events.is_empty() || events.contains(&event.event_type) {
-                                            ctx.deserialize_slice(
-                                                event.data.as_bytes(), SystemTime::now()).await?;
+        loop {
+            select! {
+                message = stream.next()  => {
+                    match message {
+                        Some(Ok(msg)) => {
+                            // comments count as heartbeats, as servers commonly use them as keep-alives
+                            heartbeat.as_mut().reset(Instant::now() + heartbeat_timeout);
+                            *attempts = 0;
 
-                                            if ctx.should_flush() {
-                                                ctx.flush_buffer().await?;
-                                            }
-                                        }
+                            match msg {
+                                SSE::Event(event) => {
+                                    if let Some(id) = event.id {
+                                        self.state.last_id = Some(id);
                                     }
-                                    SSE::Comment(s) => {
-                                        debug!("Received comment {:?}", s);
+
+                                    if events.is_empty() || events.contains(&event.event_type) {
+                                        ctx.deserialize_slice(
+                                            event.data.as_bytes(), SystemTime::now(), None).await?;
+
+                                        if ctx.should_flush() {
+                                            ctx.flush_buffer().await?;
+                                        }
                                     }
                                 }
-                            }
-                            Some(Err(e)) => {
-                                ctx.control_tx.send(
-                                    ControlResp::Error {
-                                        operator_id: ctx.task_info.operator_id.clone(),
-                                        task_index: ctx.task_info.task_index,
-                                        message: "Error while reading from EventSource".to_string(),
-                                        details: format!("{:?}", e)}
-                                ).await.unwrap();
-                                panic!("Error while reading from EventSource: {:?}", e);
-                            }
-                            None => {
-                                info!("Socket closed");
-                                return Ok(SourceFinishType::Final);
+                                SSE::Comment(s) => {
+                                    debug!("Received comment {:?}", s);
+                                }
                             }
                         }
-                    }
-                    control_message = ctx.control_rx.recv() => {
-                        if let Some(r) = self.our_handle_control_message(ctx, control_message).await {
-                            return Ok(r);
+                        Some(Err(e)) => {
+                            return Ok(ReadResult::Disconnected(format!("Error while reading from EventSource: {:?}", e)));
                         }
-                    }
-                    _ = flush_ticker.tick() => {
-                        if ctx.should_flush() {
-                            ctx.flush_buffer().await?;
+                        None => {
+                            return Ok(ReadResult::Disconnected("EventSource closed the connection".to_string()));
                         }
                     }
                 }
+                _ = &mut heartbeat, if self.heartbeat_timeout.is_some() => {
+                    return Ok(ReadResult::Disconnected(format!(
+                        "No messages received for {:?}", heartbeat_timeout)));
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.our_handle_control_message(ctx, control_message).await {
+                        return Ok(ReadResult::Finished(r));
+                    }
+                }
+                _ = flush_ticker.tick() => {
+                    if ctx.should_flush() {
+                        ctx.flush_buffer().await?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits out the reconnect backoff, while continuing to handle control messages
+    async fn wait(&mut self, ctx: &mut ArrowContext, delay: Duration) -> Option<SourceFinishType> {
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+
+        loop {
+            select! {
+                _ = &mut sleep => {
+                    return None;
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.our_handle_control_message(ctx, control_message).await {
+                        return Some(r);
+                    }
+                }
             }
-        } else {
+        }
+    }
+
+    async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
+        ctx.initialize_deserializer(
+            self.format.clone(),
+            self.framing.clone(),
+            self.bad_data.clone(),
+            &[],
+        );
+
+        // since there's no way to partition across an event source, only read on the first task
+        if ctx.task_info.task_index != 0 {
             // otherwise set idle and just process control messages
             ctx.broadcast(ArrowMessage::Signal(SignalMessage::Watermark(
                 Watermark::Idle,
@@ -224,5 +288,196 @@ impl SSESourceFunc {
                 }
             }
         }
+
+        let events: HashSet<_> = self.events.iter().cloned().collect();
+        let mut attempts = 0;
+
+        loop {
+            let mut stream = self.connect()?;
+
+            let reason = match self.read(ctx, &mut stream, &events, &mut attempts).await? {
+                ReadResult::Finished(r) => return Ok(r),
+                ReadResult::Disconnected(reason) => reason,
+            };
+
+            ctx.flush_buffer().await?;
+
+            if self
+                .max_reconnect_attempts
+                .is_some_and(|max| attempts >= max)
+            {
+                return Err(UserError::new(
+                    "Failed to connect to EventSource",
+                    format!(
+                        "giving up after {} reconnect attempts: {}",
+                        attempts, reason
+                    ),
+                ));
+            }
+
+            let delay = reconnect_backoff(attempts);
+            attempts += 1;
+            warn!("{}; reconnecting in {:?}", reason, delay);
+            ctx.report_error("EventSource disconnected", reason).await;
+
+            if let Some(r) = self.wait(ctx, delay).await {
+                return Ok(r);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use arroyo_operator::context::{batch_bounded, BatchReceiver};
+    use arroyo_rpc::df::ArroyoSchema;
+    use arroyo_rpc::formats::JsonFormat;
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+
+    /// Accepts the next connection and starts an event stream on it, returning the
+    /// Last-Event-ID the client sent
+    async fn accept(listener: &TcpListener) -> (TcpStream, Option<String>) {
+        let (stream, _) = timeout(Duration::from_secs(10), listener.accept())
+            .await
+            .expect("source did not reconnect")
+            .unwrap();
+
+        let mut reader = BufReader::new(stream);
+        let mut last_id = None;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("last-event-id") {
+                    last_id = Some(value.trim().to_string());
+                }
+            }
+        }
+
+        let mut stream = reader.into_inner();
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        (stream, last_id)
+    }
+
+    async fn send_event(stream: &mut TcpStream, id: &str, value: &str) {
+        stream
+            .write_all(format!("id: {}\ndata: {{\"value\": \"{}\"}}\n\n", id, value).as_bytes())
+            .await
+            .unwrap();
+    }
+
+    async fn next_values(data_rx: &mut BatchReceiver, count: usize) -> Vec<String> {
+        let mut values = vec![];
+        while values.len() < count {
+            match timeout(Duration::from_secs(10), data_rx.recv())
+                .await
+                .expect("timed out waiting for data")
+            {
+                Some(ArrowMessage::Data(batch)) => {
+                    let column = batch
+                        .column(1)
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .unwrap();
+                    values.extend(column.iter().map(|v| v.unwrap().to_string()));
+                }
+                Some(ArrowMessage::Signal(_)) => {}
+                None => panic!("source stopped"),
+            }
+        }
+        values
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let mut source = SSESourceFunc {
+            url: format!("http://{}/events", listener.local_addr().unwrap()),
+            headers: vec![],
+            events: vec![],
+            format: Format::Json(JsonFormat::default()),
+            framing: None,
+            bad_data: None,
+            max_reconnect_attempts: None,
+            heartbeat_timeout: Some(Duration::from_secs(1)),
+            state: SSESourceState::default(),
+        };
+
+        let (control_tx, control_rx) = mpsc::channel(16);
+        let (command_tx, _command_rx) = mpsc::channel(128);
+        let (data_tx, mut data_rx) = batch_bounded(128);
+
+        let mut ctx = ArrowContext::new(
+            arroyo_types::get_test_task_info(),
+            None,
+            control_rx,
+            command_tx,
+            vec![1],
+            vec![],
+            Some(ArroyoSchema::new_unkeyed(
+                Arc::new(Schema::new(vec![
+                    Field::new(
+                        "_timestamp",
+                        DataType::Timestamp(TimeUnit::Nanosecond, None),
+                        false,
+                    ),
+                    Field::new("value", DataType::Utf8, false),
+                ])),
+                0,
+            )),
+            None,
+            vec![vec![data_tx]],
+            source.tables(),
+        )
+        .await;
+
+        let task = tokio::spawn(async move { source.run(&mut ctx).await });
+
+        // the first connection is dropped after two events
+        let (mut stream, last_id) = accept(&listener).await;
+        assert_eq!(last_id, None);
+        send_event(&mut stream, "1", "a").await;
+        send_event(&mut stream, "2", "b").await;
+        assert_eq!(next_values(&mut data_rx, 2).await, vec!["a", "b"]);
+        drop(stream);
+
+        // the source resumes from the last event it received, and the next connection stalls
+        let (mut stalled, last_id) = accept(&listener).await;
+        assert_eq!(last_id.as_deref(), Some("2"));
+        send_event(&mut stalled, "3", "c").await;
+        assert_eq!(next_values(&mut data_rx, 1).await, vec!["c"]);
+        let stalled_at = Instant::now();
+
+        // which the heartbeat timeout detects
+        let (_stream, last_id) = accept(&listener).await;
+        assert_eq!(last_id.as_deref(), Some("3"));
+        assert!(stalled_at.elapsed() >= Duration::from_millis(900));
+        drop(stalled);
+
+        control_tx
+            .send(ControlMessage::Stop {
+                mode: StopMode::Immediate,
+            })
+            .await
+            .unwrap();
+        assert!(matches!(task.await.unwrap(), SourceFinishType::Immediate));
     }
 }
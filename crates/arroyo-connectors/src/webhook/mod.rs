@@ -2,9 +2,9 @@ mod operator;
 
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use arroyo_rpc::OperatorConfig;
 
 use arroyo_formats::ser::ArrowSerializer;
@@ -13,6 +13,7 @@ use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
 };
 use arroyo_rpc::var_str::VarStr;
+use reqwest::header::HeaderName;
 use reqwest::{Client, Request};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -20,9 +21,9 @@ use tokio::sync::mpsc::Sender;
 use tokio::sync::{Mutex, Semaphore};
 use typify::import_types;
 
-use crate::{construct_http_client, pull_opt, EmptyConfig};
+use crate::{construct_http_client, pull_opt, pull_option_to_i64, EmptyConfig};
 
-use crate::webhook::operator::WebhookSinkFunc;
+use crate::webhook::operator::{RetryPolicy, WebhookSinkFunc};
 use arroyo_operator::connector::Connector;
 use arroyo_operator::operator::OperatorNode;
 
@@ -32,6 +33,58 @@ import_types!(schema = "src/webhook/table.json", convert = { {type = "string", f
 const ICON: &str = include_str!("./webhook.svg");
 
 const MAX_INFLIGHT: u32 = 50;
+const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_RETRIES: usize = 20;
+const DEFAULT_RETRY_STATUS_CODES: &[&str] = &["408", "429", "5xx"];
+
+/// Matches HTTP status codes, either exactly (`503`) or by class (`5xx`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusMatcher {
+    Code(u16),
+    Class(u16),
+}
+
+impl StatusMatcher {
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim().to_lowercase();
+        if let Some(class) = s.strip_suffix("xx") {
+            if let Ok(class @ 1..=5) = class.parse::<u16>() {
+                return Ok(StatusMatcher::Class(class));
+            }
+        } else if let Ok(code @ 100..=599) = s.parse::<u16>() {
+            return Ok(StatusMatcher::Code(code));
+        }
+
+        bail!(
+            "invalid status code '{}'; expected a code like `429` or a class like `5xx`",
+            s
+        )
+    }
+
+    fn matches(&self, status: u16) -> bool {
+        match self {
+            StatusMatcher::Code(code) => *code == status,
+            StatusMatcher::Class(class) => status / 100 == *class,
+        }
+    }
+}
+
+impl WebhookTable {
+    fn retry_status_codes(&self) -> anyhow::Result<Vec<StatusMatcher>> {
+        if self.retry_status_codes.is_empty() {
+            DEFAULT_RETRY_STATUS_CODES
+                .iter()
+                .map(|s| StatusMatcher::parse(s))
+                .collect()
+        } else {
+            self.retry_status_codes
+                .iter()
+                .map(|s| StatusMatcher::parse(s))
+                .collect()
+        }
+    }
+}
 
 pub struct WebhookConnector {}
 
@@ -145,6 +198,26 @@ impl Connector for WebhookConnector {
     ) -> anyhow::Result<arroyo_operator::connector::Connection> {
         let description = format!("WebhookSink<{}>", table.endpoint.sub_env_vars()?);
 
+        if table.max_batch_size.is_some_and(|s| s <= 0) {
+            bail!("max_batch_size must be greater than 0");
+        }
+
+        if table.flush_interval_millis.is_some_and(|i| i <= 0) {
+            bail!("flush_interval_millis must be greater than 0");
+        }
+
+        if table.max_retries.is_some_and(|r| r < 0) {
+            bail!("max_retries must not be negative");
+        }
+
+        table.retry_status_codes()?;
+
+        if let Some(header) = &table.idempotency_header {
+            if HeaderName::try_from(header).is_err() {
+                bail!("invalid idempotency header name '{}'", header);
+            }
+        }
+
         let schema = schema
             .map(|s| s.to_owned())
             .ok_or_else(|| anyhow!("no schema defined for webhook connection"))?;
@@ -162,6 +235,7 @@ impl Connector for WebhookConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            metadata_fields: vec![],
         };
 
         Ok(Connection {
@@ -186,9 +260,25 @@ impl Connector for WebhookConnector {
 
         let headers = options.remove("headers").map(VarStr::new);
 
+        let batch_format = match options.remove("batch_format").as_deref() {
+            Some("json_array") => Some(BatchFormat::JsonArray),
+            Some("ndjson") => Some(BatchFormat::Ndjson),
+            Some("none") | None => None,
+            Some(other) => bail!("invalid value for batch_format '{}'", other),
+        };
+
         let table = WebhookTable {
             endpoint: VarStr::new(endpoint),
             headers,
+            batch_format,
+            max_batch_size: pull_option_to_i64("max_batch_size", options)?,
+            flush_interval_millis: pull_option_to_i64("flush_interval_millis", options)?,
+            retry_status_codes: options
+                .remove("retry_status_codes")
+                .map(|codes| codes.split(',').map(|c| c.trim().to_string()).collect())
+                .unwrap_or_default(),
+            max_retries: pull_option_to_i64("max_retries", options)?,
+            idempotency_header: options.remove("idempotency_header"),
         };
 
         let client = construct_http_client(
@@ -211,6 +301,7 @@ impl Connector for WebhookConnector {
         config: OperatorConfig,
     ) -> anyhow::Result<OperatorNode> {
         let url = table.endpoint.sub_env_vars()?;
+        let retry_status_codes = table.retry_status_codes()?;
         Ok(OperatorNode::from_operator(Box::new(WebhookSinkFunc {
             url: Arc::new(url.clone()),
             client: construct_http_client(
@@ -228,6 +319,30 @@ impl Connector for WebhookConnector {
                     .expect("No format configured for webhook sink"),
             ),
             last_reported_error_at: Arc::new(Mutex::new(SystemTime::UNIX_EPOCH)),
+            batch_format: table.batch_format,
+            max_batch_size: table
+                .max_batch_size
+                .map(|s| s as usize)
+                .unwrap_or(DEFAULT_MAX_BATCH_SIZE),
+            flush_interval: table
+                .flush_interval_millis
+                .map(|i| Duration::from_millis(i as u64))
+                .unwrap_or(DEFAULT_FLUSH_INTERVAL),
+            buffer: vec![],
+            retry_policy: Arc::new(RetryPolicy {
+                retry_status_codes,
+                max_retries: table
+                    .max_retries
+                    .map(|r| r as usize)
+                    .unwrap_or(DEFAULT_MAX_RETRIES),
+            }),
+            idempotency_header: table
+                .idempotency_header
+                .map(HeaderName::try_from)
+                .transpose()?,
+            failure: Arc::new(std::sync::Mutex::new(None)),
+            epoch: 1,
+            sequence: 0,
         })))
     }
 }
@@ -1,17 +1,19 @@
 use arrow::array::RecordBatch;
 use async_trait::async_trait;
+use reqwest::header::{HeaderName, HeaderValue, CONTENT_TYPE};
 use std::collections::HashMap;
 use std::{
     sync::Arc,
     time::{Duration, SystemTime},
 };
 
-use arroyo_types::CheckpointBarrier;
+use arroyo_types::{CheckpointBarrier, SignalMessage};
 
+use tokio::sync::mpsc::Sender;
 use tokio::sync::{Mutex, Semaphore};
 use tracing::warn;
 
-use crate::webhook::MAX_INFLIGHT;
+use crate::webhook::{BatchFormat, StatusMatcher, MAX_INFLIGHT};
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::ArrowOperator;
@@ -19,12 +21,249 @@ use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::ControlResp;
 use arroyo_state::global_table_config;
 
+pub struct RetryPolicy {
+    pub retry_status_codes: Vec<StatusMatcher>,
+    pub max_retries: usize,
+}
+
+impl RetryPolicy {
+    fn should_retry(&self, status: u16) -> bool {
+        self.retry_status_codes.iter().any(|m| m.matches(status))
+    }
+
+    fn backoff(retries: usize) -> Duration {
+        Duration::from_millis((50 * (1 << retries.min(10))).min(5_000))
+    }
+}
+
+struct WebhookRequest {
+    body: bytes::Bytes,
+    content_type: Option<&'static str>,
+    idempotency_key: Option<(HeaderName, HeaderValue)>,
+}
+
+struct ErrorReporter {
+    control_tx: Sender<ControlResp>,
+    last_reported_error_at: Arc<Mutex<SystemTime>>,
+    operator_id: String,
+    task_index: usize,
+}
+
+impl ErrorReporter {
+    async fn report(&self, message: String, details: String) {
+        self.control_tx
+            .send(ControlResp::Error {
+                operator_id: self.operator_id.clone(),
+                task_index: self.task_index,
+                message,
+                details,
+            })
+            .await
+            .unwrap();
+    }
+
+    // reports errors that will be retried, at most once a second across all requests
+    async fn report_throttled(&self, message: String, details: String) {
+        if let Ok(mut last_reported) = self.last_reported_error_at.try_lock() {
+            if last_reported.elapsed().unwrap_or_default() > Duration::from_secs(1) {
+                warn!("websink request failed: {}", details);
+                self.report(message, details).await;
+                *last_reported = SystemTime::now();
+            }
+        }
+    }
+}
+
+/// Sends the request, retrying according to the retry policy. If it ultimately fails, returns the
+/// error message and details.
+async fn send_with_retries(
+    client: &reqwest::Client,
+    url: &str,
+    request: &WebhookRequest,
+    retry_policy: &RetryPolicy,
+    reporter: &ErrorReporter,
+) -> Result<(), (String, String)> {
+    let mut retries = 0;
+    loop {
+        let mut req = client.post(url).body(request.body.clone());
+        if let Some(content_type) = request.content_type {
+            req = req.header(CONTENT_TYPE, content_type);
+        }
+        if let Some((header, key)) = &request.idempotency_key {
+            req = req.header(header.clone(), key.clone());
+        }
+        let req = req.build().expect("failed to build request");
+
+        let details = match client.execute(req).await {
+            Ok(resp) if resp.status().is_success() => return Ok(()),
+            Ok(resp) => {
+                let status = resp.status().as_u16();
+                let details = format!(
+                    "server responded with error code: {}: {}",
+                    status,
+                    resp.text().await.unwrap_or_default()
+                );
+
+                if !retry_policy.should_retry(status) {
+                    return Err((
+                        "webhook request failed with non-retryable status".to_string(),
+                        details,
+                    ));
+                }
+
+                details
+            }
+            Err(e) => e.to_string(),
+        };
+
+        if retries >= retry_policy.max_retries {
+            return Err((format!("webhook failed after {} retries", retries), details));
+        }
+
+        reporter
+            .report_throttled(format!("webhook failed (retry {})", retries), details)
+            .await;
+
+        retries += 1;
+
+        tokio::time::sleep(RetryPolicy::backoff(retries)).await;
+    }
+}
+
 pub struct WebhookSinkFunc {
     pub url: Arc<String>,
     pub semaphore: Arc<Semaphore>,
     pub client: reqwest::Client,
     pub serializer: ArrowSerializer,
     pub last_reported_error_at: Arc<Mutex<SystemTime>>,
+    pub batch_format: Option<BatchFormat>,
+    pub max_batch_size: usize,
+    pub flush_interval: Duration,
+    // serialized records waiting to be sent in a batched request
+    pub buffer: Vec<Vec<u8>>,
+    pub retry_policy: Arc<RetryPolicy>,
+    pub idempotency_header: Option<HeaderName>,
+    // set by a request task when it fails permanently; the operator will fail on its next
+    // batch or checkpoint
+    pub failure: Arc<std::sync::Mutex<Option<(String, String)>>>,
+    // the epoch and sequence number of the next request in that epoch, used to derive
+    // idempotency keys. These are only stable across replays if the replayed records are split
+    // into requests the same way as before, which isn't the case for batches that were sent
+    // because the flush interval elapsed rather than because they were full.
+    pub epoch: u32,
+    pub sequence: u64,
+}
+
+impl WebhookSinkFunc {
+    fn check_failure(&self) {
+        if let Some((message, details)) = self.failure.lock().unwrap().take() {
+            panic!("{}: {}", message, details);
+        }
+    }
+
+    /// Sends the request in the background, waiting first if too many are already in flight
+    async fn send(
+        &mut self,
+        body: bytes::Bytes,
+        content_type: Option<&'static str>,
+        ctx: &mut ArrowContext,
+    ) {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("websink semaphore closed");
+
+        let idempotency_key = self.idempotency_header.clone().map(|header| {
+            let key = format!(
+                "{}-{}-{}-{}-{}",
+                ctx.task_info.job_id,
+                ctx.task_info.operator_id,
+                self.epoch,
+                ctx.task_info.task_index,
+                self.sequence
+            );
+            (
+                header,
+                HeaderValue::try_from(key).expect("invalid idempotency key"),
+            )
+        });
+        self.sequence += 1;
+
+        let request = WebhookRequest {
+            body,
+            content_type,
+            idempotency_key,
+        };
+
+        let client = self.client.clone();
+        let url = self.url.clone();
+        let retry_policy = self.retry_policy.clone();
+        let failure = self.failure.clone();
+        let reporter = ErrorReporter {
+            control_tx: ctx.control_tx.clone(),
+            last_reported_error_at: self.last_reported_error_at.clone(),
+            operator_id: ctx.task_info.operator_id.clone(),
+            task_index: ctx.task_info.task_index,
+        };
+
+        tokio::task::spawn(async move {
+            // move the permit into the task
+            let _permit = permit;
+            if let Err((message, details)) =
+                send_with_retries(&client, &url, &request, &retry_policy, &reporter).await
+            {
+                *failure.lock().unwrap() = Some((message.clone(), details.clone()));
+                reporter.report(message, details).await;
+            }
+        });
+    }
+
+    /// Sends the buffered records as a single batched request
+    async fn flush(&mut self, ctx: &mut ArrowContext) {
+        let Some(format) = self.batch_format else {
+            return;
+        };
+
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let records = std::mem::take(&mut self.buffer);
+        let (body, content_type) = batch_body(format, &records);
+        self.send(body, Some(content_type), ctx).await;
+    }
+
+    /// Waits for all in-flight requests to complete, failing if any of them failed
+    async fn wait_for_inflight(&self) {
+        let _permits = self.semaphore.acquire_many(MAX_INFLIGHT).await.unwrap();
+        self.check_failure();
+    }
+}
+
+fn batch_body(format: BatchFormat, records: &[Vec<u8>]) -> (bytes::Bytes, &'static str) {
+    match format {
+        BatchFormat::JsonArray => {
+            let mut body = vec![b'['];
+            for (i, r) in records.iter().enumerate() {
+                if i > 0 {
+                    body.push(b',');
+                }
+                body.extend_from_slice(r);
+            }
+            body.push(b']');
+            (body.into(), "application/json")
+        }
+        BatchFormat::Ndjson => {
+            let mut body = vec![];
+            for r in records {
+                body.extend_from_slice(r);
+                body.push(b'\n');
+            }
+            (body.into(), "application/x-ndjson")
+        }
+    }
 }
 
 #[async_trait]
@@ -37,86 +276,360 @@ impl ArrowOperator for WebhookSinkFunc {
         global_table_config("s", "webhook sink state")
     }
 
+    fn tick_interval(&self) -> Option<Duration> {
+        self.batch_format.map(|_| self.flush_interval)
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        let state = ctx
+            .table_manager
+            .get_global_keyed_state::<usize, u32>("s")
+            .await
+            .expect("couldn't get webhook sink state");
+
+        // the last checkpointed epoch is the same across all subtasks, so we can use any of them
+        // even if the parallelism has changed
+        self.epoch = state.get_all().values().copied().max().unwrap_or(0) + 1;
+        self.sequence = 0;
+    }
+
     async fn process_batch(&mut self, record: RecordBatch, ctx: &mut ArrowContext) {
-        for body in self.serializer.serialize(&record) {
-            let permit = self
-                .semaphore
-                .clone()
-                .acquire_owned()
-                .await
-                .expect("websink semaphore closed");
-
-            let body: bytes::Bytes = body.into();
-
-            let client = self.client.clone();
-            let control_tx = ctx.control_tx.clone();
-            let error_lock = self.last_reported_error_at.clone();
-            let url = self.url.clone();
-
-            // these are just used for (potential) error reporting and we don't need to clone them
-            let operator_id = ctx.task_info.operator_id.clone();
-            let task_index = ctx.task_info.task_index;
-
-            tokio::task::spawn(async move {
-                // move the permit into the task
-                let _permit = permit;
-                let mut retries = 0;
-                loop {
-                    let req = client
-                        .post(&*url)
-                        .body(body.clone())
-                        .build()
-                        .expect("failed to build request");
-
-                    match client.execute(req).await {
-                        Ok(_) => break,
-                        Err(e) => {
-                            if let Ok(mut last_reported) = error_lock.try_lock() {
-                                if last_reported.elapsed().unwrap_or_default()
-                                    > Duration::from_secs(1)
-                                {
-                                    warn!("websink request failed: {:?}", e);
-
-                                    let details = if let Some(status) = e.status() {
-                                        format!(
-                                            "server responded with error code: {}",
-                                            status.as_u16()
-                                        )
-                                    } else {
-                                        e.to_string()
-                                    };
-
-                                    control_tx
-                                        .send(ControlResp::Error {
-                                            operator_id: operator_id.clone(),
-                                            task_index,
-                                            message: format!("webhook failed (retry {})", retries),
-                                            details,
-                                        })
-                                        .await
-                                        .unwrap();
-
-                                    *last_reported = SystemTime::now();
-                                }
-                            }
-
-                            retries += 1;
-
-                            tokio::time::sleep(Duration::from_millis(
-                                (50 * (1 << retries)).min(5_000),
-                            ))
-                            .await
-                        }
-                    }
-                }
-            });
+        self.check_failure();
+
+        let records: Vec<_> = self.serializer.serialize(&record).collect();
+
+        if self.batch_format.is_none() {
+            for r in records {
+                self.send(r.into(), None, ctx).await;
+            }
+            return;
+        }
+
+        // records are buffered across batches, and sent once there are enough of them to fill a
+        // request or when the flush interval elapses
+        for r in records {
+            self.buffer.push(r);
+            if self.buffer.len() >= self.max_batch_size {
+                self.flush(ctx).await;
+            }
         }
     }
 
-    async fn handle_checkpoint(&mut self, _: CheckpointBarrier, _ctx: &mut ArrowContext) {
-        // wait to acquire all of the permits (effectively blocking until all inflight requests are done)
-        let _permits = self.semaphore.acquire_many(MAX_INFLIGHT).await.unwrap();
+    async fn handle_tick(&mut self, _: u64, ctx: &mut ArrowContext) {
+        self.flush(ctx).await;
+    }
+
+    async fn handle_checkpoint(&mut self, barrier: CheckpointBarrier, ctx: &mut ArrowContext) {
+        // everything before the barrier must be delivered before the checkpoint can complete, so
+        // send any buffered records and wait for all of the in-flight requests, failing if any of
+        // the requests in this epoch failed
+        self.flush(ctx).await;
+        self.wait_for_inflight().await;
+
+        ctx.table_manager
+            .get_global_keyed_state("s")
+            .await
+            .expect("couldn't get webhook sink state")
+            .insert(ctx.task_info.task_index, barrier.epoch)
+            .await;
+
+        self.epoch = barrier.epoch + 1;
+        self.sequence = 0;
+    }
+
+    async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        self.flush(ctx).await;
+        self.wait_for_inflight().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int64Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arroyo_rpc::df::ArroyoSchema;
+    use arroyo_rpc::formats::{Format, JsonFormat};
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use tokio::sync::mpsc::{channel, Receiver};
+
+    type Received = Arc<std::sync::Mutex<Vec<(String, Option<String>)>>>;
+
+    async fn record(
+        State(received): State<Received>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_string());
+        received.lock().unwrap().push((body, content_type));
+        StatusCode::OK
+    }
+
+    struct TestSink {
+        sink: WebhookSinkFunc,
+        ctx: ArrowContext,
+        received: Received,
+        _command_rx: Receiver<ControlResp>,
+    }
+
+    impl TestSink {
+        async fn new(batch_format: Option<BatchFormat>, max_batch_size: usize) -> Self {
+            let received = Received::default();
+            let app = Router::new()
+                .route("/", post(record))
+                .with_state(received.clone());
+
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(
+                axum::Server::from_tcp(listener)
+                    .unwrap()
+                    .serve(app.into_make_service()),
+            );
+
+            let mut sink = WebhookSinkFunc {
+                url: Arc::new(format!("http://{}/", addr)),
+                semaphore: Arc::new(Semaphore::new(MAX_INFLIGHT as usize)),
+                client: reqwest::Client::new(),
+                serializer: ArrowSerializer::new(Format::Json(JsonFormat::default())),
+                last_reported_error_at: Arc::new(Mutex::new(SystemTime::UNIX_EPOCH)),
+                batch_format,
+                max_batch_size,
+                flush_interval: Duration::from_secs(3600),
+                buffer: vec![],
+                retry_policy: Arc::new(RetryPolicy {
+                    retry_status_codes: vec![],
+                    max_retries: 0,
+                }),
+                idempotency_header: None,
+                failure: Arc::new(std::sync::Mutex::new(None)),
+                epoch: 1,
+                sequence: 0,
+            };
+
+            let (_control_tx, control_rx) = channel(16);
+            let (command_tx, command_rx) = channel(128);
+            let mut ctx = ArrowContext::new(
+                arroyo_types::get_test_task_info(),
+                None,
+                control_rx,
+                command_tx,
+                vec![1],
+                vec![ArroyoSchema::new_unkeyed(schema(), 0)],
+                None,
+                None,
+                vec![vec![]],
+                sink.tables(),
+            )
+            .await;
+
+            sink.on_start(&mut ctx).await;
+
+            Self {
+                sink,
+                ctx,
+                received,
+                _command_rx: command_rx,
+            }
+        }
+
+        async fn process(&mut self, ids: &[i64]) {
+            let batch =
+                RecordBatch::try_new(schema(), vec![Arc::new(Int64Array::from(ids.to_vec()))])
+                    .unwrap();
+            self.sink.process_batch(batch, &mut self.ctx).await;
+        }
+
+        async fn checkpoint(&mut self) {
+            let barrier = CheckpointBarrier {
+                epoch: 1,
+                min_epoch: 0,
+                timestamp: SystemTime::now(),
+                then_stop: false,
+                drain: false,
+                unaligned: false,
+            };
+            self.sink.handle_checkpoint(barrier, &mut self.ctx).await;
+        }
+
+        /// Waits for the in-flight requests and returns the ones received so far, sorted as they
+        /// may complete in any order
+        async fn received(&mut self) -> Vec<(String, Option<String>)> {
+            self.sink.wait_for_inflight().await;
+            let mut received = self.received.lock().unwrap().clone();
+            received.sort();
+            received
+        }
+    }
+
+    fn schema() -> Arc<Schema> {
+        Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]))
+    }
+
+    fn json_array(body: &str) -> (String, Option<String>) {
+        (body.to_string(), Some("application/json".to_string()))
+    }
+
+    #[test]
+    fn test_status_matcher() {
+        assert_eq!(
+            StatusMatcher::parse("429").unwrap(),
+            StatusMatcher::Code(429)
+        );
+        assert_eq!(
+            StatusMatcher::parse(" 5XX ").unwrap(),
+            StatusMatcher::Class(5)
+        );
+
+        for invalid in ["", "42", "600", "6xx", "0xx", "xx", "5x", "abc"] {
+            assert!(StatusMatcher::parse(invalid).is_err(), "{}", invalid);
+        }
+
+        assert!(StatusMatcher::Code(429).matches(429));
+        assert!(!StatusMatcher::Code(429).matches(428));
+        assert!(StatusMatcher::Class(5).matches(503));
+        assert!(!StatusMatcher::Class(5).matches(404));
+    }
+
+    #[tokio::test]
+    async fn test_unbatched() {
+        let mut sink = TestSink::new(None, 2).await;
+        sink.process(&[1, 2, 3]).await;
+
+        assert_eq!(
+            sink.received().await,
+            (1..=3)
+                .map(|i| (format!("{{\"id\":{}}}", i), None))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batches_span_record_batches() {
+        let mut sink = TestSink::new(Some(BatchFormat::JsonArray), 3).await;
+
+        sink.process(&[1, 2]).await;
+        assert!(sink.received().await.is_empty());
+
+        // full batches are sent as soon as they fill up, across record batches
+        sink.process(&[3, 4, 5, 6, 7]).await;
+        assert_eq!(
+            sink.received().await,
+            vec![
+                json_array(r#"[{"id":1},{"id":2},{"id":3}]"#),
+                json_array(r#"[{"id":4},{"id":5},{"id":6}]"#),
+            ]
+        );
+
+        // and the remainder is sent before the checkpoint completes
+        sink.checkpoint().await;
+        let received = sink.received().await;
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[2], json_array(r#"[{"id":7}]"#));
+    }
+
+    #[tokio::test]
+    async fn test_flush_interval() {
+        let mut sink = TestSink::new(Some(BatchFormat::Ndjson), 100).await;
+        assert_eq!(sink.sink.tick_interval(), Some(Duration::from_secs(3600)));
+
+        sink.process(&[1, 2]).await;
+        sink.process(&[3]).await;
+        assert!(sink.received().await.is_empty());
+
+        sink.sink.handle_tick(0, &mut sink.ctx).await;
+        assert_eq!(
+            sink.received().await,
+            vec![(
+                "{\"id\":1}\n{\"id\":2}\n{\"id\":3}\n".to_string(),
+                Some("application/x-ndjson".to_string())
+            )]
+        );
+    }
+
+    struct MockState {
+        // the status of each response, repeating the last one
+        statuses: Vec<u16>,
+        requests: usize,
+    }
+
+    async fn respond(State(state): State<Arc<std::sync::Mutex<MockState>>>) -> StatusCode {
+        let mut state = state.lock().unwrap();
+        state.requests += 1;
+        let status = if state.statuses.len() > 1 {
+            state.statuses.remove(0)
+        } else {
+            state.statuses[0]
+        };
+        StatusCode::from_u16(status).unwrap()
+    }
+
+    async fn send(statuses: Vec<u16>, max_retries: usize) -> (usize, Result<(), (String, String)>) {
+        let state = Arc::new(std::sync::Mutex::new(MockState {
+            statuses,
+            requests: 0,
+        }));
+        let app = Router::new()
+            .route("/", post(respond))
+            .with_state(state.clone());
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        let (control_tx, _control_rx) = channel(16);
+        let result = send_with_retries(
+            &reqwest::Client::new(),
+            &format!("http://{}/", addr),
+            &WebhookRequest {
+                body: "{}".into(),
+                content_type: None,
+                idempotency_key: None,
+            },
+            &RetryPolicy {
+                retry_status_codes: vec![StatusMatcher::Code(429), StatusMatcher::Class(5)],
+                max_retries,
+            },
+            &ErrorReporter {
+                control_tx,
+                last_reported_error_at: Arc::new(Mutex::new(SystemTime::UNIX_EPOCH)),
+                operator_id: "webhook".to_string(),
+                task_index: 0,
+            },
+        )
+        .await;
+
+        let requests = state.lock().unwrap().requests;
+        (requests, result)
+    }
+
+    #[tokio::test]
+    async fn test_retries() {
+        // retryable errors are retried until the request succeeds
+        let (requests, result) = send(vec![503, 429, 200], 3).await;
+        assert_eq!(requests, 3);
+        assert!(result.is_ok());
+
+        // non-retryable errors fail immediately
+        let (requests, result) = send(vec![400], 3).await;
+        assert_eq!(requests, 1);
+        assert_eq!(
+            result.unwrap_err().0,
+            "webhook request failed with non-retryable status"
+        );
 
-        // TODO: instead of blocking checkpoints on in-progress (or failing) requests, we should store them to state
+        // and retryable ones fail once the retries are exhausted
+        let (requests, result) = send(vec![503], 2).await;
+        assert_eq!(requests, 3);
+        assert_eq!(result.unwrap_err().0, "webhook failed after 2 retries");
     }
 }
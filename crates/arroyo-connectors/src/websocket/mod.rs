@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::str::FromStr;
 use std::time::Duration;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use arroyo_operator::connector::Connection;
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
@@ -19,7 +19,7 @@ use tokio_tungstenite::{connect_async, tungstenite};
 use tungstenite::http::Request;
 use typify::import_types;
 
-use crate::{header_map, pull_opt, EmptyConfig};
+use crate::{header_map, pull_opt, pull_option_to_i64, EmptyConfig};
 
 use crate::websocket::operator::{WebsocketSourceFunc, WebsocketSourceState};
 use arroyo_operator::connector::Connector;
@@ -232,6 +232,14 @@ impl Connector for WebsocketConnector {
             })?;
         }
 
+        if table.max_reconnect_attempts.is_some_and(|a| a < 0) {
+            bail!("max_reconnect_attempts must not be negative");
+        }
+
+        if table.heartbeat_timeout_secs.is_some_and(|t| t <= 0) {
+            bail!("heartbeat_timeout_secs must be positive");
+        }
+
         let schema = schema
             .map(|s| s.to_owned())
             .ok_or_else(|| anyhow!("no schema defined for WebSocket connection"))?;
@@ -249,6 +257,7 @@ impl Connector for WebsocketConnector {
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
+            metadata_fields: vec![],
         };
 
         Ok(Connection {
@@ -271,6 +280,8 @@ impl Connector for WebsocketConnector {
     ) -> anyhow::Result<Connection> {
         let endpoint = pull_opt("endpoint", options)?;
         let headers = options.remove("headers");
+        let max_reconnect_attempts = pull_option_to_i64("max_reconnect_attempts", options)?;
+        let heartbeat_timeout_secs = pull_option_to_i64("heartbeat_timeout_secs", options)?;
         let mut subscription_messages = vec![];
 
         // add the single subscription message if it exists
@@ -302,6 +313,8 @@ impl Connector for WebsocketConnector {
                 headers: headers.map(VarStr::new),
                 subscription_message: None,
                 subscription_messages,
+                max_reconnect_attempts,
+                heartbeat_timeout_secs,
             },
             schema,
         )
@@ -339,6 +352,10 @@ impl Connector for WebsocketConnector {
                 .ok_or_else(|| anyhow!("format required for websocket source"))?,
             framing: config.framing,
             bad_data: config.bad_data,
+            max_reconnect_attempts: table.max_reconnect_attempts.map(|a| a as u32),
+            heartbeat_timeout: table
+                .heartbeat_timeout_secs
+                .map(|t| Duration::from_secs(t as u64)),
             state: WebsocketSourceState::default(),
         })))
     }
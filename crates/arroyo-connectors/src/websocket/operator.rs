@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use std::collections::HashMap;
 use std::str::FromStr;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::SourceOperator;
@@ -14,13 +14,29 @@ use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
 use arroyo_types::{ArrowMessage, SignalMessage, UserError, Watermark};
 use bincode::{Decode, Encode};
 use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
 use tokio::select;
+use tokio::time::{Instant, MissedTickBehavior};
 use tokio_tungstenite::tungstenite::handshake::client::generate_key;
 use tokio_tungstenite::tungstenite::http::Uri;
-use tokio_tungstenite::{connect_async, tungstenite};
-use tracing::{debug, info};
+use tokio_tungstenite::{connect_async, tungstenite, MaybeTlsStream, WebSocketStream};
+use tracing::{debug, info, warn};
 use tungstenite::http::Request;
 
+const INITIAL_RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+fn reconnect_backoff(attempt: u32) -> Duration {
+    INITIAL_RECONNECT_BACKOFF
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_RECONNECT_BACKOFF)
+}
+
+enum ReadResult {
+    Finished(SourceFinishType),
+    Disconnected(String),
+}
+
 #[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd, Default)]
 pub struct WebsocketSourceState {}
 
@@ -31,6 +47,8 @@ pub struct WebsocketSourceFunc {
     pub format: Format,
     pub framing: Option<Framing>,
     pub bad_data: Option<BadData>,
+    pub max_reconnect_attempts: Option<u32>,
+    pub heartbeat_timeout: Option<Duration>,
     pub state: WebsocketSourceState,
 }
 
@@ -59,6 +77,7 @@ impl SourceOperator for WebsocketSourceFunc {
             self.format.clone(),
             self.framing.clone(),
             self.bad_data.clone(),
+            &[],
         );
     }
 
@@ -122,7 +141,7 @@ impl WebsocketSourceFunc {
         msg: &[u8],
         ctx: &mut ArrowContext,
     ) -> Result<(), UserError> {
-        ctx.deserialize_slice(msg, SystemTime::now()).await?;
+        ctx.deserialize_slice(msg, SystemTime::now(), None).await?;
 
         if ctx.should_flush() {
             ctx.flush_buffer().await?;
@@ -131,132 +150,144 @@ impl WebsocketSourceFunc {
         Ok(())
     }
 
-    async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
-        let uri = match Uri::from_str(&self.url.to_string()) {
-            Ok(uri) => uri,
-            Err(e) => {
-                ctx.report_error("Failed to parse endpoint".to_string(), format!("{:?}", e))
-                    .await;
-                panic!("Failed to parse endpoint: {:?}", e);
-            }
-        };
-
-        let host = match uri.host() {
-            Some(host) => host,
-            None => {
-                ctx.report_error("Endpoint must have a host".to_string(), "".to_string())
-                    .await;
-                panic!("Endpoint must have a host");
-            }
-        };
-
+    /// Opens a new connection to the server and sends the subscription messages
+    async fn connect(
+        &self,
+        host: &str,
+    ) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, String> {
         let mut request_builder = Request::builder().uri(&self.url);
 
         for (k, v) in &self.headers {
             request_builder = request_builder.header(k, v);
         }
 
-        let request = match request_builder
+        let request = request_builder
             .header("Host", host)
             .header("Sec-WebSocket-Key", generate_key())
             .header("Sec-WebSocket-Version", "13")
             .header("Connection", "Upgrade")
             .header("Upgrade", "websocket")
             .body(())
-        {
-            Ok(request) => request,
-            Err(e) => {
-                ctx.report_error("Failed to build request".to_string(), format!("{:?}", e))
-                    .await;
-                panic!("Failed to build request: {:?}", e);
-            }
-        };
+            .map_err(|e| format!("Failed to build request: {:?}", e))?;
 
-        let ws_stream = match connect_async(request).await {
-            Ok((ws_stream, _)) => ws_stream,
-            Err(e) => {
-                ctx.report_error(
-                    "Failed to connect to websocket server".to_string(),
-                    e.to_string(),
-                )
-                .await;
-                panic!("{}", e);
-            }
-        };
-
-        let (mut tx, mut rx) = ws_stream.split();
+        let (mut ws_stream, _) = connect_async(request)
+            .await
+            .map_err(|e| format!("Failed to connect to websocket server: {}", e))?;
 
         for msg in &self.subscription_messages {
-            if let Err(e) = tx.send(tungstenite::Message::Text(msg.clone())).await {
-                ctx.report_error(
-                    "Failed to send subscription message to websocket server".to_string(),
-                    e.to_string(),
-                )
-                .await;
-                panic!(
-                    "Failed to send subscription message to websocket server: {:?}",
-                    e
-                );
-            }
+            ws_stream
+                .send(tungstenite::Message::Text(msg.clone()))
+                .await
+                .map_err(|e| {
+                    format!(
+                        "Failed to send subscription message to websocket server: {}",
+                        e
+                    )
+                })?;
         }
 
-        let mut flush_ticker = tokio::time::interval(std::time::Duration::from_millis(50));
-        flush_ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        Ok(ws_stream)
+    }
 
-        // since there's no way to partition across a websocket source, only read on the first task
-        if ctx.task_info.task_index == 0 {
-            loop {
-                select! {
-                    message = rx.next()  => {
-                        match message {
-                            Some(Ok(msg)) => {
-                                match msg {
-                                    tungstenite::Message::Text(t) => {
-                                        self.handle_message(t.as_bytes(), ctx).await?
-                                    },
-                                    tungstenite::Message::Binary(bs) => {
-                                        self.handle_message(&bs, ctx).await?
-                                    },
-                                    tungstenite::Message::Ping(d) => {
-                                        tx.send(tungstenite::Message::Pong(d)).await
-                                            .map(|_| ())
-                                            .map_err(|e| UserError::new("Failed to send pong to websocket server", e.to_string()))?
-                                    },
-                                    tungstenite::Message::Pong(_) => {
-                                        // ignore
-                                    },
-                                    tungstenite::Message::Close(_) => {
-                                        ctx.report_error("Received close frame from server".to_string(), "".to_string()).await;
-                                        panic!("Received close frame from server");
-                                    },
-                                    tungstenite::Message::Frame(_) => {
-                                        // this should be captured by tungstenite
-                                    },
-                                };
-                            }
+    /// Reads from a connection until it fails or the source is stopped
+    async fn read(
+        &mut self,
+        ctx: &mut ArrowContext,
+        ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+        attempts: &mut u32,
+    ) -> Result<ReadResult, UserError> {
+        let (mut tx, mut rx) = ws_stream.split();
+
+        let mut flush_ticker = tokio::time::interval(Duration::from_millis(50));
+        flush_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let heartbeat_timeout = self.heartbeat_timeout.unwrap_or(Duration::from_secs(3600));
+        let heartbeat = tokio::time::sleep(heartbeat_timeout);
+        tokio::pin!(heartbeat);
+
+        loop {
+            select! {
+                message = rx.next()  => {
+                    match message {
+                        Some(Ok(msg)) => {
+                            // any message, including pings, shows that the connection is alive
+                            heartbeat.as_mut().reset(Instant::now() + heartbeat_timeout);
+                            *attempts = 0;
+
+                            match msg {
+                                tungstenite::Message::Text(t) => {
+                                    self.handle_message(t.as_bytes(), ctx).await?
+                                },
+                                tungstenite::Message::Binary(bs) => {
+                                    self.handle_message(&bs, ctx).await?
+                                },
+                                tungstenite::Message::Ping(d) => {
+                                    if let Err(e) = tx.send(tungstenite::Message::Pong(d)).await {
+                                        return Ok(ReadResult::Disconnected(
+                                            format!("Failed to send pong to websocket server: {}", e)));
+                                    }
+                                },
+                                tungstenite::Message::Pong(_) => {
+                                    // ignore
+                                },
+                                tungstenite::Message::Close(frame) => {
+                                    return Ok(ReadResult::Disconnected(
+                                        format!("Received close frame from server: {:?}", frame)));
+                                },
+                                tungstenite::Message::Frame(_) => {
+                                    // this should be captured by tungstenite
+                                },
+                            };
+                        }
                         Some(Err(e)) => {
-                            ctx.report_error("Error while reading from websocket".to_string(), format!("{:?}", e)).await;
-                            panic!("Error while reading from websocket: {:?}", e);
+                            return Ok(ReadResult::Disconnected(
+                                format!("Error while reading from websocket: {:?}", e)));
                         }
                         None => {
-                            info!("Socket closed");
-                            return Ok(SourceFinishType::Final);
+                            return Ok(ReadResult::Disconnected("Socket closed".to_string()));
                         }
                     }
+                }
+                _ = &mut heartbeat, if self.heartbeat_timeout.is_some() => {
+                    return Ok(ReadResult::Disconnected(format!(
+                        "No messages received for {:?}", heartbeat_timeout)));
+                }
+                _ = flush_ticker.tick() => {
+                    if ctx.should_flush() {
+                        ctx.flush_buffer().await?;
                     }
-                    _ = flush_ticker.tick() => {
-                        if ctx.should_flush() {
-                            ctx.flush_buffer().await?;
-                        }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.our_handle_control_message(ctx, control_message).await {
+                        return Ok(ReadResult::Finished(r));
                     }
-                    control_message = ctx.control_rx.recv() => {
-                        if let Some(r) = self.our_handle_control_message(ctx, control_message).await {
-                            return Ok(r);
-                        }
+                }
+            }
+        }
+    }
+
+    /// Waits out the reconnect backoff, while continuing to handle control messages
+    async fn wait(&mut self, ctx: &mut ArrowContext, delay: Duration) -> Option<SourceFinishType> {
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+
+        loop {
+            select! {
+                _ = &mut sleep => {
+                    return None;
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.our_handle_control_message(ctx, control_message).await {
+                        return Some(r);
                     }
                 }
             }
-        } else {
+        }
+    }
+
+    async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
+        // since there's no way to partition across a websocket source, only read on the first task
+        if ctx.task_info.task_index != 0 {
             // otherwise set idle and just process control messages
             ctx.broadcast(ArrowMessage::Signal(SignalMessage::Watermark(
                 Watermark::Idle,
@@ -269,5 +300,189 @@ impl WebsocketSourceFunc {
                 }
             }
         }
+
+        let uri = Uri::from_str(&self.url)
+            .map_err(|e| UserError::new("Failed to parse endpoint", format!("{:?}", e)))?;
+
+        let host = uri
+            .host()
+            .ok_or_else(|| UserError::new("Endpoint must have a host", ""))?
+            .to_string();
+
+        let mut attempts = 0;
+
+        loop {
+            let reason = match self.connect(&host).await {
+                Ok(ws_stream) => match self.read(ctx, ws_stream, &mut attempts).await? {
+                    ReadResult::Finished(r) => return Ok(r),
+                    ReadResult::Disconnected(reason) => reason,
+                },
+                Err(reason) => reason,
+            };
+
+            ctx.flush_buffer().await?;
+
+            if self
+                .max_reconnect_attempts
+                .is_some_and(|max| attempts >= max)
+            {
+                return Err(UserError::new(
+                    "Failed to connect to websocket server",
+                    format!(
+                        "giving up after {} reconnect attempts: {}",
+                        attempts, reason
+                    ),
+                ));
+            }
+
+            let delay = reconnect_backoff(attempts);
+            attempts += 1;
+            warn!("{}; reconnecting in {:?}", reason, delay);
+            ctx.report_error("Websocket disconnected", reason).await;
+
+            if let Some(r) = self.wait(ctx, delay).await {
+                return Ok(r);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use arroyo_operator::context::{batch_bounded, BatchReceiver};
+    use arroyo_rpc::df::ArroyoSchema;
+    use arroyo_rpc::formats::JsonFormat;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+    use tokio_tungstenite::accept_async;
+
+    /// Accepts the next connection, returning it along with the first message the client sent
+    async fn accept(listener: &TcpListener) -> (WebSocketStream<TcpStream>, String) {
+        let (stream, _) = timeout(Duration::from_secs(10), listener.accept())
+            .await
+            .expect("source did not reconnect")
+            .unwrap();
+
+        let mut ws = accept_async(stream).await.unwrap();
+        let subscription = match ws.next().await {
+            Some(Ok(tungstenite::Message::Text(t))) => t,
+            m => panic!("expected subscription message, got {:?}", m),
+        };
+
+        (ws, subscription)
+    }
+
+    async fn send_value(ws: &mut WebSocketStream<TcpStream>, value: &str) {
+        ws.send(tungstenite::Message::Text(format!(
+            "{{\"value\": \"{}\"}}",
+            value
+        )))
+        .await
+        .unwrap();
+    }
+
+    async fn next_values(data_rx: &mut BatchReceiver, count: usize) -> Vec<String> {
+        let mut values = vec![];
+        while values.len() < count {
+            match timeout(Duration::from_secs(10), data_rx.recv())
+                .await
+                .expect("timed out waiting for data")
+            {
+                Some(ArrowMessage::Data(batch)) => {
+                    let column = batch
+                        .column(1)
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .unwrap();
+                    values.extend(column.iter().map(|v| v.unwrap().to_string()));
+                }
+                Some(ArrowMessage::Signal(_)) => {}
+                None => panic!("source stopped"),
+            }
+        }
+        values
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let mut source = WebsocketSourceFunc {
+            url: format!("ws://{}/", listener.local_addr().unwrap()),
+            headers: vec![],
+            subscription_messages: vec!["subscribe".to_string()],
+            format: Format::Json(JsonFormat::default()),
+            framing: None,
+            bad_data: None,
+            max_reconnect_attempts: None,
+            heartbeat_timeout: Some(Duration::from_secs(1)),
+            state: WebsocketSourceState::default(),
+        };
+
+        let (control_tx, control_rx) = mpsc::channel(16);
+        let (command_tx, _command_rx) = mpsc::channel(128);
+        let (data_tx, mut data_rx) = batch_bounded(128);
+
+        let mut ctx = ArrowContext::new(
+            arroyo_types::get_test_task_info(),
+            None,
+            control_rx,
+            command_tx,
+            vec![1],
+            vec![],
+            Some(ArroyoSchema::new_unkeyed(
+                Arc::new(Schema::new(vec![
+                    Field::new(
+                        "_timestamp",
+                        DataType::Timestamp(TimeUnit::Nanosecond, None),
+                        false,
+                    ),
+                    Field::new("value", DataType::Utf8, false),
+                ])),
+                0,
+            )),
+            None,
+            vec![vec![data_tx]],
+            source.tables(),
+        )
+        .await;
+
+        let task = tokio::spawn(async move {
+            source.on_start(&mut ctx).await;
+            source.run(&mut ctx).await
+        });
+
+        // the first connection is dropped without a close frame
+        let (mut ws, subscription) = accept(&listener).await;
+        assert_eq!(subscription, "subscribe");
+        send_value(&mut ws, "a").await;
+        assert_eq!(next_values(&mut data_rx, 1).await, vec!["a"]);
+        drop(ws);
+
+        // the source subscribes again on the new connection, which then stalls
+        let (mut stalled, subscription) = accept(&listener).await;
+        assert_eq!(subscription, "subscribe");
+        send_value(&mut stalled, "b").await;
+        assert_eq!(next_values(&mut data_rx, 1).await, vec!["b"]);
+        let stalled_at = Instant::now();
+
+        // which the heartbeat timeout detects
+        let (_ws, subscription) = accept(&listener).await;
+        assert_eq!(subscription, "subscribe");
+        assert!(stalled_at.elapsed() >= Duration::from_millis(900));
+        drop(stalled);
+
+        control_tx
+            .send(ControlMessage::Stop {
+                mode: StopMode::Immediate,
+            })
+            .await
+            .unwrap();
+        assert!(matches!(task.await.unwrap(), SourceFinishType::Immediate));
     }
 }
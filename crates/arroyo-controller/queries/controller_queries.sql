@@ -1,4 +1,4 @@
---! all_jobs : Job(ttl_micros?, state?, start_time?, finish_time?, tasks?, failure_message?, run_id?, pipeline_path?, wasm_path?)
+--! all_jobs : Job(ttl_micros?, state?, start_time?, finish_time?, tasks?, failure_message?, run_id?, pipeline_path?, wasm_path?, autoscaling?, restart_strategy?, checkpoint_config?, next_retry_time?)
 SELECT
     job_configs.id as id,
     job_configs.organization_id as org_id,
@@ -19,11 +19,17 @@ SELECT
     wasm_path,
     job_configs.restart_nonce as config_restart_nonce,
     job_statuses.restart_nonce as status_restart_nonce,
-    restart_mode
+    restart_mode,
+    job_configs.program_version as config_program_version,
+    job_statuses.program_version as status_program_version,
+    autoscaling,
+    restart_strategy,
+    checkpoint_config,
+    next_retry_time
 FROM job_configs
 LEFT JOIN job_statuses ON job_configs.id = job_statuses.id;
 
---! update_job_status (start_time?, finish_time?, tasks?, failure_message?, pipeline_path?, wasm_path?)
+--! update_job_status (start_time?, finish_time?, tasks?, failure_message?, pipeline_path?, wasm_path?, next_retry_time?)
 UPDATE job_statuses
 SET state = :state,
     start_time = :start_time,
@@ -34,8 +40,11 @@ SET state = :state,
     pipeline_path = :pipeline_path,
     wasm_path = :wasm_path,
     run_id = :run_id,
-    restart_nonce = :restart_nonce
-WHERE id = :job_id;
+    restart_nonce = :restart_nonce,
+    program_version = :program_version,
+    next_retry_time = :next_retry_time
+WHERE id = :job_id
+    AND EXISTS (SELECT 1 FROM cluster_info WHERE leader_term = :leader_term FOR SHARE);
 
 --! get_program
 SELECT program, proto_version FROM pipelines WHERE id = :id;
@@ -43,16 +52,19 @@ SELECT program, proto_version FROM pipelines WHERE id = :id;
 --! mark_checkpoints_compacted
 UPDATE checkpoints
     set state = 'compacted'
-WHERE job_id = :job_id AND epoch < :epoch;
+WHERE job_id = :job_id AND epoch < :epoch
+    AND EXISTS (SELECT 1 FROM cluster_info WHERE leader_term = :leader_term FOR SHARE);
 
 --! drop_old_checkpoint_rows
 DELETE FROM checkpoints
-WHERE job_id = :job_id AND epoch < :epoch;
+WHERE job_id = :job_id AND epoch < :epoch
+    AND EXISTS (SELECT 1 FROM cluster_info WHERE leader_term = :leader_term FOR SHARE);
 
 --! create_checkpoint
 INSERT INTO checkpoints
 (pub_id, organization_id, job_id, state_backend, epoch, min_epoch, start_time)
-VALUES (:pub_id, :organization_id, :job_id, :state_backend, :epoch, :min_epoch, :start_time)
+SELECT :pub_id, :organization_id, :job_id, :state_backend, :epoch, :min_epoch, :start_time
+WHERE EXISTS (SELECT 1 FROM cluster_info WHERE leader_term = :leader_term FOR SHARE)
 RETURNING id;
 
 --! update_checkpoint (finish_time?)
@@ -61,26 +73,30 @@ SET
     operators = :operators,
     finish_time = :finish_time,
     state = :state
-WHERE id = :id;
+WHERE id = :id
+    AND EXISTS (SELECT 1 FROM cluster_info WHERE leader_term = :leader_term FOR SHARE);
 
 --! commit_checkpoint
 UPDATE checkpoints
 SET
     finish_time = :finish_time,
     state = 'ready'
-WHERE id = :id;
+WHERE id = :id
+    AND EXISTS (SELECT 1 FROM cluster_info WHERE leader_term = :leader_term FOR SHARE);
 
 --! mark_compacting
 UPDATE checkpoints
 SET
     state = 'compacting'
-WHERE job_id = :job_id AND epoch >= :min_epoch AND epoch < :epoch;
+WHERE job_id = :job_id AND epoch >= :min_epoch AND epoch < :epoch
+    AND EXISTS (SELECT 1 FROM cluster_info WHERE leader_term = :leader_term FOR SHARE);
 
 --! mark_failed
 UPDATE checkpoints
 SET
     state = 'failed'
-WHERE job_id = :job_id AND epoch >= :epoch;
+WHERE job_id = :job_id AND epoch >= :epoch
+    AND EXISTS (SELECT 1 FROM cluster_info WHERE leader_term = :leader_term FOR SHARE);
 
 --! last_successful_checkpoint
 SELECT id, epoch, min_epoch, state = 'committing' as needs_commits
@@ -89,7 +105,37 @@ WHERE job_id = :job_id AND (state = 'ready' or state = 'committing')
 ORDER BY epoch DESC
 LIMIT 1;
 
---! create_job_log_message
+--! create_job_log_message(operator_id?, task_index?)
 INSERT INTO job_log_messages (pub_id, job_id, operator_id, task_index, log_level, message, details)
 VALUES (:pub_id, :job_id, :operator_id, :task_index, :log_level, :message, :details)
 RETURNING id;
+
+--! update_autoscaled_parallelism
+UPDATE job_configs
+SET
+    updated_at = CURRENT_TIMESTAMP,
+    parallelism_overrides = :parallelism_overrides
+WHERE id = :job_id
+    AND EXISTS (SELECT 1 FROM cluster_info WHERE leader_term = :leader_term FOR SHARE);
+
+--! try_acquire_leader_lock
+SELECT pg_try_advisory_lock(:key) as acquired;
+
+--! increment_leader_term
+UPDATE cluster_info
+SET leader_term = leader_term + 1
+RETURNING leader_term;
+
+--! leader_lock_held
+SELECT EXISTS (
+    SELECT 1 FROM pg_locks
+    WHERE locktype = 'advisory'
+        AND pid = pg_backend_pid()
+        AND granted
+        AND ((classid::bigint << 32) | objid::bigint) = :key
+) as held;
+
+--! max_checkpoint_epoch : (epoch?)
+SELECT MAX(epoch) as epoch
+FROM checkpoints
+WHERE job_id = :job_id;
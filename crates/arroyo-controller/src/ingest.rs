@@ -0,0 +1,198 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use arroyo_server_common::shutdown::ShutdownGuard;
+use arroyo_types::{ports, service_port, INGEST_PORT_ENV};
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use tracing::{info, warn};
+
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct Endpoints {
+    // subtask index -> address of that subtask's HTTP server
+    addresses: BTreeMap<u32, String>,
+    next: usize,
+}
+
+/// Routes requests sent to the controller's ingest endpoint to the subtasks of HTTP push
+/// sources, spreading them round-robin across the subtasks that have registered
+#[derive(Clone)]
+pub struct IngestRouter {
+    endpoints: Arc<Mutex<HashMap<(String, String), Endpoints>>>,
+    client: reqwest::Client,
+}
+
+impl IngestRouter {
+    pub fn new() -> Self {
+        Self {
+            endpoints: Arc::new(Mutex::new(HashMap::new())),
+            client: reqwest::Client::builder()
+                .timeout(FORWARD_TIMEOUT)
+                .build()
+                .expect("could not construct HTTP client"),
+        }
+    }
+
+    pub fn register(&self, job_id: String, path: String, subtask_index: u32, address: String) {
+        info!(
+            message = "registering ingest endpoint",
+            job_id, path, subtask_index, address
+        );
+
+        self.endpoints
+            .lock()
+            .unwrap()
+            .entry((job_id, path))
+            .or_default()
+            .addresses
+            .insert(subtask_index, address);
+    }
+
+    /// Removes all of the job's endpoints; called whenever its workers are torn down, as the
+    /// subtasks of the next run will register themselves again once they start
+    pub fn unregister_job(&self, job_id: &str) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let before = endpoints.len();
+        endpoints.retain(|(id, _), _| id != job_id);
+
+        if endpoints.len() != before {
+            info!(message = "unregistered ingest endpoints", job_id);
+        }
+    }
+
+    /// Returns the registered addresses for the endpoint, starting with the next one in the
+    /// round-robin order
+    fn candidates(&self, job_id: &str, path: &str) -> Vec<String> {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let Some(endpoint) = endpoints.get_mut(&(job_id.to_string(), path.to_string())) else {
+            return vec![];
+        };
+
+        let addresses: Vec<_> = endpoint.addresses.values().cloned().collect();
+        if addresses.is_empty() {
+            return addresses;
+        }
+
+        let start = endpoint.next % addresses.len();
+        endpoint.next = endpoint.next.wrapping_add(1);
+
+        addresses
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(addresses.len())
+            .cloned()
+            .collect()
+    }
+
+    pub fn start(self, guard: ShutdownGuard) {
+        let port = service_port("controller", ports::CONTROLLER_INGEST, INGEST_PORT_ENV);
+        let addr = format!("0.0.0.0:{}", port).parse().expect("Invalid port");
+
+        info!("Starting ingest server on {}", addr);
+
+        let app = Router::new()
+            .route("/v1/ingest/:job_id/:path", post(ingest))
+            .with_state(self);
+
+        guard.into_spawn_task(async move {
+            axum::Server::bind(&addr)
+                .serve(app.into_make_service())
+                .await
+        });
+    }
+}
+
+async fn ingest(
+    State(router): State<IngestRouter>,
+    Path((job_id, path)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let candidates = router.candidates(&job_id, &path);
+    if candidates.is_empty() {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("no ingest endpoint '{}' for job {}", path, job_id),
+        )
+            .into_response();
+    }
+
+    for address in candidates {
+        let mut request = router
+            .client
+            .post(format!("{}/{}", address, path))
+            .body(body.clone());
+
+        for name in [header::CONTENT_TYPE, header::AUTHORIZATION] {
+            if let Some(value) = headers.get(&name) {
+                request = request.header(name, value);
+            }
+        }
+
+        match request.send().await {
+            Ok(resp) => {
+                let status = resp.status();
+                let body = resp.bytes().await.unwrap_or_default();
+                return (status, body).into_response();
+            }
+            Err(e) if e.is_connect() => {
+                // the subtask may have moved or be restarting; try the next one
+                warn!(
+                    "failed to connect to ingest endpoint {} for job {}: {}",
+                    address, job_id, e
+                );
+            }
+            Err(e) => {
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    format!("failed to forward request: {}", e),
+                )
+                    .into_response();
+            }
+        }
+    }
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        format!("no subtasks of ingest endpoint '{}' are reachable", path),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_robin_and_unregister() {
+        let router = IngestRouter::new();
+        router.register("job1".into(), "events".into(), 0, "http://a".into());
+        router.register("job1".into(), "events".into(), 1, "http://b".into());
+        router.register("job2".into(), "events".into(), 0, "http://c".into());
+
+        assert_eq!(
+            router.candidates("job1", "events"),
+            vec!["http://a", "http://b"]
+        );
+        assert_eq!(
+            router.candidates("job1", "events"),
+            vec!["http://b", "http://a"]
+        );
+
+        router.unregister_job("job1");
+        assert!(router.candidates("job1", "events").is_empty());
+        assert_eq!(router.candidates("job2", "events"), vec!["http://c"]);
+
+        // subtasks of the next run register again
+        router.register("job1".into(), "events".into(), 0, "http://d".into());
+        assert_eq!(router.candidates("job1", "events"), vec!["http://d"]);
+    }
+}
@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use arroyo_datastream::logical::LogicalProgram;
+use arroyo_rpc::api_types::pipelines::AutoscalingConfig;
+use arroyo_rpc::grpc::TaskMetrics;
+use petgraph::prelude::EdgeRef;
+use petgraph::Direction;
+
+// how often we sample the metrics reported by the workers
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+// how long we observe the pipeline before deciding whether to rescale it
+const EVALUATION_WINDOW: Duration = Duration::from_secs(60);
+
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(5 * 60);
+
+// if the queues into an operator are fuller than this on average, it isn't keeping up
+const SCALE_UP_BACKPRESSURE: f64 = 0.5;
+
+// if the queues into an operator are emptier than this on average, it has spare capacity
+const SCALE_DOWN_BACKPRESSURE: f64 = 0.05;
+
+// a source that is further behind than this, and not catching up, isn't keeping up
+const SCALE_UP_SOURCE_LAG: Duration = Duration::from_secs(30);
+
+// the most we will shrink an operator by in a single step
+const SCALE_DOWN_FACTOR: f64 = 0.75;
+
+/// Operators connected by forward edges must share a parallelism, so they are scaled together
+#[derive(Debug)]
+struct ScalingGroup {
+    operators: Vec<String>,
+    parallelism: usize,
+    // operators whose output queues feed this group, and so fill up when it falls behind
+    inputs: Vec<String>,
+    // operators in this group whose output queues feed other groups, and so fill up when
+    // the rest of the pipeline falls behind
+    outputs: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct GroupSamples {
+    count: usize,
+    input_backpressure: f64,
+    output_backpressure: f64,
+    first_lag_millis: Option<u64>,
+    last_lag_millis: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScalingDecision {
+    pub operators: Vec<String>,
+    pub from: usize,
+    pub to: usize,
+    pub reason: String,
+}
+
+/// Watches the queue and source lag metrics reported by the workers and decides when operators
+/// should be run at a different parallelism. Decisions are made over windows of
+/// `EVALUATION_WINDOW`, and never until the cooldown has passed since the job was (re)scheduled.
+pub struct Autoscaler {
+    config: AutoscalingConfig,
+    started: Instant,
+    window_start: Instant,
+    last_sample: Option<Instant>,
+    groups: Vec<ScalingGroup>,
+    samples: Vec<GroupSamples>,
+    decided: bool,
+}
+
+impl Autoscaler {
+    pub fn new(config: AutoscalingConfig, program: &LogicalProgram) -> Self {
+        let groups = scaling_groups(program);
+        Self {
+            config,
+            started: Instant::now(),
+            window_start: Instant::now(),
+            last_sample: None,
+            samples: groups.iter().map(|_| GroupSamples::default()).collect(),
+            groups,
+            decided: false,
+        }
+    }
+
+    pub fn update_config(&mut self, config: AutoscalingConfig) {
+        self.config = config;
+    }
+
+    fn cooldown(&self) -> Duration {
+        self.config
+            .cooldown_micros
+            .map(Duration::from_micros)
+            .unwrap_or(DEFAULT_COOLDOWN)
+    }
+
+    /// Called periodically with the latest metrics for each task; returns the operators to
+    /// rescale once a window has passed that calls for it. Once a decision has been made no
+    /// further decisions will be returned, as the job is expected to be rescheduled.
+    pub fn evaluate(
+        &mut self,
+        metrics: &HashMap<(String, u32), TaskMetrics>,
+    ) -> Vec<ScalingDecision> {
+        if self.decided
+            || self
+                .last_sample
+                .is_some_and(|t| t.elapsed() < SAMPLE_INTERVAL)
+        {
+            return vec![];
+        }
+
+        self.last_sample = Some(Instant::now());
+        self.sample(metrics);
+
+        if self.window_start.elapsed() < EVALUATION_WINDOW {
+            return vec![];
+        }
+
+        let decisions = if self.started.elapsed() >= self.cooldown() {
+            self.decide()
+        } else {
+            vec![]
+        };
+
+        self.window_start = Instant::now();
+        for samples in &mut self.samples {
+            *samples = GroupSamples::default();
+        }
+
+        self.decided = !decisions.is_empty();
+        decisions
+    }
+
+    fn sample(&mut self, metrics: &HashMap<(String, u32), TaskMetrics>) {
+        let mut backpressure: HashMap<&str, (f64, usize)> = HashMap::new();
+        let mut lag: HashMap<&str, u64> = HashMap::new();
+
+        for ((operator_id, _), m) in metrics {
+            // this matches the backpressure metric we report in the API
+            let b = 1.0 - (m.tx_queue_rem as f64 + 1.0) / (m.tx_queue_size as f64 + 1.0);
+            let (sum, count) = backpressure.entry(operator_id.as_str()).or_default();
+            *sum += b.max(0.0);
+            *count += 1;
+
+            if let Some(l) = m.source_lag_millis {
+                let max = lag.entry(operator_id.as_str()).or_default();
+                *max = (*max).max(l);
+            }
+        }
+
+        let max_backpressure = |operators: &[String]| {
+            operators
+                .iter()
+                .filter_map(|op| backpressure.get(op.as_str()))
+                .map(|(sum, count)| sum / *count as f64)
+                .fold(0.0, f64::max)
+        };
+
+        for (group, samples) in self.groups.iter().zip(self.samples.iter_mut()) {
+            samples.count += 1;
+            samples.input_backpressure += max_backpressure(&group.inputs);
+            samples.output_backpressure += max_backpressure(&group.outputs);
+
+            let group_lag = group
+                .operators
+                .iter()
+                .filter_map(|op| lag.get(op.as_str()))
+                .max()
+                .copied();
+
+            if group_lag.is_some() {
+                samples.first_lag_millis = samples.first_lag_millis.or(group_lag);
+                samples.last_lag_millis = group_lag;
+            }
+        }
+    }
+
+    fn decide(&self) -> Vec<ScalingDecision> {
+        let min = self.config.min_parallelism.unwrap_or(1).max(1) as usize;
+        let max = self
+            .config
+            .max_parallelism
+            .map(|p| p as usize)
+            .unwrap_or(usize::MAX)
+            .max(min);
+
+        let mut decisions = vec![];
+
+        for (group, samples) in self.groups.iter().zip(&self.samples) {
+            if samples.count == 0 {
+                continue;
+            }
+
+            let input = samples.input_backpressure / samples.count as f64;
+            let output = samples.output_backpressure / samples.count as f64;
+            let p = group.parallelism;
+
+            let lag = samples.last_lag_millis.map(Duration::from_millis);
+            let falling_behind = match (samples.first_lag_millis, samples.last_lag_millis) {
+                (Some(first), Some(last)) => {
+                    Duration::from_millis(last) >= SCALE_UP_SOURCE_LAG && last >= first
+                }
+                _ => false,
+            };
+
+            let (target, reason) = if output >= SCALE_UP_BACKPRESSURE {
+                // this group is being held back by the operators downstream of it, which will
+                // be scaled up instead
+                continue;
+            } else if input >= SCALE_UP_BACKPRESSURE {
+                (
+                    ((p as f64) * (1.0 + input)).ceil() as usize,
+                    format!(
+                        "average backpressure on its inputs was {:.2} over the last {}s",
+                        input,
+                        EVALUATION_WINDOW.as_secs()
+                    ),
+                )
+            } else if falling_behind {
+                (
+                    ((p as f64) * (1.0 + SCALE_UP_BACKPRESSURE)).ceil() as usize,
+                    format!(
+                        "source lag grew from {}s to {}s over the last {}s",
+                        samples.first_lag_millis.unwrap_or_default() / 1000,
+                        samples.last_lag_millis.unwrap_or_default() / 1000,
+                        EVALUATION_WINDOW.as_secs()
+                    ),
+                )
+            } else if input < SCALE_DOWN_BACKPRESSURE
+                && output < SCALE_DOWN_BACKPRESSURE
+                && lag.map(|l| l < SCALE_UP_SOURCE_LAG).unwrap_or(true)
+            {
+                (
+                    ((p as f64) * SCALE_DOWN_FACTOR).floor() as usize,
+                    format!(
+                        "average backpressure on its inputs was {:.2} over the last {}s",
+                        input,
+                        EVALUATION_WINDOW.as_secs()
+                    ),
+                )
+            } else {
+                continue;
+            };
+
+            let target = target.clamp(min, max);
+            if target != p {
+                decisions.push(ScalingDecision {
+                    operators: group.operators.clone(),
+                    from: p,
+                    to: target,
+                    reason,
+                });
+            }
+        }
+
+        decisions
+    }
+}
+
+fn scaling_groups(program: &LogicalProgram) -> Vec<ScalingGroup> {
+    let forward_groups = program.forward_groups();
+
+    let mut group_for = HashMap::new();
+    for (i, group) in forward_groups.iter().enumerate() {
+        for idx in group {
+            group_for.insert(*idx, i);
+        }
+    }
+
+    forward_groups
+        .iter()
+        .enumerate()
+        .map(|(i, members)| {
+            let mut inputs = vec![];
+            let mut outputs = vec![];
+
+            for idx in members {
+                let operator_id = &program.graph[*idx].operator_id;
+
+                for edge in program.graph.edges_directed(*idx, Direction::Incoming) {
+                    if group_for[&edge.source()] != i {
+                        inputs.push(program.graph[edge.source()].operator_id.clone());
+                    }
+                }
+
+                for edge in program.graph.edges_directed(*idx, Direction::Outgoing) {
+                    if group_for[&edge.target()] == i {
+                        inputs.push(operator_id.clone());
+                    } else {
+                        outputs.push(operator_id.clone());
+                    }
+                }
+            }
+
+            inputs.sort();
+            inputs.dedup();
+            outputs.sort();
+            outputs.dedup();
+
+            ScalingGroup {
+                operators: members
+                    .iter()
+                    .map(|idx| program.graph[*idx].operator_id.clone())
+                    .collect(),
+                parallelism: members
+                    .iter()
+                    .map(|idx| program.graph[*idx].parallelism)
+                    .max()
+                    .unwrap_or(1),
+                inputs,
+                outputs,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use arroyo_datastream::logical::{
+        LogicalEdge, LogicalEdgeType, LogicalNode, LogicalProgram, OperatorName,
+    };
+    use arroyo_rpc::api_types::pipelines::AutoscalingConfig;
+    use arroyo_rpc::df::ArroyoSchema;
+    use arroyo_rpc::grpc::TaskMetrics;
+
+    use super::Autoscaler;
+
+    // source -> value ⤨ aggregate -> sink
+    fn program(parallelism: usize) -> LogicalProgram {
+        let schema = ArroyoSchema::new_unkeyed(
+            Arc::new(Schema::new(vec![Field::new(
+                "_timestamp",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            )])),
+            0,
+        );
+
+        let mut program = LogicalProgram::default();
+        let nodes: Vec<_> = [
+            ("source_0", OperatorName::ConnectorSource),
+            ("value_1", OperatorName::ArrowValue),
+            ("aggregate_2", OperatorName::TumblingWindowAggregate),
+            ("sink_3", OperatorName::ConnectorSink),
+        ]
+        .into_iter()
+        .map(|(id, name)| {
+            program.graph.add_node(LogicalNode {
+                operator_id: id.to_string(),
+                description: id.to_string(),
+                operator_name: name,
+                operator_config: vec![],
+                parallelism,
+            })
+        })
+        .collect();
+
+        for (i, edge_type) in [
+            LogicalEdgeType::Forward,
+            LogicalEdgeType::Shuffle,
+            LogicalEdgeType::Forward,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            program.graph.add_edge(
+                nodes[i],
+                nodes[i + 1],
+                LogicalEdge::project_all(edge_type, schema.clone()),
+            );
+        }
+
+        program
+    }
+
+    fn metrics(
+        parallelism: u32,
+        queues: &[(&str, u64)],
+        lag_millis: Option<u64>,
+    ) -> HashMap<(String, u32), TaskMetrics> {
+        let mut metrics = HashMap::new();
+        for (operator_id, rem) in queues {
+            for subtask_index in 0..parallelism {
+                metrics.insert(
+                    (operator_id.to_string(), subtask_index),
+                    TaskMetrics {
+                        operator_id: operator_id.to_string(),
+                        subtask_index,
+                        tx_queue_size: 100,
+                        tx_queue_rem: *rem,
+                        source_lag_millis: if *operator_id == "source_0" {
+                            lag_millis
+                        } else {
+                            None
+                        },
+                    },
+                );
+            }
+        }
+        metrics
+    }
+
+    fn autoscaler(program: &LogicalProgram, max_parallelism: u64) -> Autoscaler {
+        Autoscaler::new(
+            AutoscalingConfig {
+                enabled: true,
+                min_parallelism: Some(1),
+                max_parallelism: Some(max_parallelism),
+                cooldown_micros: Some(0),
+            },
+            program,
+        )
+    }
+
+    #[test]
+    fn test_scales_up_bottleneck() {
+        let program = program(2);
+        let mut autoscaler = autoscaler(&program, 8);
+
+        // the value operator's queues into the aggregate are full, while the aggregate's
+        // queue into the sink is empty, so the aggregate is the bottleneck
+        for _ in 0..3 {
+            autoscaler.sample(&metrics(
+                2,
+                &[("source_0", 50), ("value_1", 5), ("aggregate_2", 100)],
+                Some(0),
+            ));
+        }
+
+        let decisions = autoscaler.decide();
+        assert_eq!(decisions.len(), 1, "{:?}", decisions);
+        assert_eq!(
+            decisions[0].operators,
+            vec!["aggregate_2".to_string(), "sink_3".to_string()]
+        );
+        assert_eq!(decisions[0].from, 2);
+        assert_eq!(decisions[0].to, 4);
+    }
+
+    #[test]
+    fn test_scales_up_lagging_source_within_bounds() {
+        let program = program(4);
+        let mut autoscaler = autoscaler(&program, 5);
+
+        for lag in [40_000, 50_000, 60_000] {
+            autoscaler.sample(&metrics(
+                4,
+                &[("source_0", 50), ("value_1", 50), ("aggregate_2", 50)],
+                Some(lag),
+            ));
+        }
+
+        let decisions = autoscaler.decide();
+        assert_eq!(decisions.len(), 1, "{:?}", decisions);
+        assert_eq!(
+            decisions[0].operators,
+            vec!["source_0".to_string(), "value_1".to_string()]
+        );
+        assert_eq!(decisions[0].to, 5);
+    }
+
+    #[test]
+    fn test_scales_down_idle_operators() {
+        let program = program(4);
+        let mut autoscaler = autoscaler(&program, 8);
+
+        autoscaler.sample(&metrics(
+            4,
+            &[("source_0", 100), ("value_1", 100), ("aggregate_2", 100)],
+            Some(100),
+        ));
+
+        let decisions = autoscaler.decide();
+        assert_eq!(decisions.len(), 2, "{:?}", decisions);
+        assert!(decisions.iter().all(|d| d.from == 4 && d.to == 3));
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::{HashMap, HashSet};
+
+use arroyo_rpc::grpc::TaskAssignment;
+
+/// Determines which failover regions need to be restarted to recover the failed tasks, returning
+/// None if the job must instead be recovered as a whole. That's the case when every region has
+/// failed, or when a failed region spans multiple workers, as the connections between its tasks
+/// can't be re-established while the rest of the job is running.
+pub fn regions_to_restart<'a>(
+    regions: &'a [Vec<(String, u32)>],
+    failed: &HashSet<(String, u32)>,
+    assignments: &HashMap<(String, u32), TaskAssignment>,
+) -> Option<Vec<&'a Vec<(String, u32)>>> {
+    let affected: Vec<_> = regions
+        .iter()
+        .filter(|region| region.iter().any(|task| failed.contains(task)))
+        .collect();
+
+    if affected.is_empty() || affected.len() == regions.len() {
+        return None;
+    }
+
+    for region in &affected {
+        let mut workers = region
+            .iter()
+            .map(|task| assignments.get(task).map(|a| a.worker_id));
+        let first = workers.next()??;
+        if !workers.all(|w| w == Some(first)) {
+            return None;
+        }
+    }
+
+    Some(affected)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use arroyo_datastream::logical::{
+        LogicalEdge, LogicalEdgeType, LogicalNode, LogicalProgram, OperatorName,
+    };
+    use arroyo_rpc::df::ArroyoSchema;
+    use arroyo_rpc::grpc::TaskAssignment;
+
+    use super::regions_to_restart;
+
+    // source -> sink_a, source -> sink_b, with the given edge type
+    fn program(edge_type: LogicalEdgeType) -> LogicalProgram {
+        let schema = ArroyoSchema::new_unkeyed(
+            Arc::new(Schema::new(vec![Field::new(
+                "_timestamp",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            )])),
+            0,
+        );
+
+        let mut program = LogicalProgram::default();
+        let nodes: Vec<_> = [
+            ("source_0", OperatorName::ConnectorSource),
+            ("sink_a", OperatorName::ConnectorSink),
+            ("sink_b", OperatorName::ConnectorSink),
+        ]
+        .into_iter()
+        .map(|(id, name)| {
+            program.graph.add_node(LogicalNode {
+                operator_id: id.to_string(),
+                description: id.to_string(),
+                operator_name: name,
+                operator_config: vec![],
+                parallelism: 2,
+            })
+        })
+        .collect();
+
+        for sink in &nodes[1..] {
+            program.graph.add_edge(
+                nodes[0],
+                *sink,
+                LogicalEdge::project_all(edge_type, schema.clone()),
+            );
+        }
+
+        program
+    }
+
+    fn regions(program: &LogicalProgram) -> Vec<Vec<(String, u32)>> {
+        program
+            .failover_regions()
+            .into_iter()
+            .map(|r| r.into_iter().map(|(op, i)| (op, i as u32)).collect())
+            .collect()
+    }
+
+    // assigns subtask i of every operator to worker `worker_for(i)`
+    fn assignments(
+        program: &LogicalProgram,
+        worker_for: impl Fn(u32) -> u64,
+    ) -> HashMap<(String, u32), TaskAssignment> {
+        program
+            .graph
+            .node_weights()
+            .flat_map(|n| (0..n.parallelism as u32).map(move |i| (n.operator_id.clone(), i)))
+            .map(|(op, i)| {
+                (
+                    (op.clone(), i),
+                    TaskAssignment {
+                        operator_id: op,
+                        operator_subtask: i as u64,
+                        worker_id: worker_for(i),
+                        worker_addr: String::new(),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_forward_regions() {
+        let program = program(LogicalEdgeType::Forward);
+        let regions = regions(&program);
+        assert_eq!(regions.len(), 2);
+
+        let failed = HashSet::from([("sink_b".to_string(), 1)]);
+        let restart = regions_to_restart(&regions, &failed, &assignments(&program, |_| 1)).unwrap();
+
+        assert_eq!(restart.len(), 1);
+        let mut tasks = restart[0].clone();
+        tasks.sort();
+        assert_eq!(
+            tasks,
+            vec![
+                ("sink_a".to_string(), 1),
+                ("sink_b".to_string(), 1),
+                ("source_0".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shuffle_requires_full_restart() {
+        let program = program(LogicalEdgeType::Shuffle);
+        let regions = regions(&program);
+        assert_eq!(regions.len(), 1);
+
+        let failed = HashSet::from([("sink_a".to_string(), 0)]);
+        assert!(regions_to_restart(&regions, &failed, &assignments(&program, |_| 1)).is_none());
+    }
+
+    #[test]
+    fn test_region_across_workers() {
+        let program = program(LogicalEdgeType::Forward);
+        let regions = regions(&program);
+        let failed = HashSet::from([("sink_a".to_string(), 0)]);
+
+        // each region is on a single worker
+        assert!(
+            regions_to_restart(&regions, &failed, &assignments(&program, |i| i as u64)).is_some()
+        );
+
+        // the sinks of region 0 are on a different worker than its source
+        let mut split = assignments(&program, |_| 1);
+        split
+            .get_mut(&("source_0".to_string(), 0))
+            .unwrap()
+            .worker_id = 2;
+        assert!(regions_to_restart(&regions, &failed, &split).is_none());
+    }
+}
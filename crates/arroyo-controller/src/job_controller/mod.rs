@@ -1,14 +1,16 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     env,
     time::{Duration, Instant, SystemTime},
 };
 
 use crate::types::public::StopMode as SqlStopMode;
-use anyhow::bail;
+use anyhow::{bail, Context};
+use arroyo_rpc::api_types::pipelines::{AutoscalingConfig, CheckpointConfig};
 use arroyo_rpc::grpc::{
     worker_grpc_client::WorkerGrpcClient, CheckpointReq, CommitReq, JobFinishedReq,
-    LoadCompactedDataReq, StopExecutionReq, StopMode, TaskCheckpointEventType,
+    LoadCompactedDataReq, RestartTasksReq, StopExecutionReq, StopMode, TaskAssignment,
+    TaskCheckpointEventType, TaskMetrics,
 };
 use arroyo_state::{BackingStore, StateBackend};
 use arroyo_types::{to_micros, WorkerId};
@@ -24,13 +26,16 @@ use tokio::{sync::mpsc::Receiver, task::JoinHandle};
 use tonic::{transport::Channel, Request};
 use tracing::{error, info, warn};
 
-use crate::types::public::CheckpointState as DbCheckpointState;
+use crate::types::public::{CheckpointState as DbCheckpointState, LogLevel};
 use crate::{queries::controller_queries, JobConfig, JobMessage, RunningMessage};
 use arroyo_state::committing_state::CommittingState;
 
+use self::autoscaler::{Autoscaler, ScalingDecision};
 use self::checkpointer::CheckpointingOrCommittingState;
 
+mod autoscaler;
 mod checkpointer;
+mod failover;
 
 const CHECKPOINTS_TO_KEEP: u32 = 4;
 const CHECKPOINT_ROWS_TO_KEEP: u32 = 100;
@@ -60,6 +65,8 @@ impl WorkerStatus {
 #[derive(Debug, PartialEq, Eq)]
 pub enum TaskState {
     Running,
+    // restarted with its failover region, but the new task hasn't started yet
+    Restarting,
     Finished,
     Failed(String),
 }
@@ -78,15 +85,34 @@ pub enum JobState {
 
 pub struct RunningJobModel {
     job_id: String,
+    // the term of this controller's leadership, which fences its database writes
+    leader_term: i64,
     state: JobState,
     program: LogicalProgram,
+    // the oldest checkpoint that is still in progress or being committed
     checkpoint_state: Option<CheckpointingOrCommittingState>,
+    // the epoch of `checkpoint_state`
+    checkpoint_epoch: u32,
+    // checkpoints that were started while an earlier one was still in progress, in epoch order;
+    // each is only finished once all of the checkpoints before it have been
+    queued_checkpoints: VecDeque<CheckpointState>,
     epoch: u32,
+    // the epoch of the most recent checkpoint that was successfully written, which may be behind
+    // `epoch` if a checkpoint is in progress or was abandoned
+    completed_epoch: u32,
     min_epoch: u32,
+    // when the most recent checkpoint was started and finished
+    last_checkpoint_start: Instant,
     last_checkpoint: Instant,
+    // whether the job has operators that commit in two phases, which we learn from the first
+    // checkpoint that needs to be committed
+    two_phase_commits: bool,
     workers: HashMap<WorkerId, WorkerStatus>,
     tasks: HashMap<(String, u32), TaskStatus>,
+    task_metrics: HashMap<(String, u32), TaskMetrics>,
     operator_parallelism: HashMap<String, usize>,
+    assignments: HashMap<(String, u32), TaskAssignment>,
+    failover_regions: Vec<Vec<(String, u32)>>,
 }
 
 impl std::fmt::Debug for RunningJobModel {
@@ -95,6 +121,7 @@ impl std::fmt::Debug for RunningJobModel {
             .field("job_id", &self.job_id)
             .field("state", &self.state)
             .field("checkpointing", &self.checkpoint_state.is_some())
+            .field("queued_checkpoints", &self.queued_checkpoints.len())
             .field("epoch", &self.epoch)
             .field("min_epoch", &self.min_epoch)
             .field("last_checkpoint", &self.last_checkpoint)
@@ -102,26 +129,44 @@ impl std::fmt::Debug for RunningJobModel {
     }
 }
 
+// checkpoint writes are fenced by the leader term, so a write that didn't update its checkpoint
+// means that another controller has taken over the job
+fn ensure_fenced_write(updated: u64, epoch: u32) -> anyhow::Result<()> {
+    if updated == 0 {
+        bail!(
+            "failed to update checkpoint {}; this controller may no longer be the leader",
+            epoch
+        );
+    }
+    Ok(())
+}
+
 impl RunningJobModel {
-    pub async fn update_db(checkpoint_state: &CheckpointState, pool: &Pool) -> anyhow::Result<()> {
+    pub async fn update_db(
+        checkpoint_state: &CheckpointState,
+        pool: &Pool,
+        leader_term: i64,
+    ) -> anyhow::Result<()> {
         let c = pool.get().await?;
 
-        controller_queries::update_checkpoint()
+        let updated = controller_queries::update_checkpoint()
             .bind(
                 &c,
                 &serde_json::to_value(&checkpoint_state.operator_details).unwrap(),
                 &None,
                 &DbCheckpointState::inprogress,
                 &checkpoint_state.checkpoint_id(),
+                &leader_term,
             )
             .await?;
 
-        Ok(())
+        ensure_fenced_write(updated, checkpoint_state.epoch())
     }
 
     pub async fn update_checkpoint_in_db(
         checkpoint_state: &CheckpointState,
         pool: &Pool,
+        leader_term: i64,
         db_checkpoint_state: DbCheckpointState,
     ) -> anyhow::Result<()> {
         let c = pool.get().await?;
@@ -131,47 +176,60 @@ impl RunningJobModel {
             None
         };
         let operator_state = serde_json::to_value(&checkpoint_state.operator_details).unwrap();
-        controller_queries::update_checkpoint()
+        let updated = controller_queries::update_checkpoint()
             .bind(
                 &c,
                 &operator_state,
                 &finish_time,
                 &db_checkpoint_state,
                 &checkpoint_state.checkpoint_id(),
+                &leader_term,
             )
             .await?;
 
-        Ok(())
+        ensure_fenced_write(updated, checkpoint_state.epoch())
     }
 
-    pub async fn finish_committing(checkpoint_id: i64, pool: &Pool) -> anyhow::Result<()> {
+    pub async fn finish_committing(
+        checkpoint_id: i64,
+        epoch: u32,
+        pool: &Pool,
+        leader_term: i64,
+    ) -> anyhow::Result<()> {
         info!("finishing committing");
         let finish_time = SystemTime::now();
 
         let c = pool.get().await?;
-        controller_queries::commit_checkpoint()
-            .bind(&c, &finish_time.into(), &checkpoint_id)
+        let updated = controller_queries::commit_checkpoint()
+            .bind(&c, &finish_time.into(), &checkpoint_id, &leader_term)
             .await?;
 
-        Ok(())
+        ensure_fenced_write(updated, epoch)
     }
 
     pub async fn handle_message(&mut self, msg: RunningMessage, pool: &Pool) -> anyhow::Result<()> {
         match msg {
             RunningMessage::TaskCheckpointEvent(c) => {
-                if let Some(checkpoint_state) = &mut self.checkpoint_state {
-                    if c.epoch != self.epoch {
+                if let Some(checkpoint_state) = self
+                    .queued_checkpoints
+                    .iter_mut()
+                    .find(|s| s.epoch() == c.epoch)
+                {
+                    checkpoint_state.checkpoint_event(c)?;
+                    Self::update_db(checkpoint_state, pool, self.leader_term).await?
+                } else if let Some(checkpoint_state) = &mut self.checkpoint_state {
+                    if c.epoch != self.checkpoint_epoch {
                         warn!(
                             message = "Received checkpoint event for wrong epoch",
                             epoch = c.epoch,
-                            expected = self.epoch,
+                            expected = self.checkpoint_epoch,
                             job_id = self.job_id,
                         );
                     } else {
                         match checkpoint_state {
                             CheckpointingOrCommittingState::Checkpointing(checkpoint_state) => {
                                 checkpoint_state.checkpoint_event(c)?;
-                                Self::update_db(checkpoint_state, pool).await?
+                                Self::update_db(checkpoint_state, pool, self.leader_term).await?
                             }
                             CheckpointingOrCommittingState::Committing(committing_state) => {
                                 if matches!(c.event_type(), TaskCheckpointEventType::FinishedCommit)
@@ -194,12 +252,19 @@ impl RunningJobModel {
                 }
             }
             RunningMessage::TaskCheckpointFinished(c) => {
-                if let Some(checkpoint_state) = &mut self.checkpoint_state {
-                    if c.epoch != self.epoch {
+                if let Some(checkpoint_state) = self
+                    .queued_checkpoints
+                    .iter_mut()
+                    .find(|s| s.epoch() == c.epoch)
+                {
+                    checkpoint_state.checkpoint_finished(c).await?;
+                    Self::update_db(checkpoint_state, pool, self.leader_term).await?;
+                } else if let Some(checkpoint_state) = &mut self.checkpoint_state {
+                    if c.epoch != self.checkpoint_epoch {
                         warn!(
                             message = "Received checkpoint finished for wrong epoch",
                             epoch = c.epoch,
-                            expected = self.epoch,
+                            expected = self.checkpoint_epoch,
                             self.job_id,
                         );
                     } else {
@@ -209,7 +274,7 @@ impl RunningJobModel {
                             bail!("Received checkpoint finished but not checkpointing");
                         };
                         checkpoint_state.checkpoint_finished(c).await?;
-                        Self::update_db(checkpoint_state, pool).await?;
+                        Self::update_db(checkpoint_state, pool, self.leader_term).await?;
                     }
                 } else {
                     warn!(
@@ -244,7 +309,18 @@ impl RunningJobModel {
             } => {
                 let key = (operator_id, subtask_index);
                 if let Some(status) = self.tasks.get_mut(&key) {
-                    status.state = TaskState::Failed(reason);
+                    if status.state == TaskState::Restarting {
+                        // this was reported by the task before it was restarted
+                        info!(
+                            message = "ignoring failure of restarted task",
+                            job_id = self.job_id,
+                            operator_id = key.0,
+                            subtask_index,
+                            reason,
+                        );
+                    } else {
+                        status.state = TaskState::Failed(reason);
+                    }
                 } else {
                     warn!(
                         message = "Received task failed message for unknown task",
@@ -255,9 +331,19 @@ impl RunningJobModel {
                     );
                 }
             }
-            RunningMessage::WorkerHeartbeat { worker_id, time } => {
+            RunningMessage::WorkerHeartbeat {
+                worker_id,
+                time,
+                metrics,
+            } => {
                 if let Some(worker) = self.workers.get_mut(&worker_id) {
                     worker.last_heartbeat = time;
+                    for m in metrics {
+                        let key = (m.operator_id.clone(), m.subtask_index);
+                        if self.tasks.contains_key(&key) {
+                            self.task_metrics.insert(key, m);
+                        }
+                    }
                 } else {
                     warn!(
                         message = "Received heartbeat for unknown worker",
@@ -304,6 +390,8 @@ impl RunningJobModel {
         organization_id: &str,
         pool: &Pool,
         then_stop: bool,
+        drain: bool,
+        unaligned: bool,
     ) -> anyhow::Result<()> {
         self.epoch += 1;
 
@@ -311,7 +399,9 @@ impl RunningJobModel {
             message = "Starting checkpointing",
             job_id = self.job_id,
             epoch = self.epoch,
-            then_stop
+            then_stop,
+            drain,
+            unaligned
         );
 
         // TODO: maybe parallelize
@@ -324,6 +414,8 @@ impl RunningJobModel {
                     min_epoch: self.min_epoch,
                     then_stop,
                     is_commit: false,
+                    drain,
+                    unaligned,
                 }))
                 .await?;
         }
@@ -340,20 +432,113 @@ impl RunningJobModel {
                     &(self.epoch as i32),
                     &(self.min_epoch as i32),
                     &OffsetDateTime::now_utc(),
+                    &self.leader_term,
                 )
                 .one()
-                .await?
+                .await
+                .context(
+                    "failed to create checkpoint; this controller may no longer be the leader",
+                )?
         };
 
-        let state = CheckpointState::new(
+        self.add_checkpoint(CheckpointState::new(
             self.job_id.clone(),
             checkpoint_id,
             self.epoch,
             self.min_epoch,
             self.program.tasks_per_operator(),
-        );
+        ));
+
+        Ok(())
+    }
+
+    fn add_checkpoint(&mut self, state: CheckpointState) {
+        self.last_checkpoint_start = Instant::now();
+        if self.checkpoint_state.is_none() {
+            self.checkpoint_epoch = state.epoch();
+            self.checkpoint_state = Some(CheckpointingOrCommittingState::Checkpointing(state));
+        } else {
+            self.queued_checkpoints.push_back(state);
+        }
+    }
+
+    /// Whether a new checkpoint may be started. A final checkpoint waits for all others to
+    /// finish; otherwise we may start one alongside those in progress, up to `max_concurrent`,
+    /// once `min_pause` has passed since the last one finished. Jobs with two-phase commits only
+    /// run one checkpoint at a time, so that the next epoch isn't pre-committed while the commit
+    /// of the previous one is still pending.
+    fn can_start_checkpoint(
+        &self,
+        then_stop: bool,
+        max_concurrent: usize,
+        min_pause: Duration,
+    ) -> bool {
+        if then_stop {
+            return self.checkpoint_state.is_none();
+        }
+
+        let max_concurrent = if self.two_phase_commits {
+            1
+        } else {
+            max_concurrent
+        };
+
+        self.checkpoints_in_progress() < max_concurrent
+            && self.last_checkpoint.elapsed() >= min_pause
+    }
+
+    /// The number of checkpoints that have been started but not yet finished
+    pub fn checkpoints_in_progress(&self) -> usize {
+        self.checkpoint_state.iter().count() + self.queued_checkpoints.len()
+    }
+
+    // moves on to the next queued checkpoint once the oldest one has finished
+    fn next_checkpoint(&mut self) {
+        self.checkpoint_state = self.queued_checkpoints.pop_front().map(|state| {
+            self.checkpoint_epoch = state.epoch();
+            CheckpointingOrCommittingState::Checkpointing(state)
+        });
+    }
+
+    /// Returns the epoch of the oldest in-progress checkpoint if it has been running for longer
+    /// than `timeout`; checkpoints that are committing are never timed out, as their data has
+    /// already been pre-committed by the sinks
+    pub fn timed_out_checkpoint(&self, timeout: Duration) -> Option<u32> {
+        match &self.checkpoint_state {
+            Some(CheckpointingOrCommittingState::Checkpointing(checkpoint))
+                if checkpoint.start_time().elapsed().unwrap_or(Duration::ZERO) > timeout =>
+            {
+                Some(self.checkpoint_epoch)
+            }
+            _ => None,
+        }
+    }
+
+    /// Gives up on all checkpoints that are in progress (but not committing), marking them as
+    /// failed; the next checkpoint will start with a new epoch
+    pub async fn abandon_checkpoints(&mut self, pool: &Pool) -> anyhow::Result<()> {
+        let oldest = match self.checkpoint_state.take() {
+            Some(CheckpointingOrCommittingState::Checkpointing(checkpoint)) => Some(checkpoint),
+            committing => {
+                self.checkpoint_state = committing;
+                None
+            }
+        };
 
-        self.checkpoint_state = Some(CheckpointingOrCommittingState::Checkpointing(state));
+        for checkpoint in oldest.into_iter().chain(self.queued_checkpoints.drain(..)) {
+            warn!(
+                message = "abandoning checkpoint",
+                job_id = self.job_id,
+                epoch = checkpoint.epoch()
+            );
+            Self::update_checkpoint_in_db(
+                &checkpoint,
+                pool,
+                self.leader_term,
+                DbCheckpointState::failed,
+            )
+            .await?;
+        }
 
         Ok(())
     }
@@ -378,7 +563,7 @@ impl RunningJobModel {
                 // compact the operator's state and notify the workers to load the new files
                 self.job_id.clone(),
                 operator_id.clone(),
-                self.epoch,
+                self.completed_epoch,
             )
             .await?;
 
@@ -400,11 +585,13 @@ impl RunningJobModel {
     }
 
     pub async fn finish_checkpoint_if_done(&mut self, pool: &Pool) -> anyhow::Result<()> {
-        if self.checkpoint_state.as_ref().unwrap().done() {
+        // queued checkpoints may already have all of their data by the time they're next up
+        while self.checkpoint_state.as_ref().is_some_and(|s| s.done()) {
             let state = self.checkpoint_state.take().unwrap();
             match state {
                 CheckpointingOrCommittingState::Checkpointing(checkpointing) => {
                     checkpointing.save_state().await?;
+                    self.completed_epoch = self.checkpoint_epoch;
 
                     let committing_state = checkpointing.committing_state();
                     let duration = checkpointing
@@ -418,23 +605,26 @@ impl RunningJobModel {
                         Self::update_checkpoint_in_db(
                             &checkpointing,
                             pool,
+                            self.leader_term,
                             DbCheckpointState::ready,
                         )
                         .await?;
                         self.last_checkpoint = Instant::now();
-                        self.checkpoint_state = None;
                         self.compact_state().await?;
 
                         info!(
                             message = "Finished checkpointing",
                             job_id = self.job_id,
-                            epoch = self.epoch,
+                            epoch = self.checkpoint_epoch,
                             duration
                         );
+                        self.next_checkpoint();
                     } else {
+                        self.two_phase_commits = true;
                         Self::update_checkpoint_in_db(
                             &checkpointing,
                             pool,
+                            self.leader_term,
                             DbCheckpointState::committing,
                         )
                         .await?;
@@ -444,13 +634,13 @@ impl RunningJobModel {
                         info!(
                             message = "Committing checkpoint",
                             job_id = self.job_id,
-                            epoch = self.epoch,
+                            epoch = self.checkpoint_epoch,
                         );
                         for worker in self.workers.values_mut() {
                             worker
                                 .connect
                                 .commit(Request::new(CommitReq {
-                                    epoch: self.epoch,
+                                    epoch: self.checkpoint_epoch,
                                     committing_data: committing_data.clone(),
                                 }))
                                 .await?;
@@ -458,23 +648,31 @@ impl RunningJobModel {
                     }
                 }
                 CheckpointingOrCommittingState::Committing(committing) => {
-                    Self::finish_committing(committing.checkpoint_id(), pool).await?;
+                    Self::finish_committing(
+                        committing.checkpoint_id(),
+                        self.checkpoint_epoch,
+                        pool,
+                        self.leader_term,
+                    )
+                    .await?;
                     self.last_checkpoint = Instant::now();
-                    self.checkpoint_state = None;
                     info!(
                         message = "Finished committing checkpointing",
                         job_id = self.job_id,
-                        epoch = self.epoch,
+                        epoch = self.checkpoint_epoch,
                     );
+                    self.next_checkpoint();
                 }
             }
         }
         Ok(())
     }
 
-    pub fn cleanup_needed(&self) -> Option<u32> {
-        if self.epoch - self.min_epoch > CHECKPOINTS_TO_KEEP && self.epoch % COMPACT_EVERY == 0 {
-            Some(self.epoch - CHECKPOINTS_TO_KEEP)
+    pub fn cleanup_needed(&self, retained: u32) -> Option<u32> {
+        if self.completed_epoch - self.min_epoch > retained
+            && self.completed_epoch % COMPACT_EVERY == 0
+        {
+            Some(self.completed_epoch - retained)
         } else {
             None
         }
@@ -528,6 +726,7 @@ pub struct JobController {
     config: JobConfig,
     model: RunningJobModel,
     cleanup_task: Option<JoinHandle<anyhow::Result<u32>>>,
+    autoscaler: Option<Autoscaler>,
 }
 
 impl std::fmt::Debug for JobController {
@@ -536,6 +735,7 @@ impl std::fmt::Debug for JobController {
             .field("config", &self.config)
             .field("model", &self.model)
             .field("cleaning", &self.cleanup_task.is_some())
+            .field("autoscaling", &self.autoscaler.is_some())
             .finish()
     }
 }
@@ -546,23 +746,38 @@ pub enum ControllerProgress {
 }
 
 impl JobController {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         pool: Pool,
+        leader_term: i64,
         config: JobConfig,
         program: LogicalProgram,
         epoch: u32,
         min_epoch: u32,
         worker_connects: HashMap<WorkerId, WorkerGrpcClient<Channel>>,
+        assignments: Vec<TaskAssignment>,
         commit_state: Option<CommittingState>,
     ) -> Self {
+        let autoscaler = config
+            .autoscaling
+            .clone()
+            .filter(|c| c.enabled)
+            .map(|c| Autoscaler::new(c, &program));
+
         Self {
             pool,
             model: RunningJobModel {
                 job_id: config.id.clone(),
+                leader_term,
                 state: JobState::Running,
+                two_phase_commits: commit_state.is_some(),
                 checkpoint_state: commit_state.map(CheckpointingOrCommittingState::Committing),
+                checkpoint_epoch: epoch,
+                queued_checkpoints: VecDeque::new(),
                 epoch,
+                completed_epoch: epoch,
                 min_epoch,
+                last_checkpoint_start: Instant::now(),
                 last_checkpoint: Instant::now(),
                 workers: worker_connects
                     .into_iter()
@@ -592,14 +807,37 @@ impl JobController {
                         })
                     })
                     .collect(),
+                task_metrics: HashMap::new(),
                 operator_parallelism: program.tasks_per_operator(),
+                assignments: assignments
+                    .into_iter()
+                    .map(|a| ((a.operator_id.clone(), a.operator_subtask as u32), a))
+                    .collect(),
+                failover_regions: program
+                    .failover_regions()
+                    .into_iter()
+                    .map(|region| {
+                        region
+                            .into_iter()
+                            .map(|(op, subtask)| (op, subtask as u32))
+                            .collect()
+                    })
+                    .collect(),
                 program,
             },
             config,
             cleanup_task: None,
+            autoscaler,
         }
     }
 
+    /// Sets the epoch of the last successful checkpoint, for controllers that take over a running
+    /// job where checkpoints after it may have been started (and abandoned) by a previous leader
+    pub fn with_completed_epoch(mut self, completed_epoch: u32) -> Self {
+        self.model.completed_epoch = completed_epoch;
+        self
+    }
+
     pub async fn handle_message(&mut self, msg: RunningMessage) -> anyhow::Result<()> {
         self.model.handle_message(msg, &self.pool).await
     }
@@ -651,25 +889,274 @@ impl JobController {
             }
         }
 
-        if let Some(new_epoch) = self.model.cleanup_needed() {
+        let cleanup_needed = self.model.cleanup_needed(self.retained_checkpoints());
+        if let Some(new_epoch) = cleanup_needed {
             if self.cleanup_task.is_none() && self.model.checkpoint_state.is_none() {
                 self.cleanup_task = Some(self.start_cleanup(new_epoch));
             }
         }
 
         // check on checkpointing
+        self.check_checkpoint_timeout().await?;
         if self.model.checkpoint_state.is_some() {
             self.model.finish_checkpoint_if_done(&self.pool).await?;
-        } else if self.model.last_checkpoint.elapsed() > self.config.checkpoint_interval
+        }
+
+        // or do we need to start checkpointing? we hold off while cleanup is pending so that
+        // concurrent checkpoints can't keep it from running
+        if self.model.last_checkpoint_start.elapsed() > self.config.checkpoint_interval
             && self.cleanup_task.is_none()
+            && cleanup_needed.is_none()
         {
-            // or do we need to start checkpointing?
             self.checkpoint(false).await?;
         }
 
+        if let Some(autoscaler) = &mut self.autoscaler {
+            let decisions = autoscaler.evaluate(&self.model.task_metrics);
+            if !decisions.is_empty() {
+                self.rescale(decisions).await?;
+            }
+        }
+
         Ok(ControllerProgress::Continue)
     }
 
+    /// Applies changes to the checkpoint interval and settings to the running job
+    pub fn update_checkpoint_config(&mut self, interval: Duration, config: &CheckpointConfig) {
+        self.config.checkpoint_interval = interval;
+        self.config.checkpoint_config = config.clone();
+    }
+
+    fn max_concurrent_checkpoints(&self) -> usize {
+        self.config
+            .checkpoint_config
+            .max_concurrent_checkpoints
+            .unwrap_or(1)
+            .max(1) as usize
+    }
+
+    fn retained_checkpoints(&self) -> u32 {
+        self.config
+            .checkpoint_config
+            .retained_checkpoints
+            .unwrap_or(CHECKPOINTS_TO_KEEP)
+            .max(1)
+    }
+
+    // aborts the oldest checkpoint if it has exceeded the configured timeout, which fails the job
+    // so that it's restarted according to its restart strategy
+    async fn check_checkpoint_timeout(&mut self) -> anyhow::Result<()> {
+        let Some(timeout) = self
+            .config
+            .checkpoint_config
+            .timeout_micros
+            .map(Duration::from_micros)
+        else {
+            return Ok(());
+        };
+
+        if let Some(epoch) = self.model.timed_out_checkpoint(timeout) {
+            self.model.abandon_checkpoints(&self.pool).await?;
+            bail!("checkpoint {} timed out after {:?}", epoch, timeout);
+        }
+
+        Ok(())
+    }
+
+    /// Enables, disables, or reconfigures the autoscaler when the job's config changes
+    pub fn update_autoscaling(&mut self, config: Option<&AutoscalingConfig>) {
+        let Some(config) = config.filter(|c| c.enabled) else {
+            self.autoscaler = None;
+            return;
+        };
+
+        if let Some(autoscaler) = &mut self.autoscaler {
+            autoscaler.update_config(config.clone());
+        } else {
+            self.autoscaler = Some(Autoscaler::new(config.clone(), &self.model.program));
+        }
+    }
+
+    /// Applies the autoscaler's decisions by updating the job's parallelism overrides, which
+    /// causes the job to be rescaled through the same path as a user-initiated change
+    async fn rescale(&mut self, decisions: Vec<ScalingDecision>) -> anyhow::Result<()> {
+        let mut overrides = self.model.operator_parallelism.clone();
+        for decision in &decisions {
+            for op in &decision.operators {
+                overrides.insert(op.clone(), decision.to);
+            }
+        }
+
+        let c = self.pool.get().await?;
+
+        controller_queries::update_autoscaled_parallelism()
+            .bind(
+                &c,
+                &serde_json::to_value(&overrides)?,
+                &self.config.id,
+                &self.model.leader_term,
+            )
+            .await?;
+
+        for decision in decisions {
+            let message = format!(
+                "Autoscaler {} {} from parallelism {} to {}",
+                if decision.to > decision.from {
+                    "scaling up"
+                } else {
+                    "scaling down"
+                },
+                decision.operators.join(", "),
+                decision.from,
+                decision.to
+            );
+
+            info!(
+                message = "autoscaling operators",
+                job_id = self.config.id,
+                operators = decision.operators.join(", "),
+                from = decision.from,
+                to = decision.to,
+                reason = decision.reason,
+            );
+
+            controller_queries::create_job_log_message()
+                .bind(
+                    &c,
+                    &generate_id(IdTypes::JobLogMessage),
+                    &self.config.id,
+                    &decision.operators.first().cloned(),
+                    &None::<i64>,
+                    &LogLevel::info,
+                    &message,
+                    &decision.reason,
+                )
+                .one()
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Recovers from task failures by restarting only the failover regions that contain the
+    /// failed tasks from the last checkpoint, while the rest of the job keeps running. Returns
+    /// false if the job needs to be recovered as a whole instead.
+    pub async fn restart_failed_regions(&mut self) -> anyhow::Result<bool> {
+        let enabled = env::var("REGIONAL_FAILOVER_ENABLED")
+            .map(|v| v.to_lowercase() != "false")
+            .unwrap_or(true);
+
+        // tasks that are committing can't be restored without committing the whole job
+        if !enabled
+            || self.model.workers.values().any(|w| w.heartbeat_timeout())
+            || matches!(
+                self.model.checkpoint_state,
+                Some(CheckpointingOrCommittingState::Committing(_))
+            )
+        {
+            return Ok(false);
+        }
+
+        let failed: HashMap<_, _> = self
+            .model
+            .tasks
+            .iter()
+            .filter_map(|(task, status)| match &status.state {
+                TaskState::Failed(reason) => Some((task.clone(), reason.clone())),
+                _ => None,
+            })
+            .collect();
+
+        let failed_tasks: HashSet<_> = failed.keys().cloned().collect();
+        let Some(regions) = failover::regions_to_restart(
+            &self.model.failover_regions,
+            &failed_tasks,
+            &self.model.assignments,
+        ) else {
+            return Ok(false);
+        };
+
+        // the failed tasks will never finish the in-progress checkpoints, so we abandon them and
+        // the next checkpoint will start with a new epoch
+        self.model.abandon_checkpoints(&self.pool).await?;
+
+        let restore_epoch = (self.model.completed_epoch > 0).then_some(self.model.completed_epoch);
+
+        let c = self.pool.get().await?;
+
+        for region in regions {
+            let tasks: Vec<_> = region
+                .iter()
+                .map(|task| self.model.assignments[task].clone())
+                .collect();
+            let worker_id = WorkerId(tasks[0].worker_id);
+
+            let Some(worker) = self.model.workers.get_mut(&worker_id) else {
+                bail!("no worker {} for failover region", worker_id.0);
+            };
+
+            info!(
+                message = "restarting failover region",
+                job_id = self.config.id,
+                worker_id = worker_id.0,
+                tasks = tasks.len(),
+                restore_epoch
+            );
+
+            worker
+                .connect
+                .restart_tasks(Request::new(RestartTasksReq {
+                    restore_epoch,
+                    tasks,
+                }))
+                .await?;
+
+            for task in region {
+                if let Some(status) = self.model.tasks.get_mut(task) {
+                    status.state = TaskState::Restarting;
+                }
+                self.model.task_metrics.remove(task);
+            }
+
+            let (operator_id, reason) = region
+                .iter()
+                .find_map(|task| failed.get(task).map(|reason| (task.0.clone(), reason)))
+                .unwrap();
+
+            controller_queries::create_job_log_message()
+                .bind(
+                    &c,
+                    &generate_id(IdTypes::JobLogMessage),
+                    &self.config.id,
+                    &Some(operator_id.clone()),
+                    &None::<i64>,
+                    &LogLevel::warn,
+                    &format!(
+                        "Operator {} failed; restarted the {} tasks in its failover region \
+                        from {}",
+                        operator_id,
+                        region.len(),
+                        restore_epoch
+                            .map(|e| format!("checkpoint {}", e))
+                            .unwrap_or_else(|| "the beginning".to_string())
+                    ),
+                    reason,
+                )
+                .one()
+                .await?;
+        }
+
+        Ok(true)
+    }
+
+    pub fn task_started(&mut self, operator_id: String, subtask_index: u32) {
+        if let Some(status) = self.model.tasks.get_mut(&(operator_id, subtask_index)) {
+            if status.state == TaskState::Restarting {
+                status.state = TaskState::Running;
+            }
+        }
+    }
+
     pub async fn stop_job(&mut self, stop_mode: StopMode) -> anyhow::Result<()> {
         for c in self.model.workers.values_mut() {
             c.connect
@@ -683,9 +1170,39 @@ impl JobController {
     }
 
     pub async fn checkpoint(&mut self, then_stop: bool) -> anyhow::Result<bool> {
+        let min_pause =
+            Duration::from_micros(self.config.checkpoint_config.min_pause_micros.unwrap_or(0));
+        let can_start = self.model.can_start_checkpoint(
+            then_stop,
+            self.max_concurrent_checkpoints(),
+            min_pause,
+        );
+
+        // final checkpoints are always aligned, so that no in-flight data is left to replay
+        let unaligned = !then_stop && self.config.checkpoint_config.unaligned.unwrap_or(false);
+
+        if can_start {
+            self.model
+                .start_checkpoint(
+                    &self.config.organization_id,
+                    &self.pool,
+                    then_stop,
+                    false,
+                    unaligned,
+                )
+                .await?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Starts a final checkpoint that first advances the watermark to infinity, so that all
+    /// windows and timers are flushed and committed before the job finishes
+    pub async fn drain(&mut self) -> anyhow::Result<bool> {
         if self.model.checkpoint_state.is_none() {
             self.model
-                .start_checkpoint(&self.config.organization_id, &self.pool, then_stop)
+                .start_checkpoint(&self.config.organization_id, &self.pool, true, true, false)
                 .await?;
             Ok(true)
         } else {
@@ -698,6 +1215,7 @@ impl JobController {
     }
 
     pub async fn checkpoint_finished(&mut self) -> anyhow::Result<bool> {
+        self.check_checkpoint_timeout().await?;
         if self.model.checkpoint_state.is_some() {
             self.model.finish_checkpoint_if_done(&self.pool).await?;
         }
@@ -714,7 +1232,7 @@ impl JobController {
             worker
                 .connect
                 .commit(CommitReq {
-                    epoch: self.model.epoch,
+                    epoch: self.model.checkpoint_epoch,
                     committing_data: committing.committing_data(),
                 })
                 .await?;
@@ -760,28 +1278,35 @@ impl JobController {
         let min_epoch = self.model.min_epoch.max(1);
         let job_id = self.config.id.clone();
         let pool = self.pool.clone();
+        let leader_term = self.model.leader_term;
 
         info!(message = "Starting cleaning", job_id, min_epoch, new_min);
         let start = Instant::now();
-        let cur_epoch = self.model.epoch;
+        let cur_epoch = self.model.completed_epoch;
 
         tokio::spawn(async move {
             let checkpoint = StateBackend::load_checkpoint_metadata(&job_id, cur_epoch).await?;
 
             let c = pool.get().await?;
             controller_queries::mark_compacting()
-                .bind(&c, &job_id, &(min_epoch as i32), &(new_min as i32))
+                .bind(
+                    &c,
+                    &job_id,
+                    &(min_epoch as i32),
+                    &(new_min as i32),
+                    &leader_term,
+                )
                 .await?;
 
             StateBackend::cleanup_checkpoint(checkpoint, min_epoch, new_min).await?;
 
             controller_queries::mark_checkpoints_compacted()
-                .bind(&c, &job_id, &(new_min as i32))
+                .bind(&c, &job_id, &(new_min as i32), &leader_term)
                 .await?;
 
             if let Some(epoch_to_filter_before) = min_epoch.checked_sub(CHECKPOINT_ROWS_TO_KEEP) {
                 controller_queries::drop_old_checkpoint_rows()
-                    .bind(&c, &job_id, &(epoch_to_filter_before as i32))
+                    .bind(&c, &job_id, &(epoch_to_filter_before as i32), &leader_term)
                     .await?;
             }
 
@@ -797,3 +1322,145 @@ impl JobController {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> RunningJobModel {
+        RunningJobModel {
+            job_id: "job".to_string(),
+            leader_term: 1,
+            state: JobState::Running,
+            program: LogicalProgram::default(),
+            checkpoint_state: None,
+            checkpoint_epoch: 0,
+            queued_checkpoints: VecDeque::new(),
+            epoch: 0,
+            completed_epoch: 0,
+            min_epoch: 0,
+            last_checkpoint_start: Instant::now(),
+            last_checkpoint: Instant::now(),
+            two_phase_commits: false,
+            workers: HashMap::new(),
+            tasks: HashMap::new(),
+            task_metrics: HashMap::new(),
+            operator_parallelism: HashMap::new(),
+            assignments: HashMap::new(),
+            failover_regions: vec![],
+        }
+    }
+
+    fn start_checkpoint(model: &mut RunningJobModel) {
+        model.epoch += 1;
+        model.add_checkpoint(CheckpointState::new(
+            model.job_id.clone(),
+            model.epoch as i64,
+            model.epoch,
+            model.min_epoch,
+            HashMap::from([("op".to_string(), 1)]),
+        ));
+    }
+
+    fn commit(model: &mut RunningJobModel) {
+        model.two_phase_commits = true;
+        model.checkpoint_state = Some(CheckpointingOrCommittingState::Committing(
+            CommittingState::new(
+                model.checkpoint_epoch as i64,
+                HashSet::from([("op".to_string(), 0)]),
+                HashMap::new(),
+            ),
+        ));
+    }
+
+    #[test]
+    fn test_timed_out_checkpoint() {
+        let mut model = model();
+        assert_eq!(model.timed_out_checkpoint(Duration::ZERO), None);
+
+        start_checkpoint(&mut model);
+        start_checkpoint(&mut model);
+        std::thread::sleep(Duration::from_millis(5));
+
+        // the oldest checkpoint is the one that times out
+        assert_eq!(model.timed_out_checkpoint(Duration::ZERO), Some(1));
+        assert_eq!(model.timed_out_checkpoint(Duration::from_secs(60)), None);
+
+        // data that's being committed has already been pre-committed, so we keep waiting for it
+        commit(&mut model);
+        assert_eq!(model.timed_out_checkpoint(Duration::ZERO), None);
+    }
+
+    #[test]
+    fn test_cleanup_needed() {
+        let mut model = model();
+        model.min_epoch = 1;
+
+        model.completed_epoch = 4;
+        assert_eq!(model.cleanup_needed(5), None);
+        assert_eq!(model.cleanup_needed(2), Some(2));
+
+        // cleanup only runs every COMPACT_EVERY epochs
+        model.completed_epoch = 5;
+        assert_eq!(model.cleanup_needed(2), None);
+
+        model.completed_epoch = 6;
+        assert_eq!(model.cleanup_needed(5), None);
+        assert_eq!(model.cleanup_needed(4), Some(2));
+        assert_eq!(model.cleanup_needed(1), Some(5));
+    }
+
+    #[test]
+    fn test_checkpoint_concurrency() {
+        let mut model = model();
+        assert!(model.can_start_checkpoint(false, 2, Duration::ZERO));
+
+        start_checkpoint(&mut model);
+        assert!(model.can_start_checkpoint(false, 2, Duration::ZERO));
+        assert!(!model.can_start_checkpoint(false, 1, Duration::ZERO));
+
+        start_checkpoint(&mut model);
+        assert_eq!(model.checkpoints_in_progress(), 2);
+        assert!(!model.can_start_checkpoint(false, 2, Duration::ZERO));
+
+        // final checkpoints wait for all others to finish
+        assert!(!model.can_start_checkpoint(true, 3, Duration::ZERO));
+
+        // queued checkpoints are finished in order
+        model.next_checkpoint();
+        assert_eq!(model.checkpoint_epoch, 2);
+        assert_eq!(model.checkpoints_in_progress(), 1);
+        model.next_checkpoint();
+        assert_eq!(model.checkpoints_in_progress(), 0);
+        assert!(model.can_start_checkpoint(true, 1, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_checkpoint_min_pause() {
+        let mut model = model();
+        assert!(!model.can_start_checkpoint(false, 2, Duration::from_secs(60)));
+
+        // final checkpoints don't wait for the pause
+        assert!(model.can_start_checkpoint(true, 2, Duration::from_secs(60)));
+
+        model.last_checkpoint = Instant::now() - Duration::from_secs(61);
+        assert!(model.can_start_checkpoint(false, 2, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_two_phase_commits_limit_concurrency() {
+        let mut model = model();
+        start_checkpoint(&mut model);
+        commit(&mut model);
+
+        // no checkpoint may start while a commit is pending
+        assert!(!model.can_start_checkpoint(false, 3, Duration::ZERO));
+        assert!(!model.can_start_checkpoint(true, 3, Duration::ZERO));
+
+        // and once it has finished, checkpoints still run one at a time
+        model.next_checkpoint();
+        assert!(model.can_start_checkpoint(false, 3, Duration::ZERO));
+        start_checkpoint(&mut model);
+        assert!(!model.can_start_checkpoint(false, 3, Duration::ZERO));
+    }
+}
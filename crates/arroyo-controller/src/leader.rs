@@ -0,0 +1,266 @@
+//! Leader election between controller replicas.
+//!
+//! Multiple controllers may be run against the same database for high availability, but only one
+//! of them may manage jobs at a time. Leadership is determined by a session-level Postgres advisory
+//! lock, which is held on a dedicated connection for as long as the controller is leader and is
+//! released by Postgres if that connection is lost.
+//!
+//! Each new leader also increments the leader term stored in `cluster_info`. The controller's
+//! writes to job statuses and checkpoints are conditioned on that term still being current, so a
+//! controller that has lost leadership but not yet noticed can't overwrite the new leader's state.
+
+use std::time::Duration;
+
+use arroyo_server_common::shutdown::ShutdownGuard;
+use arroyo_types::DatabaseConfig;
+use tokio::sync::oneshot;
+use tokio_postgres::{Client, NoTls};
+use tracing::{info, warn};
+
+use crate::queries::controller_queries;
+
+/// The advisory lock key that the leader holds
+pub const LEADER_LOCK_KEY: i64 = 0x4152_524f;
+
+const ACQUIRE_INTERVAL: Duration = Duration::from_secs(5);
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// TCP keepalives on the lock connection, so that a dead database host is noticed even while the
+// connection is idle
+const KEEPALIVE_IDLE: Duration = Duration::from_secs(10);
+
+pub struct LeaderLock {
+    client: Client,
+    // resolves once the lock's connection has closed, which releases the lock
+    closed: oneshot::Receiver<()>,
+    term: i64,
+}
+
+impl LeaderLock {
+    /// Waits until this controller becomes the leader, returning None if the controller is shut
+    /// down first
+    pub async fn acquire(config: &DatabaseConfig, guard: &ShutdownGuard) -> Option<Self> {
+        let token = guard.token();
+        let mut waiting = false;
+        loop {
+            match Self::try_acquire(config).await {
+                Ok(Some(lock)) => {
+                    info!(message = "acquired controller leadership", term = lock.term);
+                    return Some(lock);
+                }
+                Ok(None) => {
+                    if !waiting {
+                        info!("another controller is the leader; running as standby");
+                        waiting = true;
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        message = "failed to acquire leader lock",
+                        error = format!("{:?}", e)
+                    );
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(ACQUIRE_INTERVAL) => {}
+                _ = token.cancelled() => {
+                    return None;
+                }
+            }
+        }
+    }
+
+    async fn try_acquire(config: &DatabaseConfig) -> anyhow::Result<Option<Self>> {
+        // the lock is held on its own connection (rather than one from the pool) so that we find
+        // out as soon as that connection fails
+        let (client, connection) = tokio_postgres::Config::new()
+            .dbname(&config.name)
+            .host(&config.host)
+            .port(config.port)
+            .user(&config.user)
+            .password(&config.password)
+            .keepalives_idle(KEEPALIVE_IDLE)
+            .connect(NoTls)
+            .await?;
+
+        let (tx, closed) = oneshot::channel();
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!(
+                    message = "leader lock connection failed",
+                    error = format!("{:?}", e)
+                );
+            }
+            let _ = tx.send(());
+        });
+
+        let acquired = controller_queries::try_acquire_leader_lock()
+            .bind(&client, &LEADER_LOCK_KEY)
+            .one()
+            .await?;
+
+        if !acquired {
+            return Ok(None);
+        }
+
+        let term = controller_queries::increment_leader_term()
+            .bind(&client)
+            .one()
+            .await?;
+
+        Ok(Some(Self {
+            client,
+            closed,
+            term,
+        }))
+    }
+
+    /// The leader term, which fences the controller's database writes
+    pub fn term(&self) -> i64 {
+        self.term
+    }
+
+    async fn held(&self) -> anyhow::Result<bool> {
+        Ok(controller_queries::leader_lock_held()
+            .bind(&self.client, &LEADER_LOCK_KEY)
+            .one()
+            .await?)
+    }
+
+    /// Resolves once leadership has been lost: immediately if the lock's connection fails, or on
+    /// the next check if the lock is no longer held or the database stops responding
+    pub async fn lost(&mut self) {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        interval.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = &mut self.closed => {
+                    warn!("leader lock connection closed");
+                    return;
+                }
+                _ = interval.tick() => {
+                    match tokio::time::timeout(CHECK_INTERVAL, self.held()).await {
+                        Ok(Ok(true)) => {}
+                        Ok(Ok(false)) => {
+                            warn!("leader lock is no longer held");
+                            return;
+                        }
+                        Ok(Err(e)) => {
+                            warn!(
+                                message = "failed to check leader lock",
+                                error = format!("{:?}", e)
+                            );
+                            return;
+                        }
+                        Err(_) => {
+                            warn!("timed out checking leader lock");
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    async fn connect() -> Client {
+        let config = DatabaseConfig::load();
+        let (client, connection) = tokio_postgres::Config::new()
+            .dbname(&config.name)
+            .host(&config.host)
+            .port(config.port)
+            .user(&config.user)
+            .password(&config.password)
+            .connect(NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+        client
+    }
+
+    async fn backend_pid(client: &Client) -> i32 {
+        client
+            .query_one("SELECT pg_backend_pid()", &[])
+            .await
+            .unwrap()
+            .get(0)
+    }
+
+    async fn term_is_current(client: &Client, term: i64) -> bool {
+        client
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM cluster_info WHERE leader_term = $1)",
+                &[&term],
+            )
+            .await
+            .unwrap()
+            .get(0)
+    }
+
+    #[tokio::test]
+    async fn test_leader_election() {
+        let config = DatabaseConfig::load();
+
+        let Some(mut leader) = LeaderLock::try_acquire(&config).await.unwrap() else {
+            panic!("another controller is running against the test database");
+        };
+
+        // only one controller can be leader at a time
+        assert!(LeaderLock::try_acquire(&config).await.unwrap().is_none());
+        assert!(leader.held().await.unwrap());
+
+        let client = connect().await;
+        assert!(term_is_current(&client, leader.term()).await);
+
+        // leadership is lost as soon as the lock's connection fails, without waiting for the
+        // next check
+        let pid = backend_pid(&leader.client).await;
+        client
+            .execute("SELECT pg_terminate_backend($1)", &[&pid])
+            .await
+            .unwrap();
+
+        let start = Instant::now();
+        tokio::time::timeout(CHECK_INTERVAL, leader.lost())
+            .await
+            .expect("leadership loss wasn't detected");
+        assert!(start.elapsed() < CHECK_INTERVAL);
+
+        // a new leader can take over, with a new term
+        let mut new_leader = None;
+        for _ in 0..50 {
+            new_leader = LeaderLock::try_acquire(&config).await.unwrap();
+            if new_leader.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let new_leader = new_leader.expect("lock wasn't released");
+        assert!(new_leader.term() > leader.term());
+        assert!(term_is_current(&client, new_leader.term()).await);
+        assert!(!term_is_current(&client, leader.term()).await);
+
+        // and writes from the old leader are fenced off
+        let stale_write = controller_queries::create_checkpoint()
+            .bind(
+                &client,
+                &"leader-election-test",
+                &"leader-election-test",
+                &"leader-election-test",
+                &"parquet",
+                &1,
+                &1,
+                &time::OffsetDateTime::now_utc(),
+                &leader.term(),
+            )
+            .one()
+            .await;
+        assert!(stale_write.is_err());
+    }
+}
@@ -3,21 +3,22 @@
 #![allow(clippy::type_complexity)]
 
 use anyhow::Result;
+use arroyo_rpc::api_types::pipelines::{AutoscalingConfig, CheckpointConfig, RestartStrategy};
 use arroyo_rpc::grpc::controller_grpc_server::{ControllerGrpc, ControllerGrpcServer};
 use arroyo_rpc::grpc::{
     GrpcOutputSubscription, HeartbeatNodeReq, HeartbeatNodeResp, HeartbeatReq, HeartbeatResp,
-    OutputData, RegisterNodeReq, RegisterNodeResp, RegisterWorkerReq, RegisterWorkerResp,
-    TaskCheckpointCompletedReq, TaskCheckpointCompletedResp, TaskFailedReq, TaskFailedResp,
-    TaskFinishedReq, TaskFinishedResp, TaskStartedReq, TaskStartedResp, WorkerFinishedReq,
-    WorkerFinishedResp,
+    OutputData, ReconnectWorkerReq, ReconnectWorkerResp, RegisterNodeReq, RegisterNodeResp,
+    RegisterWorkerReq, RegisterWorkerResp, TaskAssignment, TaskCheckpointCompletedReq,
+    TaskCheckpointCompletedResp, TaskFailedReq, TaskFailedResp, TaskFinishedReq, TaskFinishedResp,
+    TaskStartedReq, TaskStartedResp, WorkerFinishedReq, WorkerFinishedResp,
 };
 use arroyo_rpc::grpc::{
-    SinkDataReq, SinkDataResp, TaskCheckpointEventReq, TaskCheckpointEventResp, WorkerErrorReq,
-    WorkerErrorRes,
+    RegisterIngestEndpointReq, RegisterIngestEndpointResp, SinkDataReq, SinkDataResp,
+    TaskCheckpointEventReq, TaskCheckpointEventResp, TaskMetrics, WorkerErrorReq, WorkerErrorRes,
 };
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_server_common::shutdown::ShutdownGuard;
-use arroyo_types::{from_micros, grpc_port, ports, NodeId, WorkerId};
+use arroyo_types::{from_micros, grpc_port, ports, DatabaseConfig, NodeId, WorkerId};
 use deadpool_postgres::Pool;
 use lazy_static::lazy_static;
 use prometheus::{register_gauge, Gauge};
@@ -31,15 +32,19 @@ use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::Sender;
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
-use tracing::{debug, info, warn};
+use tracing::{debug, error, info, warn};
 
 //pub mod compiler;
+mod ingest;
 pub mod job_controller;
+mod leader;
 pub mod schedulers;
 mod states;
 
 include!(concat!(env!("OUT_DIR"), "/controller-sql.rs"));
 
+use crate::ingest::IngestRouter;
+use crate::leader::LeaderLock;
 use crate::schedulers::{NodeScheduler, ProcessScheduler, Scheduler};
 use types::public::LogLevel;
 use types::public::{RestartMode, StopMode};
@@ -72,10 +77,14 @@ pub struct JobConfig {
     pipeline_id: i64,
     stop_mode: StopMode,
     checkpoint_interval: Duration,
+    checkpoint_config: CheckpointConfig,
     ttl: Option<Duration>,
     parallelism_overrides: HashMap<String, usize>,
     restart_nonce: i32,
     restart_mode: RestartMode,
+    program_version: i32,
+    autoscaling: Option<AutoscalingConfig>,
+    restart_strategy: RestartStrategy,
 }
 
 #[derive(Clone, Debug)]
@@ -91,10 +100,14 @@ pub struct JobStatus {
     pipeline_path: Option<String>,
     wasm_path: Option<String>,
     restart_nonce: i32,
+    program_version: i32,
+    next_retry_time: Option<OffsetDateTime>,
 }
 
 impl JobStatus {
-    pub async fn update_db(&self, pool: &Pool) -> Result<(), String> {
+    /// Writes the status to the database, unless `leader_term` is no longer current because
+    /// another controller has taken over
+    pub async fn update_db(&self, pool: &Pool, leader_term: i64) -> Result<(), String> {
         let c = pool.get().await.map_err(|e| format!("{:?}", e))?;
         let res = queries::controller_queries::update_job_status()
             .bind(
@@ -109,13 +122,16 @@ impl JobStatus {
                 &self.wasm_path,
                 &self.run_id,
                 &self.restart_nonce,
+                &self.program_version,
+                &self.next_retry_time,
                 &self.id,
+                &leader_term,
             )
             .await
             .map_err(|e| format!("{:?}", e))?;
 
         if res == 0 {
-            Err("Job status does not exist".to_string())
+            Err("Job status does not exist or this controller is no longer the leader".to_string())
         } else {
             Ok(())
         }
@@ -141,6 +157,7 @@ pub enum RunningMessage {
     WorkerHeartbeat {
         worker_id: WorkerId,
         time: Instant,
+        metrics: Vec<TaskMetrics>,
     },
     WorkerFinished {
         worker_id: WorkerId,
@@ -157,6 +174,15 @@ pub enum JobMessage {
         data_address: String,
         slots: usize,
     },
+    WorkerReconnect {
+        worker_id: WorkerId,
+        node_id: NodeId,
+        run_id: i64,
+        rpc_address: String,
+        data_address: String,
+        slots: usize,
+        tasks: Vec<TaskAssignment>,
+    },
     TaskStarted {
         worker_id: WorkerId,
         operator_id: String,
@@ -170,6 +196,7 @@ pub struct ControllerServer {
     job_state: Arc<tokio::sync::Mutex<HashMap<String, StateMachine>>>,
     data_txs: Arc<tokio::sync::Mutex<HashMap<String, Vec<Sender<Result<OutputData, Status>>>>>>,
     scheduler: Arc<dyn Scheduler>,
+    ingest_router: IngestRouter,
     db: Pool,
 }
 
@@ -202,6 +229,35 @@ impl ControllerGrpc for ControllerServer {
         Ok(Response::new(RegisterWorkerResp {}))
     }
 
+    async fn reconnect_worker(
+        &self,
+        request: Request<ReconnectWorkerReq>,
+    ) -> Result<Response<ReconnectWorkerResp>, Status> {
+        info!(
+            "Worker reconnected: {:?} -- {:?}",
+            request.get_ref().worker_id,
+            request.remote_addr()
+        );
+
+        let req = request.into_inner();
+
+        self.send_to_job_queue(
+            &req.job_id,
+            JobMessage::WorkerReconnect {
+                worker_id: WorkerId(req.worker_id),
+                node_id: NodeId(req.node_id),
+                run_id: req.run_id,
+                rpc_address: req.rpc_address,
+                data_address: req.data_address,
+                slots: req.slots as usize,
+                tasks: req.tasks,
+            },
+        )
+        .await?;
+
+        Ok(Response::new(ReconnectWorkerResp {}))
+    }
+
     async fn heartbeat(
         &self,
         request: Request<HeartbeatReq>,
@@ -213,6 +269,7 @@ impl ControllerGrpc for ControllerServer {
             JobMessage::RunningMessage(RunningMessage::WorkerHeartbeat {
                 worker_id: WorkerId(req.worker_id),
                 time: Instant::now(),
+                metrics: req.task_metrics,
             }),
         )
         .await?;
@@ -411,8 +468,8 @@ impl ControllerGrpc for ControllerServer {
                 &client,
                 &generate_id(IdTypes::JobLogMessage),
                 &req.job_id,
-                &req.operator_id,
-                &(req.task_index as i64),
+                &Some(req.operator_id),
+                &Some(req.task_index as i64),
                 &LogLevel::error,
                 &req.message,
                 &req.details,
@@ -424,6 +481,16 @@ impl ControllerGrpc for ControllerServer {
             Err(err) => Err(Status::from_error(Box::new(err))),
         }
     }
+
+    async fn register_ingest_endpoint(
+        &self,
+        request: Request<RegisterIngestEndpointReq>,
+    ) -> Result<Response<RegisterIngestEndpointResp>, Status> {
+        let req = request.into_inner();
+        self.ingest_router
+            .register(req.job_id, req.path, req.subtask_index, req.address);
+        Ok(Response::new(RegisterIngestEndpointResp {}))
+    }
 }
 
 impl ControllerServer {
@@ -451,6 +518,7 @@ impl ControllerServer {
             scheduler,
             data_txs: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
             job_state: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            ingest_router: IngestRouter::new(),
             db: pool,
         }
     }
@@ -476,10 +544,11 @@ impl ControllerServer {
         }
     }
 
-    fn start_updater(&self, guard: ShutdownGuard) {
+    fn start_updater(&self, leader_term: i64, guard: ShutdownGuard) {
         let db = self.db.clone();
         let jobs = Arc::clone(&self.job_state);
         let scheduler = Arc::clone(&self.scheduler);
+        let ingest_router = self.ingest_router.clone();
 
         let token = guard.token();
 
@@ -502,6 +571,10 @@ impl ControllerServer {
                         checkpoint_interval: Duration::from_micros(
                             p.checkpoint_interval_micros as u64,
                         ),
+                        checkpoint_config: p
+                            .checkpoint_config
+                            .and_then(|c| serde_json::from_value(c).ok())
+                            .unwrap_or_default(),
                         ttl: p.ttl_micros.map(|t| Duration::from_micros(t as u64)),
                         parallelism_overrides: p
                             .parallelism_overrides
@@ -512,6 +585,12 @@ impl ControllerServer {
                             .collect(),
                         restart_nonce: p.config_restart_nonce,
                         restart_mode: p.restart_mode,
+                        program_version: p.config_program_version,
+                        autoscaling: p.autoscaling.and_then(|a| serde_json::from_value(a).ok()),
+                        restart_strategy: p
+                            .restart_strategy
+                            .and_then(|r| serde_json::from_value(r).ok())
+                            .unwrap_or_default(),
                     };
 
                     let mut jobs = jobs.lock().await;
@@ -528,6 +607,8 @@ impl ControllerServer {
                         pipeline_path: p.pipeline_path,
                         wasm_path: p.wasm_path,
                         restart_nonce: p.status_restart_nonce,
+                        program_version: p.status_program_version,
+                        next_retry_time: p.next_retry_time,
                     };
 
                     if let Some(sm) = jobs.get_mut(&config.id) {
@@ -539,7 +620,9 @@ impl ControllerServer {
                                 config,
                                 status,
                                 db.clone(),
+                                leader_term,
                                 scheduler.clone(),
+                                ingest_router.clone(),
                                 guard.clone_temporary(),
                             )
                             .await,
@@ -552,7 +635,31 @@ impl ControllerServer {
         });
     }
 
+    /// Starts the controller once it has been elected leader. Until then it runs as a standby,
+    /// without managing jobs or serving the controller API; if leadership is later lost the
+    /// controller shuts down so that it can't conflict with the new leader.
     pub fn start(self, guard: ShutdownGuard) {
+        // standbys don't serve the controller's endpoints, so they shouldn't receive traffic
+        // until they become the leader
+        arroyo_server_common::set_ready(false);
+
+        let leader_guard = guard.child("leader-election");
+        guard.into_spawn_task(async move {
+            let Some(mut lock) = LeaderLock::acquire(&DatabaseConfig::load(), &leader_guard).await
+            else {
+                return;
+            };
+
+            self.start_leader(lock.term(), leader_guard.child("leader"));
+
+            lock.lost().await;
+            error!("lost controller leadership; shutting down");
+            arroyo_server_common::set_ready(false);
+            leader_guard.cancel();
+        });
+    }
+
+    fn start_leader(self, leader_term: i64, guard: ShutdownGuard) {
         let reflection = tonic_reflection::server::Builder::configure()
             .register_encoded_file_descriptor_set(arroyo_rpc::grpc::API_FILE_DESCRIPTOR_SET)
             .build()
@@ -567,7 +674,8 @@ impl ControllerServer {
 
         info!("Starting arroyo-controller on {}", addr);
 
-        self.start_updater(guard.child("updater"));
+        self.start_updater(leader_term, guard.child("updater"));
+        self.ingest_router.clone().start(guard.child("ingest"));
         guard.into_spawn_task(
             arroyo_server_common::grpc_server()
                 .accept_http1(true)
@@ -575,5 +683,7 @@ impl ControllerServer {
                 .add_service(reflection)
                 .serve(addr),
         );
+
+        arroyo_server_common::set_ready(true);
     }
 }
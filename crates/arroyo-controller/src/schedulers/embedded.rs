@@ -100,6 +100,11 @@ impl Scheduler for EmbeddedScheduler {
 
     async fn worker_finished(&self, _: WorkerFinishedReq) {}
 
+    fn workers_outlive_controller(&self) -> bool {
+        // workers run within the controller process
+        false
+    }
+
     async fn stop_workers(&self, job_id: &str, run_id: Option<i64>, _: bool) -> anyhow::Result<()> {
         for w in self.workers_for_job(job_id, run_id).await? {
             let state = self.tasks.lock().await;
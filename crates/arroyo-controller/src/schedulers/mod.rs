@@ -63,6 +63,12 @@ pub trait Scheduler: Send + Sync {
         job_id: &str,
         run_id: Option<i64>,
     ) -> anyhow::Result<Vec<WorkerId>>;
+
+    /// Whether workers started by this scheduler keep running if the controller goes away, and so
+    /// may reconnect to a newly-elected controller
+    fn workers_outlive_controller(&self) -> bool {
+        true
+    }
 }
 
 pub struct ProcessWorker {
@@ -212,6 +218,11 @@ impl Scheduler for ProcessScheduler {
     }
     async fn worker_finished(&self, _: WorkerFinishedReq) {}
 
+    fn workers_outlive_controller(&self) -> bool {
+        // workers are child processes of the controller
+        false
+    }
+
     async fn workers_for_job(
         &self,
         job_id: &str,
@@ -420,13 +431,32 @@ impl NodeScheduler {
 impl Scheduler for NodeScheduler {
     async fn register_node(&self, req: RegisterNodeReq) {
         let mut state = self.state.lock().await;
-        if let std::collections::hash_map::Entry::Vacant(e) = state.nodes.entry(NodeId(req.node_id))
-        {
-            e.insert(NodeStatus::new(
-                NodeId(req.node_id),
-                req.task_slots as usize,
-                req.addr,
-            ));
+        let node_id = NodeId(req.node_id);
+        if let std::collections::hash_map::Entry::Vacant(e) = state.nodes.entry(node_id) {
+            let node = e.insert(NodeStatus::new(node_id, req.task_slots as usize, req.addr));
+
+            // a node re-registering with a new controller reports the workers it's already running
+            for worker in &req.workers {
+                node.take_slots(WorkerId(worker.worker_id), worker.slots as usize);
+            }
+
+            for worker in req.workers {
+                info!(
+                    message = "restoring worker from node registration",
+                    node_id = node_id.0,
+                    worker_id = worker.worker_id,
+                    job_id = worker.job_id
+                );
+                state.workers.insert(
+                    WorkerId(worker.worker_id),
+                    NodeWorker {
+                        job_id: worker.job_id,
+                        node_id,
+                        run_id: worker.run_id as i64,
+                        running: true,
+                    },
+                );
+            }
         }
     }
 
@@ -1,4 +1,8 @@
-use crate::states::StateError;
+use anyhow::anyhow;
+use tracing::info;
+
+use crate::queries::controller_queries;
+use crate::states::{fatal, StateError, StateMachine};
 
 use super::{scheduling::Scheduling, JobContext, State, Transition};
 
@@ -11,7 +15,61 @@ impl State for Compiling {
         "Compiling"
     }
 
-    async fn next(self: Box<Self>, _ctx: &mut JobContext) -> Result<Transition, StateError> {
+    async fn next(self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
+        if ctx.config.program_version != ctx.status.program_version {
+            match restored_checkpoint_needs_commit(ctx).await {
+                Ok(true) => {
+                    // operators removed by the upgrade couldn't commit the data of the checkpoint
+                    // we're restoring from, so we restore on the current program first; once the
+                    // commit has finished, the upgrade takes a new final checkpoint and brings us
+                    // back here
+                    info!(
+                        message = "deferring upgrade until the restored checkpoint is committed",
+                        job_id = ctx.config.id,
+                        program_version = ctx.config.program_version
+                    );
+                    return Ok(Transition::next(*self, Scheduling {}));
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    return Err(ctx.retryable(self, "failed to load last checkpoint", e, 10));
+                }
+            }
+
+            // the pipeline has been upgraded since we loaded its program, so we need to pick up
+            // the new one; state is restored onto it by operator id during scheduling
+            match StateMachine::get_program(&ctx.pool, &ctx.config.id, ctx.config.pipeline_id).await
+            {
+                Ok(Some(program)) => {
+                    info!(
+                        message = "loaded upgraded program",
+                        job_id = ctx.config.id,
+                        program_version = ctx.config.program_version
+                    );
+                    *ctx.program = program;
+                    ctx.status.program_version = ctx.config.program_version;
+                }
+                Ok(None) => {
+                    return Err(fatal(
+                        "Failed to load upgraded pipeline",
+                        anyhow!("pipeline {} has an invalid program", ctx.config.pipeline_id),
+                    ));
+                }
+                Err(e) => {
+                    return Err(ctx.retryable(self, "failed to load upgraded pipeline", e, 10));
+                }
+            }
+        }
+
         return Ok(Transition::next(*self, Scheduling {}));
     }
 }
+
+async fn restored_checkpoint_needs_commit(ctx: &JobContext<'_>) -> anyhow::Result<bool> {
+    let c = ctx.pool.get().await?;
+    Ok(controller_queries::last_successful_checkpoint()
+        .bind(&c, &ctx.config.id)
+        .opt()
+        .await?
+        .is_some_and(|checkpoint| checkpoint.needs_commits))
+}
@@ -0,0 +1,99 @@
+use arroyo_rpc::grpc;
+use tracing::debug;
+
+use crate::{states::StateError, types::public::StopMode, JobMessage};
+
+use super::{
+    stopping::{StopBehavior, Stopping},
+    Finished, JobContext, State, Transition,
+};
+
+/// Drains the job by taking a final checkpoint that's preceded by a watermark at infinity, which
+/// flushes all windows, joins and timers into the sinks. Once that checkpoint has been committed
+/// the job is finished, as if its sources had reached the end of their data.
+#[derive(Debug)]
+pub struct Draining {}
+
+#[async_trait::async_trait]
+impl State for Draining {
+    fn name(&self) -> &'static str {
+        "Draining"
+    }
+
+    async fn next(self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
+        let job_controller = ctx.job_controller.as_mut().unwrap();
+
+        let mut drain_started = false;
+
+        loop {
+            match job_controller.checkpoint_finished().await {
+                Ok(done) => {
+                    debug!(
+                        "checked checkpoint, got {}, job_controller.finished(): {}, drain_started: {}",
+                        done,
+                        job_controller.finished(),
+                        drain_started
+                    );
+
+                    if done && job_controller.finished() && drain_started {
+                        return Ok(Transition::next(*self, Finished {}));
+                    }
+                }
+                Err(e) => {
+                    return Err(ctx.retryable(
+                        self,
+                        "failed while monitoring drain checkpoint",
+                        e,
+                        10,
+                    ));
+                }
+            }
+
+            if !drain_started {
+                match job_controller.drain().await {
+                    Ok(started) => drain_started = started,
+                    Err(e) => {
+                        return Err(ctx.retryable(self, "failed to initiate drain", e, 10));
+                    }
+                }
+            }
+
+            match ctx.rx.recv().await.expect("channel closed while receiving") {
+                JobMessage::RunningMessage(msg) => {
+                    if let Err(e) = job_controller.handle_message(msg).await {
+                        return Err(ctx.retryable(
+                            self,
+                            "failed while waiting for job to drain",
+                            e,
+                            10,
+                        ));
+                    }
+                }
+                JobMessage::ConfigUpdate(c) => match c.stop_mode {
+                    StopMode::immediate => {
+                        return Ok(Transition::next(
+                            *self,
+                            Stopping {
+                                stop_mode: StopBehavior::StopJob(grpc::StopMode::Immediate),
+                            },
+                        ));
+                    }
+                    StopMode::force => {
+                        return Ok(Transition::next(
+                            *self,
+                            Stopping {
+                                stop_mode: StopBehavior::StopWorkers,
+                            },
+                        ));
+                    }
+                    _ => {
+                        // do nothing
+                    }
+                },
+                _ => {
+                    // ignore other messages
+                }
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::sync::RwLock;
 use std::time::{Duration, Instant};
 use std::{fmt::Debug, sync::Arc};
@@ -15,6 +16,7 @@ use tracing::{error, info, warn};
 
 use anyhow::{anyhow, Result};
 
+use crate::ingest::IngestRouter;
 use crate::job_controller::JobController;
 use crate::queries::controller_queries;
 use crate::types::public::StopMode;
@@ -25,7 +27,9 @@ use prost::Message;
 
 use self::checkpoint_stopping::CheckpointStopping;
 use self::compiling::Compiling;
+use self::draining::Draining;
 use self::finishing::Finishing;
+use self::reconnecting::Reconnecting;
 use self::recovering::Recovering;
 use self::rescaling::Rescaling;
 use self::running::Running;
@@ -34,7 +38,9 @@ use self::stopping::Stopping;
 
 mod checkpoint_stopping;
 mod compiling;
+mod draining;
 mod finishing;
+mod reconnecting;
 mod recovering;
 mod rescaling;
 mod restarting;
@@ -85,6 +91,7 @@ impl State for Created {
 }
 
 async fn handle_terminal<'a>(ctx: &mut JobContext<'a>) {
+    ctx.ingest_router.unregister_job(&ctx.config.id);
     if let Err(e) = ctx
         .scheduler
         .stop_workers(&ctx.config.id, Some(ctx.status.run_id), true)
@@ -206,16 +213,27 @@ impl TransitionTo<Recovering> for Running {
     }
 }
 impl TransitionTo<Rescaling> for Running {}
+impl TransitionTo<Compiling> for Rescaling {}
 
-impl TransitionTo<Scheduling> for Rescaling {
+impl TransitionTo<Running> for Reconnecting {}
+impl TransitionTo<Compiling> for Reconnecting {}
+impl TransitionTo<Stopping> for Reconnecting {}
+
+impl TransitionTo<Compiling> for Recovering {
     fn update_status(&self) -> TransitionFn {
         Box::new(|ctx| {
-            ctx.status.run_id += 1;
+            ctx.status.next_retry_time = None;
+        })
+    }
+}
+impl TransitionTo<Stopped> for Recovering {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(|ctx| {
+            ctx.status.next_retry_time = None;
+            done_transition(ctx);
         })
     }
 }
-
-impl TransitionTo<Compiling> for Recovering {}
 impl TransitionTo<Compiling> for Failed {
     fn update_status(&self) -> TransitionFn {
         Box::new(|ctx| {
@@ -250,6 +268,15 @@ impl TransitionTo<Finished> for Finishing {
     }
 }
 
+impl TransitionTo<Draining> for Running {}
+impl TransitionTo<Draining> for Restarting {}
+impl TransitionTo<Stopping> for Draining {}
+impl TransitionTo<Finished> for Draining {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(done_transition)
+    }
+}
+
 impl TransitionTo<Restarting> for Running {
     fn update_status(&self) -> TransitionFn {
         Box::new(|ctx| {
@@ -262,6 +289,9 @@ impl TransitionTo<Scheduling> for Restarting {
     fn update_status(&self) -> TransitionFn {
         Box::new(|ctx| {
             ctx.status.run_id += 1;
+            // a user-requested restart starts the job fresh as far as the restart strategy goes
+            ctx.status.restarts = 0;
+            ctx.failures.clear();
         })
     }
 }
@@ -273,6 +303,7 @@ impl TransitionTo<CheckpointStopping> for Restarting {}
 macro_rules! stop_if_desired_running {
     ($self: ident, $config: expr) => {
         use crate::states::checkpoint_stopping::CheckpointStopping;
+        use crate::states::draining::Draining;
         use crate::states::stopping::StopBehavior;
         use crate::states::stopping::Stopping;
         use crate::types::public::StopMode;
@@ -281,6 +312,9 @@ macro_rules! stop_if_desired_running {
             StopMode::checkpoint => {
                 return Ok(Transition::next(*$self, CheckpointStopping {}));
             }
+            StopMode::drain => {
+                return Ok(Transition::next(*$self, Draining {}));
+            }
             StopMode::graceful => {
                 return Ok(Transition::next(
                     *$self,
@@ -322,7 +356,7 @@ macro_rules! stop_if_desired_non_running {
         use crate::types::public::StopMode;
         use arroyo_rpc::grpc;
         match $config.stop_mode {
-            StopMode::checkpoint | StopMode::graceful | StopMode::immediate => {
+            StopMode::checkpoint | StopMode::drain | StopMode::graceful | StopMode::immediate => {
                 return Ok(Transition::next(
                     *$self,
                     Stopping {
@@ -354,11 +388,16 @@ pub struct JobContext<'a> {
     status: &'a mut JobStatus,
     program: &'a mut LogicalProgram,
     pool: Pool,
+    // the term of this controller's leadership, which fences its database writes
+    leader_term: i64,
     scheduler: Arc<dyn Scheduler>,
+    ingest_router: IngestRouter,
     rx: &'a mut Receiver<JobMessage>,
     retries_attempted: usize,
     job_controller: Option<JobController>,
     last_transitioned_at: Instant,
+    /// times of recent failures, used to enforce the restart strategy's failure rate
+    failures: VecDeque<Instant>,
 }
 
 impl<'a> JobContext<'a> {
@@ -526,10 +565,16 @@ async fn execute_state<'a>(
     if let Some(s) = &next {
         ctx.status.state = s.name().to_string();
 
-        ctx.status
-            .update_db(&ctx.pool)
-            .await
-            .expect("Failed to update status");
+        if let Err(e) = ctx.status.update_db(&ctx.pool, ctx.leader_term).await {
+            // most likely another controller has taken over the job, in which case we must not
+            // keep driving it
+            error!(
+                message = "failed to update job status; stopping state machine",
+                job_id = ctx.config.id,
+                error = e
+            );
+            return (None, ctx);
+        }
     }
 
     (next, ctx)
@@ -541,19 +586,24 @@ pub async fn run_to_completion(
     mut status: JobStatus,
     mut state: Box<dyn State>,
     pool: Pool,
+    leader_term: i64,
     mut rx: Receiver<JobMessage>,
     scheduler: Arc<dyn Scheduler>,
+    ingest_router: IngestRouter,
 ) {
     let mut ctx = JobContext {
         config: config.read().unwrap().clone(),
         status: &mut status,
         program: &mut program,
         pool: pool.clone(),
+        leader_term,
         scheduler,
+        ingest_router,
         rx: &mut rx,
         retries_attempted: 0,
         job_controller: None,
         last_transitioned_at: Instant::now(),
+        failures: VecDeque::new(),
     };
 
     loop {
@@ -573,7 +623,9 @@ pub struct StateMachine {
     tx: Option<Sender<JobMessage>>,
     config: Arc<RwLock<JobConfig>>,
     pool: Pool,
+    leader_term: i64,
     scheduler: Arc<dyn Scheduler>,
+    ingest_router: IngestRouter,
 }
 
 impl StateMachine {
@@ -581,14 +633,18 @@ impl StateMachine {
         config: JobConfig,
         status: JobStatus,
         pool: Pool,
+        leader_term: i64,
         scheduler: Arc<dyn Scheduler>,
+        ingest_router: IngestRouter,
         shutdown_guard: ShutdownGuard,
     ) -> Self {
         let mut this = Self {
             tx: None,
             config: Arc::new(RwLock::new(config)),
             pool,
+            leader_term,
             scheduler,
+            ingest_router,
         };
 
         this.start(status, shutdown_guard).await;
@@ -640,10 +696,13 @@ impl StateMachine {
             "Stopped" => Some(Box::new(Stopped {})),
             "Finished" => Some(Box::new(Finished {})),
             "Failed" => Some(Box::new(Failed {})),
-            "Compiling" | "Scheduling" | "Running" | "Recovering" | "Rescaling" => {
-                Some(Box::new(Compiling {}))
+            "Running" | "Reconnecting" if self.scheduler.workers_outlive_controller() => {
+                // the job may still be running if we've taken over from another controller
+                Some(Box::new(Reconnecting {}))
             }
-            "Stopping" | "CheckpointStopping" => {
+            "Compiling" | "Scheduling" | "Running" | "Reconnecting" | "Recovering"
+            | "Rescaling" => Some(Box::new(Compiling {})),
+            "Stopping" | "CheckpointStopping" | "Draining" => {
                 // TODO: do we need to handle a failure in CheckpointStopping specially?
                 if status.finish_time.is_none() {
                     status.finish_time = Some(OffsetDateTime::now_utc());
@@ -663,12 +722,18 @@ impl StateMachine {
 
         if let Some(initial_state) = initial_state {
             status.state = initial_state.name().to_string();
-            status.update_db(&self.pool).await.unwrap();
+            status.next_retry_time = None;
+            if let Err(e) = status.update_db(&self.pool, self.leader_term).await {
+                warn!("Failed to start {}: {}", status.id, e);
+                return;
+            }
             let (tx, rx) = channel(1024);
             {
                 let config = self.config.clone();
                 let pool = self.pool.clone();
+                let leader_term = self.leader_term;
                 let scheduler = self.scheduler.clone();
+                let ingest_router = self.ingest_router.clone();
 
                 let pipeline_id = config.read().unwrap().pipeline_id;
                 match Self::get_program(&pool, &status.id, pipeline_id).await {
@@ -682,8 +747,10 @@ impl StateMachine {
                                 status,
                                 initial_state,
                                 pool,
+                                leader_term,
                                 rx,
                                 scheduler,
+                                ingest_router,
                             )
                             .await;
                             info!(message = "finished state machine", job_id = id);
@@ -742,7 +809,7 @@ impl StateMachine {
     // for states that should be running, check them and restart if needed
     async fn restart_if_needed(&mut self, status: JobStatus, shutdown_guard: &ShutdownGuard) {
         match status.state.as_str() {
-            "Running" | "Recovering" | "Rescaling" => {
+            "Running" | "Reconnecting" | "Recovering" | "Rescaling" => {
                 // done() means there isn't a task running, but these states
                 // need to be advanced.
                 if self.done() {
@@ -0,0 +1,376 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use arroyo_datastream::logical::LogicalProgram;
+use arroyo_rpc::grpc::{worker_grpc_client::WorkerGrpcClient, TaskAssignment};
+use arroyo_types::WorkerId;
+use tonic::transport::Channel;
+use tracing::{info, warn};
+
+use crate::job_controller::JobController;
+use crate::queries::controller_queries;
+use crate::states::compiling::Compiling;
+use crate::states::running::Running;
+use crate::states::stop_if_desired_non_running;
+use crate::{JobMessage, RunningMessage};
+
+use super::{JobContext, State, StateError, Transition};
+
+// how long to wait for the workers of a running job to re-register after a controller failover
+// before giving up and restarting the job from its last checkpoint
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// if the scheduler doesn't know of any workers for the job and none have reconnected after this
+// long, we assume they're gone
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug)]
+struct ReconnectedWorker {
+    rpc_address: String,
+    tasks: Vec<TaskAssignment>,
+}
+
+// every worker that was assigned tasks (according to the workers that have reconnected so far)
+// needs to reconnect
+fn all_reconnected(workers: &HashMap<WorkerId, ReconnectedWorker>) -> bool {
+    workers.values().next().is_some_and(|w| {
+        w.tasks
+            .iter()
+            .all(|t| workers.contains_key(&WorkerId(t.worker_id)))
+    })
+}
+
+// the workers' assignments have to cover exactly the subtasks of the program we would run
+fn assignments_match(program: &LogicalProgram, assignments: &[TaskAssignment]) -> bool {
+    let assigned: HashSet<_> = assignments
+        .iter()
+        .map(|a| (a.operator_id.clone(), a.operator_subtask as usize))
+        .collect();
+    let expected: HashSet<_> = program
+        .graph
+        .node_weights()
+        .flat_map(|n| (0..n.parallelism).map(|i| (n.operator_id.clone(), i)))
+        .collect();
+
+    assigned.len() == assignments.len() && assigned == expected
+}
+
+// the previous leader may have started checkpoints after the last successful one that will now
+// never complete, so the next checkpoint has to come after all of them
+fn resume_epoch(completed_epoch: u32, max_epoch: Option<i32>) -> u32 {
+    max_epoch
+        .map(|e| e as u32)
+        .unwrap_or(0)
+        .max(completed_epoch)
+}
+
+/// Entered when a newly-elected controller finds a job that was running under the previous
+/// leader. Rather than restarting the job, we wait for its workers to re-register and rebuild the
+/// job controller from the assignments they report. If that isn't possible, the job is recovered
+/// from its last checkpoint as usual.
+#[derive(Debug)]
+pub struct Reconnecting {}
+
+impl Reconnecting {
+    async fn connect(rpc_address: &str) -> anyhow::Result<WorkerGrpcClient<Channel>> {
+        let mut last_err = None;
+        for _ in 0..3 {
+            match Channel::from_shared(rpc_address.to_string())?
+                .timeout(Duration::from_secs(90))
+                .connect()
+                .await
+            {
+                Ok(channel) => return Ok(WorkerGrpcClient::new(channel)),
+                Err(e) => {
+                    last_err = Some(e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        }
+
+        Err(anyhow!(
+            "failed to connect to worker at {}: {:?}",
+            rpc_address,
+            last_err
+        ))
+    }
+
+    fn restart(self: Box<Self>, ctx: &JobContext, reason: &str) -> Result<Transition, StateError> {
+        warn!(
+            message = "unable to reconnect to running job; restarting it",
+            job_id = ctx.config.id,
+            reason
+        );
+        Ok(Transition::next(*self, Compiling {}))
+    }
+}
+
+#[async_trait::async_trait]
+impl State for Reconnecting {
+    fn name(&self) -> &'static str {
+        "Reconnecting"
+    }
+
+    async fn next(self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
+        let c = match ctx.pool.get().await {
+            Ok(c) => c,
+            Err(e) => {
+                return Err(ctx.retryable(self, "failed to connect to database", e.into(), 10));
+            }
+        };
+
+        let checkpoint = match controller_queries::last_successful_checkpoint()
+            .bind(&c, &ctx.config.id)
+            .opt()
+            .await
+        {
+            Ok(checkpoint) => checkpoint,
+            Err(e) => {
+                return Err(ctx.retryable(self, "failed to load checkpoints", e.into(), 10));
+            }
+        };
+
+        if checkpoint.as_ref().is_some_and(|c| c.needs_commits) {
+            // committing is driven by the controller during startup
+            return self.restart(ctx, "last checkpoint needs to be committed");
+        }
+
+        let known_workers = !ctx
+            .scheduler
+            .workers_for_job(&ctx.config.id, Some(ctx.status.run_id))
+            .await
+            .unwrap_or_default()
+            .is_empty();
+
+        info!(
+            message = "waiting for workers to reconnect",
+            job_id = ctx.config.id,
+            run_id = ctx.status.run_id,
+            known_workers
+        );
+
+        let start = Instant::now();
+        let mut workers: HashMap<WorkerId, ReconnectedWorker> = HashMap::new();
+        loop {
+            if all_reconnected(&workers) {
+                break;
+            }
+
+            let timeout = if known_workers || !workers.is_empty() {
+                RECONNECT_TIMEOUT
+            } else {
+                DISCOVERY_TIMEOUT
+            };
+
+            tokio::select! {
+                msg = ctx.rx.recv() => {
+                    match msg {
+                        Some(JobMessage::WorkerReconnect { worker_id, run_id, rpc_address, tasks, .. }) => {
+                            if run_id != ctx.status.run_id {
+                                warn!(
+                                    message = "worker from previous run reconnected",
+                                    job_id = ctx.config.id,
+                                    worker_id = worker_id.0,
+                                    run_id
+                                );
+                                continue;
+                            }
+
+                            info!(
+                                message = "worker reconnected",
+                                job_id = ctx.config.id,
+                                worker_id = worker_id.0
+                            );
+                            workers.insert(worker_id, ReconnectedWorker { rpc_address, tasks });
+                        }
+                        Some(JobMessage::ConfigUpdate(c)) => {
+                            stop_if_desired_non_running!(self, &c);
+                        }
+                        Some(JobMessage::TaskStarted { .. })
+                        | Some(JobMessage::RunningMessage(RunningMessage::WorkerHeartbeat { .. }))
+                        | Some(JobMessage::RunningMessage(RunningMessage::TaskCheckpointEvent(_)))
+                        | Some(JobMessage::RunningMessage(RunningMessage::TaskCheckpointFinished(_))) => {
+                            // in-progress checkpoints are abandoned once we've reconnected
+                        }
+                        Some(msg) => {
+                            // a task has finished or failed while we weren't able to observe it
+                            return self.restart(ctx, &format!("received {:?} while reconnecting", msg));
+                        }
+                        None => {
+                            panic!("Job queue shutdown");
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(timeout.saturating_sub(start.elapsed())) => {
+                    return self.restart(ctx, "timed out waiting for workers to reconnect");
+                }
+            }
+        }
+
+        // the workers' view of the job has to match the program we would run
+        ctx.program
+            .update_parallelism(&ctx.config.parallelism_overrides);
+
+        let assignments = workers.values().next().unwrap().tasks.clone();
+        if !assignments_match(ctx.program, &assignments) {
+            return self.restart(ctx, "worker assignments don't match the pipeline");
+        }
+
+        let mut worker_connects = HashMap::new();
+        for (worker_id, worker) in &workers {
+            match Self::connect(&worker.rpc_address).await {
+                Ok(client) => {
+                    worker_connects.insert(*worker_id, client);
+                }
+                Err(e) => {
+                    return self.restart(ctx, &e.to_string());
+                }
+            }
+        }
+
+        let completed_epoch = checkpoint.as_ref().map(|c| c.epoch as u32).unwrap_or(0);
+        let min_epoch = checkpoint.as_ref().map(|c| c.min_epoch as u32).unwrap_or(0);
+
+        // the previous leader may have started checkpoints that will now never complete; we skip
+        // past their epochs so that the workers don't see a checkpoint barrier for an epoch twice
+        let epoch = match controller_queries::max_checkpoint_epoch()
+            .bind(&c, &ctx.config.id)
+            .one()
+            .await
+        {
+            Ok(max_epoch) => resume_epoch(completed_epoch, max_epoch),
+            Err(e) => {
+                return Err(ctx.retryable(self, "failed to load checkpoints", e.into(), 10));
+            }
+        };
+
+        if let Err(e) = controller_queries::mark_failed()
+            .bind(
+                &c,
+                &ctx.config.id,
+                &(completed_epoch as i32 + 1),
+                &ctx.leader_term,
+            )
+            .await
+        {
+            return Err(ctx.retryable(
+                self,
+                "failed to mark in-progress checkpoints as failed",
+                e.into(),
+                10,
+            ));
+        }
+
+        info!(
+            message = "reconnected to running job",
+            job_id = ctx.config.id,
+            workers = workers.len(),
+            epoch,
+            completed_epoch
+        );
+
+        ctx.status.tasks = Some(ctx.program.task_count() as i32);
+        ctx.job_controller = Some(
+            JobController::new(
+                ctx.pool.clone(),
+                ctx.leader_term,
+                ctx.config.clone(),
+                ctx.program.clone(),
+                epoch,
+                min_epoch,
+                worker_connects,
+                assignments,
+                None,
+            )
+            .with_completed_epoch(completed_epoch),
+        );
+
+        Ok(Transition::next(*self, Running {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_datastream::logical::{LogicalNode, OperatorName};
+
+    fn task(operator_id: &str, subtask: u64, worker_id: u64) -> TaskAssignment {
+        TaskAssignment {
+            operator_id: operator_id.to_string(),
+            operator_subtask: subtask,
+            worker_id,
+            worker_addr: format!("localhost:{}", worker_id),
+        }
+    }
+
+    fn reconnected(tasks: &[TaskAssignment]) -> ReconnectedWorker {
+        ReconnectedWorker {
+            rpc_address: "http://localhost:9000".to_string(),
+            tasks: tasks.to_vec(),
+        }
+    }
+
+    fn program(operators: &[(&str, usize)]) -> LogicalProgram {
+        let mut program = LogicalProgram::default();
+        for (operator_id, parallelism) in operators {
+            program.graph.add_node(LogicalNode {
+                operator_id: operator_id.to_string(),
+                description: operator_id.to_string(),
+                operator_name: OperatorName::ArrowValue,
+                operator_config: vec![],
+                parallelism: *parallelism,
+            });
+        }
+        program
+    }
+
+    #[test]
+    fn test_all_reconnected() {
+        let tasks = vec![task("source_0", 0, 1), task("source_0", 1, 2)];
+
+        let mut workers = HashMap::new();
+        assert!(!all_reconnected(&workers));
+
+        workers.insert(WorkerId(1), reconnected(&tasks));
+        assert!(!all_reconnected(&workers));
+
+        workers.insert(WorkerId(2), reconnected(&tasks));
+        assert!(all_reconnected(&workers));
+    }
+
+    #[test]
+    fn test_assignments_match() {
+        let program = program(&[("source_0", 2), ("sink_1", 1)]);
+
+        let tasks = vec![
+            task("source_0", 0, 1),
+            task("source_0", 1, 2),
+            task("sink_1", 0, 1),
+        ];
+        assert!(assignments_match(&program, &tasks));
+
+        // a subtask is missing
+        assert!(!assignments_match(&program, &tasks[1..]));
+
+        // the job has been rescaled since the workers were scheduled
+        let mut rescaled = tasks.clone();
+        rescaled.push(task("sink_1", 1, 2));
+        assert!(!assignments_match(&program, &rescaled));
+
+        // a subtask is assigned twice
+        let mut duplicated = tasks.clone();
+        duplicated.push(task("sink_1", 0, 2));
+        assert!(!assignments_match(&program, &duplicated));
+    }
+
+    #[test]
+    fn test_resume_epoch() {
+        assert_eq!(resume_epoch(0, None), 0);
+        assert_eq!(resume_epoch(5, Some(5)), 5);
+        // skip past checkpoints that the previous leader started but never finished
+        assert_eq!(resume_epoch(5, Some(7)), 7);
+        // checkpoints may have been cleaned up
+        assert_eq!(resume_epoch(5, Some(3)), 5);
+    }
+}
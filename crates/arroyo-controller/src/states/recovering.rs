@@ -1,11 +1,73 @@
+use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
 use anyhow::bail;
+use arroyo_rpc::api_types::pipelines::{RestartBackoff, RestartStrategy};
 use arroyo_rpc::grpc::StopMode;
+use time::OffsetDateTime;
 use tokio::time::timeout;
 use tracing::{info, warn};
 
-use super::{compiling::Compiling, JobContext, State, StateError, Transition};
+use crate::types::public::StopMode as DesiredStopMode;
+use crate::JobMessage;
+
+use super::{compiling::Compiling, JobContext, State, StateError, Stopped, Transition};
+
+/// Records a failure of the job and checks it against the restart strategy, returning the reason
+/// the job should fail permanently if it's not allowed to restart again
+pub fn check_restart(
+    strategy: &RestartStrategy,
+    restarts: i32,
+    failures: &mut VecDeque<Instant>,
+    now: Instant,
+) -> Result<(), String> {
+    if let Some(max_restarts) = strategy.max_restarts {
+        if restarts as u64 >= max_restarts {
+            return Err("Job has restarted too many times".to_string());
+        }
+    }
+
+    if let Some(rate) = &strategy.failure_rate {
+        // failures are only recorded while a rate is configured, so that the queue stays bounded
+        failures.push_back(now);
+
+        let interval = Duration::from_micros(rate.interval_micros);
+        while failures
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > interval)
+        {
+            failures.pop_front();
+        }
+
+        if failures.len() as u64 > rate.max_failures {
+            return Err(format!(
+                "Job failed {} times in the last {:?}",
+                failures.len(),
+                interval
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// How long to wait before the `restarts`-th consecutive restart
+pub fn restart_delay(backoff: &RestartBackoff, restarts: i32) -> Duration {
+    match backoff {
+        RestartBackoff::Fixed { delay_micros } => Duration::from_micros(*delay_micros),
+        RestartBackoff::Exponential {
+            initial_delay_micros,
+            max_delay_micros,
+        } => {
+            let exponent = (restarts.max(1) - 1).min(63) as u32;
+            Duration::from_micros(
+                initial_delay_micros
+                    .saturating_mul(1 << exponent)
+                    .min(*max_delay_micros),
+            )
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Recovering {}
@@ -13,6 +75,9 @@ pub struct Recovering {}
 impl Recovering {
     // tries, with increasing levels of force, to tear down the existing cluster
     pub async fn cleanup<'a>(ctx: &mut JobContext<'a>) -> anyhow::Result<()> {
+        // the failed workers can no longer take requests for the job's ingest endpoints
+        ctx.ingest_router.unregister_job(&ctx.config.id);
+
         let job_controller = ctx.job_controller.as_mut().unwrap();
 
         // first try to stop it gracefully
@@ -96,6 +161,105 @@ impl State for Recovering {
             return Err(ctx.retryable(self, "failed to tear down existing cluster", e, 10));
         }
 
+        let delay = restart_delay(&ctx.config.restart_strategy.backoff, ctx.status.restarts);
+        if !delay.is_zero() {
+            info!(
+                message = "waiting before restarting job",
+                job_id = ctx.config.id,
+                restarts = ctx.status.restarts,
+                delay_ms = delay.as_millis() as u64
+            );
+
+            ctx.status.next_retry_time = Some(OffsetDateTime::now_utc() + delay);
+            if let Err(e) = ctx.status.update_db(&ctx.pool, ctx.leader_term).await {
+                warn!(
+                    message = "failed to record next retry time",
+                    error = format!("{:?}", e),
+                    job_id = ctx.config.id
+                );
+            }
+
+            let retry_at = tokio::time::Instant::now() + delay;
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(retry_at) => break,
+                    msg = ctx.rx.recv() => match msg {
+                        Some(JobMessage::ConfigUpdate(c)) if c.stop_mode != DesiredStopMode::none => {
+                            // the cluster is already torn down, so there's nothing left to stop
+                            return Ok(Transition::next(*self, Stopped {}));
+                        }
+                        Some(_) => {
+                            // no workers are running, so there's nothing to do with other messages
+                        }
+                        None => {
+                            panic!("job queue shut down");
+                        }
+                    }
+                }
+            }
+        }
+
         Ok(Transition::next(*self, Compiling))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arroyo_rpc::api_types::pipelines::FailureRate;
+
+    #[test]
+    fn test_exponential_delay() {
+        let backoff = RestartBackoff::Exponential {
+            initial_delay_micros: 1_000_000,
+            max_delay_micros: 10_000_000,
+        };
+
+        assert_eq!(restart_delay(&backoff, 1), Duration::from_secs(1));
+        assert_eq!(restart_delay(&backoff, 2), Duration::from_secs(2));
+        assert_eq!(restart_delay(&backoff, 4), Duration::from_secs(8));
+        assert_eq!(restart_delay(&backoff, 5), Duration::from_secs(10));
+        assert_eq!(restart_delay(&backoff, 1000), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_max_restarts() {
+        let strategy = RestartStrategy::default();
+        let mut failures = VecDeque::new();
+        let now = Instant::now();
+
+        assert!(check_restart(&strategy, 9, &mut failures, now).is_ok());
+        assert!(check_restart(&strategy, 10, &mut failures, now).is_err());
+
+        // without a failure rate, failures aren't recorded
+        assert!(failures.is_empty());
+    }
+
+    #[test]
+    fn test_failure_rate() {
+        let strategy = RestartStrategy {
+            backoff: RestartBackoff::Fixed { delay_micros: 0 },
+            max_restarts: None,
+            failure_rate: Some(FailureRate {
+                max_failures: 2,
+                interval_micros: 60_000_000,
+            }),
+        };
+        let mut failures = VecDeque::new();
+        let start = Instant::now();
+
+        assert!(check_restart(&strategy, 0, &mut failures, start).is_ok());
+        assert!(
+            check_restart(&strategy, 0, &mut failures, start + Duration::from_secs(10)).is_ok()
+        );
+
+        // the first failure has aged out of the interval
+        assert!(
+            check_restart(&strategy, 0, &mut failures, start + Duration::from_secs(61)).is_ok()
+        );
+        assert!(
+            check_restart(&strategy, 0, &mut failures, start + Duration::from_secs(62)).is_err()
+        );
+        assert_eq!(failures.len(), 3);
+    }
+}
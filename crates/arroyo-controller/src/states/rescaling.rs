@@ -1,7 +1,9 @@
 use crate::{states::stop_if_desired_non_running, JobMessage};
 
-use super::{scheduling::Scheduling, JobContext, State, StateError, Transition};
+use super::{compiling::Compiling, JobContext, State, StateError, Transition};
 
+/// Takes a final checkpoint and stops the job so that it can be rescheduled from that checkpoint,
+/// either with new parallelism or (for upgrades) with a new program
 #[derive(Debug)]
 pub struct Rescaling {}
 
@@ -20,7 +22,7 @@ impl State for Rescaling {
             match job_controller.checkpoint_finished().await {
                 Ok(done) => {
                     if done && job_controller.finished() && final_checkpoint_started {
-                        return Ok(Transition::next(*self, Scheduling {}));
+                        return Ok(Transition::next(*self, Compiling {}));
                     }
                 }
                 Err(e) => {
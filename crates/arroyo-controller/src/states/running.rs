@@ -6,7 +6,7 @@ use tokio::time::MissedTickBehavior;
 use tracing::error;
 
 use crate::states::finishing::Finishing;
-use crate::states::recovering::Recovering;
+use crate::states::recovering::{check_restart, Recovering};
 use crate::states::rescaling::Rescaling;
 use crate::states::restarting::Restarting;
 use crate::states::{fatal, stop_if_desired_running};
@@ -20,9 +20,6 @@ use super::{JobContext, State, Transition};
 // after this amount of time, we consider the job to be healthy and reset the restarts counter
 const HEALTHY_DURATION: Duration = Duration::from_secs(2 * 60);
 
-// how many times we allow the job to restart before moving it to failed
-const RESTARTS_ALLOWED: usize = 10;
-
 #[derive(Debug)]
 pub struct Running {}
 
@@ -35,7 +32,12 @@ impl State for Running {
     async fn next(mut self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
         stop_if_desired_running!(self, ctx.config);
 
-        let running_start = Instant::now();
+        if ctx.config.program_version != ctx.status.program_version {
+            // the pipeline was upgraded while we were starting up
+            return Ok(Transition::next(*self, Rescaling {}));
+        }
+
+        let mut healthy_since = Instant::now();
 
         let mut log_interval = tokio::time::interval(Duration::from_secs(60));
         log_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
@@ -62,7 +64,16 @@ impl State for Running {
                                 }));
                             }
 
-                            let job_controller = ctx.job_controller.as_ref().unwrap();
+                            if c.program_version != ctx.status.program_version {
+                                // the query has been upgraded; take a final checkpoint and
+                                // restart with the new program
+                                return Ok(Transition::next(*self, Rescaling {}));
+                            }
+
+                            let job_controller = ctx.job_controller.as_mut().unwrap();
+                            job_controller.update_autoscaling(c.autoscaling.as_ref());
+                            job_controller.update_checkpoint_config(c.checkpoint_interval, &c.checkpoint_config);
+
                             for (op, p) in &c.parallelism_overrides {
                                 if let Some(actual) = job_controller.operator_parallelism(op){
                                     if actual != *p {
@@ -79,6 +90,10 @@ impl State for Running {
                                 return Err(ctx.retryable(self, "job encountered an error", e, 10));
                             }
                         }
+                        Some(JobMessage::TaskStarted { operator_id, operator_subtask, .. }) => {
+                            // sent by tasks restarted along with their failover region
+                            ctx.job_controller.as_mut().unwrap().task_started(operator_id, operator_subtask as u32);
+                        }
                         Some(msg) => {
                             ctx.handle(msg)?;
                         }
@@ -88,10 +103,10 @@ impl State for Running {
                     }
                 }
                 _ = tokio::time::sleep(Duration::from_millis(200)) => {
-                    if ctx.status.restarts > 0 && running_start.elapsed() > HEALTHY_DURATION {
+                    if ctx.status.restarts > 0 && healthy_since.elapsed() > HEALTHY_DURATION {
                         let restarts = ctx.status.restarts;
                         ctx.status.restarts = 0;
-                        if let Err(e) = ctx.status.update_db(&ctx.pool).await {
+                        if let Err(e) = ctx.status.update_db(&ctx.pool, ctx.leader_term).await {
                             error!(message = "Failed to update status", error = format!("{:?}", e),
                                 job_id = ctx.config.id);
                             ctx.status.restarts = restarts;
@@ -116,16 +131,39 @@ impl State for Running {
                                 "job_id": ctx.config.id,
                                 "error": format!("{:?}", err),
                             }));
-                            if ctx.status.restarts >= RESTARTS_ALLOWED as i32 {
-                                return Err(fatal(
-                                    "Job has restarted too many times",
-                                    err
-                                ));
+                            if let Err(reason) = check_restart(
+                                &ctx.config.restart_strategy,
+                                ctx.status.restarts,
+                                &mut ctx.failures,
+                                Instant::now(),
+                            ) {
+                                return Err(fatal(reason, err));
+                            }
+
+                            match ctx.job_controller.as_mut().unwrap().restart_failed_regions().await {
+                                Ok(true) => {
+                                    ctx.status.restarts += 1;
+                                    healthy_since = Instant::now();
+                                    if let Err(e) = ctx.status.update_db(&ctx.pool, ctx.leader_term).await {
+                                        error!(message = "Failed to update status", error = format!("{:?}", e),
+                                            job_id = ctx.config.id);
+                                    }
+                                }
+                                Ok(false) => {
+                                    return Ok(Transition::next(
+                                        *self,
+                                        Recovering {}
+                                    ))
+                                }
+                                Err(e) => {
+                                    error!(message = "failed to restart failover region", error = format!("{:?}", e),
+                                        job_id = ctx.config.id);
+                                    return Ok(Transition::next(
+                                        *self,
+                                        Recovering {}
+                                    ))
+                                }
                             }
-                            return Ok(Transition::next(
-                                *self,
-                                Recovering {}
-                            ))
                         }
                     }
                 }
@@ -236,7 +236,8 @@ impl State for Scheduling {
     }
 
     async fn next(mut self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
-        // clear out any existing workers for this job
+        // clear out any existing workers for this job, along with the ingest endpoints they served
+        ctx.ingest_router.unregister_job(&ctx.config.id);
         if let Err(e) = ctx.scheduler.stop_workers(&ctx.config.id, None, true).await {
             warn!(
                 message = "failed to clean cluster prior to scheduling",
@@ -343,7 +344,12 @@ impl State for Scheduling {
                 .map(|checkpoint_info| checkpoint_info.epoch)
                 .unwrap_or(0);
             controller_queries::mark_failed()
-                .bind(&c, &ctx.config.id, &(last_epoch as i32 + 1))
+                .bind(
+                    &c,
+                    &ctx.config.id,
+                    &(last_epoch as i32 + 1),
+                    &ctx.leader_term,
+                )
                 .await
                 .unwrap();
         }
@@ -425,16 +431,27 @@ impl State for Scheduling {
                             }
                             arroyo_rpc::grpc::TableEnum::ExpiringKeyedTimeTable => todo!(),
                         } {
-                            committing_data
-                                .entry(operator_id.clone())
-                                .or_default()
-                                .insert(table_name.to_string(), commit_data);
-                            let program_node = ctx
+                            let Some(program_node) = ctx
                                 .program
                                 .graph
                                 .node_weights()
                                 .find(|node| node.operator_id == *operator_id)
-                                .unwrap();
+                            else {
+                                // upgrades are deferred until pending commits have finished, but
+                                // if the program was replaced anyways we can't drop the data
+                                return Err(fatal(
+                                    "Failed to restore job; the checkpoint has uncommitted data for an operator that is no longer in the pipeline",
+                                    anyhow!(
+                                        "operator {} has commit data in checkpoint {} but is not in the pipeline",
+                                        operator_id,
+                                        epoch
+                                    ),
+                                ));
+                            };
+                            committing_data
+                                .entry(operator_id.clone())
+                                .or_default()
+                                .insert(table_name.to_string(), commit_data);
                             for subtask_index in 0..program_node.parallelism {
                                 commit_subtasks.insert((operator_id.clone(), subtask_index as u32));
                             }
@@ -538,6 +555,7 @@ impl State for Scheduling {
 
         let mut controller = JobController::new(
             ctx.pool.clone(),
+            ctx.leader_term,
             ctx.config.clone(),
             ctx.program.clone(),
             checkpoint_info.as_ref().map(|info| info.epoch).unwrap_or(0),
@@ -546,6 +564,7 @@ impl State for Scheduling {
                 .map(|info| info.min_epoch)
                 .unwrap_or(0),
             worker_connects,
+            assignments,
             committing_state,
         );
         if needs_commit {
@@ -8,15 +8,16 @@ use arroyo_rpc::grpc::api;
 use arroyo_rpc::grpc::api::{
     ArrowDylibUdfConfig, ArrowProgram, ArrowProgramConfig, ConnectorOp, EdgeType,
 };
-use petgraph::graph::DiGraph;
+use petgraph::graph::{DiGraph, NodeIndex};
 use petgraph::prelude::EdgeRef;
+use petgraph::unionfind::UnionFind;
 use petgraph::Direction;
 use prost::Message;
 use rand::distributions::Alphanumeric;
 use rand::prelude::SmallRng;
 use rand::{Rng, SeedableRng};
 use std::collections::hash_map::DefaultHasher;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::Hasher;
 use strum::{Display, EnumString};
@@ -242,6 +243,71 @@ impl LogicalProgram {
         tasks_per_operator
     }
 
+    /// Groups the operators that are connected by forward edges; as each subtask in a forward
+    /// connection sends to exactly one downstream subtask, every operator in a group must run
+    /// with the same parallelism
+    pub fn forward_groups(&self) -> Vec<Vec<NodeIndex>> {
+        let mut union_find = UnionFind::new(self.graph.node_bound());
+        for edge in self.graph.edge_references() {
+            if edge.weight().edge_type == LogicalEdgeType::Forward {
+                union_find.union(edge.source().index(), edge.target().index());
+            }
+        }
+
+        let mut groups: BTreeMap<usize, Vec<NodeIndex>> = BTreeMap::new();
+        for idx in self.graph.node_indices() {
+            groups
+                .entry(union_find.find(idx.index()))
+                .or_default()
+                .push(idx);
+        }
+
+        groups.into_values().collect()
+    }
+
+    /// Partitions the subtasks of the program into failover regions, the sets of subtasks that
+    /// exchange data (directly or transitively) and so must be restarted together when one of
+    /// them fails. A forward edge only connects subtask i of its source to subtask i of its
+    /// target, while any other edge connects every subtask of the source to every subtask of
+    /// the target.
+    pub fn failover_regions(&self) -> Vec<Vec<(String, usize)>> {
+        let mut offsets = HashMap::new();
+        let mut tasks = vec![];
+        for idx in self.graph.node_indices() {
+            let node = &self.graph[idx];
+            offsets.insert(idx, tasks.len());
+            tasks.extend((0..node.parallelism).map(|i| (node.operator_id.clone(), i)));
+        }
+
+        let mut union_find = UnionFind::new(tasks.len());
+        for edge in self.graph.edge_references() {
+            let source = offsets[&edge.source()];
+            let target = offsets[&edge.target()];
+            let source_parallelism = self.graph[edge.source()].parallelism;
+            let target_parallelism = self.graph[edge.target()].parallelism;
+
+            if edge.weight().edge_type == LogicalEdgeType::Forward {
+                for i in 0..source_parallelism.min(target_parallelism) {
+                    union_find.union(source + i, target + i);
+                }
+            } else {
+                for i in 0..source_parallelism {
+                    union_find.union(source, source + i);
+                }
+                for i in 0..target_parallelism {
+                    union_find.union(source, target + i);
+                }
+            }
+        }
+
+        let mut regions: BTreeMap<usize, Vec<(String, usize)>> = BTreeMap::new();
+        for (i, task) in tasks.into_iter().enumerate() {
+            regions.entry(union_find.find(i)).or_default().push(task);
+        }
+
+        regions.into_values().collect()
+    }
+
     pub fn features(&self) -> HashSet<String> {
         let mut s = HashSet::new();
 
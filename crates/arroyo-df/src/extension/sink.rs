@@ -57,7 +57,9 @@ impl SinkExtension {
                         schema = input.schema().clone();
                     }
                     (true, false) => {
-                        return plan_err!("input is updating, but sink is not updating");
+                        if !connector_table.accepts_updating_input() {
+                            return plan_err!("input is updating, but sink is not updating");
+                        }
                     }
                     (false, false) => {}
                 }
@@ -32,7 +32,8 @@ impl TableSourceExtension {
             .fields
             .iter()
             .filter_map(|field| match field {
-                crate::tables::FieldSpec::StructField(field) => {
+                crate::tables::FieldSpec::StructField(field)
+                | crate::tables::FieldSpec::MetadataField { field, .. } => {
                     Some(DFField::from_qualified(&name, Arc::new(field.clone())))
                 }
                 crate::tables::FieldSpec::VirtualField { .. } => None,
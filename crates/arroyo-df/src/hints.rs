@@ -0,0 +1,120 @@
+use anyhow::{anyhow, bail, Result};
+use arroyo_datastream::logical::{LogicalProgram, OperatorName};
+use datafusion::sql::sqlparser::ast::{Expr as SqlExpr, Statement, Value};
+use std::collections::HashMap;
+
+const PARALLELISM: &str = "parallelism";
+
+const OPERATOR_KINDS: [&str; 9] = [
+    "source",
+    "sink",
+    "join",
+    "aggregate",
+    "window_function",
+    "watermark",
+    "key",
+    "value",
+    "async_udf",
+];
+
+fn operator_kind(name: OperatorName) -> &'static str {
+    match name {
+        OperatorName::ConnectorSource => "source",
+        OperatorName::ConnectorSink => "sink",
+        OperatorName::Join | OperatorName::InstantJoin => "join",
+        OperatorName::TumblingWindowAggregate
+        | OperatorName::SlidingWindowAggregate
+        | OperatorName::SessionWindowAggregate
+        | OperatorName::UpdatingAggregate => "aggregate",
+        OperatorName::WindowFunction => "window_function",
+        OperatorName::ExpressionWatermark => "watermark",
+        OperatorName::ArrowKey => "key",
+        OperatorName::ArrowValue => "value",
+        OperatorName::AsyncUdf => "async_udf",
+    }
+}
+
+/// Parallelism hints set in the query, either for the entire pipeline
+/// (`SET parallelism = 4;`) or for a particular kind of operator (`SET join.parallelism = 8;`),
+/// which allows expensive operators to scale independently of the rest of the pipeline.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ParallelismHints {
+    default: Option<usize>,
+    operators: HashMap<&'static str, usize>,
+}
+
+impl ParallelismHints {
+    /// Records the hint if this is a `SET` statement, returning false for any other statement
+    pub(crate) fn try_add(&mut self, statement: &Statement) -> Result<bool> {
+        let Statement::SetVariable {
+            variable, value, ..
+        } = statement
+        else {
+            return Ok(false);
+        };
+
+        let parts: Vec<String> = variable.0.iter().map(|i| i.value.to_lowercase()).collect();
+
+        let kind = match parts.as_slice() {
+            [p] if p == PARALLELISM => None,
+            [kind, p] if p == PARALLELISM => Some(
+                *OPERATOR_KINDS
+                    .iter()
+                    .find(|k| **k == kind.as_str())
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "unknown operator kind '{}' in SET {}; expected one of {}",
+                            kind,
+                            variable,
+                            OPERATOR_KINDS.join(", ")
+                        )
+                    })?,
+            ),
+            _ => bail!(
+                "unsupported variable '{}' in SET statement; only 'parallelism' and \
+                '<operator>.parallelism' may be set",
+                variable
+            ),
+        };
+
+        let parallelism = match value.as_slice() {
+            [SqlExpr::Value(Value::Number(n, _))] => n.parse::<usize>().ok(),
+            _ => None,
+        }
+        .filter(|p| *p > 0)
+        .ok_or_else(|| anyhow!("SET {} must be a positive integer", variable))?;
+
+        match kind {
+            Some(kind) => {
+                self.operators.insert(kind, parallelism);
+            }
+            None => {
+                self.default = Some(parallelism);
+            }
+        }
+
+        Ok(true)
+    }
+
+    pub(crate) fn apply(&self, program: &mut LogicalProgram, default_parallelism: usize) {
+        let default = self.default.unwrap_or(default_parallelism);
+
+        // operators connected by forward edges must share a parallelism, so a hint on any of
+        // them applies to the whole group (taking the largest if several are hinted)
+        for group in program.forward_groups() {
+            let parallelism = group
+                .iter()
+                .filter_map(|idx| {
+                    self.operators
+                        .get(operator_kind(program.graph[*idx].operator_name))
+                        .copied()
+                })
+                .max()
+                .unwrap_or(default);
+
+            for idx in group {
+                program.graph[idx].parallelism = parallelism;
+            }
+        }
+    }
+}
@@ -3,6 +3,7 @@
 pub mod builder;
 pub(crate) mod extension;
 pub mod external;
+mod hints;
 mod json;
 pub mod logical;
 pub mod physical;
@@ -48,6 +49,7 @@ use tables::{Insert, Table};
 
 use crate::builder::PlanToGraphVisitor;
 use crate::extension::sink::SinkExtension;
+use crate::hints::ParallelismHints;
 use crate::plan::ArroyoRewriter;
 use arroyo_datastream::logical::{DylibUdfConfig, ProgramConfig};
 use arroyo_rpc::api_types::connections::ConnectionProfile;
@@ -481,12 +483,16 @@ pub fn rewrite_plan(
 pub async fn parse_and_get_arrow_program(
     query: String,
     mut schema_provider: ArroyoSchemaProvider,
-    // TODO: use config
-    _config: SqlConfig,
+    config: SqlConfig,
 ) -> Result<CompiledSql> {
     let dialect = PostgreSqlDialect {};
     let mut inserts = vec![];
+    let mut parallelism_hints = ParallelismHints::default();
     for statement in Parser::parse_sql(&dialect, &query)? {
+        if parallelism_hints.try_add(&statement)? {
+            continue;
+        }
+
         if let Some(table) = Table::try_from_statement(&statement, &schema_provider)? {
             schema_provider.insert_table(table);
         } else {
@@ -567,13 +573,15 @@ pub async fn parse_and_get_arrow_program(
         plan_to_graph_visitor.add_plan(extension)?;
     }
     let graph = plan_to_graph_visitor.into_graph();
-    let program = LogicalProgram {
+    let mut program = LogicalProgram {
         graph,
         program_config: ProgramConfig {
             udf_dylibs: schema_provider.dylib_udfs.clone(),
         },
     };
 
+    parallelism_hints.apply(&mut program, config.default_parallelism);
+
     Ok(CompiledSql {
         program,
         connection_ids: used_connections.into_iter().collect(),
@@ -47,10 +47,13 @@ impl<'a> SourceRewriter<'a> {
                 .find_map(|f| {
                     if f.field().name() == &watermark_field {
                         return match f {
-                            FieldSpec::StructField(f) => Some(Expr::Column(Column {
-                                relation: None,
-                                name: f.name().to_string(),
-                            })),
+                            FieldSpec::StructField(f)
+                            | FieldSpec::MetadataField { field: f, .. } => {
+                                Some(Expr::Column(Column {
+                                    relation: None,
+                                    name: f.name().to_string(),
+                                }))
+                            }
                             FieldSpec::VirtualField { expression, .. } => Some(expression.clone()),
                         };
                     }
@@ -82,10 +85,12 @@ impl<'a> SourceRewriter<'a> {
             .fields
             .iter()
             .map(|field| match field {
-                FieldSpec::StructField(f) => Expr::Column(Column {
-                    relation: Some(qualifier.clone()),
-                    name: f.name().to_string(),
-                }),
+                FieldSpec::StructField(f) | FieldSpec::MetadataField { field: f, .. } => {
+                    Expr::Column(Column {
+                        relation: Some(qualifier.clone()),
+                        name: f.name().to_string(),
+                    })
+                }
                 FieldSpec::VirtualField { field, expression } => expression
                     .clone()
                     .alias_qualified(Some(qualifier.clone()), field.name().to_string()),
@@ -104,10 +109,13 @@ impl<'a> SourceRewriter<'a> {
                 .find_map(|f| {
                     if f.field().name() == &event_time_field {
                         return match f {
-                            FieldSpec::StructField(f) => Some(Expr::Column(Column {
-                                relation: Some(qualifier.clone()),
-                                name: f.name().to_string(),
-                            })),
+                            FieldSpec::StructField(f)
+                            | FieldSpec::MetadataField { field: f, .. } => {
+                                Some(Expr::Column(Column {
+                                    relation: Some(qualifier.clone()),
+                                    name: f.name().to_string(),
+                                }))
+                            }
                             FieldSpec::VirtualField { expression, .. } => Some(expression.clone()),
                         };
                     }
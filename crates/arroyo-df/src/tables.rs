@@ -9,7 +9,7 @@ use arroyo_connectors::connector_for_type;
 use arroyo_datastream::preview_sink;
 use arroyo_operator::connector::Connection;
 use arroyo_rpc::api_types::connections::{
-    ConnectionProfile, ConnectionSchema, ConnectionType, SourceField,
+    ConnectionProfile, ConnectionSchema, ConnectionType, MetadataField, SourceField,
 };
 use arroyo_rpc::formats::{BadData, Format, Framing, JsonFormat};
 use arroyo_rpc::grpc::api::ConnectorOp;
@@ -21,7 +21,10 @@ use datafusion::{
     optimizer::{analyzer::Analyzer, optimizer::Optimizer, OptimizerContext},
     sql::{
         planner::SqlToRel,
-        sqlparser::ast::{ColumnDef, ColumnOption, Statement, Value},
+        sqlparser::ast::{
+            ColumnDef, ColumnOption, Expr as SqlExpr, FunctionArg, FunctionArgExpr, Statement,
+            Value,
+        },
     },
 };
 use datafusion_common::Column;
@@ -59,19 +62,21 @@ pub struct ConnectorTable {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum FieldSpec {
     StructField(Field),
+    MetadataField { field: Field, key: String },
     VirtualField { field: Field, expression: Expr },
 }
 
 impl FieldSpec {
     fn is_virtual(&self) -> bool {
         match self {
-            FieldSpec::StructField(_) => false,
+            FieldSpec::StructField(_) | FieldSpec::MetadataField { .. } => false,
             FieldSpec::VirtualField { .. } => true,
         }
     }
     pub fn field(&self) -> &Field {
         match self {
             FieldSpec::StructField(f) => f,
+            FieldSpec::MetadataField { field, .. } => field,
             FieldSpec::VirtualField { field, .. } => field,
         }
     }
@@ -152,7 +157,7 @@ impl ConnectorTable {
                         }
                         _ => field_spec,
                     },
-                    FieldSpec::VirtualField { .. } => {
+                    FieldSpec::MetadataField { .. } | FieldSpec::VirtualField { .. } => {
                         unreachable!("delta lake is only a sink, can't have virtual fields")
                     }
                 })
@@ -165,6 +170,51 @@ impl ConnectorTable {
 
         let framing = Framing::from_opts(options).map_err(|e| anyhow!("invalid framing: '{e}'"))?;
 
+        let metadata_fields = fields
+            .iter()
+            .filter_map(|f| match f {
+                FieldSpec::MetadataField { field, key } => Some((field, key)),
+                _ => None,
+            })
+            .map(|(field, key)| {
+                let Some(def) = connector.metadata_defs().iter().find(|d| d.name == key) else {
+                    let available: Vec<_> = connector
+                        .metadata_defs()
+                        .iter()
+                        .map(|d| format!("'{}'", d.name))
+                        .collect();
+                    if available.is_empty() {
+                        bail!(
+                            "connector '{}' does not support metadata fields",
+                            connector.name()
+                        );
+                    }
+                    bail!(
+                        "unknown metadata key '{}' for field '{}'; connector '{}' supports {}",
+                        key,
+                        field.name(),
+                        connector.name(),
+                        available.join(", ")
+                    );
+                };
+
+                if &def.data_type != field.data_type() {
+                    bail!(
+                        "metadata field '{}' has type {:?}, but metadata '{}' is of type {:?}",
+                        field.name(),
+                        field.data_type(),
+                        key,
+                        def.data_type
+                    );
+                }
+
+                Ok(MetadataField {
+                    field_name: field.name().to_string(),
+                    key: key.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         let mut input_to_schema_fields = fields.clone();
 
         if let Some(Format::Json(JsonFormat { debezium: true, .. })) = &format {
@@ -172,6 +222,9 @@ impl ConnectorTable {
             if fields.iter().any(|f| f.is_virtual()) {
                 bail!("can't use virtual fields with debezium format")
             }
+            if !metadata_fields.is_empty() {
+                bail!("can't use metadata fields with debezium format")
+            }
             let df_struct_type =
                 DataType::Struct(fields.iter().map(|f| f.field().clone()).collect());
             let before_field_spec =
@@ -207,6 +260,7 @@ impl ConnectorTable {
             schema_fields,
             None,
             Some(fields.is_empty()),
+            metadata_fields,
         )?;
 
         let connection =
@@ -348,7 +402,11 @@ impl ConnectorTable {
                 .fields
                 .iter()
                 .filter_map(|field| match field {
-                    FieldSpec::StructField(struct_field) => Some(Arc::new(struct_field.clone())),
+                    FieldSpec::StructField(struct_field)
+                    | FieldSpec::MetadataField {
+                        field: struct_field,
+                        ..
+                    } => Some(Arc::new(struct_field.clone())),
                     FieldSpec::VirtualField { .. } => None,
                 })
                 .collect(),
@@ -371,6 +429,14 @@ impl ConnectorTable {
             Some(Format::Json(JsonFormat { debezium: true, .. }))
         )
     }
+
+    /// Whether this table's connector can write updating input directly, applying retractions
+    /// itself rather than through a debezium-formatted stream
+    pub(crate) fn accepts_updating_input(&self) -> bool {
+        connector_for_type(&self.connector)
+            .map(|c| c.supports_updating_input())
+            .unwrap_or(false)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -408,6 +474,25 @@ fn value_to_inner_string(value: &Value) -> Result<String> {
     }
 }
 
+/// Returns the key if this is a `metadata('<key>')` expression, which marks a column as being
+/// populated by the connector from message metadata
+fn metadata_key(expr: &SqlExpr) -> Option<String> {
+    let SqlExpr::Function(function) = expr else {
+        return None;
+    };
+
+    if function.name.to_string().to_lowercase() != "metadata" {
+        return None;
+    }
+
+    match function.args.as_slice() {
+        [FunctionArg::Unnamed(FunctionArgExpr::Expr(SqlExpr::Value(Value::SingleQuotedString(
+            key,
+        ))))] => Some(key.clone()),
+        _ => None,
+    }
+}
+
 impl Table {
     fn schema_from_columns(
         columns: &[ColumnDef],
@@ -446,8 +531,8 @@ impl Table {
             .iter()
             .filter_map(
                 |(field, generating_expression)| match generating_expression {
-                    Some(_) => None,
-                    None => Some(field.clone()),
+                    Some(expr) if metadata_key(expr).is_none() => None,
+                    _ => Some(field.clone()),
                 },
             )
             .collect();
@@ -470,7 +555,12 @@ impl Table {
         struct_field_pairs
             .into_iter()
             .map(|(struct_field, generating_expression)| {
-                if let Some(generating_expression) = generating_expression {
+                if let Some(key) = generating_expression.as_ref().and_then(metadata_key) {
+                    Ok(FieldSpec::MetadataField {
+                        field: struct_field,
+                        key,
+                    })
+                } else if let Some(generating_expression) = generating_expression {
                     // TODO: Implement automatic type coercion here, as we have elsewhere.
                     // It is done by calling the Analyzer which inserts CAST operators where necessary.
 
@@ -521,6 +611,13 @@ impl Table {
                         bail!("Virtual fields are not supported in memory tables; instead write a query");
                     }
 
+                    if fields
+                        .iter()
+                        .any(|f| matches!(f, FieldSpec::MetadataField { .. }))
+                    {
+                        bail!("Metadata fields are not supported in memory tables");
+                    }
+
                     if !with_map.is_empty() {
                         if connector.is_some() {
                             bail!("Memory tables do not allow with options");
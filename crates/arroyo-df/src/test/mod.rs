@@ -5,6 +5,7 @@ use arroyo_connectors::{
     nexmark::{NexmarkConnector, NexmarkTable},
     EmptyConfig,
 };
+use arroyo_datastream::logical::OperatorName;
 use arroyo_operator::connector::Connector;
 use arroyo_udf_host::parse::NullableType;
 use test_log::test;
@@ -58,3 +59,50 @@ async fn test_udf() {
         .await
         .unwrap();
 }
+
+#[test(tokio::test)]
+async fn test_parallelism_hints() {
+    let sql = "
+    SET parallelism = 2;
+    SET join.parallelism = 8;
+
+    CREATE TABLE impulse WITH (
+        connector = 'impulse',
+        event_rate = '10000'
+    );
+
+    SELECT evens.even_counter FROM
+        (SELECT counter as even_counter FROM impulse where counter % 2 = 0) evens
+        JOIN impulse on evens.even_counter = impulse.counter;";
+
+    let program = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap()
+        .program;
+
+    // the join hint also applies to the operators chained to the join by forward edges
+    for group in program.forward_groups() {
+        let expected = if group
+            .iter()
+            .any(|idx| program.graph[*idx].operator_name == OperatorName::Join)
+        {
+            8
+        } else {
+            2
+        };
+
+        for idx in group {
+            let node = &program.graph[idx];
+            assert_eq!(node.parallelism, expected, "{}", node.operator_id);
+        }
+    }
+
+    assert!(program
+        .graph
+        .node_weights()
+        .any(|n| n.operator_name == OperatorName::Join && n.parallelism == 8));
+    assert!(program
+        .graph
+        .node_weights()
+        .any(|n| n.operator_name == OperatorName::ConnectorSource && n.parallelism == 2));
+}
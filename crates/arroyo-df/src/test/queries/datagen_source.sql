@@ -0,0 +1,23 @@
+CREATE TABLE orders (
+  id BIGINT NOT NULL,
+  customer TEXT,
+  status TEXT NOT NULL,
+  amount DOUBLE,
+  created_at TIMESTAMP
+) WITH (
+  connector = 'datagen',
+  event_rate = '100',
+  out_of_order_millis = '500',
+  'fields.id.kind' = 'sequence',
+  'fields.customer.faker' = 'name',
+  'fields.customer.null_ratio' = '0.1',
+  'fields.status.values' = 'pending,shipped,delivered',
+  'fields.status.distribution' = 'exponential',
+  'fields.amount.min' = '1',
+  'fields.amount.max' = '500',
+  'fields.amount.distribution' = 'normal'
+);
+
+SELECT status, count(*), avg(amount)
+FROM orders
+GROUP BY status, tumble(interval '10 seconds');
@@ -0,0 +1,4 @@
+--fail=unknown operator kind 'shuffle'
+SET shuffle.parallelism = 4;
+
+SELECT bid FROM nexmark;
@@ -0,0 +1,23 @@
+CREATE TABLE impulse WITH (
+  connector = 'impulse',
+  event_rate = '10'
+);
+
+CREATE TABLE leaderboard (
+  user_id TEXT NOT NULL,
+  count BIGINT NOT NULL
+) WITH (
+  connector = 'redis',
+  address = 'redis://localhost:6379',
+  type = 'sink',
+  format = 'json',
+  target = 'sorted_set',
+  'target.key_prefix' = 'leaderboard',
+  'target.score_column' = 'count',
+  'target.member_column' = 'user_id'
+);
+
+INSERT INTO leaderboard
+SELECT CAST(counter % 10 AS TEXT) as user_id, count(*) as count
+FROM impulse
+GROUP BY 1;
@@ -278,6 +278,7 @@ mod tests {
                 Format::Avro(format),
                 None,
                 arroyo_schema.clone(),
+                &[],
                 BadData::Fail {},
                 resolver,
             ),
@@ -295,7 +296,7 @@ mod tests {
             deserializer_with_schema(format.clone(), writer_schema);
 
         let errors = deserializer
-            .deserialize_slice(&mut builders, message, SystemTime::now())
+            .deserialize_slice(&mut builders, message, SystemTime::now(), None)
             .await;
         assert_eq!(errors, vec![]);
 
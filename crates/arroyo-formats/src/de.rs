@@ -1,7 +1,12 @@
 use crate::avro::de;
 use arrow::compute::kernels;
-use arrow_array::builder::{ArrayBuilder, StringBuilder, TimestampNanosecondBuilder};
-use arrow_array::{RecordBatch, StringArray};
+use arrow_array::builder::{
+    make_builder, ArrayBuilder, BinaryBuilder, Int32Builder, Int64Builder, StringBuilder,
+    TimestampNanosecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch, StringArray};
+use arrow_schema::{DataType, Schema, TimeUnit};
+use arroyo_rpc::api_types::connections::MetadataField;
 use arroyo_rpc::df::ArroyoSchema;
 use arroyo_rpc::formats::{AvroFormat, BadData, Format, Framing, FramingMethod, JsonFormat};
 use arroyo_rpc::schema_resolver::{FailingSchemaResolver, FixedSchemaResolver, SchemaResolver};
@@ -83,12 +88,80 @@ impl<'a> Iterator for FramingIterator<'a> {
     }
 }
 
+/// A value for a metadata field, provided by the source alongside each message
+#[derive(Debug, Clone, Copy)]
+pub enum FieldValueType<'a> {
+    Int32(i32),
+    Int64(i64),
+    String(&'a str),
+    Bytes(&'a [u8]),
+    Timestamp(SystemTime),
+}
+
+struct MetadataBuilder {
+    key: String,
+    idx: usize,
+    data_type: DataType,
+    // only used when decoding via the JSON decoder; otherwise values are appended directly
+    // to the output buffer
+    builder: Box<dyn ArrayBuilder>,
+}
+
+fn append_metadata(
+    builder: &mut dyn ArrayBuilder,
+    data_type: &DataType,
+    value: Option<&FieldValueType>,
+) {
+    let builder = builder.as_any_mut();
+    match data_type {
+        DataType::Int32 => {
+            let builder = builder.downcast_mut::<Int32Builder>().unwrap();
+            match value {
+                Some(FieldValueType::Int32(v)) => builder.append_value(*v),
+                _ => builder.append_null(),
+            }
+        }
+        DataType::Int64 => {
+            let builder = builder.downcast_mut::<Int64Builder>().unwrap();
+            match value {
+                Some(FieldValueType::Int64(v)) => builder.append_value(*v),
+                _ => builder.append_null(),
+            }
+        }
+        DataType::Utf8 => {
+            let builder = builder.downcast_mut::<StringBuilder>().unwrap();
+            match value {
+                Some(FieldValueType::String(v)) => builder.append_value(v),
+                _ => builder.append_null(),
+            }
+        }
+        DataType::Binary => {
+            let builder = builder.downcast_mut::<BinaryBuilder>().unwrap();
+            match value {
+                Some(FieldValueType::Bytes(v)) => builder.append_value(v),
+                _ => builder.append_null(),
+            }
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, None) => {
+            let builder = builder
+                .downcast_mut::<TimestampNanosecondBuilder>()
+                .unwrap();
+            match value {
+                Some(FieldValueType::Timestamp(v)) => builder.append_value(to_nanos(*v) as i64),
+                _ => builder.append_null(),
+            }
+        }
+        dt => unreachable!("unsupported type for metadata field: {:?}", dt),
+    }
+}
+
 pub struct ArrowDeserializer {
     format: Arc<Format>,
     framing: Option<Arc<Framing>>,
     schema: ArroyoSchema,
     bad_data: BadData,
     json_decoder: Option<(arrow::json::reader::Decoder, TimestampNanosecondBuilder)>,
+    metadata_builders: Vec<MetadataBuilder>,
     buffered_count: usize,
     buffered_since: Instant,
     schema_registry: Arc<Mutex<HashMap<u32, apache_avro::schema::Schema>>>,
@@ -99,6 +172,7 @@ impl ArrowDeserializer {
     pub fn new(
         format: Format,
         schema: ArroyoSchema,
+        metadata_fields: &[MetadataField],
         framing: Option<Framing>,
         bad_data: BadData,
     ) -> Self {
@@ -113,16 +187,52 @@ impl ArrowDeserializer {
             Arc::new(FailingSchemaResolver::new()) as Arc<dyn SchemaResolver + Sync>
         };
 
-        Self::with_schema_resolver(format, framing, schema, bad_data, resolver)
+        Self::with_schema_resolver(format, framing, schema, metadata_fields, bad_data, resolver)
     }
 
     pub fn with_schema_resolver(
         format: Format,
         framing: Option<Framing>,
         schema: ArroyoSchema,
+        metadata_fields: &[MetadataField],
         bad_data: BadData,
         schema_resolver: Arc<dyn SchemaResolver + Sync>,
     ) -> Self {
+        let metadata_builders: Vec<_> = schema
+            .schema
+            .fields()
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, f)| {
+                let key = &metadata_fields
+                    .iter()
+                    .find(|m| &m.field_name == f.name())?
+                    .key;
+                Some(MetadataBuilder {
+                    key: key.clone(),
+                    idx,
+                    data_type: f.data_type().clone(),
+                    builder: make_builder(f.data_type(), 16),
+                })
+            })
+            .collect();
+
+        // the JSON decoder only handles fields from the payload, so exclude the timestamp and
+        // any metadata fields
+        let decoder_schema = Schema::new(
+            schema
+                .schema
+                .fields()
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| {
+                    *idx != schema.timestamp_index
+                        && !metadata_builders.iter().any(|m| m.idx == *idx)
+                })
+                .map(|(_, f)| f.clone())
+                .collect::<Vec<_>>(),
+        );
+
         Self {
             json_decoder: matches!(
                 format,
@@ -133,19 +243,17 @@ impl ArrowDeserializer {
                     })
             )
             .then(|| {
-                // exclude the timestamp field
                 (
-                    arrow_json::reader::ReaderBuilder::new(Arc::new(
-                        schema.schema_without_timestamp(),
-                    ))
-                    .with_limit_to_batch_size(false)
-                    .with_strict_mode(false)
-                    .with_allow_bad_data(matches!(bad_data, BadData::Drop { .. }))
-                    .build_decoder()
-                    .unwrap(),
+                    arrow_json::reader::ReaderBuilder::new(Arc::new(decoder_schema))
+                        .with_limit_to_batch_size(false)
+                        .with_strict_mode(false)
+                        .with_allow_bad_data(matches!(bad_data, BadData::Drop { .. }))
+                        .build_decoder()
+                        .unwrap(),
                     TimestampNanosecondBuilder::new(),
                 )
             }),
+            metadata_builders,
             format: Arc::new(format),
             framing: framing.map(Arc::new),
             schema,
@@ -162,11 +270,15 @@ impl ArrowDeserializer {
         buffer: &mut [Box<dyn ArrayBuilder>],
         msg: &[u8],
         timestamp: SystemTime,
+        additional_fields: Option<&HashMap<&str, FieldValueType<'_>>>,
     ) -> Vec<SourceError> {
         match &*self.format {
-            Format::Avro(_) => self.deserialize_slice_avro(buffer, msg, timestamp).await,
+            Format::Avro(_) => {
+                self.deserialize_slice_avro(buffer, msg, timestamp, additional_fields)
+                    .await
+            }
             _ => FramingIterator::new(self.framing.clone(), msg)
-                .map(|t| self.deserialize_single(buffer, t, timestamp))
+                .map(|t| self.deserialize_single(buffer, t, timestamp, additional_fields))
                 .filter_map(|t| t.err())
                 .collect(),
         }
@@ -189,7 +301,24 @@ impl ArrowDeserializer {
                     let mut columns = batch.columns().to_vec();
                     let timestamp = kernels::filter::filter(&timestamp.finish(), &mask).unwrap();
 
-                    columns.insert(self.schema.timestamp_index, Arc::new(timestamp));
+                    let mut additional: Vec<(usize, ArrayRef)> = self
+                        .metadata_builders
+                        .iter_mut()
+                        .map(|m| {
+                            (
+                                m.idx,
+                                kernels::filter::filter(&m.builder.finish(), &mask).unwrap(),
+                            )
+                        })
+                        .collect();
+                    additional.push((self.schema.timestamp_index, Arc::new(timestamp)));
+
+                    // insert in index order so that each column ends up at its schema position
+                    additional.sort_by_key(|(idx, _)| *idx);
+                    for (idx, column) in additional {
+                        columns.insert(idx, column);
+                    }
+
                     RecordBatch::try_new(self.schema.schema.clone(), columns).unwrap()
                 }),
         )
@@ -200,6 +329,7 @@ impl ArrowDeserializer {
         buffer: &mut [Box<dyn ArrayBuilder>],
         msg: &[u8],
         timestamp: SystemTime,
+        additional_fields: Option<&HashMap<&str, FieldValueType<'_>>>,
     ) -> Result<(), SourceError> {
         match &*self.format {
             Format::RawString(_)
@@ -208,6 +338,7 @@ impl ArrowDeserializer {
             }) => {
                 self.deserialize_raw_string(buffer, msg);
                 add_timestamp(buffer, self.schema.timestamp_index, timestamp);
+                self.add_metadata_to_buffer(buffer, additional_fields);
             }
             Format::Json(json) => {
                 let msg = if json.confluent_schema_registry {
@@ -224,6 +355,7 @@ impl ArrowDeserializer {
                     .decode(msg)
                     .map_err(|e| SourceError::bad_data(format!("invalid JSON: {:?}", e)))?;
                 timestamp_builder.append_value(to_nanos(timestamp) as i64);
+                self.add_metadata_to_builders(additional_fields);
                 self.buffered_count += 1;
             }
             Format::Avro(_) => unreachable!("this should not be called for avro"),
@@ -238,6 +370,7 @@ impl ArrowDeserializer {
         builders: &mut [Box<dyn ArrayBuilder>],
         msg: &'a [u8],
         timestamp: SystemTime,
+        additional_fields: Option<&HashMap<&str, FieldValueType<'_>>>,
     ) -> Vec<SourceError> {
         let Format::Avro(format) = &*self.format else {
             unreachable!("not avro");
@@ -278,6 +411,7 @@ impl ArrowDeserializer {
 
                     array.append_value(de::avro_to_json(value).to_string());
                     add_timestamp(builders, self.schema.timestamp_index, timestamp);
+                    self.add_metadata_to_buffer(builders, additional_fields);
                     self.buffered_count += 1;
                 } else {
                     // for now round-trip through json in order to handle unsupported avro features
@@ -293,6 +427,7 @@ impl ArrowDeserializer {
                         .map_err(|e| SourceError::bad_data(format!("invalid JSON: {:?}", e)))?;
                     self.buffered_count += 1;
                     timestamp_builder.append_value(to_nanos(timestamp) as i64);
+                    self.add_metadata_to_builders(additional_fields);
                 }
 
                 Ok(())
@@ -316,6 +451,33 @@ impl ArrowDeserializer {
             .append_value(String::from_utf8_lossy(msg));
     }
 
+    fn add_metadata_to_buffer(
+        &self,
+        buffer: &mut [Box<dyn ArrayBuilder>],
+        additional_fields: Option<&HashMap<&str, FieldValueType<'_>>>,
+    ) {
+        for m in &self.metadata_builders {
+            append_metadata(
+                buffer[m.idx].as_mut(),
+                &m.data_type,
+                additional_fields.and_then(|f| f.get(m.key.as_str())),
+            );
+        }
+    }
+
+    fn add_metadata_to_builders(
+        &mut self,
+        additional_fields: Option<&HashMap<&str, FieldValueType<'_>>>,
+    ) {
+        for m in &mut self.metadata_builders {
+            append_metadata(
+                m.builder.as_mut(),
+                &m.data_type,
+                additional_fields.and_then(|f| f.get(m.key.as_str())),
+            );
+        }
+    }
+
     pub fn bad_data(&self) -> &BadData {
         &self.bad_data
     }
@@ -335,9 +497,19 @@ pub(crate) fn add_timestamp(
 
 #[cfg(test)]
 mod tests {
-    use crate::de::FramingIterator;
-    use arroyo_rpc::formats::{Framing, FramingMethod, NewlineDelimitedFraming};
+    use crate::de::{ArrowDeserializer, FieldValueType, FramingIterator};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int64Type;
+    use arrow_array::Array;
+    use arrow_schema::{DataType, Field};
+    use arroyo_rpc::api_types::connections::MetadataField;
+    use arroyo_rpc::df::ArroyoSchema;
+    use arroyo_rpc::formats::{
+        BadData, Format, Framing, FramingMethod, JsonFormat, NewlineDelimitedFraming,
+    };
+    use std::collections::HashMap;
     use std::sync::Arc;
+    use std::time::SystemTime;
 
     #[test]
     fn test_line_framing() {
@@ -408,4 +580,66 @@ mod tests {
             result
         );
     }
+
+    #[tokio::test]
+    async fn test_metadata_fields() {
+        let schema = ArroyoSchema::from_fields(vec![
+            Field::new("x", DataType::Int64, true),
+            Field::new("offset", DataType::Int64, true),
+            Field::new("topic", DataType::Utf8, true),
+        ]);
+
+        let mut deserializer = ArrowDeserializer::new(
+            Format::Json(JsonFormat::default()),
+            schema.clone(),
+            &[
+                MetadataField {
+                    field_name: "offset".to_string(),
+                    key: "offset".to_string(),
+                },
+                MetadataField {
+                    field_name: "topic".to_string(),
+                    key: "topic".to_string(),
+                },
+            ],
+            None,
+            BadData::Fail {},
+        );
+
+        let mut builders = schema.builders();
+
+        for i in 0..3 {
+            let mut additional_fields = HashMap::new();
+            additional_fields.insert("offset", FieldValueType::Int64(i * 10));
+            let errors = deserializer
+                .deserialize_slice(
+                    &mut builders,
+                    format!("{{\"x\": {}}}", i).as_bytes(),
+                    SystemTime::now(),
+                    Some(&additional_fields),
+                )
+                .await;
+            assert!(errors.is_empty());
+        }
+
+        let batch = deserializer.flush_buffer().unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(
+            batch
+                .column(0)
+                .as_primitive::<Int64Type>()
+                .values()
+                .to_vec(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(
+            batch
+                .column(1)
+                .as_primitive::<Int64Type>()
+                .values()
+                .to_vec(),
+            vec![0, 10, 20]
+        );
+        assert_eq!(batch.column(2).null_count(), 3);
+    }
 }
@@ -5,7 +5,7 @@ use arrow_array::RecordBatch;
 use arrow_json::writer::record_batches_to_json_rows_opts;
 use arrow_schema::{DataType, Field};
 use arroyo_rpc::formats::{AvroFormat, Format, JsonFormat, RawStringFormat, TimestampFormat};
-use arroyo_rpc::TIMESTAMP_FIELD;
+use arroyo_rpc::{IS_RETRACT_FIELD, TIMESTAMP_FIELD};
 use serde_json::Value;
 use std::sync::Arc;
 
@@ -31,7 +31,7 @@ impl ArrowSerializer {
             .fields
             .iter()
             .enumerate()
-            .filter(|(_, f)| f.name() != TIMESTAMP_FIELD)
+            .filter(|(_, f)| f.name() != TIMESTAMP_FIELD && f.name() != IS_RETRACT_FIELD)
             .map(|(i, _)| i)
             .collect()
     }
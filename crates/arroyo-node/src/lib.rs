@@ -25,11 +25,10 @@ use base64::Engine;
 use lazy_static::lazy_static;
 use prometheus::{register_gauge, Gauge};
 use rand::random;
-use std::process::exit;
 use tokio::sync::mpsc::{channel, Sender};
 use tokio::{process::Command, select};
 use tonic::{Request, Response, Status};
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 
 lazy_static! {
     static ref WORKERS: Gauge = register_gauge!(
@@ -42,6 +41,7 @@ lazy_static! {
 pub struct WorkerStatus {
     name: String,
     job_id: String,
+    run_id: u64,
     slots: usize,
     running: bool,
     pid: u32,
@@ -130,6 +130,7 @@ impl NodeServer {
             WorkerStatus {
                 name: req.name,
                 job_id: req.job_id.clone(),
+                run_id: req.run_id,
                 slots: slots as usize,
                 running: true,
                 pid: child
@@ -253,6 +254,21 @@ impl NodeGrpc for NodeServer {
     }
 }
 
+fn running_workers(workers: &Mutex<HashMap<WorkerId, WorkerStatus>>) -> Vec<NodeWorker> {
+    workers
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, w)| w.running)
+        .map(|(id, w)| NodeWorker {
+            worker_id: id.0,
+            job_id: w.job_id.clone(),
+            run_id: w.run_id,
+            slots: w.slots as u64,
+        })
+        .collect()
+}
+
 pub async fn start_server(guard: ShutdownGuard) -> NodeId {
     let controller_addr =
         std::env::var(CONTROLLER_ADDR_ENV).unwrap_or_else(|_| default_controller_addr());
@@ -267,9 +283,11 @@ pub async fn start_server(guard: ShutdownGuard) -> NodeId {
 
     let (worker_finished_tx, mut worker_finished_rx) = channel(128);
 
+    let workers = Arc::new(Mutex::new(HashMap::new()));
+
     let server = NodeServer {
         id: node_id,
-        workers: Arc::new(Mutex::new(HashMap::new())),
+        workers: workers.clone(),
         worker_finished_tx,
     };
 
@@ -302,25 +320,36 @@ pub async fn start_server(guard: ShutdownGuard) -> NodeId {
         loop {
             match ControllerGrpcClient::connect(controller_addr.clone()).await {
                 Ok(mut controller) => {
-                    controller
+                    // if we're re-registering (for example, with a newly-elected controller) it
+                    // needs to know about the workers we're already running
+                    let running_workers = running_workers(&workers);
+
+                    if let Err(e) = controller
                         .register_node(Request::new(RegisterNodeReq {
                             node_id: node_id.0,
                             task_slots: task_slots as u64,
                             addr: req_addr.clone(),
+                            workers: running_workers,
                         }))
                         .await
-                        .unwrap();
+                    {
+                        warn!("failed to register with controller: {:?}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
 
                     info!("Connected to controller");
+                    attempts = 0;
                     loop {
                         select! {
                             _ = tokio::time::sleep(Duration::from_secs(5)) => {},
                             msg = worker_finished_rx.recv() => {
-                                controller.worker_finished(Request::new(msg.unwrap())).await
-                                .unwrap_or_else(|err| {
-                                    error!("shutting down: controller failed to report finished worker with {:?}", err);
-                                    exit(1);
-                                });
+                                if let Err(err) = controller.worker_finished(Request::new(msg.unwrap())).await {
+                                    // the finished worker won't be included when we re-register,
+                                    // so its slots will be released
+                                    warn!("controller failed to report finished worker with {:?}; reconnecting", err);
+                                    break;
+                                }
                             }
                         }
 
@@ -331,8 +360,8 @@ pub async fn start_server(guard: ShutdownGuard) -> NodeId {
                             }))
                             .await
                         {
-                            error!("shutting down: controller failed heartbeat with {:?}", e);
-                            return;
+                            warn!("controller failed heartbeat with {:?}; reconnecting", e);
+                            break;
                         }
                     }
                 }
@@ -1,5 +1,6 @@
 use crate::operator::OperatorNode;
 use anyhow::anyhow;
+use arrow::datatypes::DataType;
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
 };
@@ -22,6 +23,14 @@ pub struct Connection {
     pub description: String,
 }
 
+/// A metadata key that a connector can populate for each record, which users can select in a
+/// table definition with `GENERATED ALWAYS AS (metadata('<key>')) STORED`
+#[derive(Debug, Clone)]
+pub struct MetadataDef {
+    pub name: &'static str,
+    pub data_type: DataType,
+}
+
 #[allow(clippy::wrong_self_convention)]
 pub trait Connector: Send {
     type ProfileT: DeserializeOwned + Serialize;
@@ -44,6 +53,16 @@ pub trait Connector: Send {
 
     fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector;
 
+    fn metadata_defs(&self) -> &'static [MetadataDef] {
+        &[]
+    }
+
+    /// Whether this connector's sink can consume updating input directly (with retractions
+    /// marked by the `_is_retract` column) rather than requiring a debezium format
+    fn supports_updating_input(&self) -> bool {
+        false
+    }
+
     fn table_type(&self, config: Self::ProfileT, table: Self::TableT) -> ConnectionType;
 
     #[allow(unused)]
@@ -115,6 +134,10 @@ pub trait ErasedConnector: Send {
 
     fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector;
 
+    fn metadata_defs(&self) -> &'static [MetadataDef];
+
+    fn supports_updating_input(&self) -> bool;
+
     fn validate_config(&self, s: &serde_json::Value) -> Result<(), serde_json::Error>;
 
     fn validate_table(&self, s: &serde_json::Value) -> Result<(), serde_json::Error>;
@@ -185,6 +208,14 @@ impl<C: Connector> ErasedConnector for C {
         self.metadata()
     }
 
+    fn metadata_defs(&self) -> &'static [MetadataDef] {
+        self.metadata_defs()
+    }
+
+    fn supports_updating_input(&self) -> bool {
+        self.supports_updating_input()
+    }
+
     fn config_description(&self, s: &serde_json::Value) -> Result<String, serde_json::Error> {
         Ok(self.config_description(self.parse_config(s)?))
     }
@@ -1,26 +1,35 @@
-use crate::{server_for_hash_array, RateLimiter};
-use arrow::array::{make_builder, Array, ArrayBuilder, PrimitiveArray, RecordBatch};
-use arrow::compute::{partition, sort_to_indices, take};
+use crate::{logical_input, server_for_hash_array, RateLimiter};
+use arrow::array::{
+    make_builder, Array, ArrayBuilder, PrimitiveArray, RecordBatch, TimestampNanosecondArray,
+    UInt64Array,
+};
+use arrow::compute::kernels::cmp::eq;
+use arrow::compute::{filter_record_batch, max, partition, sort_to_indices, take};
 use arrow::datatypes::{SchemaRef, UInt64Type};
-use arroyo_formats::de::ArrowDeserializer;
-use arroyo_metrics::{register_queue_gauge, QueueGauges, TaskCounters};
+use arroyo_formats::de::{ArrowDeserializer, FieldValueType};
+use arroyo_metrics::{gauge_for_task, register_queue_gauge, QueueGauges, TaskCounters};
+use arroyo_rpc::api_types::connections::MetadataField;
 use arroyo_rpc::df::ArroyoSchema;
 use arroyo_rpc::formats::{BadData, Format, Framing};
-use arroyo_rpc::grpc::{CheckpointMetadata, TableConfig, TaskCheckpointEventType};
+use arroyo_rpc::grpc::{
+    CheckpointMetadata, OperatorCheckpointMetadata, OperatorMetadata, TableConfig,
+    TaskCheckpointEventType,
+};
 use arroyo_rpc::schema_resolver::SchemaResolver;
 use arroyo_rpc::{get_hasher, CompactionResult, ControlMessage, ControlResp};
 use arroyo_state::tables::table_manager::TableManager;
 use arroyo_state::{BackingStore, StateBackend};
 use arroyo_types::{
-    from_micros, should_flush, ArrowMessage, CheckpointBarrier, SourceError, TaskInfo, UserError,
-    Watermark,
+    from_micros, should_flush, to_nanos, ArrowMessage, CheckpointBarrier, SignalMessage,
+    SourceError, TaskInfo, UserError, Watermark, SOURCE_LAG,
 };
 use datafusion::common::hash_utils;
+use prometheus::IntGauge;
 use rand::Rng;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::mem::size_of_val;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Instant, SystemTime};
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::{unbounded_channel, Receiver, Sender, UnboundedReceiver, UnboundedSender};
@@ -82,6 +91,10 @@ impl WatermarkHolder {
     }
 }
 
+// Unaligned checkpoint barriers skip ahead of the data in the queue; each is stored along with the
+// number of data messages that had been sent before it
+type PriorityQueue = Arc<Mutex<VecDeque<(u64, QueueItem)>>>;
+
 /// A wrapper for an UnboundedSender<QueueItem> that bounds by the number of rows within
 /// a batch rather than the number of batches
 #[derive(Clone)]
@@ -91,6 +104,9 @@ pub struct BatchSender {
     queued_messages: Arc<AtomicU32>,
     queued_bytes: Arc<AtomicU64>,
     notify: Arc<Notify>,
+    sent_data: Arc<AtomicU64>,
+    priority: PriorityQueue,
+    priority_notify: Arc<Notify>,
 }
 
 #[inline]
@@ -111,6 +127,12 @@ fn message_bytes(item: &QueueItem) -> u64 {
 
 impl BatchSender {
     pub async fn send(&self, item: QueueItem) -> Result<(), SendError<QueueItem>> {
+        if let QueueItem::Signal(SignalMessage::Barrier(barrier)) = &item {
+            if barrier.unaligned {
+                return self.send_priority(item);
+            }
+        }
+
         // Ensure that every message is sendable, even if it's bigger than our max size
         let count = message_count(&item, self.size);
         loop {
@@ -129,6 +151,9 @@ impl BatchSender {
                     Ok(_) => {
                         self.queued_bytes
                             .fetch_add(message_bytes(&item), Ordering::AcqRel);
+                        if matches!(item, QueueItem::Data(_)) {
+                            self.sent_data.fetch_add(1, Ordering::AcqRel);
+                        }
                         return self.tx.send(item);
                     }
                     Err(_) => {
@@ -144,6 +169,39 @@ impl BatchSender {
         }
     }
 
+    /// Sends an unaligned barrier that has already overtaken `overtaken` data messages which are
+    /// yet to be sent on this queue, as happens when it's forwarded ahead of them over the network
+    pub fn send_overtaking(
+        &self,
+        item: QueueItem,
+        overtaken: usize,
+    ) -> Result<(), SendError<QueueItem>> {
+        self.send_priority_after(item, overtaken as u64)
+    }
+
+    fn send_priority(&self, item: QueueItem) -> Result<(), SendError<QueueItem>> {
+        self.send_priority_after(item, 0)
+    }
+
+    // unaligned barriers aren't subject to backpressure, as their purpose is to let checkpoints
+    // complete while the queue is full
+    fn send_priority_after(
+        &self,
+        item: QueueItem,
+        overtaken: u64,
+    ) -> Result<(), SendError<QueueItem>> {
+        if self.tx.is_closed() {
+            return Err(SendError(item));
+        }
+
+        self.priority
+            .lock()
+            .unwrap()
+            .push_back((self.sent_data.load(Ordering::Acquire) + overtaken, item));
+        self.priority_notify.notify_one();
+        Ok(())
+    }
+
     pub fn capacity(&self) -> u32 {
         self.size
             .saturating_sub(self.queued_messages.load(Ordering::Relaxed))
@@ -164,19 +222,57 @@ pub struct BatchReceiver {
     queued_messages: Arc<AtomicU32>,
     queued_bytes: Arc<AtomicU64>,
     notify: Arc<Notify>,
+    received_data: u64,
+    priority: PriorityQueue,
+    priority_notify: Arc<Notify>,
 }
 
 impl BatchReceiver {
+    /// Receives the next item in the order it was sent
     pub async fn recv(&mut self) -> Option<QueueItem> {
-        let item = self.rx.recv().await;
-        if let Some(item) = &item {
-            let count = message_count(item, self.size);
-            self.queued_messages.fetch_sub(count, Ordering::SeqCst);
-            self.queued_bytes
-                .fetch_sub(message_bytes(item), Ordering::AcqRel);
-            self.notify.notify_waiters();
+        self.next(false).await.map(|(item, _)| item)
+    }
+
+    /// Receives the next item, along with the number of data messages it overtook. Unaligned
+    /// checkpoint barriers are delivered ahead of any data that's still queued, so the next that
+    /// many data messages were sent before the barrier.
+    pub async fn recv_prioritized(&mut self) -> Option<(QueueItem, usize)> {
+        self.next(true).await
+    }
+
+    async fn next(&mut self, prioritize: bool) -> Option<(QueueItem, usize)> {
+        loop {
+            let next = {
+                let mut priority = self.priority.lock().unwrap();
+                match priority.front() {
+                    Some((sent_data, _)) if prioritize || *sent_data <= self.received_data => {
+                        priority.pop_front()
+                    }
+                    _ => None,
+                }
+            };
+
+            if let Some((sent_data, item)) = next {
+                return Some((item, sent_data.saturating_sub(self.received_data) as usize));
+            }
+
+            tokio::select! {
+                biased;
+                _ = self.priority_notify.notified() => {}
+                item = self.rx.recv() => {
+                    let item = item?;
+                    let count = message_count(&item, self.size);
+                    self.queued_messages.fetch_sub(count, Ordering::SeqCst);
+                    self.queued_bytes
+                        .fetch_sub(message_bytes(&item), Ordering::AcqRel);
+                    self.notify.notify_waiters();
+                    if matches!(item, QueueItem::Data(_)) {
+                        self.received_data += 1;
+                    }
+                    return Some((item, 0));
+                }
+            }
         }
-        item
     }
 }
 
@@ -185,6 +281,8 @@ pub fn batch_bounded(size: u32) -> (BatchSender, BatchReceiver) {
     let notify = Arc::new(Notify::new());
     let queued_messages = Arc::new(AtomicU32::new(0));
     let queued_bytes = Arc::new(AtomicU64::new(0));
+    let priority = PriorityQueue::default();
+    let priority_notify = Arc::new(Notify::new());
     (
         BatchSender {
             size,
@@ -192,6 +290,9 @@ pub fn batch_bounded(size: u32) -> (BatchSender, BatchReceiver) {
             queued_messages: queued_messages.clone(),
             queued_bytes: queued_bytes.clone(),
             notify: notify.clone(),
+            sent_data: Arc::new(AtomicU64::new(0)),
+            priority: priority.clone(),
+            priority_notify: priority_notify.clone(),
         },
         BatchReceiver {
             size,
@@ -199,6 +300,9 @@ pub fn batch_bounded(size: u32) -> (BatchSender, BatchReceiver) {
             notify,
             queued_bytes,
             queued_messages,
+            received_data: 0,
+            priority,
+            priority_notify,
         },
     )
 }
@@ -248,6 +352,8 @@ pub struct ArrowContext {
     pub error_reporter: ErrorReporter,
     pub watermarks: WatermarkHolder,
     pub in_schemas: Vec<ArroyoSchema>,
+    // the number of partitions of each logical input, in the same order as `in_schemas`
+    pub input_partitions: Vec<usize>,
     pub out_schema: Option<ArroyoSchema>,
     pub collector: ArrowCollector,
     buffer: Option<ContextBuffer>,
@@ -286,6 +392,23 @@ pub struct ArrowCollector {
     tx_queue_rem_gauges: QueueGauges,
     tx_queue_size_gauges: QueueGauges,
     tx_queue_bytes_gauges: QueueGauges,
+    source_lag_gauge: Option<IntGauge>,
+}
+
+// selects the rows of a keyed batch that `repartition` would route to the given subtask
+fn rows_for_subtask(
+    record: &RecordBatch,
+    keys: &[usize],
+    task_index: usize,
+    parallelism: usize,
+) -> RecordBatch {
+    let mut buf = vec![0; record.num_rows()];
+    let keys: Vec<_> = keys.iter().map(|i| record.column(*i).clone()).collect();
+    hash_utils::create_hashes(&keys[..], &get_hasher(), &mut buf).unwrap();
+
+    let servers = server_for_hash_array(&PrimitiveArray::from(buf), parallelism).unwrap();
+    let mask = eq(&servers, &UInt64Array::new_scalar(task_index as u64)).unwrap();
+    filter_record_batch(record, &mask).unwrap()
 }
 
 fn repartition<'a>(
@@ -374,6 +497,18 @@ impl ArrowCollector {
                 );
             });
 
+        if let Some(gauge) = &self.source_lag_gauge {
+            if let Some(latest) = record
+                .column(out_schema.timestamp_index)
+                .as_any()
+                .downcast_ref::<TimestampNanosecondArray>()
+                .and_then(max)
+            {
+                let now = to_nanos(SystemTime::now()) as i64;
+                gauge.set(now.saturating_sub(latest).max(0) / 1_000_000);
+            }
+        }
+
         for (i, out_q) in self.out_qs.iter_mut().enumerate() {
             let partitions = repartition(&record, &out_schema.key_indices, out_q.len());
 
@@ -419,7 +554,7 @@ impl ArrowContext {
         restore_from: Option<CheckpointMetadata>,
         control_rx: Receiver<ControlMessage>,
         control_tx: Sender<ControlResp>,
-        input_partitions: usize,
+        input_partitions: Vec<usize>,
         in_schemas: Vec<ArroyoSchema>,
         out_schema: Option<ArroyoSchema>,
         projection: Option<Vec<usize>>,
@@ -427,25 +562,43 @@ impl ArrowContext {
         tables: HashMap<String, TableConfig>,
     ) -> Self {
         let (watermark, metadata) = if let Some(metadata) = restore_from {
-            let (watermark, operator_metadata) = {
-                let metadata = StateBackend::load_operator_metadata(
-                    &task_info.job_id,
-                    &task_info.operator_id,
-                    metadata.epoch,
-                )
-                .await
-                .expect("lookup should succeed")
-                .expect("require metadata");
-                (
-                    metadata
-                        .operator_metadata
-                        .as_ref()
-                        .unwrap()
-                        .min_watermark
-                        .map(from_micros),
-                    metadata,
-                )
-            };
+            let (watermark, operator_metadata) =
+                if !metadata.operator_ids.contains(&task_info.operator_id) {
+                    // this operator was added by a pipeline upgrade, so there's no state to
+                    // restore; it starts empty, but at the same epoch as the rest of the pipeline
+                    (
+                        None,
+                        OperatorCheckpointMetadata {
+                            operator_metadata: Some(OperatorMetadata {
+                                job_id: task_info.job_id.clone(),
+                                operator_id: task_info.operator_id.clone(),
+                                epoch: metadata.epoch,
+                                min_watermark: None,
+                                max_watermark: None,
+                                parallelism: task_info.parallelism as u64,
+                            }),
+                            ..Default::default()
+                        },
+                    )
+                } else {
+                    let metadata = StateBackend::load_operator_metadata(
+                        &task_info.job_id,
+                        &task_info.operator_id,
+                        metadata.epoch,
+                    )
+                    .await
+                    .expect("lookup should succeed")
+                    .expect("require metadata");
+                    (
+                        metadata
+                            .operator_metadata
+                            .as_ref()
+                            .unwrap()
+                            .min_watermark
+                            .map(from_micros),
+                        metadata,
+                    )
+                };
 
             (watermark, Some(operator_metadata))
         } else {
@@ -473,6 +626,18 @@ impl ArrowContext {
             &out_qs,
         );
 
+        // for sources, tracks how far the data being read is behind the current time
+        let source_lag_gauge = if in_schemas.is_empty() && out_schema.is_some() {
+            gauge_for_task(
+                &task_info,
+                SOURCE_LAG,
+                "Milliseconds between now and the event time of the latest record emitted by a source",
+                HashMap::new(),
+            )
+        } else {
+            None
+        };
+
         let task_info = Arc::new(task_info);
 
         let table_manager =
@@ -486,9 +651,10 @@ impl ArrowContext {
             control_tx: control_tx.clone(),
             watermarks: WatermarkHolder::new(vec![
                 watermark.map(Watermark::EventTime);
-                input_partitions
+                input_partitions.iter().sum()
             ]),
             in_schemas,
+            input_partitions,
             out_schema: out_schema.clone(),
             collector: ArrowCollector {
                 task_info: task_info.clone(),
@@ -496,6 +662,7 @@ impl ArrowContext {
                 tx_queue_rem_gauges,
                 tx_queue_size_gauges,
                 tx_queue_bytes_gauges,
+                source_lag_gauge,
                 out_schema: out_schema.clone(),
                 projection,
             },
@@ -582,6 +749,18 @@ impl ArrowContext {
         Ok(())
     }
 
+    /// Discards records that have been deserialized but not yet flushed downstream, for sources
+    /// that reject a group of records as a whole when any of them is invalid
+    pub fn discard_buffer(&mut self) {
+        if let Some(buffer) = self.buffer.as_mut() {
+            *buffer = ContextBuffer::new(buffer.schema.clone());
+        }
+
+        if let Some(deserializer) = self.deserializer.as_mut() {
+            let _ = deserializer.flush_buffer();
+        }
+    }
+
     pub async fn collect(&mut self, record: RecordBatch) {
         self.collector.collect(record).await;
     }
@@ -640,6 +819,65 @@ impl ArrowContext {
             .unwrap();
     }
 
+    /// Maps the index of an input queue to the logical input (edge) it belongs to and the
+    /// partition within that input
+    pub fn logical_input(&self, idx: usize) -> (usize, usize) {
+        logical_input(&self.input_partitions, idx)
+    }
+
+    /// Returns the data that was in flight to this subtask when the unaligned checkpoint it's
+    /// restoring from was taken, along with the input queue it should be replayed on. If the
+    /// parallelism has changed, in-flight data is redistributed across the new subtasks.
+    pub async fn restore_in_flight(&mut self) -> Vec<(usize, RecordBatch)> {
+        let restored = self
+            .table_manager
+            .take_in_flight()
+            .await
+            .expect("should be able to read in-flight data");
+
+        let task_index = self.task_info.task_index;
+        let parallelism = self.task_info.parallelism;
+
+        restored
+            .into_iter()
+            .filter_map(|r| {
+                let input = r.batch.logical_input;
+                let Some(partitions) = self.input_partitions.get(input) else {
+                    warn!(
+                        "dropping in-flight data for input {} of {}-{}, which only has {} inputs",
+                        input,
+                        self.task_info.operator_id,
+                        task_index,
+                        self.input_partitions.len()
+                    );
+                    return None;
+                };
+
+                // in-flight data is only ever remapped between partitions of the same input
+                let idx = self.input_partitions[..input].iter().sum::<usize>()
+                    + r.batch.input_index * partitions / r.batch.input_partitions.max(1);
+
+                if r.parallelism == parallelism {
+                    return (r.subtask_index == task_index).then_some((idx, r.batch.batch));
+                }
+
+                let keys = self
+                    .in_schemas
+                    .get(input)
+                    .and_then(|schema| schema.key_indices.as_ref());
+
+                match keys {
+                    Some(keys) => {
+                        let batch = rows_for_subtask(&r.batch.batch, keys, task_index, parallelism);
+                        (batch.num_rows() > 0).then_some((idx, batch))
+                    }
+                    None => (r.subtask_index % parallelism == task_index)
+                        .then_some((idx, r.batch.batch)),
+                }
+            })
+            .collect()
+    }
+
     pub async fn load_compacted(&mut self, compaction: CompactionResult) {
         //TODO: support compaction in the table manager
         self.table_manager
@@ -653,6 +891,7 @@ impl ArrowContext {
         format: Format,
         framing: Option<Framing>,
         bad_data: Option<BadData>,
+        metadata_fields: &[MetadataField],
     ) {
         if self.deserializer.is_some() {
             panic!("Deserialize already initialized");
@@ -661,6 +900,7 @@ impl ArrowContext {
         self.deserializer = Some(ArrowDeserializer::new(
             format,
             self.out_schema.as_ref().expect("no out schema").clone(),
+            metadata_fields,
             framing,
             bad_data.unwrap_or_default(),
         ));
@@ -671,12 +911,14 @@ impl ArrowContext {
         format: Format,
         framing: Option<Framing>,
         bad_data: Option<BadData>,
+        metadata_fields: &[MetadataField],
         schema_resolver: Arc<dyn SchemaResolver + Sync>,
     ) {
         self.deserializer = Some(ArrowDeserializer::with_schema_resolver(
             format,
             framing,
             self.out_schema.as_ref().expect("no out schema").clone(),
+            metadata_fields,
             bad_data.unwrap_or_default(),
             schema_resolver,
         ));
@@ -686,6 +928,7 @@ impl ArrowContext {
         &mut self,
         msg: &[u8],
         time: SystemTime,
+        additional_fields: Option<&HashMap<&str, FieldValueType<'_>>>,
     ) -> Result<(), UserError> {
         let deserializer = self
             .deserializer
@@ -696,6 +939,7 @@ impl ArrowContext {
                 &mut self.buffer.as_mut().expect("no out schema").buffer,
                 msg,
                 time,
+                additional_fields,
             )
             .await;
         self.collect_source_errors(errors).await?;
@@ -750,7 +994,11 @@ mod tests {
     use arrow::array::{ArrayRef, Int64Array, TimestampNanosecondArray, UInt64Array};
     use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
     use arroyo_types::to_nanos;
+    use std::collections::HashSet;
     use std::time::Duration;
+    use tokio::sync::oneshot;
+
+    use crate::CheckpointCounter;
 
     use super::*;
 
@@ -850,6 +1098,7 @@ mod tests {
             tx_queue_rem_gauges,
             tx_queue_size_gauges,
             tx_queue_bytes_gauges,
+            source_lag_gauge: None,
         };
 
         collector.collect(record).await;
@@ -897,4 +1146,258 @@ mod tests {
 
         assert_eq!(tx.capacity(), 8);
     }
+
+    #[test]
+    fn test_rows_for_subtask_matches_repartition() {
+        let record = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new(
+                "key",
+                DataType::UInt64,
+                false,
+            )])),
+            vec![Arc::new(UInt64Array::from((0..100).collect::<Vec<u64>>()))],
+        )
+        .unwrap();
+        let keys = Some(vec![0]);
+
+        let values = |batch: &RecordBatch| {
+            let mut values: Vec<u64> = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .unwrap()
+                .values()
+                .to_vec();
+            values.sort();
+            values
+        };
+
+        for (server, batch) in repartition(&record, &keys, 3) {
+            assert_eq!(
+                values(&batch),
+                values(&rows_for_subtask(&record, &[0], server, 3))
+            );
+        }
+
+        let total: usize = (0..3)
+            .map(|i| rows_for_subtask(&record, &[0], i, 3).num_rows())
+            .sum();
+        assert_eq!(total, 100);
+    }
+
+    fn int_batch(values: &[i64]) -> RecordBatch {
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("x", DataType::Int64, false)])),
+            vec![Arc::new(Int64Array::from(values.to_vec()))],
+        )
+        .unwrap()
+    }
+
+    fn int_values(batch: &RecordBatch) -> Vec<i64> {
+        batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap()
+            .values()
+            .to_vec()
+    }
+
+    fn unaligned_barrier() -> ArrowMessage {
+        ArrowMessage::Signal(SignalMessage::Barrier(CheckpointBarrier {
+            epoch: 1,
+            min_epoch: 0,
+            timestamp: SystemTime::now(),
+            then_stop: false,
+            drain: false,
+            unaligned: true,
+        }))
+    }
+
+    async fn recv_data(rx: &mut BatchReceiver) -> RecordBatch {
+        match rx.recv_prioritized().await.unwrap() {
+            (ArrowMessage::Data(batch), 0) => batch,
+            (item, overtaken) => panic!("expected data, got {:?} ({})", item, overtaken),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unaligned_barrier_overtakes_queued_data() {
+        let (tx, mut rx) = batch_bounded(8);
+
+        tx.send(ArrowMessage::Data(int_batch(&[1, 2, 3, 4])))
+            .await
+            .unwrap();
+        tx.send(ArrowMessage::Data(int_batch(&[5, 6, 7, 8])))
+            .await
+            .unwrap();
+        assert_eq!(tx.capacity(), 0);
+
+        // the queue is full, but unaligned barriers aren't subject to backpressure
+        tx.send(unaligned_barrier()).await.unwrap();
+
+        let (item, overtaken) = rx.recv_prioritized().await.unwrap();
+        assert!(
+            matches!(item, ArrowMessage::Signal(SignalMessage::Barrier(barrier)) if barrier.unaligned)
+        );
+        assert_eq!(overtaken, 2);
+
+        assert_eq!(int_values(&recv_data(&mut rx).await), vec![1, 2, 3, 4]);
+        assert_eq!(int_values(&recv_data(&mut rx).await), vec![5, 6, 7, 8]);
+
+        // in-order receivers still see the barrier in its place
+        tx.send(ArrowMessage::Data(int_batch(&[9]))).await.unwrap();
+        tx.send(unaligned_barrier()).await.unwrap();
+        tx.send(ArrowMessage::Data(int_batch(&[10]))).await.unwrap();
+
+        assert!(matches!(rx.recv().await, Some(ArrowMessage::Data(_))));
+        assert!(matches!(
+            rx.recv().await,
+            Some(ArrowMessage::Signal(SignalMessage::Barrier(_)))
+        ));
+        assert!(matches!(rx.recv().await, Some(ArrowMessage::Data(_))));
+    }
+
+    #[tokio::test]
+    async fn test_barrier_overtaking_data_in_flight() {
+        let (tx, mut rx) = batch_bounded(8);
+
+        // a barrier forwarded from another queue ahead of data that hasn't arrived here yet
+        tx.send_overtaking(unaligned_barrier(), 1).unwrap();
+        tx.send(ArrowMessage::Data(int_batch(&[1]))).await.unwrap();
+        tx.send(ArrowMessage::Data(int_batch(&[2]))).await.unwrap();
+
+        let (item, overtaken) = rx.recv_prioritized().await.unwrap();
+        assert!(matches!(
+            item,
+            ArrowMessage::Signal(SignalMessage::Barrier(_))
+        ));
+        assert_eq!(overtaken, 1);
+        assert_eq!(int_values(&recv_data(&mut rx).await), vec![1]);
+        assert_eq!(int_values(&recv_data(&mut rx).await), vec![2]);
+
+        // in-order receivers get it once the data it overtook has been received
+        tx.send_overtaking(unaligned_barrier(), 1).unwrap();
+        tx.send(ArrowMessage::Data(int_batch(&[3]))).await.unwrap();
+        tx.send(ArrowMessage::Data(int_batch(&[4]))).await.unwrap();
+
+        assert!(matches!(rx.recv().await, Some(ArrowMessage::Data(_))));
+        assert!(matches!(
+            rx.recv().await,
+            Some(ArrowMessage::Signal(SignalMessage::Barrier(_)))
+        ));
+        assert!(matches!(rx.recv().await, Some(ArrowMessage::Data(_))));
+    }
+
+    #[tokio::test]
+    async fn test_unaligned_checkpoint_replay() {
+        let (tx0, mut rx0) = batch_bounded(1024);
+        let (tx1, mut rx1) = batch_bounded(1024);
+        let mut counter = CheckpointCounter::new(vec![1, 1]);
+
+        // every row the operator processes is added to its state
+        let mut state: Vec<i64> = vec![];
+
+        tx0.send(ArrowMessage::Data(int_batch(&[0, 1])))
+            .await
+            .unwrap();
+        let batch = recv_data(&mut rx0).await;
+        counter.record_in_flight(0, &batch);
+        state.extend(int_values(&batch));
+
+        for item in [
+            ArrowMessage::Data(int_batch(&[2, 3])),
+            ArrowMessage::Data(int_batch(&[4, 5])),
+            unaligned_barrier(),
+            ArrowMessage::Data(int_batch(&[6, 7])),
+        ] {
+            tx0.send(item).await.unwrap();
+        }
+        tx1.send(ArrowMessage::Data(int_batch(&[100, 101])))
+            .await
+            .unwrap();
+
+        // the barrier skips ahead of the two batches queued before it, and the state is snapshotted
+        let (barrier, overtaken) = rx0.recv_prioritized().await.unwrap();
+        let ArrowMessage::Signal(SignalMessage::Barrier(barrier)) = barrier else {
+            panic!("expected barrier, got {:?}", barrier);
+        };
+        assert_eq!(overtaken, 2);
+        assert!(counter.mark_unaligned(0, &barrier, overtaken));
+        let snapshot = state.clone();
+        let (in_flight_tx, mut in_flight_rx) = oneshot::channel();
+        counter.start_unaligned(0, &barrier, overtaken, &HashSet::new(), in_flight_tx);
+
+        for _ in 0..3 {
+            let batch = recv_data(&mut rx0).await;
+            counter.record_in_flight(0, &batch);
+            state.extend(int_values(&batch));
+        }
+
+        // the other input's barrier hasn't been sent yet, so its data is in flight
+        let batch = recv_data(&mut rx1).await;
+        counter.record_in_flight(1, &batch);
+        state.extend(int_values(&batch));
+
+        for item in [
+            ArrowMessage::Data(int_batch(&[102, 103])),
+            unaligned_barrier(),
+            ArrowMessage::Data(int_batch(&[104, 105])),
+        ] {
+            tx1.send(item).await.unwrap();
+        }
+
+        let (barrier, overtaken) = rx1.recv_prioritized().await.unwrap();
+        let ArrowMessage::Signal(SignalMessage::Barrier(barrier)) = barrier else {
+            panic!("expected barrier, got {:?}", barrier);
+        };
+        assert_eq!(overtaken, 1);
+        assert!(!counter.mark_unaligned(1, &barrier, overtaken));
+        assert!(in_flight_rx.try_recv().is_err());
+
+        for _ in 0..2 {
+            let batch = recv_data(&mut rx1).await;
+            counter.record_in_flight(1, &batch);
+            state.extend(int_values(&batch));
+        }
+
+        let in_flight = in_flight_rx.await.unwrap();
+        assert_eq!(
+            in_flight
+                .iter()
+                .map(|b| (b.logical_input, b.input_index, int_values(&b.batch)))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, 0, vec![2, 3]),
+                (0, 0, vec![4, 5]),
+                (1, 0, vec![100, 101]),
+                (1, 0, vec![102, 103]),
+            ]
+        );
+
+        // restoring replays the in-flight data on top of the snapshot, and the sources resume with
+        // the data sent after the barriers; every row must be processed exactly once
+        let mut restored = snapshot;
+        for batch in &in_flight {
+            restored.extend(int_values(&batch.batch));
+        }
+        restored.extend([6, 7, 104, 105]);
+        restored.sort();
+        state.sort();
+
+        assert_eq!(restored, state);
+        assert_eq!(
+            restored,
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 100, 101, 102, 103, 104, 105]
+        );
+    }
+
+    #[test]
+    fn test_logical_input() {
+        let input_partitions = [2, 3];
+        assert_eq!(logical_input(&input_partitions, 0), (0, 0));
+        assert_eq!(logical_input(&input_partitions, 1), (0, 1));
+        assert_eq!(logical_input(&input_partitions, 2), (1, 0));
+        assert_eq!(logical_input(&input_partitions, 4), (1, 2));
+    }
 }
@@ -3,14 +3,19 @@ use std::future::Future;
 use std::ops::Sub;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use std::{collections::HashSet, time::SystemTime};
+use std::{
+    collections::{HashMap, HashSet},
+    time::SystemTime,
+};
 
 use crate::inq_reader::InQReader;
 use arrow::array::types::{TimestampNanosecondType, UInt64Type};
 use arrow::array::{Array, PrimitiveArray, RecordBatch, UInt64Array};
 use arrow::compute::kernels::numeric::{div, rem};
+use arroyo_state::tables::in_flight::InFlightBatch;
 use arroyo_types::{ArrowMessage, CheckpointBarrier, Data, SignalMessage, TaskInfoRef};
 use bincode::{Decode, Encode};
+use tokio::sync::oneshot;
 
 use crate::context::ArrowContext;
 use crate::operator::Registry;
@@ -40,6 +45,22 @@ pub fn server_for_hash_array(
     Ok(result.clone())
 }
 
+/// Maps the index of one of an operator's input queues to the logical input (edge) it belongs to
+/// and the partition within that input, given the number of partitions of each logical input
+pub fn logical_input(input_partitions: &[usize], idx: usize) -> (usize, usize) {
+    let mut partition = idx;
+    for (input, partitions) in input_partitions.iter().enumerate() {
+        if partition < *partitions {
+            return (input, partition);
+        }
+        partition -= partitions;
+    }
+    panic!(
+        "input queue {} is out of range for inputs {:?}",
+        idx, input_partitions
+    );
+}
+
 pub enum SourceFinishType {
     // stop messages should be propagated through the dataflow
     Graceful,
@@ -66,17 +87,42 @@ pub enum ControlOutcome {
     Finish,
 }
 
+// An unaligned checkpoint whose state has already been snapshotted, but which is still waiting for
+// the barrier on some of the inputs; data received on those inputs in the meantime is recorded so
+// that it can be persisted along with the checkpoint. Barriers skip ahead of queued data, so the
+// data they overtook is recorded as well.
+#[derive(Debug)]
+struct PendingUnalignedCheckpoint {
+    epoch: u32,
+    pending_inputs: HashSet<usize>,
+    overtaken: HashMap<usize, usize>,
+    in_flight: Vec<InFlightBatch>,
+    tx: oneshot::Sender<Vec<InFlightBatch>>,
+}
+
+impl PendingUnalignedCheckpoint {
+    fn is_complete(&self) -> bool {
+        self.pending_inputs.is_empty() && self.overtaken.is_empty()
+    }
+}
+
 #[derive(Debug)]
 pub struct CheckpointCounter {
     inputs: Vec<Option<u32>>,
+    input_partitions: Vec<usize>,
     counter: Option<usize>,
+    unaligned: Vec<PendingUnalignedCheckpoint>,
 }
 
 impl CheckpointCounter {
-    pub fn new(size: usize) -> CheckpointCounter {
+    /// Creates a counter for an operator with the given number of partitions for each of its
+    /// logical inputs
+    pub fn new(input_partitions: Vec<usize>) -> CheckpointCounter {
         CheckpointCounter {
-            inputs: vec![None; size],
+            inputs: vec![None; input_partitions.iter().sum()],
+            input_partitions,
             counter: None,
+            unaligned: vec![],
         }
     }
 
@@ -109,6 +155,113 @@ impl CheckpointCounter {
 
         self.counter.is_none()
     }
+
+    /// Whether the barrier should be handled without alignment
+    pub fn is_unaligned(&self, checkpoint: &CheckpointBarrier) -> bool {
+        checkpoint.unaligned
+    }
+
+    /// Records an unaligned barrier received on input `idx`, which overtook `overtaken` data
+    /// messages in its queue. Returns true if this is the first barrier for its epoch, in which
+    /// case the caller should snapshot its state and then call `start_unaligned`.
+    pub fn mark_unaligned(
+        &mut self,
+        idx: usize,
+        checkpoint: &CheckpointBarrier,
+        overtaken: usize,
+    ) -> bool {
+        let Some(pending) = self
+            .unaligned
+            .iter_mut()
+            .find(|p| p.epoch == checkpoint.epoch)
+        else {
+            return true;
+        };
+
+        pending.pending_inputs.remove(&idx);
+        if overtaken > 0 {
+            pending.overtaken.insert(idx, overtaken);
+        }
+        self.complete_unaligned();
+        false
+    }
+
+    /// Starts tracking the in-flight data for an unaligned checkpoint whose first barrier arrived
+    /// on input `idx`, having overtaken `overtaken` data messages; the data is sent on `tx` once
+    /// every other open input has delivered the barrier and all overtaken data has been received
+    pub fn start_unaligned(
+        &mut self,
+        idx: usize,
+        checkpoint: &CheckpointBarrier,
+        overtaken: usize,
+        closed: &HashSet<usize>,
+        tx: oneshot::Sender<Vec<InFlightBatch>>,
+    ) {
+        self.unaligned.push(PendingUnalignedCheckpoint {
+            epoch: checkpoint.epoch,
+            pending_inputs: (0..self.inputs.len())
+                .filter(|i| *i != idx && !closed.contains(i))
+                .collect(),
+            overtaken: if overtaken > 0 {
+                HashMap::from([(idx, overtaken)])
+            } else {
+                HashMap::new()
+            },
+            in_flight: vec![],
+            tx,
+        });
+        self.complete_unaligned();
+    }
+
+    /// Records a batch received on input `idx` as in flight for every unaligned checkpoint that
+    /// is still waiting for a barrier on that input, or whose barrier overtook it
+    pub fn record_in_flight(&mut self, idx: usize, batch: &RecordBatch) {
+        let (input, partition) = logical_input(&self.input_partitions, idx);
+        for pending in &mut self.unaligned {
+            let in_flight = if pending.pending_inputs.contains(&idx) {
+                true
+            } else if let Some(remaining) = pending.overtaken.get_mut(&idx) {
+                *remaining -= 1;
+                if *remaining == 0 {
+                    pending.overtaken.remove(&idx);
+                }
+                true
+            } else {
+                false
+            };
+
+            if in_flight {
+                pending.in_flight.push(InFlightBatch {
+                    logical_input: input,
+                    input_index: partition,
+                    input_partitions: self.input_partitions[input],
+                    batch: batch.clone(),
+                });
+            }
+        }
+        self.complete_unaligned();
+    }
+
+    /// A closed input will never deliver the barriers that unaligned checkpoints are waiting on
+    pub fn close_input(&mut self, idx: usize) {
+        for pending in &mut self.unaligned {
+            pending.pending_inputs.remove(&idx);
+            pending.overtaken.remove(&idx);
+        }
+        self.complete_unaligned();
+    }
+
+    fn complete_unaligned(&mut self) {
+        let (complete, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.unaligned)
+            .into_iter()
+            .partition(|p| p.is_complete());
+        self.unaligned = pending;
+
+        for checkpoint in complete {
+            // the receiver is only dropped if the state backend has already failed
+            let _ = checkpoint.tx.send(checkpoint.in_flight);
+        }
+    }
 }
 
 #[allow(unused)]
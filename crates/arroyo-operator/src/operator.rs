@@ -9,8 +9,9 @@ use arroyo_datastream::logical::DylibUdfConfig;
 use arroyo_metrics::TaskCounters;
 use arroyo_rpc::grpc::{TableConfig, TaskCheckpointEventType};
 use arroyo_rpc::{ControlMessage, ControlResp};
+use arroyo_state::tables::in_flight::InFlightBatch;
 use arroyo_storage::StorageProvider;
-use arroyo_types::{ArrowMessage, CheckpointBarrier, SignalMessage, Watermark};
+use arroyo_types::{from_nanos, ArrowMessage, CheckpointBarrier, SignalMessage, Watermark};
 use arroyo_udf_host::parse::inner_type;
 use arroyo_udf_host::{ContainerOrLocal, LocalUdf, SyncUdfDylib, UdfDylib, UdfInterface};
 use async_trait::async_trait;
@@ -28,7 +29,7 @@ use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
-use tokio::sync::Barrier;
+use tokio::sync::{oneshot, Barrier};
 use tokio_stream::StreamExt;
 use tracing::{debug, error, info, warn, Instrument};
 
@@ -139,6 +140,14 @@ async fn run_checkpoint(checkpoint_barrier: CheckpointBarrier, ctx: &mut ArrowCo
     ctx.send_checkpoint_event(checkpoint_barrier, TaskCheckpointEventType::FinishedSync)
         .await;
 
+    if checkpoint_barrier.drain {
+        // flush everything downstream before the final checkpoint is taken
+        ctx.broadcast(ArrowMessage::Signal(SignalMessage::Watermark(
+            Watermark::EventTime(from_nanos(u64::MAX as u128)),
+        )))
+        .await;
+    }
+
     ctx.broadcast(ArrowMessage::Signal(SignalMessage::Barrier(
         checkpoint_barrier,
     )))
@@ -147,6 +156,31 @@ async fn run_checkpoint(checkpoint_barrier: CheckpointBarrier, ctx: &mut ArrowCo
     checkpoint_barrier.then_stop
 }
 
+// snapshots the operator's state as soon as the first barrier arrives and forwards the barrier,
+// rather than waiting for it on the other inputs; the returned sender completes the checkpoint
+// with the data that arrives on those inputs before their barriers do
+async fn run_unaligned_checkpoint(
+    checkpoint_barrier: CheckpointBarrier,
+    ctx: &mut ArrowContext,
+) -> oneshot::Sender<Vec<InFlightBatch>> {
+    let watermark = ctx.watermarks.last_present_watermark();
+
+    let in_flight = ctx
+        .table_manager
+        .checkpoint_unaligned(checkpoint_barrier, watermark)
+        .await;
+
+    ctx.send_checkpoint_event(checkpoint_barrier, TaskCheckpointEventType::FinishedSync)
+        .await;
+
+    ctx.broadcast(ArrowMessage::Signal(SignalMessage::Barrier(
+        checkpoint_barrier,
+    )))
+    .await;
+
+    in_flight
+}
+
 #[async_trait]
 pub trait SourceOperator: Send + 'static {
     fn name(&self) -> String;
@@ -194,19 +228,27 @@ async fn operator_run_behavior(
 
     let task_info = ctx.task_info.clone();
     let name = this.name();
-    let mut counter = CheckpointCounter::new(in_qs.len());
+    let mut counter = CheckpointCounter::new(ctx.input_partitions.clone());
     let mut closed: HashSet<usize> = HashSet::new();
     let mut sel = InQReader::new();
     let in_partitions = in_qs.len();
 
     for (i, q) in in_qs.iter_mut().enumerate() {
         let stream = async_stream::stream! {
-          while let Some(item) = q.recv().await {
-            yield(i,item);
+          while let Some((item, overtaken)) = q.recv_prioritized().await {
+            yield(i,item,overtaken);
           }
         };
         sel.push(Box::pin(stream));
     }
+
+    // data that was in flight when the unaligned checkpoint we're restoring from was taken must be
+    // processed before anything new
+    for (idx, batch) in ctx.restore_in_flight().await {
+        this.process_batch_index(idx, in_partitions, batch, ctx)
+            .await;
+    }
+
     let mut blocked = vec![];
     let mut final_message = None;
 
@@ -224,7 +266,7 @@ async fn operator_run_behavior(
 
             p = sel.next() => {
                 match p {
-                    Some(((idx, message), s)) => {
+                    Some(((idx, message, overtaken), s)) => {
                         let local_idx = idx;
 
                         debug!("[{}] Handling message {}-{}, {:?}",
@@ -235,6 +277,7 @@ async fn operator_run_behavior(
                                 TaskCounters::BatchesReceived.for_task(&ctx.task_info, |c| c.inc());
                                 TaskCounters::MessagesReceived.for_task(&ctx.task_info, |c| c.inc_by(record.num_rows() as u64));
                                 TaskCounters::BytesReceived.for_task(&ctx.task_info, |c| c.inc_by(record.get_array_memory_size() as u64));
+                                counter.record_in_flight(idx, &record);
                                 this.process_batch_index(idx, in_partitions, record, ctx)
                                     .instrument(tracing::trace_span!("handle_fn",
                                         name,
@@ -243,7 +286,7 @@ async fn operator_run_behavior(
                                 ).await;
                             }
                             ArrowMessage::Signal(signal) => {
-                                match this.handle_control_message(idx, &signal, &mut counter, &mut closed, in_partitions, ctx).await {
+                                match this.handle_control_message(idx, &signal, overtaken, &mut counter, &mut closed, in_partitions, ctx).await {
                                     ControlOutcome::Continue => {}
                                     ControlOutcome::Stop => {
                                         // just stop; the stop will have already been broadcast for example by
@@ -339,10 +382,12 @@ pub trait ArrowOperator: Send + 'static {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn handle_control_message(
         &mut self,
         idx: usize,
         message: &SignalMessage,
+        overtaken: usize,
         counter: &mut CheckpointCounter,
         closed: &mut HashSet<usize>,
         in_partitions: usize,
@@ -358,6 +403,35 @@ pub trait ArrowOperator: Send + 'static {
                     idx
                 );
 
+                if counter.is_unaligned(t) {
+                    if counter.mark_unaligned(idx, t, overtaken) {
+                        debug!(
+                            "Checkpointing {}-{}-{} without alignment",
+                            self.name(),
+                            ctx.task_info.operator_id,
+                            ctx.task_info.task_index
+                        );
+
+                        ctx.send_checkpoint_event(
+                            *t,
+                            TaskCheckpointEventType::StartedCheckpointing,
+                        )
+                        .await;
+
+                        self.handle_checkpoint(*t, ctx).await;
+
+                        ctx.send_checkpoint_event(
+                            *t,
+                            TaskCheckpointEventType::FinishedOperatorSetup,
+                        )
+                        .await;
+
+                        let in_flight = run_unaligned_checkpoint(*t, ctx).await;
+                        counter.start_unaligned(idx, t, overtaken, closed, in_flight);
+                    }
+                    return ControlOutcome::Continue;
+                }
+
                 if counter.all_clear() {
                     ctx.control_tx
                         .send(ControlResp::CheckpointEvent(arroyo_rpc::CheckpointEvent {
@@ -415,12 +489,14 @@ pub trait ArrowOperator: Send + 'static {
             }
             SignalMessage::Stop => {
                 closed.insert(idx);
+                counter.close_input(idx);
                 if closed.len() == in_partitions {
                     return ControlOutcome::StopAndSendStop;
                 }
             }
             SignalMessage::EndOfData => {
                 closed.insert(idx);
+                counter.close_input(idx);
                 if closed.len() == in_partitions {
                     return ControlOutcome::Finish;
                 }
@@ -221,6 +221,15 @@ pub enum SchemaDefinition {
     RawSchema(String),
 }
 
+/// A field whose value is populated by the connector from message metadata (like a Kafka offset)
+/// rather than deserialized from the payload
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataField {
+    pub field_name: String,
+    pub key: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ConnectionSchema {
@@ -231,6 +240,8 @@ pub struct ConnectionSchema {
     pub fields: Vec<SourceField>,
     pub definition: Option<SchemaDefinition>,
     pub inferred: Option<bool>,
+    #[serde(default)]
+    pub metadata_fields: Vec<MetadataField>,
 }
 
 impl ConnectionSchema {
@@ -242,6 +253,7 @@ impl ConnectionSchema {
         fields: Vec<SourceField>,
         definition: Option<SchemaDefinition>,
         inferred: Option<bool>,
+        metadata_fields: Vec<MetadataField>,
     ) -> anyhow::Result<Self> {
         let s = ConnectionSchema {
             format,
@@ -251,6 +263,7 @@ impl ConnectionSchema {
             fields,
             definition,
             inferred,
+            metadata_fields,
         };
 
         s.validate()
@@ -259,10 +272,21 @@ impl ConnectionSchema {
     pub fn validate(self) -> anyhow::Result<Self> {
         match &self.format {
             Some(Format::RawString(_)) => {
-                if self.fields.len() != 1
-                    || self.fields.first().unwrap().field_type.r#type
+                let payload_fields: Vec<_> = self
+                    .fields
+                    .iter()
+                    .filter(|f| {
+                        !self
+                            .metadata_fields
+                            .iter()
+                            .any(|m| m.field_name == f.field_name)
+                    })
+                    .collect();
+
+                if payload_fields.len() != 1
+                    || payload_fields[0].field_type.r#type
                         != FieldType::Primitive(PrimitiveType::String)
-                    || self.fields.first().unwrap().field_name != "value"
+                    || payload_fields[0].field_name != "value"
                 {
                     bail!("raw_string format requires a schema with a single field called `value` of type TEXT");
                 }
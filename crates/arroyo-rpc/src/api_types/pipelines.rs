@@ -1,6 +1,7 @@
 use crate::api_types::udfs::Udf;
 use crate::grpc as grpc_proto;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use utoipa::ToSchema;
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -31,8 +32,97 @@ pub struct PipelinePost {
 #[serde(rename_all = "camelCase")]
 pub struct PipelinePatch {
     pub parallelism: Option<u64>,
+    /// Parallelism for individual operators, by node id; takes precedence over `parallelism`.
+    /// Setting an operator also sets the operators connected to it by forward edges.
+    pub operator_parallelism: Option<HashMap<String, u64>>,
     pub checkpoint_interval_micros: Option<u64>,
     pub stop: Option<StopType>,
+    pub query: Option<String>,
+    pub udfs: Option<Vec<Udf>>,
+    pub dry_run: Option<bool>,
+    pub autoscaling: Option<AutoscalingConfig>,
+    pub restart_strategy: Option<RestartStrategy>,
+    pub checkpoint_config: Option<CheckpointConfig>,
+}
+
+/// Configures the autoscaler, which adjusts the parallelism of each operator based on
+/// backpressure, queue utilization and source lag
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoscalingConfig {
+    pub enabled: bool,
+    /// The smallest parallelism the autoscaler will scale an operator down to; defaults to 1
+    pub min_parallelism: Option<u64>,
+    /// The largest parallelism the autoscaler will scale an operator up to; defaults to the
+    /// maximum parallelism allowed for the organization
+    pub max_parallelism: Option<u64>,
+    /// Minimum time after the job starts or is rescaled before the autoscaler will rescale it
+    pub cooldown_micros: Option<u64>,
+}
+
+/// Controls how the job is restarted after a failure, and when it gives up and moves to Failed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RestartStrategy {
+    pub backoff: RestartBackoff,
+    /// The job fails permanently after this many consecutive restarts; the count is reset once
+    /// the job has been running healthily for a few minutes
+    pub max_restarts: Option<u64>,
+    /// The job fails permanently if it fails more than `maxFailures` times within `intervalMicros`
+    pub failure_rate: Option<FailureRate>,
+}
+
+impl Default for RestartStrategy {
+    fn default() -> Self {
+        Self {
+            backoff: RestartBackoff::Fixed { delay_micros: 0 },
+            max_restarts: Some(10),
+            failure_rate: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum RestartBackoff {
+    /// Waits the same amount of time before every restart
+    #[serde(rename_all = "camelCase")]
+    Fixed { delay_micros: u64 },
+    /// Doubles the wait after each consecutive restart, starting from `initialDelayMicros` and
+    /// capped at `maxDelayMicros`
+    #[serde(rename_all = "camelCase")]
+    Exponential {
+        initial_delay_micros: u64,
+        max_delay_micros: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FailureRate {
+    pub max_failures: u64,
+    pub interval_micros: u64,
+}
+
+/// Controls how checkpoints are taken and how many are kept; checkpoints are started every
+/// `checkpointIntervalMicros`, subject to these limits
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckpointConfig {
+    /// A checkpoint that hasn't completed after this long is aborted and counted as a job
+    /// failure; by default checkpoints never time out
+    pub timeout_micros: Option<u64>,
+    /// The minimum time between the end of one checkpoint and the start of the next; defaults to 0
+    pub min_pause_micros: Option<u64>,
+    /// How many checkpoints may be in progress at once; defaults to 1. Pipelines with sinks that
+    /// commit in two phases (like exactly-once Kafka sinks) always run one checkpoint at a time
+    pub max_concurrent_checkpoints: Option<u32>,
+    /// How many completed checkpoints are retained in storage; defaults to 4
+    pub retained_checkpoints: Option<u32>,
+    /// If set, checkpoint barriers skip ahead of queued data and operators don't wait for them
+    /// to align across inputs, instead persisting the data that is still in flight; this keeps
+    /// checkpoint times low under backpressure at the cost of larger checkpoints. Defaults to false
+    pub unaligned: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -56,6 +146,41 @@ pub struct Pipeline {
     pub action_in_progress: bool,
     pub graph: PipelineGraph,
     pub preview: bool,
+    pub upgrade: Option<PipelineUpgrade>,
+    pub autoscaling: Option<AutoscalingConfig>,
+    pub restart_strategy: Option<RestartStrategy>,
+    pub checkpoint_config: Option<CheckpointConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineUpgrade {
+    pub dry_run: bool,
+    pub graph: PipelineGraph,
+    pub operators: Vec<OperatorUpgrade>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorUpgrade {
+    pub node_id: String,
+    pub previous_node_id: Option<String>,
+    pub description: String,
+    pub state: OperatorUpgradeState,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum OperatorUpgradeState {
+    /// The operator will be restored from the state of the matching operator in the old pipeline
+    Restored,
+    /// The operator matches an operator in the old pipeline but has changed, so it will start
+    /// without state
+    Reset,
+    /// The operator is new and will start without state
+    New,
+    /// The operator has been removed from the pipeline, and its state will be dropped
+    Dropped,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -89,6 +214,8 @@ pub struct PipelineEdge {
 pub enum StopType {
     None,
     Checkpoint,
+    /// Flushes all windows and pending joins into the sinks, then finishes the job
+    Drain,
     Graceful,
     Immediate,
     Force,
@@ -105,6 +232,8 @@ pub struct Job {
     pub finish_time: Option<u64>,
     pub tasks: Option<u64>,
     pub failure_message: Option<String>,
+    /// When the job is waiting to be restarted after a failure, the time of the next attempt
+    pub next_retry_time: Option<u64>,
     pub created_at: u64,
 }
 
@@ -8,7 +8,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::{fs, time::SystemTime};
 
-use crate::api_types::connections::PrimitiveType;
+use crate::api_types::connections::{MetadataField, PrimitiveType};
 use crate::formats::{BadData, Format, Framing};
 use crate::grpc::{LoadCompactedDataReq, SubtaskCheckpointMetadata};
 use anyhow::Result;
@@ -173,6 +173,8 @@ pub struct OperatorConfig {
     pub bad_data: Option<BadData>,
     pub framing: Option<Framing>,
     pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub metadata_fields: Vec<MetadataField>,
 }
 
 impl Default for OperatorConfig {
@@ -184,6 +186,7 @@ impl Default for OperatorConfig {
             bad_data: None,
             framing: None,
             rate_limit: None,
+            metadata_fields: vec![],
         }
     }
 }
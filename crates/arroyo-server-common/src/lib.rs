@@ -19,6 +19,7 @@ use pyroscope_pprofrs::{pprof_backend, PprofConfig};
 use reqwest::Client;
 use serde_json::{json, Value};
 use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use tonic::body::BoxBody;
@@ -47,6 +48,16 @@ const PYROSCOPE_SERVER_ADDRESS_ENV: &str = "PYROSCOPE_SERVER_ADDRESS";
 
 static CLUSTER_ID: OnceCell<String> = OnceCell::new();
 
+// reported by the admin server's `/ready` endpoint
+static READY: AtomicBool = AtomicBool::new(true);
+
+/// Sets whether this process is ready to receive traffic. Processes are ready by default, but
+/// services that only start serving once some condition is met (like a standby controller
+/// becoming the leader) can clear this until then.
+pub fn set_ready(ready: bool) {
+    READY.store(ready, Ordering::SeqCst);
+}
+
 pub fn init_logging(name: &str) -> Option<WorkerGuard> {
     if let Err(e) = LogTracer::init() {
         eprintln!("Failed to initialize log tracer {:?}", e);
@@ -161,6 +172,14 @@ async fn status<'a>() -> String {
     "ok".to_string()
 }
 
+async fn ready() -> (StatusCode, &'static str) {
+    if READY.load(Ordering::SeqCst) {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not ready")
+    }
+}
+
 async fn metrics() -> Result<Bytes, StatusCode> {
     let encoder = TextEncoder::new();
     let registry = prometheus::default_registry();
@@ -190,6 +209,7 @@ pub async fn start_admin_server(service: &str, default_port: u16) {
     });
     let app = Router::new()
         .route("/status", get(status))
+        .route("/ready", get(ready))
         .route("/name", get(root))
         .route("/metrics", get(metrics))
         .route("/details", get(details))
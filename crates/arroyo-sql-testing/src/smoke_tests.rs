@@ -15,7 +15,7 @@ use tokio::sync::mpsc::Receiver;
 
 use crate::udfs::get_udfs;
 use arroyo_rpc::grpc::{StopMode, TaskCheckpointCompletedReq, TaskCheckpointEventReq};
-use arroyo_rpc::{CompactionResult, ControlMessage, ControlResp};
+use arroyo_rpc::{ControlMessage, ControlResp};
 use arroyo_state::checkpoint_state::CheckpointState;
 use arroyo_types::{to_micros, CheckpointBarrier};
 use arroyo_udf_host::LocalUdf;
@@ -88,6 +88,12 @@ struct SmokeTestContext<'a> {
 }
 
 async fn checkpoint(ctx: &mut SmokeTestContext<'_>, epoch: u32) {
+    run_checkpoint(ctx, epoch, false).await;
+}
+
+// triggers a checkpoint (a final, draining one if `drain` is set) and waits for it to complete,
+// returning the number of tasks that finished in the meantime
+async fn run_checkpoint(ctx: &mut SmokeTestContext<'_>, epoch: u32, drain: bool) -> usize {
     let checkpoint_id = epoch as i64;
     let mut checkpoint_state = CheckpointState::new(
         ctx.job_id.clone(),
@@ -103,7 +109,9 @@ async fn checkpoint(ctx: &mut SmokeTestContext<'_>, epoch: u32) {
         epoch,
         min_epoch: 0,
         timestamp: SystemTime::now(),
-        then_stop: false,
+        then_stop: drain,
+        drain,
+        unaligned: false,
     };
 
     for source in ctx.engine.source_controls() {
@@ -113,6 +121,7 @@ async fn checkpoint(ctx: &mut SmokeTestContext<'_>, epoch: u32) {
             .unwrap();
     }
 
+    let mut finished = 0;
     while !checkpoint_state.done() {
         let c: ControlResp = ctx.control_rx.recv().await.unwrap();
 
@@ -141,6 +150,9 @@ async fn checkpoint(ctx: &mut SmokeTestContext<'_>, epoch: u32) {
                 };
                 checkpoint_state.checkpoint_finished(req).await.unwrap();
             }
+            ControlResp::TaskFinished { .. } => {
+                finished += 1;
+            }
             _ => {}
         }
     }
@@ -148,32 +160,59 @@ async fn checkpoint(ctx: &mut SmokeTestContext<'_>, epoch: u32) {
     checkpoint_state.save_state().await.unwrap();
 
     info!("Smoke test checkpoint completed");
+    finished
 }
 
-async fn compact(
-    job_id: String,
-    running_engine: &RunningEngine,
-    tasks_per_operator: HashMap<String, usize>,
+// checkpoints until the files that the workers started compacting in the background after
+// `epoch` have been replaced in a checkpoint, returning the epoch of that checkpoint
+async fn wait_for_compaction(
+    ctx: &mut SmokeTestContext<'_>,
     epoch: u32,
-) {
-    let operator_controls = running_engine.operator_controls();
-    for (operator, _) in tasks_per_operator {
-        if let Ok(compacted) =
-            ParquetBackend::compact_operator(job_id.clone(), operator.clone(), epoch).await
-        {
-            let operator_controls = operator_controls.get(&operator).unwrap();
-            for s in operator_controls {
-                s.send(ControlMessage::LoadCompacted {
-                    compacted: CompactionResult {
-                        operator_id: operator.to_string(),
-                        compacted_tables: compacted.clone(),
-                    },
-                })
+    checkpoint_interval: i32,
+) -> u32 {
+    let mut files_to_compact = HashSet::new();
+    for (operator, parallelism) in &ctx.tasks_per_operator {
+        files_to_compact.extend(
+            ParquetBackend::files_to_compact(&ctx.job_id, operator, epoch, *parallelism)
                 .await
-                .unwrap();
+                .unwrap(),
+        );
+    }
+
+    let mut compacted_files = HashSet::new();
+    for epoch in epoch + 1..epoch + 20 {
+        // give the background compactions a chance to finish
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        advance(ctx.engine, checkpoint_interval).await;
+        checkpoint(ctx, epoch).await;
+
+        let mut files = HashSet::new();
+        for operator in ctx.tasks_per_operator.keys() {
+            for file in ParquetBackend::expiring_table_files(&ctx.job_id, operator, epoch)
+                .await
+                .unwrap()
+            {
+                if file.generation > 0 {
+                    compacted_files.insert(file.file.clone());
+                }
+                files.insert(file.file);
+            }
+        }
+        if files.is_disjoint(&files_to_compact) {
+            if !files_to_compact.is_empty() {
+                assert!(
+                    !compacted_files.is_empty(),
+                    "files {:?} were removed without being compacted",
+                    files_to_compact
+                );
             }
+            return epoch;
         }
     }
+    panic!(
+        "background compaction of {:?} did not finish",
+        files_to_compact
+    );
 }
 
 async fn advance(engine: &RunningEngine, count: i32) {
@@ -196,6 +235,99 @@ async fn run_until_finished(engine: &RunningEngine, control_rx: &mut Receiver<Co
     }
 }
 
+#[test_log(tokio::test)]
+async fn test_drain() {
+    let job_id = "drain";
+    let output_location = format!(
+        "{}/arroyo-sql-testing/outputs/{}.json",
+        parent_directory(),
+        job_id
+    );
+    if Path::new(&output_location).exists() {
+        std::fs::remove_file(&output_location).unwrap();
+    }
+
+    // the source runs forever and the window is a day long, so its output can only be emitted
+    // by the drain
+    let query = format!(
+        "CREATE TABLE impulse WITH (
+            connector = 'impulse',
+            event_rate = '1000'
+        );
+        CREATE TABLE drain_output (
+            count BIGINT,
+            max_counter BIGINT
+        ) WITH (
+            connector = 'single_file',
+            path = '{}',
+            format = 'json',
+            type = 'sink'
+        );
+        INSERT INTO drain_output
+        SELECT count, max_counter FROM (
+            SELECT TUMBLE(INTERVAL '1' day) AS window, COUNT(*) AS count,
+                CAST(MAX(counter) AS BIGINT) AS max_counter
+            FROM impulse
+            GROUP BY 1
+        )",
+        output_location
+    );
+
+    let udfs = get_udfs();
+    let graph = get_graph(query, &udfs).await.unwrap().graph;
+    let program = Program::local_from_logical(job_id.to_string(), &graph, &udfs);
+    let tasks_per_operator = program.tasks_per_operator();
+    let tasks: usize = tasks_per_operator.values().sum();
+
+    let engine = Engine::for_local(program, job_id.to_string());
+    let (running_engine, mut control_rx) = engine
+        .start(StreamConfig {
+            restore_epoch: None,
+        })
+        .await;
+
+    let ctx = &mut SmokeTestContext {
+        job_id: job_id.to_string(),
+        engine: &running_engine,
+        control_rx: &mut control_rx,
+        tasks_per_operator,
+    };
+
+    // let the source fill the window for a bit
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut finished = run_checkpoint(ctx, 1, true).await;
+
+    // the window was flushed into the sink before the final checkpoint completed
+    let output: Vec<Value> = read_to_string(&output_location)
+        .await
+        .unwrap()
+        .lines()
+        .map(|s| serde_json::from_str(s).unwrap())
+        .collect();
+    assert!(!output.is_empty(), "drain did not flush the window");
+
+    // and it covers every record the source emitted before stopping
+    let count: i64 = output.iter().map(|v| v["count"].as_i64().unwrap()).sum();
+    let max_counter = output
+        .iter()
+        .map(|v| v["max_counter"].as_i64().unwrap())
+        .max()
+        .unwrap();
+    assert_eq!(count, max_counter + 1);
+
+    // then every task finishes
+    while finished < tasks {
+        match ctx.control_rx.recv().await {
+            Some(ControlResp::TaskFinished { .. }) => finished += 1,
+            Some(ControlResp::TaskFailed { error, .. }) => panic!("task failed: {}", error),
+            Some(_) => {}
+            None => break,
+        }
+    }
+    assert_eq!(finished, tasks);
+}
+
 fn set_internal_parallelism(graph: &mut Graph<LogicalNode, LogicalEdge>, parallelism: usize) {
     let watermark_nodes: HashSet<_> = graph
         .node_indices()
@@ -245,7 +377,8 @@ fn set_internal_parallelism(graph: &mut Graph<LogicalNode, LogicalEdge>, paralle
     }
 }
 
-async fn run_and_checkpoint(job_id: &str, program: Program, checkpoint_interval: i32) {
+// returns the epoch of the last checkpoint
+async fn run_and_checkpoint(job_id: &str, program: Program, checkpoint_interval: i32) -> u32 {
     let tasks_per_operator = program.tasks_per_operator();
     let engine = Engine::for_local(program, job_id.to_string());
     let (running_engine, mut control_rx) = engine
@@ -260,7 +393,7 @@ async fn run_and_checkpoint(job_id: &str, program: Program, checkpoint_interval:
         job_id: job_id.to_string(),
         engine: &running_engine,
         control_rx: &mut control_rx,
-        tasks_per_operator: tasks_per_operator.clone(),
+        tasks_per_operator,
     };
 
     // trigger a couple checkpoints
@@ -268,19 +401,11 @@ async fn run_and_checkpoint(job_id: &str, program: Program, checkpoint_interval:
     checkpoint(ctx, 1).await;
     advance(&running_engine, checkpoint_interval).await;
     checkpoint(ctx, 2).await;
-    advance(&running_engine, checkpoint_interval).await;
 
-    compact(
-        job_id.to_string(),
-        &running_engine,
-        tasks_per_operator.clone(),
-        2,
-    )
-    .await;
-
-    // trigger checkpoint 3, which will include the compacted files
-    advance(&running_engine, checkpoint_interval).await;
-    checkpoint(ctx, 3).await;
+    // keep checkpointing until a checkpoint includes the files compacted in the background by
+    // the workers after checkpoint 2; the engine is later restored from that checkpoint, which
+    // reads back the compacted files
+    let epoch = wait_for_compaction(ctx, 2, checkpoint_interval).await;
     // shut down the engine
     for source in running_engine.source_controls() {
         source
@@ -291,13 +416,14 @@ async fn run_and_checkpoint(job_id: &str, program: Program, checkpoint_interval:
             .unwrap();
     }
     run_until_finished(&running_engine, &mut control_rx).await;
+    epoch
 }
 
-async fn finish_from_checkpoint(job_id: &str, program: Program) {
+async fn finish_from_checkpoint(job_id: &str, program: Program, epoch: u32) {
     let engine = Engine::for_local(program, job_id.to_string());
     let (running_engine, mut control_rx) = engine
         .start(StreamConfig {
-            restore_epoch: Some(3),
+            restore_epoch: Some(epoch),
         })
         .await;
 
@@ -331,11 +457,11 @@ async fn run_pipeline_and_assert_outputs(
 
     set_internal_parallelism(&mut graph, 2);
 
-    run_and_checkpoint(job_id, get_program(&graph), checkpoint_interval).await;
+    let epoch = run_and_checkpoint(job_id, get_program(&graph), checkpoint_interval).await;
 
     set_internal_parallelism(&mut graph, 3);
 
-    finish_from_checkpoint(job_id, get_program(&graph)).await;
+    finish_from_checkpoint(job_id, get_program(&graph), epoch).await;
 
     check_output_files(
         "resuming from checkpointing",
@@ -499,12 +625,7 @@ async fn check_output_files(
         });
 }
 
-pub async fn correctness_run_codegen(
-    test_name: impl Into<String>,
-    query: impl Into<String>,
-    checkpoint_interval: i32,
-) -> Result<()> {
-    let test_name = test_name.into();
+fn parent_directory() -> String {
     let parent_directory = std::env::current_dir()
         .unwrap()
         .to_string_lossy()
@@ -512,14 +633,23 @@ pub async fn correctness_run_codegen(
 
     // Depending on run location the directory might end with arroyo-sql-testing.
     // If so, remove it.
-    let parent_directory = if parent_directory.ends_with("arroyo-sql-testing") {
+    if parent_directory.ends_with("arroyo-sql-testing") {
         parent_directory
             .strip_suffix("arroyo-sql-testing")
             .unwrap()
             .to_string()
     } else {
         parent_directory
-    };
+    }
+}
+
+pub async fn correctness_run_codegen(
+    test_name: impl Into<String>,
+    query: impl Into<String>,
+    checkpoint_interval: i32,
+) -> Result<()> {
+    let test_name = test_name.into();
+    let parent_directory = parent_directory();
 
     // replace $input_file with the current directory and then inputs/query_name.json
     let physical_input_dir = format!("{}/arroyo-sql-testing/inputs/", parent_directory,);
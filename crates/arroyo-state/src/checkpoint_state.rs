@@ -172,6 +172,10 @@ impl CheckpointState {
         self.checkpoint_id
     }
 
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
     pub fn start_time(&self) -> SystemTime {
         self.start_time
     }
@@ -16,6 +16,7 @@ use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::ops::RangeInclusive;
 use std::time::{Duration, SystemTime};
+use tables::in_flight::InFlightBatch;
 
 pub mod checkpoint_state;
 pub mod committing_state;
@@ -39,6 +40,9 @@ pub struct CheckpointMessage {
     time: SystemTime,
     watermark: Option<SystemTime>,
     then_stop: bool,
+    // for unaligned checkpoints, resolves to the data that was in flight to the operator once all
+    // of its inputs have delivered the barrier
+    in_flight: Option<tokio::sync::oneshot::Receiver<Vec<InFlightBatch>>>,
 }
 
 #[derive(Debug)]
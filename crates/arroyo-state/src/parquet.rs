@@ -1,12 +1,15 @@
-use crate::tables::expiring_time_key_map::ExpiringTimeKeyTable;
+use crate::tables::expiring_time_key_map::{self, ExpiringTimeKeyTable};
 use crate::tables::global_keyed_map::GlobalKeyedTable;
 use crate::tables::{CompactionConfig, ErasedTable};
 use crate::BackingStore;
 use anyhow::{bail, Context, Result};
 use arroyo_rpc::grpc;
-use arroyo_rpc::grpc::{CheckpointMetadata, OperatorCheckpointMetadata, TableCheckpointMetadata};
+use arroyo_rpc::grpc::{
+    CheckpointMetadata, ExpiringKeyedTimeTableCheckpointMetadata, OperatorCheckpointMetadata,
+    ParquetTimeFile, TableCheckpointMetadata,
+};
 use arroyo_storage::StorageProvider;
-use arroyo_types::{CHECKPOINT_URL_ENV, S3_ENDPOINT_ENV, S3_REGION_ENV};
+use arroyo_types::{range_for_server, CHECKPOINT_URL_ENV, S3_ENDPOINT_ENV, S3_REGION_ENV};
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 
@@ -225,6 +228,62 @@ impl ParquetBackend {
         Ok(result)
     }
 
+    /// Returns the files of the operator's expiring time-key tables in the given epoch
+    pub async fn expiring_table_files(
+        job_id: &str,
+        operator_id: &str,
+        epoch: u32,
+    ) -> Result<Vec<ParquetTimeFile>> {
+        let Some(operator_metadata) =
+            Self::load_operator_metadata(job_id, operator_id, epoch).await?
+        else {
+            return Ok(vec![]);
+        };
+        let mut files = vec![];
+        for metadata in operator_metadata.table_checkpoint_metadata.into_values() {
+            if metadata.table_type() == grpc::TableEnum::ExpiringKeyedTimeTable {
+                files.extend(
+                    ExpiringKeyedTimeTableCheckpointMetadata::decode(&metadata.data[..])?.files,
+                );
+            }
+        }
+        Ok(files)
+    }
+
+    /// Returns the files that the operator's subtasks will compact in the background after
+    /// checkpointing the given epoch
+    pub async fn files_to_compact(
+        job_id: &str,
+        operator_id: &str,
+        epoch: u32,
+        parallelism: usize,
+    ) -> Result<HashSet<String>> {
+        let files = Self::expiring_table_files(job_id, operator_id, epoch).await?;
+        let mut result = HashSet::new();
+        for subtask in 0..parallelism {
+            let key_range = range_for_server(subtask, parallelism);
+            let subtask_files: Vec<_> = files
+                .iter()
+                .filter(|file| {
+                    file.max_routing_key >= *key_range.start()
+                        && *key_range.end() >= file.min_routing_key
+                })
+                .cloned()
+                .collect();
+            result.extend(
+                expiring_time_key_map::files_to_compact(
+                    &subtask_files,
+                    &key_range,
+                    expiring_time_key_map::key_ranges_per_subtask(),
+                    expiring_time_key_map::min_files_to_compact(),
+                )
+                .into_iter()
+                .map(|file| file.file),
+            );
+        }
+        Ok(result)
+    }
+
     /// Delete files no longer referenced by the new min epoch
     pub async fn cleanup_operator(
         job_id: String,
@@ -261,21 +320,24 @@ impl ParquetBackend {
         let storage_client = get_storage_provider().await?;
 
         for epoch_to_remove in old_min_epoch..new_min_epoch {
-            let Some(metadata) =
+            let Some(epoch_metadata) =
                 Self::load_operator_metadata(&job_id, &operator_id, epoch_to_remove).await?
             else {
                 continue;
             };
 
             // delete any files that are not in the new min epoch
-            for file in metadata
+            for file in epoch_metadata
                 .table_checkpoint_metadata
                 .iter()
                 // TODO: factor this out
                 .flat_map(|(table_name, metadata)| {
+                    // tables like the in-flight data of unaligned checkpoints may only exist in
+                    // some epochs, so fall back to the config stored with the epoch being removed
                     let table_config = operator_metadata
                         .table_configs
                         .get(table_name)
+                        .or_else(|| epoch_metadata.table_configs.get(table_name))
                         .ok_or_else(|| anyhow::anyhow!("missing table config for operator {}, table {}, metadata is {:?}, operator_metadata is {:?}",
                          operator_id, table_name, metadata, operator_metadata)).unwrap()
                         .clone();
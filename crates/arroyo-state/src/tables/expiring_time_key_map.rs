@@ -1,21 +1,20 @@
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
-    mem,
+    collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
+    env, mem,
+    ops::RangeInclusive,
     sync::Arc,
     time::{Duration, SystemTime},
 };
 
 use anyhow::{anyhow, bail, Ok, Result};
-use arrow::compute::{concat_batches, filter_record_batch, kernels::aggregate, take};
+use arrow::compute::{concat_batches, filter_record_batch, kernels::aggregate};
 use arrow::row::OwnedRow;
 use arrow_array::{
-    cast::AsArray,
-    types::{TimestampNanosecondType, UInt64Type},
-    BooleanArray, PrimitiveArray, RecordBatch, TimestampNanosecondArray, UInt64Array,
+    cast::AsArray, types::TimestampNanosecondType, BooleanArray, PrimitiveArray, RecordBatch,
+    TimestampNanosecondArray, UInt64Array,
 };
-use arrow_ord::{partition::partition, sort::sort_to_indices};
+use arrow_ord::partition::partition;
 use arroyo_rpc::{
-    df::server_for_hash_array,
     grpc::{
         ExpiringKeyedTimeSubtaskCheckpointMetadata, ExpiringKeyedTimeTableCheckpointMetadata,
         ExpiringKeyedTimeTableConfig, OperatorMetadata, ParquetTimeFile, TableEnum,
@@ -23,11 +22,9 @@ use arroyo_rpc::{
     Converter,
 };
 use arroyo_storage::StorageProviderRef;
-use arroyo_types::{
-    from_micros, from_nanos, print_time, server_for_hash, to_micros, to_nanos, TaskInfoRef,
-};
+use arroyo_types::{from_micros, from_nanos, print_time, to_micros, to_nanos, TaskInfoRef};
 
-use futures::{StreamExt, TryStreamExt};
+use futures::{future::BoxFuture, StreamExt, TryStreamExt};
 use parquet::{
     arrow::{async_reader::ParquetObjectReader, AsyncArrowWriter, ParquetRecordBatchStreamBuilder},
     basic::{Compression, ZstdLevel},
@@ -52,6 +49,8 @@ pub struct ExpiringTimeKeyTable {
     retention: Duration,
     storage_provider: StorageProviderRef,
     checkpoint_files: Vec<ParquetTimeFile>,
+    // the number of ranges the subtask's key range is split into for checkpoint files
+    key_ranges: usize,
 }
 
 impl ExpiringTimeKeyTable {
@@ -282,6 +281,7 @@ impl Table for ExpiringTimeKeyTable {
             retention: Duration::from_micros(config.retention_micros),
             storage_provider,
             checkpoint_files,
+            key_ranges: key_ranges_per_subtask(),
         })
     }
 
@@ -389,217 +389,277 @@ impl Table for ExpiringTimeKeyTable {
     }
 
     async fn compact_data(
-        config: Self::ConfigMessage,
-        compaction_config: &CompactionConfig,
-        operator_metadata: &OperatorMetadata,
-        current_metadata: Self::TableCheckpointMessage,
+        _config: Self::ConfigMessage,
+        _compaction_config: &CompactionConfig,
+        _operator_metadata: &OperatorMetadata,
+        _current_metadata: Self::TableCheckpointMessage,
     ) -> Result<Option<Self::TableCheckpointMessage>> {
-        let mut epochs_in_generation: HashMap<u64, HashSet<u32>> = HashMap::new();
-        let mut files_by_generation: BTreeMap<u64, HashMap<String, ParquetTimeFile>> =
-            BTreeMap::new();
-        for file in current_metadata.files {
-            if compaction_config
-                .compact_generations
-                .contains(&file.generation)
-            {
-                epochs_in_generation
-                    .entry(file.generation)
-                    .or_default()
-                    .insert(file.epoch);
-            }
-            files_by_generation
-                .entry(file.generation)
-                .or_default()
-                .insert(file.file.to_string(), file);
-        }
-        let schema: ArroyoSchema = config
-            .schema
-            .ok_or_else(|| anyhow!("expect schema"))?
-            .try_into()?;
-        let state_schema = SchemaWithHashAndOperation::new(Arc::new(schema), config.generational);
+        // compaction for these tables happens on the workers; see `background_compaction`
+        Ok(None)
+    }
 
-        for (generation, epochs) in epochs_in_generation {
-            if epochs.len() < compaction_config.min_compaction_epochs {
-                continue;
-            }
-            let mut files = TimeTableCompactor::compact_files(
-                config.table_name,
-                epochs.into_iter().max().unwrap(),
-                generation + 1,
-                compaction_config.storage_provider.clone(),
-                state_schema,
-                Duration::from_micros(config.retention_micros),
-                operator_metadata,
-                files_by_generation
-                    .remove(&generation)
-                    .expect("will have been populated"),
-            )
-            .await?;
-            files.extend(
-                files_by_generation
-                    .into_values()
-                    .flat_map(|files| files.into_values()),
-            );
-            return Ok(Some(ExpiringKeyedTimeTableCheckpointMetadata { files }));
+    fn background_compaction(
+        &self,
+        epoch: u32,
+        subtask_metadata: &Self::TableSubtaskCheckpointMetadata,
+    ) -> Option<(
+        HashSet<String>,
+        BoxFuture<'static, Result<Self::TableSubtaskCheckpointMetadata>>,
+    )> {
+        let files = files_to_compact(
+            &subtask_metadata.files,
+            &self.task_info.key_range,
+            self.key_ranges,
+            min_files_to_compact(),
+        );
+        if files.is_empty() {
+            return None;
         }
-        Ok(None)
+
+        let replaced_files = files.iter().map(|file| file.file.clone()).collect();
+        let cutoff = subtask_metadata
+            .watermark
+            .and_then(|watermark| from_micros(watermark).checked_sub(self.retention));
+        let subtask_index = subtask_metadata.subtask_index;
+        let table = self.clone();
+
+        Some((
+            replaced_files,
+            Box::pin(async move {
+                let files = table.compact_files(epoch, cutoff, files).await?;
+                Ok(ExpiringKeyedTimeSubtaskCheckpointMetadata {
+                    subtask_index,
+                    watermark: None,
+                    files,
+                })
+            }),
+        ))
+    }
+
+    fn apply_background_compaction(
+        &self,
+        replaced_files: &HashSet<String>,
+        compacted: Self::TableSubtaskCheckpointMetadata,
+        subtask_metadata: Self::TableSubtaskCheckpointMetadata,
+    ) -> Result<Self::TableSubtaskCheckpointMetadata> {
+        let mut files: Vec<_> = subtask_metadata
+            .files
+            .into_iter()
+            .filter(|file| !replaced_files.contains(&file.file))
+            .collect();
+        files.extend(compacted.files);
+
+        Ok(Self::TableSubtaskCheckpointMetadata {
+            subtask_index: subtask_metadata.subtask_index,
+            watermark: subtask_metadata.watermark,
+            files,
+        })
     }
 }
 
-struct CompactedFileWriter {
-    file_name: String,
-    schema: SchemaWithHashAndOperation,
-    writer: Option<AsyncArrowWriter<Box<dyn AsyncWrite + Send + Unpin>>>,
-    parquet_stats: Option<ParquetStats>,
+// the number of files in a generation that triggers compacting them into the next generation
+pub(crate) fn min_files_to_compact() -> usize {
+    env::var("MIN_FILES_TO_COMPACT")
+        .ok()
+        .and_then(|min_files| min_files.parse().ok())
+        .unwrap_or(4)
+        .max(2)
 }
 
-struct TimeTableCompactor {
-    storage_provider: StorageProviderRef,
-    schema: SchemaWithHashAndOperation,
-    operator_metadata: OperatorMetadata,
-    table: String,
-    writers: HashMap<usize, CompactedFileWriter>,
+// the number of ranges each subtask's key range is split into. Each checkpoint only writes files
+// for the ranges that received data, and compaction rewrites each range on its own, so unchanged
+// ranges are neither uploaded nor rewritten.
+pub(crate) fn key_ranges_per_subtask() -> usize {
+    env::var("CHECKPOINT_KEY_RANGES")
+        .ok()
+        .and_then(|key_ranges| key_ranges.parse().ok())
+        .unwrap_or(8)
+        .max(1)
+}
+
+// the index of the range within `key_range` that the hash belongs to; hashes outside of the key
+// range, which can appear in files restored after a rescale, are clamped to the nearest range
+fn key_range_index(key_range: &RangeInclusive<u64>, key_ranges: usize, hash: u64) -> usize {
+    let width = (key_range.end() - key_range.start()) / key_ranges as u64 + 1;
+    (hash.saturating_sub(*key_range.start()) / width).min(key_ranges as u64 - 1) as usize
+}
+
+// picks the files a subtask should compact: for each key range, those of the youngest generation
+// that has accumulated enough files, so that each file is rewritten once per generation rather
+// than on every compaction. Files spanning several ranges are grouped by their smallest key.
+pub(crate) fn files_to_compact(
+    files: &[ParquetTimeFile],
+    key_range: &RangeInclusive<u64>,
+    key_ranges: usize,
+    min_files: usize,
+) -> Vec<ParquetTimeFile> {
+    let mut files_by_range: BTreeMap<(usize, u64), Vec<ParquetTimeFile>> = BTreeMap::new();
+    for file in files {
+        let range = key_range_index(key_range, key_ranges, file.min_routing_key);
+        files_by_range
+            .entry((range, file.generation))
+            .or_default()
+            .push(file.clone());
+    }
+
+    let mut compacted_ranges = HashSet::new();
+    let mut result: Vec<_> = files_by_range
+        .into_iter()
+        .filter(|((range, _), files)| files.len() >= min_files && compacted_ranges.insert(*range))
+        .flat_map(|(_, files)| files)
+        .collect();
+    result.sort_by_key(|file| file.epoch);
+    result
 }
 
-impl TimeTableCompactor {
-    #[allow(clippy::too_many_arguments)]
+impl ExpiringTimeKeyTable {
+    // splits a state batch into the parts belonging to each of the subtask's key ranges
+    fn split_by_key_range(&self, batch: &RecordBatch) -> Result<Vec<(usize, RecordBatch)>> {
+        let hashes: &UInt64Array = batch
+            .column(self.schema.hash_index())
+            .as_primitive_opt()
+            .ok_or_else(|| anyhow!("failed to find key hash column"))?;
+        let ranges: Vec<_> = hashes
+            .values()
+            .iter()
+            .map(|hash| key_range_index(&self.task_info.key_range, self.key_ranges, *hash))
+            .collect();
+
+        let distinct_ranges: BTreeSet<_> = ranges.iter().copied().collect();
+        if distinct_ranges.len() <= 1 {
+            return Ok(distinct_ranges
+                .into_iter()
+                .map(|range| (range, batch.clone()))
+                .collect());
+        }
+        distinct_ranges
+            .into_iter()
+            .map(|range| {
+                let in_range: BooleanArray = ranges.iter().map(|r| Some(*r == range)).collect();
+                Ok((range, filter_record_batch(batch, &in_range)?))
+            })
+            .collect()
+    }
+
+    fn key_range_file_path(&self, epoch: u32, compacted: bool, key_range: usize) -> String {
+        format!(
+            "{}-{:0>3}",
+            table_checkpoint_path(
+                &self.task_info.job_id,
+                &self.task_info.operator_id,
+                &self.table_name,
+                self.task_info.task_index,
+                epoch,
+                compacted,
+            ),
+            key_range
+        )
+    }
+
+    // merges the files into one file per key range, each in the generation after the youngest
+    // file it was built from, keeping only the rows that are in this subtask's key range and
+    // haven't expired
     async fn compact_files(
-        table: String,
+        &self,
         epoch: u32,
-        generation: u64,
-        storage_provider: StorageProviderRef,
-        schema: SchemaWithHashAndOperation,
-        retention: Duration,
-        operator_metadata: &OperatorMetadata,
-        files: HashMap<String, ParquetTimeFile>,
+        cutoff: Option<SystemTime>,
+        files: Vec<ParquetTimeFile>,
     ) -> Result<Vec<ParquetTimeFile>> {
-        let mut compactor = Self {
-            table,
-            storage_provider,
-            schema: schema.clone(),
-            operator_metadata: operator_metadata.clone(),
-            writers: HashMap::new(),
-        };
-        let cutoff = operator_metadata
-            .min_watermark
-            .map(|min_micros| from_micros(min_micros) - retention);
-        for (file_name, file) in files {
-            let max_file_timestamp = from_micros(file.max_timestamp_micros);
-            if cutoff
-                .map(|cutoff| max_file_timestamp < cutoff)
-                .unwrap_or(false)
-            {
+        let key_range = &self.task_info.key_range;
+        let mut writers: BTreeMap<usize, (ParquetTimeFileWriter, u64)> = BTreeMap::new();
+
+        for file in files {
+            if cutoff.is_some_and(|cutoff| from_micros(file.max_timestamp_micros) < cutoff) {
                 continue;
             }
-            let reader = ParquetObjectReader::new(
-                compactor.storage_provider.get_backing_store(),
-                compactor
-                    .storage_provider
-                    .get_backing_store()
-                    .head(&(file_name.clone().into()))
-                    .await?,
-            );
-            let first_partition =
-                server_for_hash(file.min_routing_key, operator_metadata.parallelism as usize);
-            let last_partition =
-                server_for_hash(file.max_routing_key, operator_metadata.parallelism as usize);
-            let multiple_partitions = first_partition != last_partition;
-            let reader_builder = ParquetRecordBatchStreamBuilder::new(reader).await?;
-            let mut stream = reader_builder.build()?;
-            // projection to trim the metadata fields. Should probably be factored out.
-            while let Some(batch) = stream.try_next().await? {
-                // Filter by _timestamp field
-                let time_filtered = schema.state_schema().filter_by_time(batch, cutoff)?;
-                if time_filtered.num_rows() == 0 {
+            // files restored after a rescale may contain keys owned by other subtasks
+            let needs_hash_filtering = *key_range.end() < file.max_routing_key
+                || *key_range.start() > file.min_routing_key;
+
+            let object_meta = self
+                .storage_provider
+                .get_backing_store()
+                .head(&(file.file.into()))
+                .await?;
+            let reader =
+                ParquetObjectReader::new(self.storage_provider.get_backing_store(), object_meta);
+            let mut stream = ParquetRecordBatchStreamBuilder::new(reader)
+                .await?
+                .build()?;
+
+            while let Some(mut batch) = stream.try_next().await? {
+                if needs_hash_filtering {
+                    match self.schema.filter_by_hash_index(batch, key_range)? {
+                        None => continue,
+                        Some(filtered_batch) => batch = filtered_batch,
+                    }
+                }
+                let batch = self.schema.state_schema().filter_by_time(batch, cutoff)?;
+                if batch.num_rows() == 0 {
                     continue;
                 }
-                if !multiple_partitions {
-                    compactor
-                        .write_batch(first_partition, time_filtered)
-                        .await?;
-                } else {
-                    // this record batch contains data belonging to multiple partitions.
-                    let partitions = server_for_hash_array(
-                        time_filtered
-                            .column(schema.hash_index())
-                            .as_any()
-                            .downcast_ref::<PrimitiveArray<UInt64Type>>()
-                            .unwrap(),
-                        operator_metadata.parallelism as usize,
-                    )?;
-                    let indices = sort_to_indices(&partitions, None, None).unwrap();
-                    let columns = time_filtered
-                        .columns()
-                        .iter()
-                        .map(|c| take(c, &indices, None).unwrap())
-                        .collect();
-                    let sorted =
-                        RecordBatch::try_new(schema.state_schema().schema.clone(), columns)?;
-                    let sorted_keys = take(&partitions, &indices, None)?;
-
-                    let partition = partition(vec![sorted_keys.clone()].as_slice())?;
-                    let typed_keys: &PrimitiveArray<UInt64Type> =
-                        sorted_keys.as_any().downcast_ref().unwrap();
-                    for range in partition.ranges() {
-                        let partition = typed_keys.value(range.start);
-                        compactor
-                            .write_batch(
-                                partition as usize,
-                                sorted.slice(range.start, range.end - range.start),
+
+                for (range, batch) in self.split_by_key_range(&batch)? {
+                    let (writer, generation) = match writers.entry(range) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => entry.insert((
+                            ParquetTimeFileWriter::new(
+                                &self.storage_provider,
+                                self.key_range_file_path(epoch, true, range),
+                                self.schema.clone(),
                             )
-                            .await?
-                    }
+                            .await?,
+                            0,
+                        )),
+                    };
+                    *generation = (*generation).max(file.generation + 1);
+                    writer.write_batch(batch).await?;
                 }
             }
         }
-        compactor.finish(epoch, generation).await
-    }
 
-    async fn write_batch(&mut self, partition: usize, record_batch: RecordBatch) -> Result<()> {
-        if let std::collections::hash_map::Entry::Vacant(e) = self.writers.entry(partition) {
-            let file_name = table_checkpoint_path(
-                &self.operator_metadata.job_id,
-                &self.operator_metadata.operator_id,
-                &self.table,
-                partition,
-                self.operator_metadata.epoch,
-                true,
-            );
-            let (_multipart_id, async_writer) = self
-                .storage_provider
-                .get_backing_store()
-                .put_multipart(&(file_name.clone().into()))
-                .await?;
-            let writer = Some(AsyncArrowWriter::try_new(
-                async_writer,
-                self.schema.state_schema().schema.clone(),
-                10_000_000,
-                None,
-            )?);
-            e.insert(CompactedFileWriter {
-                file_name,
-                schema: self.schema.clone(),
-                writer,
-                parquet_stats: None,
-            });
+        let mut compacted = vec![];
+        for (writer, generation) in writers.into_values() {
+            compacted.push(writer.finish(epoch, generation).await?);
         }
-        let writer = self.writers.get_mut(&partition).unwrap();
-
-        writer.write_batch(record_batch).await?;
-
-        Ok(())
+        Ok(compacted)
     }
+}
 
-    async fn finish(self, epoch: u32, generation: u64) -> Result<Vec<ParquetTimeFile>> {
-        let mut results = vec![];
-        for writer in self.writers.into_values() {
-            results.push(writer.finish(epoch, generation).await?);
-        }
-        Ok(results)
-    }
+struct ParquetTimeFileWriter {
+    file_name: String,
+    schema: SchemaWithHashAndOperation,
+    writer: Option<AsyncArrowWriter<Box<dyn AsyncWrite + Send + Unpin>>>,
+    parquet_stats: Option<ParquetStats>,
 }
 
-impl CompactedFileWriter {
+impl ParquetTimeFileWriter {
+    async fn new(
+        storage_provider: &StorageProviderRef,
+        file_name: String,
+        schema: SchemaWithHashAndOperation,
+    ) -> Result<Self> {
+        let (_multipart_id, async_writer) = storage_provider
+            .get_backing_store()
+            .put_multipart(&(file_name.clone().into()))
+            .await?;
+        let writer_properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+        let writer = Some(AsyncArrowWriter::try_new(
+            async_writer,
+            schema.state_schema().schema.clone(),
+            10_000_000,
+            Some(writer_properties),
+        )?);
+        Ok(Self {
+            file_name,
+            schema,
+            writer,
+            parquet_stats: None,
+        })
+    }
+
     async fn write_batch(&mut self, record_batch: RecordBatch) -> Result<()> {
         let mut parquet_stats = self.schema.batch_stats_from_state_batch(&record_batch)?;
         if let Some(other) = self.parquet_stats.take() {
@@ -617,7 +677,7 @@ impl CompactedFileWriter {
         let writer = self
             .writer
             .take()
-            .ok_or_else(|| anyhow!("unset compacted file writer {}", self.file_name))?;
+            .ok_or_else(|| anyhow!("unset writer for {}", self.file_name))?;
         let _closed = writer.close().await?;
         let stats = self.parquet_stats.take().expect("should have stats");
         Ok(ParquetTimeFile {
@@ -632,11 +692,10 @@ impl CompactedFileWriter {
 }
 
 pub struct ExpiringTimeKeyTableCheckpointer {
-    file_name: String,
     parent: ExpiringTimeKeyTable,
     epoch: u32,
-    writer: Option<AsyncArrowWriter<Box<dyn AsyncWrite + Send + Unpin>>>,
-    parquet_stats: Option<ParquetStats>,
+    // one writer for each key range that received data this epoch
+    writers: BTreeMap<usize, ParquetTimeFileWriter>,
     prior_files: Vec<ParquetTimeFile>,
 }
 
@@ -646,41 +705,13 @@ impl ExpiringTimeKeyTableCheckpointer {
         epoch: u32,
         prior_files: Vec<ParquetTimeFile>,
     ) -> Result<Self> {
-        let file_name = table_checkpoint_path(
-            &parent.task_info.job_id,
-            &parent.task_info.operator_id,
-            &parent.table_name,
-            parent.task_info.task_index,
-            epoch,
-            false,
-        );
         Ok(Self {
-            file_name,
             parent,
             epoch,
-            writer: None,
-            parquet_stats: None,
+            writers: BTreeMap::new(),
             prior_files,
         })
     }
-    async fn init_writer(&mut self) -> Result<()> {
-        let (_multipart_id, async_writer) = self
-            .parent
-            .storage_provider
-            .get_backing_store()
-            .put_multipart(&self.file_name.clone().into())
-            .await?;
-        let writer_properties = WriterProperties::builder()
-            .set_compression(Compression::ZSTD(ZstdLevel::default()))
-            .build();
-        self.writer = Some(AsyncArrowWriter::try_new(
-            async_writer,
-            self.parent.schema.state_schema().schema.clone(),
-            10_000_000,
-            Some(writer_properties),
-        )?);
-        Ok(())
-    }
 }
 
 #[async_trait::async_trait]
@@ -691,21 +722,26 @@ impl TableEpochCheckpointer for ExpiringTimeKeyTableCheckpointer {
         let TableData::RecordBatch(batch) = data else {
             bail!("expect record batch data for expiring time key map tables")
         };
-        if self.writer.is_none() {
-            self.init_writer().await?;
+        let (annotated_batch, _batch_stats) = self.annotate_record_batch(&batch)?;
+        for (range, batch) in self.parent.split_by_key_range(&annotated_batch)? {
+            let writer = match self.writers.entry(range) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(
+                    ParquetTimeFileWriter::new(
+                        &self.parent.storage_provider,
+                        self.parent.key_range_file_path(self.epoch, false, range),
+                        self.parent.schema.clone(),
+                    )
+                    .await?,
+                ),
+            };
+            writer.write_batch(batch).await?;
         }
-        let (annotated_batch, batch_stats) = self.annotate_record_batch(&batch)?;
-        self.update_parquet_stats(batch_stats);
-        self.writer
-            .as_mut()
-            .expect("writer should be set")
-            .write(&annotated_batch)
-            .await?;
         Ok(())
     }
 
     async fn finish(
-        mut self,
+        self,
         checkpoint: &CheckpointMessage,
     ) -> Result<Option<(Self::SubTableCheckpointMessage, usize)>> {
         let cutoff = checkpoint
@@ -723,26 +759,16 @@ impl TableEpochCheckpointer for ExpiringTimeKeyTableCheckpointer {
             })
             .collect();
         let mut bytes = 0;
-        if let Some(writer) = self.writer.take() {
-            let _result = writer.close().await?;
-
-            let stats = self.parquet_stats.expect("should have set parquet stats");
+        for writer in self.writers.into_values() {
+            let file = writer.finish(self.epoch, 0).await?;
             let meta = self
                 .parent
                 .storage_provider
                 .get_backing_store()
-                .head(&(self.file_name.clone().into()))
+                .head(&(file.file.clone().into()))
                 .await?;
             bytes += meta.size;
-            let file = ParquetTimeFile {
-                epoch: self.epoch,
-                file: self.file_name,
-                min_routing_key: stats.min_routing_key,
-                max_routing_key: stats.max_routing_key,
-                max_timestamp_micros: to_micros(stats.max_timestamp),
-                generation: 0,
-            };
-            files.push(file)
+            files.push(file);
         }
         if files.is_empty() {
             Ok(None)
@@ -774,17 +800,6 @@ impl ExpiringTimeKeyTableCheckpointer {
     ) -> Result<(RecordBatch, ParquetStats)> {
         self.parent.schema.annotate_record_batch(record_batch)
     }
-
-    fn update_parquet_stats(&mut self, parquet_stats: ParquetStats) {
-        match self.parquet_stats.as_mut() {
-            None => {
-                self.parquet_stats = Some(parquet_stats);
-            }
-            Some(current_stats) => {
-                current_stats.merge(parquet_stats);
-            }
-        }
-    }
 }
 
 #[derive(Debug)]
@@ -1253,3 +1268,242 @@ impl LastKeyValueView {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use arroyo_storage::StorageProvider;
+    use arroyo_types::TaskInfo;
+
+    async fn test_table(key_ranges: usize) -> ExpiringTimeKeyTable {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("key", DataType::UInt64, false),
+            Field::new(
+                "_timestamp",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+        let schema = ArroyoSchema::new(schema, 1, Some(vec![0]));
+        let dir = env::temp_dir().join(format!("arroyo-state-test-{}", rand::random::<u64>()));
+        let storage_provider =
+            StorageProvider::for_url(&format!("file://{}", dir.to_str().unwrap()))
+                .await
+                .unwrap();
+
+        let mut table = ExpiringTimeKeyTable::from_config(
+            ExpiringKeyedTimeTableConfig {
+                table_name: "t".to_string(),
+                description: "test table".to_string(),
+                retention_micros: Duration::from_secs(3600).as_micros() as u64,
+                generational: false,
+                schema: Some(schema.try_into().unwrap()),
+            },
+            Arc::new(TaskInfo::for_test("job", "op")),
+            Arc::new(storage_provider),
+            None,
+        )
+        .unwrap();
+        table.key_ranges = key_ranges;
+        table
+    }
+
+    fn batch(keys: Vec<u64>, time: SystemTime) -> RecordBatch {
+        let timestamps = vec![to_nanos(time) as i64; keys.len()];
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("key", DataType::UInt64, false),
+                Field::new(
+                    "_timestamp",
+                    DataType::Timestamp(TimeUnit::Nanosecond, None),
+                    false,
+                ),
+            ])),
+            vec![
+                Arc::new(UInt64Array::from(keys)),
+                Arc::new(TimestampNanosecondArray::from(timestamps)),
+            ],
+        )
+        .unwrap()
+    }
+
+    async fn checkpoint(
+        table: &ExpiringTimeKeyTable,
+        epoch: u32,
+        prior: Option<ExpiringKeyedTimeSubtaskCheckpointMetadata>,
+        data: RecordBatch,
+    ) -> ExpiringKeyedTimeSubtaskCheckpointMetadata {
+        let mut checkpointer = table.epoch_checkpointer(epoch, prior).unwrap();
+        checkpointer
+            .insert_data(TableData::RecordBatch(data))
+            .await
+            .unwrap();
+        let (metadata, _) = checkpointer
+            .finish(&CheckpointMessage {
+                epoch,
+                time: SystemTime::now(),
+                watermark: None,
+                then_stop: false,
+                in_flight: None,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        metadata
+    }
+
+    async fn count_rows(table: &ExpiringTimeKeyTable, files: &[ParquetTimeFile]) -> usize {
+        let mut rows = 0;
+        for file in files {
+            let object_meta = table
+                .storage_provider
+                .get_backing_store()
+                .head(&(file.file.clone().into()))
+                .await
+                .unwrap();
+            let reader =
+                ParquetObjectReader::new(table.storage_provider.get_backing_store(), object_meta);
+            let batches: Vec<_> = ParquetRecordBatchStreamBuilder::new(reader)
+                .await
+                .unwrap()
+                .build()
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            rows += batches.iter().map(|batch| batch.num_rows()).sum::<usize>();
+        }
+        rows
+    }
+
+    fn time_file(file: &str, epoch: u32, min_routing_key: u64, generation: u64) -> ParquetTimeFile {
+        ParquetTimeFile {
+            epoch,
+            file: file.to_string(),
+            min_routing_key,
+            max_routing_key: min_routing_key,
+            max_timestamp_micros: 0,
+            generation,
+        }
+    }
+
+    #[test]
+    fn test_key_range_index() {
+        let full = 0..=u64::MAX;
+        assert_eq!(key_range_index(&full, 4, 0), 0);
+        assert_eq!(key_range_index(&full, 4, (1 << 62) - 1), 0);
+        assert_eq!(key_range_index(&full, 4, 1 << 62), 1);
+        assert_eq!(key_range_index(&full, 4, u64::MAX), 3);
+        assert_eq!(key_range_index(&full, 1, u64::MAX), 0);
+
+        let partial = 100..=199;
+        assert_eq!(key_range_index(&partial, 4, 124), 0);
+        assert_eq!(key_range_index(&partial, 4, 125), 1);
+        assert_eq!(key_range_index(&partial, 4, 199), 3);
+        // keys from other subtasks' ranges are clamped
+        assert_eq!(key_range_index(&partial, 4, 50), 0);
+        assert_eq!(key_range_index(&partial, 4, 500), 3);
+    }
+
+    #[test]
+    fn test_files_to_compact() {
+        let full = 0..=u64::MAX;
+        let upper = u64::MAX - 1;
+        let files = vec![
+            time_file("a", 1, 0, 1),
+            time_file("b", 2, 0, 1),
+            time_file("c", 3, 0, 0),
+            time_file("d", 4, 0, 0),
+            time_file("e", 3, upper, 0),
+            time_file("f", 4, upper, 1),
+        ];
+
+        // the youngest generation with enough files is compacted in each range
+        let compacted: Vec<_> = files_to_compact(&files, &full, 2, 2)
+            .into_iter()
+            .map(|file| file.file)
+            .collect();
+        assert_eq!(compacted, vec!["c", "d"]);
+
+        let compacted: Vec<_> = files_to_compact(&files, &full, 2, 3)
+            .into_iter()
+            .map(|file| file.file)
+            .collect();
+        assert!(compacted.is_empty());
+
+        // with a single range, files of different ranges are grouped together
+        let compacted: Vec<_> = files_to_compact(&files, &full, 1, 3)
+            .into_iter()
+            .map(|file| file.file)
+            .collect();
+        assert_eq!(compacted, vec!["c", "e", "d"]);
+    }
+
+    #[tokio::test]
+    async fn test_checkpoints_only_write_changed_key_ranges() {
+        let table = test_table(4).await;
+        let now = SystemTime::now();
+
+        let metadata = checkpoint(&table, 1, None, batch((0..100).collect(), now)).await;
+        assert_eq!(metadata.files.len(), 4);
+        for file in &metadata.files {
+            assert_eq!(
+                key_range_index(&table.task_info.key_range, 4, file.min_routing_key),
+                key_range_index(&table.task_info.key_range, 4, file.max_routing_key),
+            );
+        }
+        assert_eq!(count_rows(&table, &metadata.files).await, 100);
+
+        // a single key only changes a single range
+        let metadata = checkpoint(&table, 2, Some(metadata), batch(vec![7], now)).await;
+        assert_eq!(metadata.files.len(), 5);
+        let new_files: Vec<_> = metadata
+            .files
+            .iter()
+            .filter(|file| file.epoch == 2)
+            .cloned()
+            .collect();
+        assert_eq!(new_files.len(), 1);
+        assert_eq!(count_rows(&table, &new_files).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_background_compaction() {
+        let table = test_table(4).await;
+        let now = SystemTime::now();
+
+        let mut metadata = None;
+        for epoch in 1..=4 {
+            metadata =
+                Some(checkpoint(&table, epoch, metadata, batch((0..100).collect(), now)).await);
+        }
+        let metadata = metadata.unwrap();
+        assert_eq!(metadata.files.len(), 16);
+
+        let (replaced_files, compaction) = table.background_compaction(4, &metadata).unwrap();
+        assert_eq!(replaced_files.len(), 16);
+        let compacted = compaction.await.unwrap();
+        assert_eq!(compacted.files.len(), 4);
+        for file in &compacted.files {
+            assert_eq!(file.generation, 1);
+            assert_eq!(file.epoch, 4);
+        }
+        assert_eq!(count_rows(&table, &compacted.files).await, 400);
+
+        // files written while the compaction was running are kept
+        let metadata = checkpoint(&table, 5, Some(metadata), batch(vec![7], now)).await;
+        let applied = table
+            .apply_background_compaction(&replaced_files, compacted, metadata)
+            .unwrap();
+        assert_eq!(applied.files.len(), 5);
+        assert!(applied
+            .files
+            .iter()
+            .all(|file| !replaced_files.contains(&file.file)));
+        assert_eq!(count_rows(&table, &applied.files).await, 401);
+
+        // the compacted generation isn't compacted again until it has accumulated enough files
+        assert!(table.background_compaction(5, &applied).is_none());
+    }
+}
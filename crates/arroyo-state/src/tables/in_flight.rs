@@ -0,0 +1,145 @@
+use crate::{global_table_config, CheckpointMessage, TableData, BINCODE_CONFIG};
+use anyhow::{anyhow, Result};
+use arrow::ipc::reader::StreamReader;
+use arrow::ipc::writer::StreamWriter;
+use arrow_array::RecordBatch;
+use arroyo_rpc::grpc::{TableCheckpointMetadata, TableConfig, TableSubtaskCheckpointMetadata};
+use arroyo_storage::StorageProviderRef;
+use arroyo_types::TaskInfoRef;
+use bincode::{Decode, Encode};
+use tokio::sync::mpsc::Sender;
+
+use super::global_keyed_map::GlobalKeyedTable;
+use super::{ErasedCheckpointer, ErasedTable};
+use crate::StateMessage;
+
+/// Reserved table that holds the data that was in flight to an operator when it took an unaligned
+/// checkpoint. It's only written for epochs that have such data.
+pub const IN_FLIGHT_TABLE: &str = "__in_flight";
+
+/// A batch that was sent to one of an operator's inputs before the barrier of an unaligned
+/// checkpoint, but that the operator only processed after snapshotting its state. `input_index`
+/// is the partition within the logical input (edge) that the batch was received on, of
+/// `input_partitions`.
+#[derive(Debug, Clone)]
+pub struct InFlightBatch {
+    pub logical_input: usize,
+    pub input_index: usize,
+    pub input_partitions: usize,
+    pub batch: RecordBatch,
+}
+
+/// An in-flight batch read back from a checkpoint, along with the subtask that persisted it and
+/// the operator's parallelism at the time
+#[derive(Debug, Clone)]
+pub struct RestoredInFlightBatch {
+    pub subtask_index: usize,
+    pub parallelism: usize,
+    pub batch: InFlightBatch,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+struct InFlightEntry {
+    parallelism: u32,
+    logical_input: u32,
+    input_index: u32,
+    input_partitions: u32,
+    ipc: Vec<u8>,
+}
+
+pub(crate) fn in_flight_table_config() -> TableConfig {
+    global_table_config(
+        IN_FLIGHT_TABLE,
+        "data in flight during an unaligned checkpoint",
+    )
+    .remove(IN_FLIGHT_TABLE)
+    .unwrap()
+}
+
+fn encode_batch(batch: &RecordBatch) -> Result<Vec<u8>> {
+    let mut writer = StreamWriter::try_new(Vec::new(), &batch.schema())?;
+    writer.write(batch)?;
+    writer.finish()?;
+    Ok(writer.into_inner()?)
+}
+
+fn decode_batch(data: &[u8]) -> Result<RecordBatch> {
+    StreamReader::try_new(data, None)?
+        .next()
+        .ok_or_else(|| anyhow!("in-flight entry contains no batch"))?
+        .map_err(|e| anyhow!("failed to decode in-flight batch: {:?}", e))
+}
+
+/// Writes the in-flight batches for this subtask's checkpoint, in the order they were received
+pub(crate) async fn write_in_flight(
+    task_info: TaskInfoRef,
+    storage: StorageProviderRef,
+    checkpoint: &CheckpointMessage,
+    batches: Vec<InFlightBatch>,
+) -> Result<Option<(TableSubtaskCheckpointMetadata, usize)>> {
+    let table = <GlobalKeyedTable as ErasedTable>::from_config(
+        in_flight_table_config(),
+        task_info.clone(),
+        storage,
+        None,
+    )?;
+    let mut checkpointer = ErasedTable::epoch_checkpointer(&table, checkpoint.epoch, None)?;
+
+    for (seq, batch) in batches.into_iter().enumerate() {
+        let key = (task_info.task_index as u32, seq as u32);
+        let entry = InFlightEntry {
+            parallelism: task_info.parallelism as u32,
+            logical_input: batch.logical_input as u32,
+            input_index: batch.input_index as u32,
+            input_partitions: batch.input_partitions as u32,
+            ipc: encode_batch(&batch.batch)?,
+        };
+        checkpointer
+            .insert_data(TableData::KeyedData {
+                key: bincode::encode_to_vec(key, BINCODE_CONFIG)?,
+                value: bincode::encode_to_vec(entry, BINCODE_CONFIG)?,
+            })
+            .await?;
+    }
+
+    checkpointer.finish(checkpoint).await
+}
+
+/// Reads back all in-flight batches persisted by any subtask of the operator, ordered by subtask
+/// and then by the order in which they were received
+pub(crate) async fn read_in_flight(
+    task_info: TaskInfoRef,
+    storage: StorageProviderRef,
+    metadata: TableCheckpointMetadata,
+    state_tx: Sender<StateMessage>,
+) -> Result<Vec<RestoredInFlightBatch>> {
+    let table = <GlobalKeyedTable as ErasedTable>::from_config(
+        in_flight_table_config(),
+        task_info,
+        storage,
+        Some(metadata),
+    )?;
+
+    let view = table
+        .memory_view::<(u32, u32), InFlightEntry>(state_tx)
+        .await?;
+
+    let mut entries: Vec<_> = view.get_all().iter().collect();
+    entries.sort_by_key(|(key, _)| **key);
+
+    entries
+        .into_iter()
+        .map(|((subtask_index, _), entry)| {
+            Ok(RestoredInFlightBatch {
+                subtask_index: *subtask_index as usize,
+                parallelism: entry.parallelism as usize,
+                batch: InFlightBatch {
+                    logical_input: entry.logical_input as usize,
+                    input_index: entry.input_index as usize,
+                    input_partitions: entry.input_partitions as usize,
+                    batch: decode_batch(&entry.ipc)?,
+                },
+            })
+        })
+        .collect()
+}
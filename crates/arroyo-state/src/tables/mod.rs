@@ -6,6 +6,8 @@ use arroyo_rpc::grpc::{
 };
 use arroyo_storage::StorageProviderRef;
 use arroyo_types::TaskInfoRef;
+use futures::future::BoxFuture;
+use futures::FutureExt;
 use prost::Message;
 use std::any::Any;
 use std::collections::{HashMap, HashSet};
@@ -14,6 +16,7 @@ use tracing::debug;
 
 pub mod expiring_time_key_map;
 pub mod global_keyed_map;
+pub mod in_flight;
 pub mod table_manager;
 
 pub(crate) fn table_checkpoint_path(
@@ -140,6 +143,31 @@ pub(crate) trait Table: Send + Sync + 'static + Clone {
     {
         None
     }
+
+    // A worker method that starts compacting the subtask's files if enough have accumulated.
+    // Returns the files being replaced and a future that resolves to metadata holding only
+    // the compacted files, which is run in the background while checkpointing continues.
+    #[allow(clippy::type_complexity)]
+    fn background_compaction(
+        &self,
+        _epoch: u32,
+        _subtask_metadata: &Self::TableSubtaskCheckpointMetadata,
+    ) -> Option<(
+        HashSet<String>,
+        BoxFuture<'static, Result<Self::TableSubtaskCheckpointMetadata>>,
+    )> {
+        None
+    }
+
+    // Swaps the files replaced by a background compaction for the compacted ones
+    fn apply_background_compaction(
+        &self,
+        _replaced_files: &HashSet<String>,
+        _compacted: Self::TableSubtaskCheckpointMetadata,
+        subtask_metadata: Self::TableSubtaskCheckpointMetadata,
+    ) -> Result<Self::TableSubtaskCheckpointMetadata> {
+        Ok(subtask_metadata)
+    }
 }
 
 pub struct CompactionConfig {
@@ -236,6 +264,25 @@ pub trait ErasedTable: Send + Sync + 'static {
         compacted_checkpoint: TableSubtaskCheckpointMetadata,
         subtask_metadata: TableSubtaskCheckpointMetadata,
     ) -> Result<TableSubtaskCheckpointMetadata>;
+
+    #[allow(clippy::type_complexity)]
+    fn background_compaction(
+        &self,
+        epoch: u32,
+        subtask_metadata: &TableSubtaskCheckpointMetadata,
+    ) -> Result<
+        Option<(
+            HashSet<String>,
+            BoxFuture<'static, Result<TableSubtaskCheckpointMetadata>>,
+        )>,
+    >;
+
+    fn apply_background_compaction(
+        &self,
+        replaced_files: &HashSet<String>,
+        compacted: TableSubtaskCheckpointMetadata,
+        subtask_metadata: TableSubtaskCheckpointMetadata,
+    ) -> Result<TableSubtaskCheckpointMetadata>;
 }
 
 impl<T: Table + Sized + 'static> ErasedTable for T {
@@ -330,6 +377,55 @@ impl<T: Table + Sized + 'static> ErasedTable for T {
         })
     }
 
+    fn background_compaction(
+        &self,
+        epoch: u32,
+        subtask_metadata: &TableSubtaskCheckpointMetadata,
+    ) -> Result<
+        Option<(
+            HashSet<String>,
+            BoxFuture<'static, Result<TableSubtaskCheckpointMetadata>>,
+        )>,
+    > {
+        let subtask_metadata = Self::checked_proto_decode(
+            subtask_metadata.table_type(),
+            subtask_metadata.data.clone(),
+        )?;
+        let subtask_index = self.task_info().task_index as u32;
+        Ok(
+            T::background_compaction(self, epoch, &subtask_metadata).map(
+                |(replaced_files, compaction)| {
+                    let compaction = compaction.map(move |result| {
+                        result.map(|metadata| TableSubtaskCheckpointMetadata {
+                            subtask_index,
+                            table_type: T::table_type().into(),
+                            data: metadata.encode_to_vec(),
+                        })
+                    });
+                    (replaced_files, compaction.boxed())
+                },
+            ),
+        )
+    }
+
+    fn apply_background_compaction(
+        &self,
+        replaced_files: &HashSet<String>,
+        compacted: TableSubtaskCheckpointMetadata,
+        subtask_metadata: TableSubtaskCheckpointMetadata,
+    ) -> Result<TableSubtaskCheckpointMetadata> {
+        let compacted = Self::checked_proto_decode(compacted.table_type(), compacted.data)?;
+        let subtask_metadata =
+            Self::checked_proto_decode(subtask_metadata.table_type(), subtask_metadata.data)?;
+        let result =
+            T::apply_background_compaction(self, replaced_files, compacted, subtask_metadata)?;
+        Ok(TableSubtaskCheckpointMetadata {
+            subtask_index: self.task_info().task_index as u32,
+            table_type: T::table_type().into(),
+            data: result.encode_to_vec(),
+        })
+    }
+
     fn table_type() -> TableEnum
     where
         Self: Sized,
@@ -1,13 +1,18 @@
 use std::any::Any;
 
-use std::{collections::HashMap, env, sync::Arc, time::SystemTime};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    sync::Arc,
+    time::SystemTime,
+};
 
 use anyhow::{anyhow, bail, Context, Result};
 use arroyo_rpc::CompactionResult;
 use arroyo_rpc::{
     grpc::{
-        OperatorCheckpointMetadata, SubtaskCheckpointMetadata, TableConfig, TableEnum,
-        TableSubtaskCheckpointMetadata,
+        OperatorCheckpointMetadata, SubtaskCheckpointMetadata, TableCheckpointMetadata,
+        TableConfig, TableEnum, TableSubtaskCheckpointMetadata,
     },
     CheckpointCompleted, ControlResp,
 };
@@ -21,12 +26,13 @@ use tokio::sync::{
 use tracing::{debug, error, info, warn};
 
 use crate::{tables::global_keyed_map::GlobalKeyedTable, StateMessage};
-use crate::{CheckpointMessage, TableData};
+use crate::{BackingStore, CheckpointMessage, StateBackend, TableData};
 
 use super::expiring_time_key_map::{
     ExpiringTimeKeyTable, ExpiringTimeKeyView, KeyTimeView, LastKeyValueView,
 };
 use super::global_keyed_map::GlobalKeyedView;
+use super::in_flight::{self, InFlightBatch, RestoredInFlightBatch, IN_FLIGHT_TABLE};
 use super::{ErasedCheckpointer, ErasedTable};
 
 #[allow(unused)]
@@ -39,6 +45,8 @@ pub struct TableManager {
     task_info: TaskInfoRef,
     storage: StorageProviderRef,
     caches: HashMap<String, Box<dyn Any + Send>>,
+    // in-flight data persisted by an unaligned checkpoint we're restoring from, if any
+    in_flight: Option<TableCheckpointMetadata>,
 }
 
 pub struct BackendWriter {
@@ -59,6 +67,17 @@ pub struct BackendFlusher {
     table_checkpointers: HashMap<String, Box<dyn ErasedCheckpointer>>,
     current_epoch: u32,
     last_epoch_checkpoints: HashMap<String, TableSubtaskCheckpointMetadata>,
+    // tables with a background compaction in progress
+    compacting: HashSet<String>,
+    compaction_tx: Sender<FinishedCompaction>,
+    compaction_rx: Receiver<FinishedCompaction>,
+    finished_compactions: Vec<FinishedCompaction>,
+}
+
+struct FinishedCompaction {
+    table: String,
+    replaced_files: HashSet<String>,
+    result: Result<TableSubtaskCheckpointMetadata>,
 }
 
 impl BackendFlusher {
@@ -124,12 +143,17 @@ impl BackendFlusher {
                         }
                     }
                 }
+                Some(compaction) = self.compaction_rx.recv() => {
+                    self.compacting.remove(&compaction.table);
+                    self.finished_compactions.push(compaction);
+                }
             }
         }
-        let Some(cp) = checkpoint_epoch else {
+        let Some(mut cp) = checkpoint_epoch else {
             bail!("somehow exited loop without checkpoint_epoch being set");
         };
         let mut metadatas = HashMap::new();
+        let mut table_configs = self.table_configs.clone();
         let mut bytes = 0;
         for (table_name, checkpointer) in self.table_checkpointers.drain() {
             if let Some((subtask_checkpoint_data, size)) = checkpointer.finish(&cp).await? {
@@ -138,6 +162,33 @@ impl BackendFlusher {
             }
         }
 
+        if let Some(in_flight_rx) = cp.in_flight.take() {
+            let batches = in_flight_rx.await.map_err(|_| {
+                anyhow!(
+                    "operator stopped before completing unaligned checkpoint {}",
+                    cp.epoch
+                )
+            })?;
+
+            if !batches.is_empty() {
+                if let Some((subtask_checkpoint_data, size)) = in_flight::write_in_flight(
+                    self.task_info.clone(),
+                    self.storage.clone(),
+                    &cp,
+                    batches,
+                )
+                .await?
+                {
+                    metadatas.insert(IN_FLIGHT_TABLE.to_string(), subtask_checkpoint_data);
+                    table_configs.insert(
+                        IN_FLIGHT_TABLE.to_string(),
+                        in_flight::in_flight_table_config(),
+                    );
+                    bytes += size;
+                }
+            }
+        }
+
         if let Some(compaction_metas) = compacted_tables {
             for (table_name, compacted_metadata) in compaction_metas {
                 let table = self.tables.get(&table_name).unwrap();
@@ -158,7 +209,34 @@ impl BackendFlusher {
                 }
             }
         }
+        for compaction in self.finished_compactions.drain(..) {
+            let compacted = match compaction.result {
+                Ok(compacted) => compacted,
+                Err(err) => {
+                    // the uncompacted files are still in place, so this only costs us efficiency
+                    warn!(
+                        "background compaction of table {} for operator {} failed: {:?}",
+                        compaction.table, self.task_info.operator_id, err
+                    );
+                    continue;
+                }
+            };
+            let (Some(table), Some(current_metadata)) = (
+                self.tables.get(&compaction.table),
+                metadatas.get(&compaction.table),
+            ) else {
+                continue;
+            };
+            let new_metadata = table.apply_background_compaction(
+                &compaction.replaced_files,
+                compacted,
+                current_metadata.clone(),
+            )?;
+            metadatas.insert(compaction.table, new_metadata);
+        }
+
         self.last_epoch_checkpoints = metadatas.clone();
+        self.last_epoch_checkpoints.remove(IN_FLIGHT_TABLE);
         self.current_epoch += 1;
 
         // send controller the subtask metadata
@@ -168,7 +246,7 @@ impl BackendFlusher {
             finish_time: to_micros(SystemTime::now()),
             watermark: cp.watermark.map(to_micros),
             table_metadata: metadatas,
-            table_configs: self.table_configs.clone(),
+            table_configs,
             bytes: bytes as u64,
         };
         self.control_tx
@@ -178,6 +256,10 @@ impl BackendFlusher {
                 subtask_metadata,
             }))
             .await?;
+        if !cp.then_stop {
+            self.start_compactions(cp.epoch)?;
+        }
+
         if cp.then_stop {
             self.finish_tx
                 .take()
@@ -190,6 +272,48 @@ impl BackendFlusher {
     }
 }
 
+impl BackendFlusher {
+    // compacts tables in the background so that checkpoints only need to upload the data that
+    // changed in each epoch, while the number of files to restore from stays bounded
+    fn start_compactions(&mut self, epoch: u32) -> Result<()> {
+        for (table_name, metadata) in &self.last_epoch_checkpoints {
+            if self.compacting.contains(table_name) {
+                continue;
+            }
+            let Some(table) = self.tables.get(table_name) else {
+                continue;
+            };
+            let Some((replaced_files, compaction)) =
+                table.background_compaction(epoch, metadata)?
+            else {
+                continue;
+            };
+
+            debug!(
+                "starting background compaction of {} files for table {} of operator {}",
+                replaced_files.len(),
+                table_name,
+                self.task_info.operator_id
+            );
+            self.compacting.insert(table_name.clone());
+            let table = table_name.clone();
+            let tx = self.compaction_tx.clone();
+            tokio::spawn(async move {
+                let result = compaction.await;
+                // the flusher may have shut down in the meantime
+                let _ = tx
+                    .send(FinishedCompaction {
+                        table,
+                        replaced_files,
+                        result,
+                    })
+                    .await;
+            });
+        }
+        Ok(())
+    }
+}
+
 impl BackendWriter {
     fn new(
         task_info: TaskInfoRef,
@@ -202,6 +326,7 @@ impl BackendWriter {
     ) -> Self {
         let (tx, rx) = mpsc::channel(1024 * 1024);
         let (finish_tx, finish_rx) = oneshot::channel();
+        let (compaction_tx, compaction_rx) = mpsc::channel(16);
 
         (BackendFlusher {
             queue: rx,
@@ -214,6 +339,10 @@ impl BackendWriter {
             current_epoch,
             table_checkpointers: HashMap::new(),
             last_epoch_checkpoints,
+            compacting: HashSet::new(),
+            compaction_tx,
+            compaction_rx,
+            finished_compactions: vec![],
         })
         .start();
 
@@ -281,6 +410,7 @@ impl TableManager {
         let epoch;
         let min_epoch;
         let mut last_epoch_checkpoints = HashMap::new();
+        let mut in_flight = None;
         match checkpoint_metadata {
             Some(metadata) => {
                 // TODO: validate this logic.
@@ -290,6 +420,10 @@ impl TableManager {
                 epoch = operator_metadata.epoch + 1;
                 min_epoch = operator_metadata.epoch;
                 for (table, table_metadata) in metadata.table_checkpoint_metadata.clone() {
+                    if table == IN_FLIGHT_TABLE {
+                        in_flight = Some(table_metadata);
+                        continue;
+                    }
                     let table_implementation = tables
                         .get(&table)
                         .ok_or_else(|| anyhow!("missing table {}", table))?;
@@ -323,6 +457,7 @@ impl TableManager {
             task_info,
             storage,
             caches: HashMap::new(),
+            in_flight,
         })
     }
 
@@ -334,6 +469,7 @@ impl TableManager {
                 time: barrier.timestamp,
                 watermark,
                 then_stop: barrier.then_stop,
+                in_flight: None,
             }))
             .await
             .expect("should be able to send checkpoint");
@@ -346,6 +482,44 @@ impl TableManager {
         }
     }
 
+    /// Starts an unaligned checkpoint of the operator's state. The checkpoint is completed by
+    /// sending the data that arrives on each input before that input delivers the barrier.
+    pub async fn checkpoint_unaligned(
+        &mut self,
+        barrier: CheckpointBarrier,
+        watermark: Option<SystemTime>,
+    ) -> oneshot::Sender<Vec<InFlightBatch>> {
+        let (tx, rx) = oneshot::channel();
+        self.writer
+            .sender
+            .send(StateMessage::Checkpoint(CheckpointMessage {
+                epoch: barrier.epoch,
+                time: barrier.timestamp,
+                watermark,
+                then_stop: false,
+                in_flight: Some(rx),
+            }))
+            .await
+            .expect("should be able to send checkpoint");
+        tx
+    }
+
+    /// Takes the in-flight data persisted by the unaligned checkpoint this operator was restored
+    /// from, which must be replayed before processing any new data
+    pub async fn take_in_flight(&mut self) -> Result<Vec<RestoredInFlightBatch>> {
+        let Some(metadata) = self.in_flight.take() else {
+            return Ok(vec![]);
+        };
+
+        in_flight::read_in_flight(
+            self.task_info.clone(),
+            self.storage.clone(),
+            metadata,
+            self.writer.sender.clone(),
+        )
+        .await
+    }
+
     pub async fn load_compacted(&mut self, compacted: CompactionResult) -> Result<()> {
         if compacted.operator_id != self.task_info.operator_id {
             bail!("shouldn't be loading compaction for other operator");
@@ -357,6 +531,12 @@ impl TableManager {
         Ok(())
     }
 
+    /// The first epoch this operator will process: one past the checkpoint it was restored from,
+    /// or 1 if it is starting fresh
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
     pub async fn insert_committing_data(&mut self, table: &str, data: Vec<u8>) -> Result<()> {
         self.writer
             .sender
@@ -403,6 +583,43 @@ impl TableManager {
         Ok(cache)
     }
 
+    /// Reads a global table as of an earlier checkpoint, including the data written by every other
+    /// subtask; returns None if that checkpoint's metadata hasn't been written
+    pub async fn read_global_keyed_state_at<K: Key, V: Data>(
+        &self,
+        table_name: &str,
+        epoch: u32,
+    ) -> Result<Option<HashMap<K, V>>> {
+        let Some(metadata) = StateBackend::load_operator_metadata(
+            &self.task_info.job_id,
+            &self.task_info.operator_id,
+            epoch,
+        )
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let (Some(config), Some(table_metadata)) = (
+            metadata.table_configs.get(table_name),
+            metadata.table_checkpoint_metadata.get(table_name),
+        ) else {
+            return Ok(Some(HashMap::new()));
+        };
+
+        let table = <GlobalKeyedTable as ErasedTable>::from_config(
+            config.clone(),
+            self.task_info.clone(),
+            self.storage.clone(),
+            Some(table_metadata.clone()),
+        )?;
+
+        let view = table
+            .memory_view::<K, V>(self.writer.sender.clone())
+            .await?;
+        Ok(Some(view.get_all().clone()))
+    }
+
     pub async fn get_expiring_time_key_table(
         &mut self,
         table_name: &str,
@@ -102,6 +102,7 @@ pub const DATABASE_PASSWORD_ENV: &str = "DATABASE_PASSWORD";
 pub const ADMIN_PORT_ENV: &str = "ADMIN_PORT";
 pub const GRPC_PORT_ENV: &str = "GRPC_PORT";
 pub const HTTP_PORT_ENV: &str = "HTTP_PORT";
+pub const INGEST_PORT_ENV: &str = "INGEST_PORT";
 pub const COMPILER_PORT_ENV: &str = "COMPILER_PORT";
 
 pub const UPDATE_AGGREGATE_FLUSH_MS_ENV: &str = "UPDATE_AGGREGATE_FLUSH_MS";
@@ -235,6 +236,7 @@ impl DatabaseConfig {
 pub mod ports {
     pub const CONTROLLER_GRPC: u16 = 9190;
     pub const CONTROLLER_ADMIN: u16 = 9191;
+    pub const CONTROLLER_INGEST: u16 = 9192;
 
     pub const NODE_GRPC: u16 = 9290;
     pub const NODE_ADMIN: u16 = 9291;
@@ -640,6 +642,7 @@ pub static BATCHES_RECV: &str = "arroyo_worker_batches_recv";
 pub static BATCHES_SENT: &str = "arroyo_worker_batches_sent";
 pub static TX_QUEUE_SIZE: &str = "arroyo_worker_tx_queue_size";
 pub static TX_QUEUE_REM: &str = "arroyo_worker_tx_queue_rem";
+pub static SOURCE_LAG: &str = "arroyo_worker_source_lag";
 pub static DESERIALIZATION_ERRORS: &str = "arroyo_worker_deserialization_errors";
 
 #[derive(Debug, Copy, Clone, Encode, Decode, PartialEq, Eq)]
@@ -648,6 +651,11 @@ pub struct CheckpointBarrier {
     pub min_epoch: u32,
     pub timestamp: SystemTime,
     pub then_stop: bool,
+    // sources advance the watermark to infinity before the barrier, flushing all windows and timers
+    pub drain: bool,
+    // the barrier skips ahead of queued data and operators don't align it across their inputs;
+    // instead the data it overtook is persisted along with their state
+    pub unaligned: bool,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Hash, Serialize)]
@@ -145,11 +145,12 @@ impl ArrowOperator for JoinWithExpiration {
     async fn process_batch_index(
         &mut self,
         index: usize,
-        total_inputs: usize,
+        _total_inputs: usize,
         record_batch: RecordBatch,
         ctx: &mut ArrowContext,
     ) {
-        match index / (total_inputs / 2) {
+        // the left side's edge sorts before the right's, and the two may differ in parallelism
+        match ctx.logical_input(index).0 {
             0 => self
                 .process_left(record_batch, ctx)
                 .await
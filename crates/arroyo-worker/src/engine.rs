@@ -1,6 +1,6 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::{mem, thread};
 
 use std::time::SystemTime;
@@ -45,6 +45,7 @@ use petgraph::Direction;
 use prometheus::labels;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::Barrier;
+use tokio::task::AbortHandle;
 
 #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
 pub struct TimerValue<K: Key, T: Decode + Encode + Clone + PartialEq + Eq> {
@@ -57,7 +58,6 @@ pub struct SubtaskNode {
     pub id: String,
     pub subtask_idx: usize,
     pub parallelism: usize,
-    pub in_schemas: Vec<ArroyoSchema>,
     pub out_schema: Option<ArroyoSchema>,
     pub projection: Option<Vec<usize>>,
     pub node: OperatorNode,
@@ -217,11 +217,6 @@ impl Program {
         }
 
         for idx in logical.node_indices() {
-            let in_schemas: Vec<_> = logical
-                .edges_directed(idx, Direction::Incoming)
-                .map(|edge| edge.weight().schema.clone())
-                .collect();
-
             let out_schema = logical
                 .edges_directed(idx, Direction::Outgoing)
                 .map(|edge| edge.weight().schema.clone())
@@ -243,7 +238,6 @@ impl Program {
                     id: node.operator_id.clone(),
                     subtask_idx: i,
                     parallelism,
-                    in_schemas: in_schemas.clone(),
                     out_schema: out_schema.clone(),
                     node: construct_operator(
                         node.operator_name,
@@ -331,6 +325,8 @@ impl Program {
     }
 }
 
+type TaskHandles = Arc<Mutex<HashMap<(String, usize), AbortHandle>>>;
+
 pub struct Engine {
     program: Program,
     worker_id: WorkerId,
@@ -338,19 +334,111 @@ pub struct Engine {
     job_id: String,
     network_manager: NetworkManager,
     assignments: HashMap<(String, usize), TaskAssignment>,
+    task_handles: TaskHandles,
 }
 
 pub struct StreamConfig {
     pub restore_epoch: Option<u32>,
 }
 
+async fn load_checkpoint_metadata(
+    job_id: &str,
+    restore_epoch: Option<u32>,
+) -> Option<CheckpointMetadata> {
+    let epoch = restore_epoch?;
+    info!("Restoring checkpoint {} for job {}", epoch, job_id);
+    Some(
+        StateBackend::load_checkpoint_metadata(job_id, epoch)
+            .await
+            .unwrap_or_else(|_| panic!("failed to load checkpoint metadata for epoch {}", epoch)),
+    )
+}
+
 pub struct RunningEngine {
     program: Program,
     assignments: HashMap<(String, usize), TaskAssignment>,
     worker_id: WorkerId,
+    job_id: String,
+    run_id: String,
+    control_tx: Sender<ControlResp>,
+    task_handles: TaskHandles,
 }
 
 impl RunningEngine {
+    /// Restarts the given local tasks from the checkpoint at `restore_epoch`, rebuilding them
+    /// from the logical graph. The tasks must make up a failover region, so that none of their
+    /// queues are shared with tasks that keep running.
+    pub async fn restart_tasks(
+        &mut self,
+        tasks: &HashSet<(String, usize)>,
+        logical: &LogicalGraph,
+        registry: Registry,
+        restore_epoch: Option<u32>,
+    ) {
+        info!(
+            "Restarting {} tasks for job {} from epoch {:?}",
+            tasks.len(),
+            self.job_id,
+            restore_epoch
+        );
+
+        {
+            let mut handles = self.task_handles.lock().unwrap();
+            for task in tasks {
+                if let Some(handle) = handles.remove(task) {
+                    handle.abort();
+                }
+            }
+        }
+
+        let assignments: Vec<_> = self.assignments.values().cloned().collect();
+        let engine = Engine {
+            program: Program::from_logical(
+                self.program.name.clone(),
+                logical,
+                &assignments,
+                registry,
+            ),
+            worker_id: self.worker_id,
+            run_id: self.run_id.clone(),
+            job_id: self.job_id.clone(),
+            network_manager: NetworkManager::new(0),
+            assignments: self.assignments.clone(),
+            task_handles: self.task_handles.clone(),
+        };
+
+        let checkpoint_metadata = load_checkpoint_metadata(&self.job_id, restore_epoch).await;
+
+        let node_indexes: Vec<_> = {
+            let graph = engine.program.graph.read().unwrap();
+            graph
+                .node_indices()
+                .filter(|idx| {
+                    let w = graph.node_weight(*idx).unwrap();
+                    tasks.contains(&(w.id().to_string(), w.subtask_idx()))
+                })
+                .collect()
+        };
+
+        let ready = Arc::new(Barrier::new(node_indexes.len()));
+        for idx in &node_indexes {
+            engine
+                .schedule_node(&checkpoint_metadata, &self.control_tx, *idx, ready.clone())
+                .await;
+        }
+
+        // both graphs are built from the same logical graph and assignments, so their node
+        // indices line up, and we can swap the restarted tasks' queues into the running program
+        let mut new_graph = engine.program.graph.write().unwrap();
+        let mut graph = self.program.graph.write().unwrap();
+        for idx in node_indexes {
+            mem::swap(
+                graph.node_weight_mut(idx).unwrap(),
+                new_graph.node_weight_mut(idx).unwrap(),
+            );
+        }
+    }
+
     pub fn source_controls(&self) -> Vec<Sender<ControlMessage>> {
         let graph = self.program.graph.read().unwrap();
         graph
@@ -435,6 +523,7 @@ impl Engine {
             run_id,
             network_manager,
             assignments,
+            task_handles: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -472,24 +561,15 @@ impl Engine {
             run_id: "0".to_string(),
             network_manager: NetworkManager::new(0),
             assignments,
+            task_handles: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
     pub async fn start(mut self, config: StreamConfig) -> (RunningEngine, Receiver<ControlResp>) {
         info!("Starting job {}", self.job_id);
 
-        let checkpoint_metadata = if let Some(epoch) = config.restore_epoch {
-            info!("Restoring checkpoint {} for job {}", epoch, self.job_id);
-            Some(
-                StateBackend::load_checkpoint_metadata(&self.job_id, epoch)
-                    .await
-                    .unwrap_or_else(|_| {
-                        panic!("failed to load checkpoint metadata for epoch {}", epoch)
-                    }),
-            )
-        } else {
-            None
-        };
+        let checkpoint_metadata =
+            load_checkpoint_metadata(&self.job_id, config.restore_epoch).await;
 
         let node_indexes: Vec<_> = self.program.graph.read().unwrap().node_indices().collect();
 
@@ -530,6 +610,10 @@ impl Engine {
                 program: self.program,
                 assignments: self.assignments,
                 worker_id,
+                job_id: self.job_id,
+                run_id: self.run_id,
+                control_tx,
+                task_handles: self.task_handles,
             },
             control_rx,
         )
@@ -674,7 +758,8 @@ impl Engine {
             node.parallelism
         );
 
-        let mut in_qs_map: BTreeMap<(LogicalEdgeType, usize), Vec<BatchReceiver>> = BTreeMap::new();
+        let mut in_qs_map: BTreeMap<(LogicalEdgeType, usize), (ArroyoSchema, Vec<BatchReceiver>)> =
+            BTreeMap::new();
         let mut out_qs_map: BTreeMap<usize, BTreeMap<usize, BatchSender>> = BTreeMap::new();
         let task_info = {
             let mut graph = self.program.graph.write().unwrap();
@@ -683,7 +768,8 @@ impl Engine {
                     let weight = graph.edge_weight_mut(edge).unwrap();
                     in_qs_map
                         .entry((weight.edge, weight.in_logical_idx))
-                        .or_default()
+                        .or_insert_with(|| (weight.schema.clone(), vec![]))
+                        .1
                         .push(weight.rx.take().unwrap());
                 }
             }
@@ -713,15 +799,19 @@ impl Engine {
         let task_index = task_info.task_index;
 
         let tables = node.node.tables();
-        let in_qs: Vec<_> = in_qs_map.into_values().flatten().collect();
+        // each logical input's queues are contiguous, and its schema and number of partitions
+        // are at the same position in in_schemas and input_partitions
+        let (in_schemas, in_qs): (Vec<_>, Vec<_>) = in_qs_map.into_values().unzip();
+        let input_partitions: Vec<_> = in_qs.iter().map(Vec::len).collect();
+        let in_qs: Vec<_> = in_qs.into_iter().flatten().collect();
 
         let ctx = ArrowContext::new(
             task_info,
             checkpoint_metadata.clone(),
             control_rx,
             control_tx.clone(),
-            in_qs.len(),
-            node.in_schemas,
+            input_partitions,
+            in_schemas,
             node.out_schema,
             node.projection,
             out_qs_map
@@ -736,6 +826,10 @@ impl Engine {
         let join_task = tokio::spawn(async move {
             operator.start(ctx, in_qs, ready).await;
         });
+        self.task_handles
+            .lock()
+            .unwrap()
+            .insert((operator_id.clone(), task_index), join_task.abort_handle());
 
         let send_copy = control_tx.clone();
         tokio::spawn(async move {
@@ -747,16 +841,21 @@ impl Engine {
                 })
                 .await
                 .unwrap();
-            if let Err(error) = join_task.await {
-                send_copy
-                    .send(ControlResp::TaskFailed {
-                        operator_id,
-                        task_index,
-                        error: error.to_string(),
-                    })
-                    .await
-                    .ok();
-            };
+            match join_task.await {
+                // the task was aborted to be restarted with its failover region
+                Err(error) if error.is_cancelled() => {}
+                Err(error) => {
+                    send_copy
+                        .send(ControlResp::TaskFailed {
+                            operator_id,
+                            task_index,
+                            error: error.to_string(),
+                        })
+                        .await
+                        .ok();
+                }
+                Ok(_) => {}
+            }
         });
     }
 
@@ -2,7 +2,7 @@
 // TODO: factor out complex types
 #![allow(clippy::type_complexity)]
 
-use crate::engine::{Engine, Program, StreamConfig, SubtaskNode};
+use crate::engine::{Engine, Program, RunningEngine, StreamConfig, SubtaskNode};
 use crate::network_manager::NetworkManager;
 use anyhow::Result;
 
@@ -10,14 +10,15 @@ use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
 use arroyo_rpc::grpc::worker_grpc_server::{WorkerGrpc, WorkerGrpcServer};
 use arroyo_rpc::grpc::{
     api, CheckpointReq, CheckpointResp, CommitReq, CommitResp, HeartbeatReq, JobFinishedReq,
-    JobFinishedResp, LoadCompactedDataReq, LoadCompactedDataRes, RegisterWorkerReq,
-    StartExecutionReq, StartExecutionResp, StopExecutionReq, StopExecutionResp,
-    TaskCheckpointCompletedReq, TaskCheckpointEventReq, TaskFailedReq, TaskFinishedReq,
-    TaskStartedReq, WorkerErrorReq, WorkerResources,
+    JobFinishedResp, LoadCompactedDataReq, LoadCompactedDataRes, ReconnectWorkerReq,
+    RegisterWorkerReq, RestartTasksReq, RestartTasksResp, StartExecutionReq, StartExecutionResp,
+    StopExecutionReq, StopExecutionResp, TaskCheckpointCompletedReq, TaskCheckpointEventReq,
+    TaskFailedReq, TaskFinishedReq, TaskMetrics, TaskStartedReq, WorkerErrorReq, WorkerResources,
 };
 use arroyo_types::{
     default_controller_addr, from_millis, grpc_port, to_micros, CheckpointBarrier, NodeId,
-    WorkerId, ARROYO_PROGRAM_ENV, ARROYO_PROGRAM_FILE_ENV, JOB_ID_ENV, RUN_ID_ENV,
+    WorkerId, ARROYO_PROGRAM_ENV, ARROYO_PROGRAM_FILE_ENV, JOB_ID_ENV, RUN_ID_ENV, SOURCE_LAG,
+    TX_QUEUE_REM, TX_QUEUE_SIZE,
 };
 use local_ip_address::local_ip;
 use rand::random;
@@ -30,11 +31,12 @@ use std::fmt::{Debug, Display, Formatter};
 use std::future::Future;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::net::TcpListener;
 use tokio::select;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Channel;
 use tonic::{Request, Response, Status};
 use tracing::{debug, error, info, warn};
 
@@ -44,6 +46,7 @@ use prost::Message;
 
 use arroyo_datastream::logical::{LogicalGraph, LogicalProgram, ProgramConfig};
 use arroyo_df::physical::new_registry;
+use arroyo_operator::operator::Registry;
 use arroyo_server_common::shutdown::ShutdownGuard;
 
 pub mod arrow;
@@ -54,6 +57,10 @@ mod network_manager;
 pub const PROMETHEUS_PUSH_GATEWAY: &str = "localhost:9091";
 pub const METRICS_PUSH_INTERVAL: Duration = Duration::from_secs(1);
 
+// how long we'll try to re-register with a controller (for example, a newly-elected leader) after
+// losing our connection to it before shutting down
+const CONTROLLER_RECONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
 pub static TIMER_TABLE: char = '[';
 
 #[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
@@ -97,6 +104,7 @@ struct EngineState {
     sources: Vec<Sender<ControlMessage>>,
     sinks: Vec<Sender<ControlMessage>>,
     operator_controls: HashMap<String, Vec<Sender<ControlMessage>>>, // operator_id -> vec of control tx
+    engine: Arc<tokio::sync::Mutex<RunningEngine>>,
     shutdown_guard: ShutdownGuard,
 }
 
@@ -149,6 +157,7 @@ pub struct WorkerServer {
     program_config: ProgramConfig,
     state: Arc<Mutex<Option<EngineState>>>,
     network: Arc<Mutex<Option<NetworkManager>>>,
+    registration: Option<RegisterWorkerReq>,
     shutdown_guard: ShutdownGuard,
 }
 
@@ -226,6 +235,7 @@ impl WorkerServer {
             program_config: logical.program_config,
             state: Arc::new(Mutex::new(None)),
             network: Arc::new(Mutex::new(None)),
+            registration: None,
             shutdown_guard,
         }
     }
@@ -238,7 +248,7 @@ impl WorkerServer {
         &self.job_id
     }
 
-    pub async fn start_async(self) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn start_async(mut self) -> Result<(), Box<dyn std::error::Error>> {
         let slots = std::env::var(arroyo_types::TASK_SLOTS_ENV)
             .map(|s| usize::from_str(&s).unwrap())
             .unwrap_or(8);
@@ -272,6 +282,19 @@ impl WorkerServer {
         let data_address = format!("{}:{}", local_ip, data_port);
         let job_id = self.job_id.clone();
 
+        let registration = RegisterWorkerReq {
+            worker_id: id.0,
+            node_id: node_id.map(|n| n.0).unwrap_or(1),
+            job_id,
+            rpc_address,
+            data_address,
+            resources: Some(WorkerResources {
+                slots: std::thread::available_parallelism().unwrap().get() as u64,
+            }),
+            slots: slots as u64,
+        };
+        self.registration = Some(registration.clone());
+
         self.shutdown_guard.child("grpc").into_spawn_task(
             arroyo_server_common::grpc_server()
                 .add_service(WorkerGrpcServer::new(self))
@@ -282,23 +305,31 @@ impl WorkerServer {
         tokio::time::sleep(Duration::from_millis(50)).await;
 
         client
-            .register_worker(Request::new(RegisterWorkerReq {
-                worker_id: id.0,
-                node_id: node_id.map(|n| n.0).unwrap_or(1),
-                job_id,
-                rpc_address,
-                data_address,
-                resources: Some(WorkerResources {
-                    slots: std::thread::available_parallelism().unwrap().get() as u64,
-                }),
-                slots: slots as u64,
-            }))
+            .register_worker(Request::new(registration))
             .await
             .unwrap();
 
         Ok(())
     }
 
+    async fn load_registry(&self) -> Result<Registry, Status> {
+        let mut registry = new_registry();
+
+        for (udf_name, dylib_config) in &self.program_config.udf_dylibs {
+            info!("Loading UDF {}", udf_name);
+            registry
+                .load_dylib(udf_name, dylib_config)
+                .await
+                .map_err(|e| {
+                    Status::failed_precondition(
+                        e.context(format!("loading UDF {udf_name}")).to_string(),
+                    )
+                })?;
+        }
+
+        Ok(registry)
+    }
+
     #[tokio::main]
     pub async fn start(self) -> Result<(), Box<dyn std::error::Error>> {
         self.start_async().await
@@ -309,6 +340,7 @@ impl WorkerServer {
         mut control_rx: Receiver<ControlResp>,
         worker_id: WorkerId,
         job_id: String,
+        reconnect: ReconnectWorkerReq,
     ) -> impl Future<Output = ()> {
         let addr = self.controller_addr.clone();
 
@@ -323,88 +355,25 @@ impl WorkerServer {
             loop {
                 select! {
                     msg = control_rx.recv() => {
-                        let err = match msg {
-                            Some(ControlResp::CheckpointEvent(c)) => {
-                                controller.task_checkpoint_event(Request::new(
-                                    TaskCheckpointEventReq {
-                                        worker_id: worker_id.0,
-                                        time: to_micros(c.time),
-                                        job_id: job_id.clone(),
-                                        operator_id: c.operator_id,
-                                        subtask_index: c.subtask_index,
-                                        epoch: c.checkpoint_epoch,
-                                        event_type: c.event_type as i32,
-                                    }
-                                )).await.err()
-                            }
-                            Some(ControlResp::CheckpointCompleted(c)) => {
-                                controller.task_checkpoint_completed(Request::new(
-                                    TaskCheckpointCompletedReq {
-                                        worker_id: worker_id.0,
-                                        time: c.subtask_metadata.finish_time,
-                                        job_id: job_id.clone(),
-                                        operator_id: c.operator_id,
-                                        epoch: c.checkpoint_epoch,
-                                        needs_commit: false,
-                                        metadata: Some(c.subtask_metadata),
-                                    }
-                                )).await.err()
-                            }
-                            Some(ControlResp::TaskFinished { operator_id, task_index }) => {
-                                info!(message = "Task finished", operator_id, task_index);
-                                controller.task_finished(Request::new(
-                                    TaskFinishedReq {
-                                        worker_id: worker_id.0,
-                                        job_id: job_id.clone(),
-                                        time: to_micros(SystemTime::now()),
-                                        operator_id: operator_id.to_string(),
-                                        operator_subtask: task_index as u64,
-                                    }
-                                )).await.err()
-                            }
-                            Some(ControlResp::TaskFailed { operator_id, task_index, error }) => {
-                                controller.task_failed(Request::new(
-                                    TaskFailedReq {
-                                        worker_id: worker_id.0,
-                                        job_id: job_id.clone(),
-                                        time: to_micros(SystemTime::now()),
-                                        operator_id: operator_id.to_string(),
-                                        operator_subtask: task_index as u64,
-                                        error,
-                                    }
-                                )).await.err()
-                            }
-                            Some(ControlResp::Error { operator_id, task_index, message, details}) => {
-                                controller.worker_error(Request::new(
-                                    WorkerErrorReq {
-                                        job_id: job_id.clone(),
-                                        operator_id,
-                                        task_index: task_index as u32,
-                                        message,
-                                        details
-                                    }
-                                )).await.err()
-                            }
-                            Some(ControlResp::TaskStarted {operator_id, task_index, start_time}) => {
-                                controller.task_started(Request::new(
-                                    TaskStartedReq {
-                                        worker_id: worker_id.0,
-                                        job_id: job_id.clone(),
-                                        time: to_micros(start_time),
-                                        operator_id: operator_id.to_string(),
-                                        operator_subtask: task_index as u64,
-                                    }
-                                )).await.err()
-                            }
-                            None => {
-                                // TODO: remove the control queue from the select at this point
-                                tokio::time::sleep(Duration::from_millis(50)).await;
-                                None
-                            }
+                        let Some(msg) = msg else {
+                            // TODO: remove the control queue from the select at this point
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            continue;
                         };
-                        if let Some(err) = err {
-                            error!("encountered control message failure {}", err);
-                            cancel_token.cancel();
+
+                        if let Err(err) = send_control_resp(&mut controller, msg.clone(), worker_id, &job_id).await {
+                            warn!("encountered control message failure {}; reconnecting to controller", err);
+                            let Some(c) = reconnect_to_controller(&addr, &reconnect).await else {
+                                error!("unable to reconnect to controller; shutting down");
+                                cancel_token.cancel();
+                                break;
+                            };
+                            controller = c;
+
+                            if let Err(err) = send_control_resp(&mut controller, msg, worker_id, &job_id).await {
+                                error!("encountered control message failure {}", err);
+                                cancel_token.cancel();
+                            }
                         }
                     }
                     _ = tick.tick() => {
@@ -412,10 +381,16 @@ impl WorkerServer {
                             job_id: job_id.clone(),
                             time: to_micros(SystemTime::now()),
                             worker_id: worker_id.0,
+                            task_metrics: task_metrics(),
                         })).await;
                         if let Err(err) = result {
-                            error!("heartbeat failed {:?}", err);
-                            break;
+                            warn!("heartbeat failed {:?}; reconnecting to controller", err);
+                            let Some(c) = reconnect_to_controller(&addr, &reconnect).await else {
+                                error!("unable to reconnect to controller; shutting down");
+                                cancel_token.cancel();
+                                break;
+                            };
+                            controller = c;
                         }
                     }
                 }
@@ -424,6 +399,188 @@ impl WorkerServer {
     }
 }
 
+async fn send_control_resp(
+    controller: &mut ControllerGrpcClient<Channel>,
+    msg: ControlResp,
+    worker_id: WorkerId,
+    job_id: &str,
+) -> Result<(), Status> {
+    let job_id = job_id.to_string();
+    match msg {
+        ControlResp::CheckpointEvent(c) => {
+            controller
+                .task_checkpoint_event(Request::new(TaskCheckpointEventReq {
+                    worker_id: worker_id.0,
+                    time: to_micros(c.time),
+                    job_id,
+                    operator_id: c.operator_id,
+                    subtask_index: c.subtask_index,
+                    epoch: c.checkpoint_epoch,
+                    event_type: c.event_type as i32,
+                }))
+                .await?;
+        }
+        ControlResp::CheckpointCompleted(c) => {
+            controller
+                .task_checkpoint_completed(Request::new(TaskCheckpointCompletedReq {
+                    worker_id: worker_id.0,
+                    time: c.subtask_metadata.finish_time,
+                    job_id,
+                    operator_id: c.operator_id,
+                    epoch: c.checkpoint_epoch,
+                    needs_commit: false,
+                    metadata: Some(c.subtask_metadata),
+                }))
+                .await?;
+        }
+        ControlResp::TaskFinished {
+            operator_id,
+            task_index,
+        } => {
+            info!(message = "Task finished", operator_id, task_index);
+            controller
+                .task_finished(Request::new(TaskFinishedReq {
+                    worker_id: worker_id.0,
+                    job_id,
+                    time: to_micros(SystemTime::now()),
+                    operator_id: operator_id.to_string(),
+                    operator_subtask: task_index as u64,
+                }))
+                .await?;
+        }
+        ControlResp::TaskFailed {
+            operator_id,
+            task_index,
+            error,
+        } => {
+            controller
+                .task_failed(Request::new(TaskFailedReq {
+                    worker_id: worker_id.0,
+                    job_id,
+                    time: to_micros(SystemTime::now()),
+                    operator_id: operator_id.to_string(),
+                    operator_subtask: task_index as u64,
+                    error,
+                }))
+                .await?;
+        }
+        ControlResp::Error {
+            operator_id,
+            task_index,
+            message,
+            details,
+        } => {
+            controller
+                .worker_error(Request::new(WorkerErrorReq {
+                    job_id,
+                    operator_id,
+                    task_index: task_index as u32,
+                    message,
+                    details,
+                }))
+                .await?;
+        }
+        ControlResp::TaskStarted {
+            operator_id,
+            task_index,
+            start_time,
+        } => {
+            controller
+                .task_started(Request::new(TaskStartedReq {
+                    worker_id: worker_id.0,
+                    job_id,
+                    time: to_micros(start_time),
+                    operator_id: operator_id.to_string(),
+                    operator_subtask: task_index as u64,
+                }))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Re-registers this worker and its running tasks with the controller, which may have changed
+/// since we started if the previous leader failed
+async fn reconnect_to_controller(
+    addr: &str,
+    req: &ReconnectWorkerReq,
+) -> Option<ControllerGrpcClient<Channel>> {
+    let start = Instant::now();
+    while start.elapsed() < CONTROLLER_RECONNECT_TIMEOUT {
+        match ControllerGrpcClient::connect(addr.to_string()).await {
+            Ok(mut controller) => {
+                match controller.reconnect_worker(Request::new(req.clone())).await {
+                    Ok(_) => {
+                        info!("reconnected to controller");
+                        return Some(controller);
+                    }
+                    Err(e) => {
+                        warn!("failed to re-register with controller: {:?}", e);
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("failed to connect to controller on {}: {:?}", addr, e);
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    None
+}
+
+/// Collects the output queue and source lag metrics for this worker's tasks, which are sent to
+/// the controller with each heartbeat for use by the autoscaler
+fn task_metrics() -> Vec<TaskMetrics> {
+    let mut metrics: HashMap<(String, u32), TaskMetrics> = HashMap::new();
+
+    for family in prometheus::gather() {
+        let name = family.get_name();
+        if name != TX_QUEUE_SIZE && name != TX_QUEUE_REM && name != SOURCE_LAG {
+            continue;
+        }
+
+        for metric in family.get_metric() {
+            let label = |l: &str| {
+                metric
+                    .get_label()
+                    .iter()
+                    .find(|p| p.get_name() == l)
+                    .map(|p| p.get_value())
+            };
+
+            let (Some(operator_id), Some(subtask_index)) = (
+                label("operator_id"),
+                label("subtask_idx").and_then(|s| s.parse::<u32>().ok()),
+            ) else {
+                continue;
+            };
+
+            let value = metric.get_gauge().get_value().max(0.0) as u64;
+
+            let task = metrics
+                .entry((operator_id.to_string(), subtask_index))
+                .or_insert_with(|| TaskMetrics {
+                    operator_id: operator_id.to_string(),
+                    subtask_index,
+                    ..Default::default()
+                });
+
+            if name == TX_QUEUE_SIZE {
+                task.tx_queue_size += value;
+            } else if name == TX_QUEUE_REM {
+                task.tx_queue_rem += value;
+            } else {
+                task.source_lag_millis = Some(value);
+            }
+        }
+    }
+
+    metrics.into_values().collect()
+}
+
 #[tonic::async_trait]
 impl WorkerGrpc for WorkerServer {
     async fn start_execution(
@@ -441,22 +598,8 @@ impl WorkerGrpc for WorkerServer {
         }
 
         let req = request.into_inner();
-        let mut registry = new_registry();
-
-        for (udf_name, dylib_config) in &self.program_config.udf_dylibs {
-            info!("Loading UDF {}", udf_name);
-            registry
-                .load_dylib(udf_name, dylib_config)
-                .await
-                .map_err(|e| {
-                    Status::failed_precondition(
-                        e.context(format!("loading UDF {udf_name}")).to_string(),
-                    )
-                })?;
-            if dylib_config.is_async {
-                continue;
-            }
-        }
+        let registry = self.load_registry().await?;
+        let tasks = req.tasks.clone();
 
         let (engine, control_rx) = {
             let network = { self.network.lock().unwrap().take().unwrap() };
@@ -474,7 +617,7 @@ impl WorkerGrpc for WorkerServer {
                 self.job_id.clone(),
                 self.run_id.clone(),
                 network,
-                req.tasks,
+                req.tasks.clone(),
             );
             engine
                 .start(StreamConfig {
@@ -483,9 +626,26 @@ impl WorkerGrpc for WorkerServer {
                 .await
         };
 
+        let registration = self.registration.clone().unwrap_or_default();
+        let reconnect = ReconnectWorkerReq {
+            worker_id: self.id.0,
+            node_id: registration.node_id,
+            job_id: self.job_id.clone(),
+            run_id: self.run_id.parse().unwrap_or_default(),
+            rpc_address: registration.rpc_address,
+            data_address: registration.data_address,
+            slots: registration.slots,
+            tasks,
+        };
+
         self.shutdown_guard
             .child("control-thread")
-            .into_spawn_task(self.start_control_thread(control_rx, self.id, self.job_id.clone()));
+            .into_spawn_task(self.start_control_thread(
+                control_rx,
+                self.id,
+                self.job_id.clone(),
+                reconnect,
+            ));
 
         let sources = engine.source_controls();
         let sinks = engine.sink_controls();
@@ -496,6 +656,7 @@ impl WorkerGrpc for WorkerServer {
             sources,
             sinks,
             operator_controls,
+            engine: Arc::new(tokio::sync::Mutex::new(engine)),
             shutdown_guard: self.shutdown_guard.child("engine-state"),
         });
 
@@ -551,6 +712,8 @@ impl WorkerGrpc for WorkerServer {
             min_epoch: req.min_epoch,
             timestamp: from_millis(req.timestamp),
             then_stop: req.then_stop,
+            drain: req.drain,
+            unaligned: req.unaligned,
         };
 
         for n in &senders {
@@ -648,6 +811,54 @@ impl WorkerGrpc for WorkerServer {
         Ok(Response::new(StopExecutionResp {}))
     }
 
+    async fn restart_tasks(
+        &self,
+        request: Request<RestartTasksReq>,
+    ) -> Result<Response<RestartTasksResp>, Status> {
+        let req = request.into_inner();
+
+        let engine = {
+            let state = self.state.lock().unwrap();
+            let Some(state) = state.as_ref() else {
+                return Err(Status::failed_precondition(
+                    "Worker has not yet started execution",
+                ));
+            };
+            state.engine.clone()
+        };
+
+        if let Some(task) = req.tasks.iter().find(|t| t.worker_id != self.id.0) {
+            return Err(Status::invalid_argument(format!(
+                "task {}-{} is not assigned to this worker",
+                task.operator_id, task.operator_subtask
+            )));
+        }
+
+        let tasks: HashSet<_> = req
+            .tasks
+            .iter()
+            .map(|t| (t.operator_id.clone(), t.operator_subtask as usize))
+            .collect();
+
+        let registry = self.load_registry().await?;
+
+        let mut engine = engine.lock().await;
+        engine
+            .restart_tasks(&tasks, &self.logical_graph, registry, req.restore_epoch)
+            .await;
+
+        // the restarted tasks have new control queues
+        let mut state = self.state.lock().unwrap();
+        let state = state.as_mut().unwrap();
+        state.sources = engine.source_controls();
+        state.sinks = engine.sink_controls();
+        state.operator_controls = engine.operator_controls();
+
+        info!("[{:?}] Restarted {} tasks", self.id, tasks.len());
+
+        Ok(Response::new(RestartTasksResp {}))
+    }
+
     async fn job_finished(
         &self,
         _request: Request<JobFinishedReq>,
@@ -74,6 +74,22 @@ impl Senders {
                     .expect("couldn't decode signal message, probably a record.")
                     .0,
             ),
+            MessageType::PrioritySignal => {
+                // the signal overtook data that the sender had queued, which is still to come on
+                // this link; it's prefixed with the number of messages it overtook
+                let mut buf = &data[..];
+                let overtaken = buf.get_u64_le() as usize;
+                let message = ArrowMessage::Signal(
+                    bincode::decode_from_slice(buf, config::standard())
+                        .expect("couldn't decode signal message, probably a record.")
+                        .0,
+                );
+
+                if let Err(send_error) = sender.tx.send_overtaking(message, overtaken) {
+                    warn!("couldn't send {:?}", send_error.0);
+                }
+                return;
+            }
         };
 
         if let Err(send_error) = sender.tx.send(message).await {
@@ -96,6 +112,7 @@ pub struct InNetworkLink {
 pub enum MessageType {
     Data,
     Signal,
+    PrioritySignal,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -139,6 +156,7 @@ impl Header {
             message_type: match bytes.get_u32_le() {
                 0 => MessageType::Data,
                 1 => MessageType::Signal,
+                2 => MessageType::PrioritySignal,
                 b => panic!("invalid message type: {}", b),
             },
         }
@@ -155,6 +173,7 @@ impl Header {
         buf.put_u32_le(match self.message_type {
             MessageType::Data => 0,
             MessageType::Signal => 1,
+            MessageType::PrioritySignal => 2,
         });
 
         writer.write_all(&bytes).await.unwrap();
@@ -255,9 +274,11 @@ impl OutNetworkLink {
                 dictionary_tracker,
             } in self.receivers
             {
+                // unaligned barriers are sent ahead of queued data, so that they don't wait behind
+                // it for the network
                 let stream = async_stream::stream! {
-                    while let Some(item) = rx.recv().await {
-                        yield (quad, dictionary_tracker.clone(), item);
+                    while let Some((item, overtaken)) = rx.recv_prioritized().await {
+                        yield (quad, dictionary_tracker.clone(), item, overtaken);
                     }
                 };
                 sel.push(Box::pin(stream));
@@ -268,8 +289,15 @@ impl OutNetworkLink {
 
             loop {
                 select! {
-                    Some(((quad, dictionary_tracker, msg), s)) = sel.next() => {
+                    Some(((quad, dictionary_tracker, msg, overtaken), s)) = sel.next() => {
                         match msg {
+                            ArrowMessage::Signal(signal) if overtaken > 0 => {
+                                let mut data = (overtaken as u64).to_le_bytes().to_vec();
+                                bincode::encode_into_std_write(&signal, &mut data, config::standard()).unwrap();
+                                let header = Header::from_quad(quad, data.len(), MessageType::PrioritySignal);
+                                header.write(&mut Pin::new(&mut self.stream)).await;
+                                self.stream.write_all(&data).await.unwrap();
+                            }
                             ArrowMessage::Signal(signal) => {
                                 let data = bincode::encode_to_vec(&signal, config::standard()).unwrap();
                                 let header = Header::from_quad(quad, data.len(), MessageType::Signal);
@@ -591,6 +619,8 @@ mod test {
             min_epoch: 3,
             timestamp: SystemTime::now(),
             then_stop: false,
+            drain: false,
+            unaligned: false,
         }));
 
         client_tx.send(message.clone()).await.unwrap();
@@ -602,4 +632,77 @@ mod test {
 
         assert_eq!(result, message);
     }
+
+    #[tokio::test]
+    async fn test_unaligned_barrier_overtakes_data() {
+        let (server_tx, mut server_rx) = batch_bounded(10);
+
+        let quad = Quad {
+            src_id: 1,
+            src_idx: 0,
+            dst_id: 2,
+            dst_idx: 0,
+        };
+
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "id",
+            arrow_schema::DataType::UInt64,
+            false,
+        )]));
+
+        let mut senders = Senders::new();
+        senders.add(quad, schema.clone(), server_tx);
+
+        let shutdown = Shutdown::new("test");
+        let mut nm = NetworkManager::new(0);
+        let port = nm.open_listener(shutdown.guard("test")).await;
+
+        let (client_tx, client_rx) = batch_bounded(10);
+        nm.connect(format!("localhost:{}", port), quad, client_rx)
+            .await;
+
+        // queue data and then a barrier before the link starts forwarding
+        let batches: Vec<_> = (0..2)
+            .map(|i| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(UInt64Array::from(vec![i])) as ArrayRef],
+                )
+                .unwrap()
+            })
+            .collect();
+        for batch in &batches {
+            client_tx
+                .send(ArrowMessage::Data(batch.clone()))
+                .await
+                .unwrap();
+        }
+
+        let barrier = ArrowMessage::Signal(SignalMessage::Barrier(CheckpointBarrier {
+            epoch: 1,
+            min_epoch: 0,
+            timestamp: SystemTime::now(),
+            then_stop: false,
+            drain: false,
+            unaligned: true,
+        }));
+        client_tx.send(barrier.clone()).await.unwrap();
+
+        nm.start(senders).await;
+
+        let (result, overtaken) = timeout(Duration::from_secs(1), server_rx.recv_prioritized())
+            .await
+            .unwrap()
+            .expect("timed out");
+        assert_eq!(result, barrier);
+        assert_eq!(overtaken, 2);
+
+        for batch in batches {
+            let result = timeout(Duration::from_secs(1), server_rx.recv())
+                .await
+                .unwrap()
+                .expect("timed out");
+            assert_eq!(result, ArrowMessage::Data(batch));
+        }
+    }
 }
@@ -218,9 +218,16 @@ pub async fn main() {
         &api_conf,
         &pipeline_id,
         PipelinePatch {
+            autoscaling: None,
+            checkpoint_config: None,
             checkpoint_interval_micros: None,
+            dry_run: None,
+            operator_parallelism: None,
             parallelism: None,
+            query: None,
+            restart_strategy: None,
             stop: Some(Some(StopType::Checkpoint)),
+            udfs: None,
         },
     )
     .await
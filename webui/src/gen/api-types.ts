@@ -132,6 +132,11 @@ export interface paths {
     /**
      * Update a pipeline 
      * @description Update a pipeline
+     * 
+     * Changing the query or UDFs upgrades the pipeline in place: the job takes a final checkpoint
+     * and is restarted on the new program, with the state of each operator carried over to its
+     * match in the new program. Set `dryRun` to see how state would be mapped without making any
+     * changes.
      */
     patch: operations["patch_pipeline"];
   };
@@ -216,6 +221,29 @@ export type webhooks = Record<string, never>;
 
 export interface components {
   schemas: {
+    /**
+     * @description Configures the autoscaler, which adjusts the parallelism of each operator based on
+     * backpressure, queue utilization and source lag
+     */
+    AutoscalingConfig: {
+      /**
+       * Format: int64
+       * @description Minimum time after the job starts or is rescaled before the autoscaler will rescale it
+       */
+      cooldownMicros?: number | null;
+      enabled: boolean;
+      /**
+       * Format: int64
+       * @description The largest parallelism the autoscaler will scale an operator up to; defaults to the
+       * maximum parallelism allowed for the organization
+       */
+      maxParallelism?: number | null;
+      /**
+       * Format: int64
+       * @description The smallest parallelism the autoscaler will scale an operator down to; defaults to 1
+       */
+      minParallelism?: number | null;
+    };
     AvroFormat: {
       confluentSchemaRegistry?: boolean;
       intoUnstructuredJson?: boolean;
@@ -241,6 +269,40 @@ export interface components {
     CheckpointCollection: {
       data: (components["schemas"]["Checkpoint"])[];
     };
+    /**
+     * @description Controls how checkpoints are taken and how many are kept; checkpoints are started every
+     * `checkpointIntervalMicros`, subject to these limits
+     */
+    CheckpointConfig: {
+      /**
+       * Format: int32
+       * @description How many checkpoints may be in progress at once; defaults to 1. Pipelines with sinks that
+       * commit in two phases (like exactly-once Kafka sinks) always run one checkpoint at a time
+       */
+      maxConcurrentCheckpoints?: number | null;
+      /**
+       * Format: int64
+       * @description The minimum time between the end of one checkpoint and the start of the next; defaults to 0
+       */
+      minPauseMicros?: number | null;
+      /**
+       * Format: int32
+       * @description How many completed checkpoints are retained in storage; defaults to 4
+       */
+      retainedCheckpoints?: number | null;
+      /**
+       * Format: int64
+       * @description A checkpoint that hasn't completed after this long is aborted and counted as a job
+       * failure; by default checkpoints never time out
+       */
+      timeoutMicros?: number | null;
+      /**
+       * @description If set, checkpoint barriers skip ahead of queued data and operators don't wait for them
+       * to align across inputs, instead persisting the data that is still in flight; this keeps
+       * checkpoint times low under backpressure at the cost of larger checkpoints. Defaults to false
+       */
+      unaligned?: boolean | null;
+    };
     CheckpointEventSpan: {
       description: string;
       /** Format: int64 */
@@ -278,6 +340,7 @@ export interface components {
       format?: components["schemas"]["Format"] | null;
       framing?: components["schemas"]["Framing"] | null;
       inferred?: boolean | null;
+      metadataFields?: (components["schemas"]["MetadataField"])[];
       structName?: string | null;
     };
     ConnectionTable: {
@@ -323,6 +386,12 @@ export interface components {
     ConnectorCollection: {
       data: (components["schemas"]["Connector"])[];
     };
+    FailureRate: {
+      /** Format: int64 */
+      intervalMicros: number;
+      /** Format: int64 */
+      maxFailures: number;
+    };
     FieldType: OneOf<[{
       primitive: components["schemas"]["PrimitiveType"];
     }, {
@@ -367,6 +436,11 @@ export interface components {
       /** Format: int64 */
       finishTime?: number | null;
       id: string;
+      /**
+       * Format: int64
+       * @description When the job is waiting to be restarted after a failure, the time of the next attempt
+       */
+      nextRetryTime?: number | null;
       /** Format: int64 */
       runId: number;
       runningDesired: boolean;
@@ -405,6 +479,10 @@ export interface components {
       timestampFormat?: components["schemas"]["TimestampFormat"];
       unstructured?: boolean;
     };
+    MetadataField: {
+      fieldName: string;
+      key: string;
+    };
     Metric: {
       /** Format: int64 */
       time: number;
@@ -437,6 +515,14 @@ export interface components {
     OperatorMetricGroupCollection: {
       data: (components["schemas"]["OperatorMetricGroup"])[];
     };
+    OperatorUpgrade: {
+      description: string;
+      nodeId: string;
+      previousNodeId?: string | null;
+      state: components["schemas"]["OperatorUpgradeState"];
+    };
+    /** @enum {string} */
+    OperatorUpgradeState: "restored" | "reset" | "new" | "dropped";
     OutputData: {
       operatorId: string;
       /** Format: int64 */
@@ -453,6 +539,8 @@ export interface components {
       action?: components["schemas"]["StopType"] | null;
       actionInProgress: boolean;
       actionText: string;
+      autoscaling?: components["schemas"]["AutoscalingConfig"] | null;
+      checkpointConfig?: components["schemas"]["CheckpointConfig"] | null;
       /** Format: int64 */
       checkpointIntervalMicros: number;
       /** Format: int64 */
@@ -462,8 +550,10 @@ export interface components {
       name: string;
       preview: boolean;
       query: string;
+      restartStrategy?: components["schemas"]["RestartStrategy"] | null;
       stop: components["schemas"]["StopType"];
       udfs: (components["schemas"]["Udf"])[];
+      upgrade?: components["schemas"]["PipelineUpgrade"] | null;
     };
     PipelineCollection: {
       data: (components["schemas"]["Pipeline"])[];
@@ -488,11 +578,24 @@ export interface components {
       parallelism: number;
     };
     PipelinePatch: {
+      autoscaling?: components["schemas"]["AutoscalingConfig"] | null;
+      checkpointConfig?: components["schemas"]["CheckpointConfig"] | null;
       /** Format: int64 */
       checkpointIntervalMicros?: number | null;
+      dryRun?: boolean | null;
+      /**
+       * @description Parallelism for individual operators, by node id; takes precedence over `parallelism`.
+       * Setting an operator also sets the operators connected to it by forward edges.
+       */
+      operatorParallelism?: {
+        [key: string]: number | undefined;
+      } | null;
       /** Format: int64 */
       parallelism?: number | null;
+      query?: string | null;
+      restartStrategy?: components["schemas"]["RestartStrategy"] | null;
       stop?: components["schemas"]["StopType"] | null;
+      udfs?: (components["schemas"]["Udf"])[] | null;
     };
     PipelinePost: {
       name: string;
@@ -505,6 +608,11 @@ export interface components {
     PipelineRestart: {
       force?: boolean | null;
     };
+    PipelineUpgrade: {
+      dryRun: boolean;
+      graph: components["schemas"]["PipelineGraph"];
+      operators: (components["schemas"]["OperatorUpgrade"])[];
+    };
     /** @enum {string} */
     PrimitiveType: "int32" | "int64" | "u_int32" | "u_int64" | "f32" | "f64" | "bool" | "string" | "bytes" | "unix_millis" | "unix_micros" | "unix_nanos" | "date_time" | "json";
     QueryValidationResult: {
@@ -512,6 +620,30 @@ export interface components {
       graph?: components["schemas"]["PipelineGraph"] | null;
     };
     RawStringFormat: Record<string, never>;
+    RestartBackoff: OneOf<[{
+      fixed: {
+        /** Format: int64 */
+        delayMicros: number;
+      };
+    }, {
+      exponential: {
+        /** Format: int64 */
+        initialDelayMicros: number;
+        /** Format: int64 */
+        maxDelayMicros: number;
+      };
+    }]>;
+    /** @description Controls how the job is restarted after a failure, and when it gives up and moves to Failed */
+    RestartStrategy: {
+      backoff: components["schemas"]["RestartBackoff"];
+      failureRate?: components["schemas"]["FailureRate"] | null;
+      /**
+       * Format: int64
+       * @description The job fails permanently after this many consecutive restarts; the count is reset once
+       * the job has been running healthily for a few minutes
+       */
+      maxRestarts?: number | null;
+    };
     SchemaDefinition: OneOf<[{
       json_schema: string;
     }, {
@@ -531,7 +663,7 @@ export interface components {
       type: components["schemas"]["FieldType"];
     };
     /** @enum {string} */
-    StopType: "none" | "checkpoint" | "graceful" | "immediate" | "force";
+    StopType: "none" | "checkpoint" | "drain" | "graceful" | "immediate" | "force";
     StructType: {
       fields: (components["schemas"]["SourceField"])[];
       name?: string | null;
@@ -892,6 +1024,11 @@ export interface operations {
   /**
    * Update a pipeline 
    * @description Update a pipeline
+   * 
+   * Changing the query or UDFs upgrades the pipeline in place: the job takes a final checkpoint
+   * and is restarted on the new program, with the state of each operator carried over to its
+   * match in the new program. Set `dryRun` to see how state would be mapped without making any
+   * changes.
    */
   patch_pipeline: {
     parameters: {